@@ -17,7 +17,7 @@ fn main() -> Result<()> {
         <security>
             <requestedPrivileges>
                 <requestedExecutionLevel
-                    level="requireAdministrator"
+                    level="asInvoker"
                     uiAccess="false"
                 />
             </requestedPrivileges>
@@ -0,0 +1,105 @@
+//! Names for screen readers and the high contrast look of the windows.
+//!
+//! egui hands the widget tree to screen readers through AccessKit. Widgets with text are
+//! named after it, the rest are named here.
+
+use egui::{accesskit, Color32, Context, Response, Stroke, Theme, Visuals};
+
+use crate::trace::TraceNode;
+
+pub trait Described {
+    /// Has screen readers call the widget `name`, for widgets with nothing to read of their
+    /// own like inputs that only show a hint.
+    fn named(self, name: &str) -> Self;
+
+    /// [`Described::named`] that also shows `name` on hover, for buttons that only show an icon.
+    fn described(self, name: &str) -> Self;
+}
+
+impl Described for Response {
+    fn named(self, name: &str) -> Self {
+        self.ctx.accesskit_node_builder(self.id, |node| node.set_name(name));
+        self
+    }
+
+    fn described(self, name: &str) -> Self {
+        self.named(name).on_hover_text(name)
+    }
+}
+
+/// Names `response` and has screen readers announce when the name changes while it has the
+/// focus, not only when it gets it.
+pub fn live(response: &Response, name: &str) {
+    response.ctx.accesskit_node_builder(response.id, |node| {
+        node.set_role(accesskit::Role::Group);
+        node.set_live(accesskit::Live::Polite);
+        node.set_name(name);
+    });
+}
+
+/// What a screen reader says for a hop, e.g. "Hop 7 of example.com, 192.0.2.1,
+/// core1.example.net, Frankfurt, Germany, AS3320 Deutsche Telekom, 12 ms".
+pub fn describe_hop(target: &str, ttl: usize, node: &TraceNode) -> String {
+    let mut parts = vec![format!("Hop {} of {}", ttl, target), node.ip.clone()];
+    if !node.hostname.is_empty() && node.hostname != node.ip {
+        parts.push(node.hostname.clone());
+    }
+    let place = node.place();
+    if !place.is_empty() {
+        parts.push(place);
+    }
+    if let Some(asn) = node.asn {
+        parts.push(format!("AS{} {}", asn, node.as_name).trim().to_string());
+    }
+    if let Some(rtt) = node.rtt {
+        parts.push(format!("{:.0} ms", rtt.as_secs_f64() * 1000.0));
+    }
+    parts.join(", ")
+}
+
+/// Puts the windows in high contrast or back, in both the dark and the light theme.
+pub fn set_high_contrast(ctx: &Context, on: bool) {
+    for (theme, visuals) in [(Theme::Dark, Visuals::dark()), (Theme::Light, Visuals::light())] {
+        ctx.set_visuals_of(theme, if on { high_contrast(visuals) } else { visuals });
+    }
+}
+
+/// `visuals` with plain black and white, strong outlines around every widget and thicker
+/// focus and selection marks.
+fn high_contrast(mut visuals: Visuals) -> Visuals {
+    let (fg, bg) = if visuals.dark_mode {
+        (Color32::WHITE, Color32::BLACK)
+    } else {
+        (Color32::BLACK, Color32::WHITE)
+    };
+    visuals.override_text_color = Some(fg);
+    visuals.panel_fill = bg;
+    visuals.window_fill = bg;
+    visuals.extreme_bg_color = bg;
+    visuals.faint_bg_color = bg;
+    visuals.window_stroke = Stroke::new(2.0, fg);
+    let widgets = &mut visuals.widgets;
+    for widget in [
+        &mut widgets.noninteractive,
+        &mut widgets.inactive,
+        &mut widgets.hovered,
+        &mut widgets.active,
+        &mut widgets.open,
+    ] {
+        widget.fg_stroke = Stroke::new(widget.fg_stroke.width.max(1.5), fg);
+        widget.bg_stroke = Stroke::new(widget.bg_stroke.width.max(1.0), fg);
+    }
+    widgets.inactive.bg_fill = bg;
+    widgets.inactive.weak_bg_fill = bg;
+    widgets.hovered.bg_stroke.width = 2.0;
+    widgets.active.bg_stroke.width = 2.5;
+    let accent = if visuals.dark_mode {
+        Color32::from_rgb(255, 215, 0)
+    } else {
+        Color32::from_rgb(0, 0, 190)
+    };
+    visuals.hyperlink_color = accent;
+    visuals.selection.bg_fill = accent;
+    visuals.selection.stroke = Stroke::new(2.0, bg);
+    visuals
+}
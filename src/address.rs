@@ -0,0 +1,68 @@
+use std::net::IpAddr;
+
+use crate::i18n::tr;
+
+/// Renders `ip` the one way it's shown everywhere: IPv6 in its short form and
+/// IPv4-mapped IPv6 as plain IPv4. Anything that doesn't parse is left alone.
+pub fn canonical(ip: &str) -> String {
+    match ip.trim().parse::<IpAddr>() {
+        Ok(addr) => addr.to_canonical().to_string(),
+        Err(_) => ip.to_string(),
+    }
+}
+
+/// Ways a hop's address can be copied to the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyFormat {
+    Ip,
+    /// Host route, /32 or /128
+    Cidr,
+    /// IPv6 in brackets so the port stays unambiguous
+    HostPort,
+    Ptr,
+    Whois,
+}
+
+impl CopyFormat {
+    pub const ALL: [CopyFormat; 5] = [
+        CopyFormat::Ip,
+        CopyFormat::Cidr,
+        CopyFormat::HostPort,
+        CopyFormat::Ptr,
+        CopyFormat::Whois,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CopyFormat::Ip => "Copy IP",
+            CopyFormat::Cidr => "Copy as CIDR",
+            CopyFormat::HostPort => "Copy host:port",
+            CopyFormat::Ptr => "Copy PTR name",
+            CopyFormat::Whois => "Copy whois command",
+        }
+    }
+
+    /// The text to copy, none if the hop has nothing to copy in this format
+    /// (no PTR name, or an address that doesn't parse).
+    pub fn render(&self, ip: &str, hostname: &str, port: u16) -> Option<String> {
+        let addr: IpAddr = ip.trim().parse().ok()?;
+        let addr = addr.to_canonical();
+        match self {
+            CopyFormat::Ip => Some(addr.to_string()),
+            CopyFormat::Cidr => {
+                let len = if addr.is_ipv4() { 32 } else { 128 };
+                Some(format!("{}/{}", addr, len))
+            }
+            CopyFormat::HostPort => match addr {
+                IpAddr::V4(v4) => Some(format!("{}:{}", v4, port)),
+                IpAddr::V6(v6) => Some(format!("[{}]:{}", v6, port)),
+            },
+            // The tracer falls back to the bare IP when there's no PTR record
+            CopyFormat::Ptr => {
+                let name = hostname.trim();
+                (!name.is_empty() && name.parse::<IpAddr>().is_err() && name != "Local" && name != tr("Local")).then(|| name.to_string())
+            }
+            CopyFormat::Whois => Some(format!("whois {}", addr)),
+        }
+    }
+}
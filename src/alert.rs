@@ -0,0 +1,50 @@
+//! Things worth telling the user about even when they aren't looking at the window.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    /// A trace started from the UI finished
+    TraceComplete,
+    /// A target was reached over a different path than the time before
+    RouteChanged,
+    /// A scheduled trace failed, or a trace left its baseline
+    MonitorAlert,
+}
+
+impl AlertKind {
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    pub const ALL: [AlertKind; 3] = [AlertKind::TraceComplete, AlertKind::RouteChanged, AlertKind::MonitorAlert];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertKind::TraceComplete => "Trace complete",
+            AlertKind::RouteChanged => "Route changed",
+            AlertKind::MonitorAlert => "Monitor alert",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub target: String,
+    /// What happened, e.g. the hop that changed
+    pub detail: String,
+}
+
+impl Alert {
+    pub fn new(kind: AlertKind, target: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            target: target.into(),
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}, {}", self.kind.name(), self.target, self.detail)
+    }
+}
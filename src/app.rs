@@ -1,14 +1,48 @@
-use std::collections::HashMap;
-use tokio::runtime::Runtime;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{channel, Receiver},
+    time::{Duration, Instant},
+};
 
 use egui::Context;
-use walkers::{HttpOptions, HttpTiles, Map, MapMemory, Position, Tiles};
+use log::info;
+use serde::{Deserialize, Serialize};
+use walkers::{HttpOptions, HttpTiles, Map, MapMemory, Position, TileId, Tiles};
 
-use crate::{plugins, windows};
+use crate::{
+    accessibility, bus,
+    config::Config,
+    executor::{self, Executor},
+    geo, geocache, history,
+    i18n::{self, tr, trf},
+    network, plugins, sources, trace,
+    ui::toasts,
+    windows,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Provider {
-    OpenStreetMap
+    OpenStreetMap,
+    OpenTopoMap,
+    CartoDarkMatter,
+    CartoPositron,
+    EsriWorldImagery,
+    Custom,
+    FloorPlan,
+}
+
+impl Provider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::OpenStreetMap => "OpenStreetMap",
+            Provider::OpenTopoMap => "OpenTopoMap",
+            Provider::CartoDarkMatter => "CARTO Dark Matter",
+            Provider::CartoPositron => "CARTO Positron",
+            Provider::EsriWorldImagery => "Esri Satellite",
+            Provider::Custom => tr("Custom"),
+            Provider::FloorPlan => tr("Floor plan"),
+        }
+    }
 }
 
 fn http_options() -> HttpOptions {
@@ -23,7 +57,7 @@ fn http_options() -> HttpOptions {
     }
 }
 
-fn providers(egui_ctx: Context) -> HashMap<Provider, Box<dyn Tiles + Send>> {
+fn providers(egui_ctx: Context, config: &Config) -> HashMap<Provider, Box<dyn Tiles + Send>> {
     let mut providers: HashMap<Provider, Box<dyn Tiles + Send>> = HashMap::default();
 
     providers.insert(
@@ -35,52 +69,648 @@ fn providers(egui_ctx: Context) -> HashMap<Provider, Box<dyn Tiles + Send>> {
         )),
     );
 
+    providers.insert(
+        Provider::OpenTopoMap,
+        Box::new(HttpTiles::with_options(
+            sources::OpenTopoMap,
+            http_options(),
+            egui_ctx.to_owned(),
+        )),
+    );
+
+    providers.insert(
+        Provider::CartoDarkMatter,
+        Box::new(HttpTiles::with_options(
+            sources::Carto {
+                style: sources::CartoStyle::DarkMatter,
+            },
+            http_options(),
+            egui_ctx.to_owned(),
+        )),
+    );
+
+    providers.insert(
+        Provider::CartoPositron,
+        Box::new(HttpTiles::with_options(
+            sources::Carto {
+                style: sources::CartoStyle::Positron,
+            },
+            http_options(),
+            egui_ctx.to_owned(),
+        )),
+    );
+
+    providers.insert(
+        Provider::EsriWorldImagery,
+        Box::new(HttpTiles::with_options(
+            sources::EsriWorldImagery,
+            http_options(),
+            egui_ctx.to_owned(),
+        )),
+    );
+
+    if let Some(tiles) = config.custom_tiles.tiles(egui_ctx.to_owned()) {
+        providers.insert(Provider::Custom, tiles);
+    }
+
+    if let Some(tiles) = config.floor_plan.tiles(egui_ctx) {
+        providers.insert(Provider::FloorPlan, tiles);
+    }
+
     providers
 }
 
+/// User-configured XYZ tile server, e.g. Mapbox, Thunderforest or Stadia.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CustomTiles {
+    /// Template with `{z}`, `{x}`, `{y}` and optionally `{key}` placeholders.
+    pub url: String,
+    pub api_key: String,
+}
+
+impl CustomTiles {
+    /// Falls back to the TILE_URL / TILE_API_KEY environment variables.
+    pub fn from_env() -> Self {
+        Self {
+            url: std::env::var("TILE_URL").unwrap_or_default(),
+            api_key: std::env::var("TILE_API_KEY").unwrap_or_default(),
+        }
+    }
+
+    fn tiles(&self, egui_ctx: Context) -> Option<Box<dyn Tiles + Send>> {
+        if self.url.trim().is_empty() {
+            return None;
+        }
+        Some(Box::new(HttpTiles::with_options(
+            sources::CustomUrl::new(self.url.trim().to_string(), self.api_key.trim().to_string()),
+            http_options(),
+            egui_ctx,
+        )))
+    }
+}
+
+/// Keyboard shortcuts handled in `App::shortcuts`, as listed in the help window.
+pub const SHORTCUTS: [(&str, &str); 12] = [
+    ("Ctrl+Space", "Open the quick launcher"),
+    ("Ctrl+L", "Focus the target input"),
+    ("Ctrl+G", "Go to a location on the map"),
+    ("Ctrl+,", "Open the settings"),
+    ("Enter", "Start the trace"),
+    ("Esc", "Cancel running traces"),
+    ("Ctrl+E", "Export the latest trace"),
+    ("+ / -", "Zoom the map"),
+    ("F", "Fit the map to the visible traces"),
+    ("Tab / arrows", "Go through the hops once the map has the focus"),
+    ("F1", "Show this help"),
+    ("F12", "Show the debug window"),
+];
+
+/// Zoom the map opens at once it knows where home is, about a country.
+const HOME_ZOOM: f64 = 6.0;
+/// How long to keep downloading the tiles around home, some may never come.
+const PREFETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tiles the map shows around `home` when opened there, and one zoom step in and out.
+fn home_tiles(home: Position) -> Vec<TileId> {
+    let zoom = HOME_ZOOM as u8;
+    [(zoom, 3), (zoom - 1, 2), (zoom + 1, 1)]
+        .into_iter()
+        .flat_map(|(zoom, radius)| geo::tiles_around(home, zoom, radius))
+        .collect()
+}
+
 pub struct App {
     providers: HashMap<Provider, Box<dyn Tiles + Send>>,
-    selected_provider: Provider,
+    config: Config,
     map_memory: MapMemory,
-    trace_path: plugins::TracePath,
-    runtime: Runtime,
+    secondary_map_memory: MapMemory,
+    trace_set: plugins::TraceSet,
+    magnifier: plugins::Magnifier,
+    measure: plugins::Measure,
+    history: history::History,
+    ip_input: windows::IpInput,
+    history_panel: windows::HistoryPanel,
+    compliance: windows::ComplianceWindow,
+    planner: windows::PlannerWindow,
+    baseline: windows::BaselineWindow,
+    profile: windows::ProfileWindow,
+    stats: windows::StatsWindow,
+    schedule: windows::ScheduleWindow,
+    split_tunnel: windows::SplitTunnelWindow,
+    network: windows::NetworkWindow,
+    relocate: windows::RelocateWindow,
+    privileges: windows::PrivilegesWindow,
+    ipinfo: windows::IpInfoWindow,
+    workspace: windows::WorkspaceWindow,
+    sounds: windows::SoundsWindow,
+    whois: windows::WhoisWindows,
+    ping: windows::PingWindow,
+    places: windows::PlaceSearch,
+    quick_launch: windows::QuickLaunch,
+    geofences: windows::GeofenceWindow,
+    policies: windows::PolicyWindow,
+    sweep: windows::SweepWindow,
+    notes: windows::NotesWindow,
+    settings: windows::SettingsWindow,
+    export: windows::ExportWindow,
+    flows: windows::FlowsWindow,
+    timeline: windows::Timeline,
+    route_diff: windows::RouteDiffWindow,
+    snmp: windows::SnmpWindows,
+    toasts: toasts::Toasts,
+    enrichment: trace::Enrichment,
+    bus: bus::Bus,
+    /// The UI's subscription to `bus.traces`
+    trace_events: Receiver<(usize, trace::TraceEvent)>,
+    network_events: Receiver<network::NetworkContext>,
+    runtime: Executor,
+    capture: windows::MapCapture,
     show_debug: bool,
+    show_help: bool,
+    /// Scale of the display the window was on last frame, as keyed in `ui_scales`
+    display_scale: Option<String>,
+    /// Where the maps are until moved, see `ViewConfig::home`
+    home: Position,
+    /// Whether to locate the public address once the network is known
+    locate: bool,
+    /// Location of the public address, once looked up
+    located: Option<Receiver<Position>>,
+    /// Tiles around home still to download, and until when to keep trying
+    prefetch: (Vec<TileId>, Instant),
+    /// Whether the windows are in high contrast, none before the first frame
+    high_contrast: Option<bool>,
+    #[cfg(feature = "tray")]
+    tray: Option<crate::tray::Tray>,
 }
 
 impl App {
-    pub fn new(egui_ctx: Context) -> Self {
+    /// `start` is a target and tags to trace as soon as the window is up.
+    pub fn new(cc: &eframe::CreationContext, start: Option<(String, Vec<String>)>) -> Self {
+        let egui_ctx = cc.egui_ctx.clone();
         egui_extras::install_image_loaders(&egui_ctx);
 
+        let mut config = Config::load();
+        let providers = providers(egui_ctx.to_owned(), &config);
+        if !providers.contains_key(&config.provider) {
+            config.provider = Provider::OpenStreetMap;
+        }
+        trace::set_ipinfo_token(&config.ipinfo_token);
+        trace::set_resolver(config.resolver, &config.dns_server);
+        i18n::set_language(config.language);
+        i18n::install_fonts(&egui_ctx, config.language);
+
+        // Open where the user is, or was last time, rather than at 0, 0 off Africa
+        let home = config
+            .view
+            .home
+            .or(config.view.located)
+            .map(|[lat, lon]| Position::from_lat_lon(lat, lon));
         let mut map_memory = MapMemory::default();
-        map_memory.set_zoom(1.0).ok();
+        map_memory.set_zoom(if home.is_some() { HOME_ZOOM } else { 1.0 }).ok();
+        let locate = config.view.home.is_none();
 
-        Self {
-            providers: providers(egui_ctx.to_owned()),
-            selected_provider: Provider::OpenStreetMap,
-            map_memory,
-            trace_path: Default::default(),
-            runtime: Runtime::new().unwrap(),
+        let mut trace_set = plugins::TraceSet::default();
+        trace_set.separate_selection = config.view.separate_selection;
+        trace_set.simplify = config.view.simplify_path;
+        trace_set.cluster = config.view.cluster_nodes;
+        trace_set.color_by_as = config.view.color_by_as;
+        trace_set.show_private = config.view.show_private_hops;
+        trace_set.city_labels = config.view.city_labels;
+        trace_set.border_ticks = config.view.border_ticks;
+        trace_set.sparklines = config.view.sparklines;
+        trace_set.copy_port = config.view.copy_port;
+        trace_set.set_style(config.view.style);
+        trace_set.pinned = config.pinned_targets.iter().cloned().collect();
+        trace_set.geofences = config.geofences.clone();
+        trace_set.stall_after = std::time::Duration::from_secs(config.stall_secs);
+        trace_set.start_requests.extend(start.map(|(target, tags)| plugins::StartRequest::new(target, tags)));
+        let magnifier = plugins::Magnifier {
+            boost: config.view.magnifier_boost,
+            ..Default::default()
+        };
+        let mut history = history::History {
+            saved_filters: config.saved_filters.clone(),
+            ..Default::default()
+        };
+        history.open_shared(&config.shared_dir);
+        history.open_store();
+        let enrichment = trace::Enrichment::load(&config.site_map);
+        let runtime = Executor::new(config.worker_threads).expect("Failed to start the async runtime");
+        let bus = bus::Bus::default();
+        let network_events = bus.network.subscribe();
+        network::watch(bus.network.clone(), &runtime);
+        let schedule = windows::ScheduleWindow::new(&config, &enrichment, &bus, &runtime);
+        let ip_input = windows::IpInput::new(&config.last_target);
+        trace_set.hop_rtts = schedule.hop_history();
+        trace_set.toasts = bus.toasts.clone();
+        #[cfg(feature = "tray")]
+        let tray = config
+            .tray
+            .enabled
+            .then(|| crate::tray::Tray::new(&config.favorite_targets, &egui_ctx))
+            .and_then(|tray| tray.map_err(|e| log::warn!("No tray icon: {}", e)).ok());
+        let timeline = windows::Timeline {
+            #[cfg(feature = "syslog")]
+            syslog: crate::syslog::Listener::new(&config.syslog, &bus, &runtime),
+        };
+
+        let mut app = Self {
+            providers,
+            config,
+            map_memory: map_memory.clone(),
+            secondary_map_memory: map_memory,
+            trace_set,
+            magnifier,
+            measure: Default::default(),
+            history,
+            ip_input,
+            history_panel: Default::default(),
+            compliance: Default::default(),
+            planner: Default::default(),
+            baseline: Default::default(),
+            profile: Default::default(),
+            stats: Default::default(),
+            schedule,
+            split_tunnel: Default::default(),
+            network: Default::default(),
+            relocate: Default::default(),
+            privileges: Default::default(),
+            ipinfo: Default::default(),
+            workspace: Default::default(),
+            sounds: windows::SoundsWindow::new(&bus),
+            whois: windows::WhoisWindows::new(&bus),
+            ping: Default::default(),
+            places: Default::default(),
+            quick_launch: Default::default(),
+            geofences: Default::default(),
+            policies: Default::default(),
+            sweep: Default::default(),
+            notes: Default::default(),
+            settings: Default::default(),
+            export: Default::default(),
+            flows: Default::default(),
+            timeline,
+            route_diff: Default::default(),
+            snmp: windows::SnmpWindows::new(&bus),
+            toasts: toasts::Toasts::new(&bus),
+            enrichment,
+            trace_events: bus.traces.subscribe(),
+            network_events,
+            bus,
+            runtime,
+            capture: Default::default(),
             show_debug: false,
+            show_help: false,
+            display_scale: None,
+            home: home.unwrap_or(Position::from_lat_lon(0.0, 0.0)),
+            locate,
+            located: None,
+            prefetch: (home.map(home_tiles).unwrap_or_default(), Instant::now() + PREFETCH_TIMEOUT),
+            high_contrast: None,
+            #[cfg(feature = "tray")]
+            tray,
+        };
+        if let Some(error) = app.config.load_error.take() {
+            app.bus.toasts.publish(toasts::Toast::error(error));
+        }
+        let open = app.config.view.open_windows.clone();
+        for (name, window) in app.tool_windows() {
+            *window = open.iter().any(|o| o == name);
+        }
+        app
+    }
+}
+
+impl App {
+    /// Open flags of the windows opened from the controls, by the name they're remembered under.
+    fn tool_windows(&mut self) -> [(&'static str, &mut bool); 12] {
+        [
+            ("settings", &mut self.settings.open),
+            ("compliance", &mut self.compliance.open),
+            ("geofences", &mut self.geofences.open),
+            ("policies", &mut self.policies.open),
+            ("planner", &mut self.planner.open),
+            ("baselines", &mut self.baseline.open),
+            ("profile", &mut self.profile.open),
+            ("schedule", &mut self.schedule.open),
+            ("split_tunnel", &mut self.split_tunnel.open),
+            ("sounds", &mut self.sounds.open),
+            ("workspace", &mut self.workspace.open),
+            ("ipinfo", &mut self.ipinfo.open),
+        ]
+    }
+
+    /// Marks new traces with the current network, and offers to trace the pinned targets
+    /// again when it changes.
+    fn watch_network(&mut self, ctx: &egui::Context) {
+        // Changes come in from the background, look for them even when nothing else happens
+        ctx.request_repaint_after(network::POLL_INTERVAL);
+        while let Ok(context) = self.network_events.try_recv() {
+            if let Some(ip) = context.public_ip.clone().filter(|_| self.locate) {
+                self.locate = false;
+                self.locate_home(ip);
+            }
+            let previous = self.trace_set.network.replace(context.clone());
+            let Some(previous) = previous else {
+                continue;
+            };
+            if self.trace_set.pinned.is_empty() {
+                continue;
+            }
+            if self.config.auto_retrace {
+                info!("Tracing {} pinned targets again on {}", self.trace_set.pinned.len(), context);
+                self.trace_set.rerun_pinned();
+            } else {
+                self.network.open(previous, context);
+            }
+        }
+    }
+
+    /// Looks up where `ip`, the public address, is for `warm_start` to pick up.
+    fn locate_home(&mut self, ip: String) {
+        let (sender, receiver) = channel();
+        let provider = self.config.geolocation;
+        let sites = self.enrichment.sites.clone();
+        let ttl = self.config.trace.cache_ttl();
+        self.runtime.spawn(async move {
+            let client = reqwest::Client::new();
+            if let Some(location) = trace::get_location(&client, provider, &sites, &ip, ttl).await {
+                sender.send(location.position.into()).ok();
+            }
+        });
+        self.located = Some(receiver);
+    }
+
+    /// Makes `position` home, zooms the maps that weren't moved in on it and downloads the
+    /// tiles around it.
+    fn go_home(&mut self, position: Position) {
+        self.home = position;
+        for memory in [&mut self.map_memory, &mut self.secondary_map_memory] {
+            if memory.detached().is_none() && memory.zoom() < HOME_ZOOM {
+                memory.set_zoom(HOME_ZOOM).ok();
+            }
+        }
+        self.prefetch = (home_tiles(position), Instant::now() + PREFETCH_TIMEOUT);
+    }
+
+    /// Moves home to where the public address is unless one was set, and requests the tiles
+    /// around home a few at a time, as many as the download queue takes each frame.
+    fn warm_start(&mut self, ctx: &egui::Context) {
+        if let Some(position) = self.located.as_ref().and_then(|located| located.try_recv().ok()) {
+            self.located = None;
+            info!("Public address is at {:.2}, {:.2}", position.lat(), position.lon());
+            self.config.view.located = Some([position.lat(), position.lon()]);
+            if self.config.view.home.is_none() {
+                self.go_home(position);
+            }
+        }
+        if std::mem::take(&mut self.settings.set_home) {
+            let center = self.map_memory.detached().unwrap_or(self.home);
+            info!("Home set to {:.3}, {:.3}", center.lat(), center.lon());
+            self.config.view.home = Some([center.lat(), center.lon()]);
+            self.home = center;
+        }
+
+        let (pending, until) = &mut self.prefetch;
+        if pending.is_empty() {
+            return;
+        }
+        if Instant::now() > *until {
+            info!("Gave up prefetching {} tiles", pending.len());
+            pending.clear();
+            return;
+        }
+        let Some(tiles) = self.providers.get_mut(&self.config.provider) else {
+            return;
+        };
+        // Until a tile is in, `at` hands out a stretched one from another zoom if it can
+        let whole = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        pending.retain(|tile| tiles.at(*tile).is_none_or(|texture| texture.uv != whole));
+        ctx.request_repaint_after(Duration::from_millis(100));
+    }
+
+    /// Runs what was picked from the tray, and hides the window instead of closing it if asked to.
+    #[cfg(feature = "tray")]
+    fn tray(&mut self, ctx: &egui::Context) {
+        use crate::tray::{self, Action};
+
+        let Some(icon) = &mut self.tray else {
+            return;
+        };
+        icon.update(&self.config.favorite_targets);
+        for action in icon.poll() {
+            match action {
+                Action::Trace(target) => {
+                    info!("Tracing {} from the tray", target);
+                    self.trace_set.start_requests.push(plugins::StartRequest::new(target, Vec::new()));
+                    tray::show(ctx);
+                }
+                Action::Show => tray::show(ctx),
+                Action::Quit => {
+                    icon.quitting = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            }
+        }
+        if self.config.tray.close_to_tray && !icon.quitting && ctx.input(|i| i.viewport().close_requested()) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+    }
+
+    /// Zooms the UI by what was picked for the display the window is on when it moves to
+    /// another one, and remembers what is picked, with the settings or Ctrl +/-.
+    fn scale(&mut self, ctx: &egui::Context) {
+        let display = format!("{:.2}", ctx.native_pixels_per_point().unwrap_or(1.0));
+        let scales = &mut self.config.view.ui_scales;
+        if self.display_scale.as_ref() != Some(&display) {
+            let zoom = scales.get(&display).copied().unwrap_or(1.0);
+            info!("Display scale is {}, zooming by {}", display, zoom);
+            ctx.set_zoom_factor(zoom);
+            self.display_scale = Some(display);
+            return;
+        }
+        let zoom = ctx.zoom_factor();
+        if (zoom - 1.0).abs() < 0.01 {
+            scales.remove(&display);
+        } else {
+            scales.insert(display, zoom);
+        }
+    }
+
+    /// Handles every keyboard shortcut in `SHORTCUTS` except Enter, which belongs to the target input.
+    fn shortcuts(&mut self, ctx: &egui::Context) {
+        use egui::{Key, KeyboardShortcut, Modifiers};
+
+        let (launch, focus, export, places, settings) = ctx.input_mut(|i| {
+            (
+                i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Space)),
+                i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::L)),
+                i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::E)),
+                i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::G)),
+                i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Comma)),
+            )
+        });
+        if settings {
+            self.settings.open = !self.settings.open;
+        }
+        if focus {
+            ctx.memory_mut(|m| m.request_focus(egui::Id::new(windows::TARGET_INPUT)));
+        }
+        if launch {
+            self.quick_launch.toggle(ctx);
+        }
+        if places {
+            self.places.show(ctx);
+        }
+        if export {
+            self.trace_set.export_request = self.trace_set.traces.iter().rev().find(|t| !t.tracing).map(|t| t.id);
+        }
+        // Esc in the quick launcher only closes it
+        if ctx.input(|i| i.key_pressed(Key::Escape)) && self.trace_set.tracing() && !self.quick_launch.is_open() {
+            self.trace_set.cancel();
+        }
+        if ctx.input(|i| i.key_pressed(Key::F1)) {
+            self.show_help = !self.show_help;
+        }
+        if ctx.input(|i| i.key_pressed(Key::F12)) {
+            self.show_debug = !self.show_debug;
+        }
+
+        // Plain keys, only when nobody is typing
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let (zoom_in, zoom_out, fit) = ctx.input(|i| {
+            (
+                i.key_pressed(Key::Plus) || i.key_pressed(Key::Equals),
+                i.key_pressed(Key::Minus),
+                i.key_pressed(Key::F),
+            )
+        });
+        if zoom_in {
+            self.map_memory.zoom_in().ok();
+        }
+        if zoom_out {
+            self.map_memory.zoom_out().ok();
+        }
+        if fit {
+            let positions: Vec<_> = self
+                .trace_set
+                .traces
+                .iter()
+                .filter(|t| t.visible)
+                .flat_map(|t| t.nodes.iter().map(|(_, node)| node.position))
+                .collect();
+            if let Some((center, zoom)) = geo::fit(&positions, ctx.available_rect().size()) {
+                self.map_memory.center_at(center);
+                self.map_memory.set_zoom(zoom).ok();
+            }
         }
     }
 }
 
 impl eframe::App for App {
+    fn on_exit(&mut self) {
+        self.config.view.separate_selection = self.trace_set.separate_selection;
+        self.config.view.simplify_path = self.trace_set.simplify;
+        self.config.view.cluster_nodes = self.trace_set.cluster;
+        self.config.view.color_by_as = self.trace_set.color_by_as;
+        self.config.view.show_private_hops = self.trace_set.show_private;
+        self.config.view.city_labels = self.trace_set.city_labels;
+        self.config.view.border_ticks = self.trace_set.border_ticks;
+        self.config.view.sparklines = self.trace_set.sparklines;
+        self.config.view.copy_port = self.trace_set.copy_port;
+        self.config.view.style = self.trace_set.style();
+        self.config.view.magnifier_boost = self.magnifier.boost;
+        self.config.saved_filters = self.history.saved_filters.clone();
+        self.config.pinned_targets = self.trace_set.pinned.iter().cloned().collect();
+        self.config.view.open_windows = self
+            .tool_windows()
+            .into_iter()
+            .filter(|(_, open)| **open)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        self.config.store_profile();
+        self.config.save();
+        geocache::save(self.config.trace.cache_ttl());
+        // Stops traces, monitors and lookups instead of leaving them running in the background
+        self.runtime.shutdown(executor::SHUTDOWN_TIMEOUT);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Check for F12 key press to toggle debug window
-        if ctx.input(|i| i.key_pressed(egui::Key::F12)) {
-            self.show_debug = !self.show_debug;
+        let capturing = self.capture.hide_windows();
+        if capturing {
+            // Set by the latency profile, which isn't drawn while capturing
+            self.trace_set.highlight = None;
         }
 
         // Show debug window if enabled
-        if self.show_debug {
-            egui::Window::new("Debug Info")
+        if self.show_debug && !capturing {
+            egui::Window::new(tr("Debug Info"))
+                .id(egui::Id::new("Debug Info"))
                 .resizable(true)
                 .show(ctx, |ui| {
                     ctx.inspection_ui(ui);
                 });
         }
 
+        if let Some(positions) = windows::countries(ctx, &self.trace_set) {
+            let size = ctx.available_rect().size();
+            if let Some((center, zoom)) = geo::fit(&positions, size) {
+                self.map_memory.center_at(center);
+                self.map_memory.set_zoom(zoom).ok();
+            }
+        }
+
+        if let Some((position, second_view)) = self.trace_set.center_request.take() {
+            if second_view {
+                self.secondary_map_memory.center_at(position);
+            } else {
+                self.map_memory.center_at(position);
+            }
+        }
+        // Follows the map's high contrast setting
+        let high_contrast = self.trace_set.style().high_contrast;
+        if self.high_contrast != Some(high_contrast) {
+            accessibility::set_high_contrast(ctx, high_contrast);
+            self.high_contrast = Some(high_contrast);
+        }
+
+        self.scale(ctx);
+        self.warm_start(ctx);
+        self.shortcuts(ctx);
+        if std::mem::take(&mut self.settings.reset_layout) {
+            windows::reset_layout(ctx);
+            // The settings window asked for it, so it stays
+            for (_, open) in self.tool_windows().into_iter().filter(|(name, _)| *name != "settings") {
+                *open = false;
+            }
+        }
+        if std::mem::take(&mut self.settings.reload_shared) {
+            self.history.open_shared(&self.config.shared_dir);
+        }
+        self.history.sync();
+        self.watch_network(ctx);
+        #[cfg(feature = "tray")]
+        self.tray(ctx);
+        windows::run_requests(
+            ctx,
+            &mut self.ip_input,
+            &mut self.trace_set,
+            &mut self.config,
+            &self.enrichment,
+            &self.bus,
+            &self.runtime,
+        );
+        windows::apply_trace_events(
+            &mut self.trace_set,
+            &mut self.history,
+            &self.config,
+            &self.bus,
+            &self.trace_events,
+        );
+
         let rimless = egui::Frame {
             fill: ctx.style().visuals.panel_fill,
             ..Default::default()
@@ -91,33 +721,234 @@ impl eframe::App for App {
             .show(ctx, |ui| {
                 let tiles = self
                     .providers
-                    .get_mut(&self.selected_provider)
+                    .get_mut(&self.config.provider)
                     .unwrap()
                     .as_mut();
                 let attribution = tiles.attribution();
 
-                // In egui, widgets are constructed and consumed in each frame.
-                let map = Map::new(Some(tiles), &mut self.map_memory, Position::from_lat_lon(0.0, 0.0));
+                let home = self.home;
+                self.capture.rect = ui.max_rect();
 
-                // Attach the trace path plugin instead of click watcher
-                let map = map.with_plugin(&mut self.trace_path);
+                if self.config.view.split_view {
+                    // Two independent viewports over the same tiles, e.g. origin and destination
+                    let (primary, secondary) = ui.columns(2, |columns| {
+                        let map = Map::new(Some(&mut *tiles), &mut self.map_memory, home)
+                            .with_plugin(self.trace_set.layer(false))
+                            .with_plugin(self.measure.layer())
+                            .with_plugin(self.geofences.draft_layer());
+                        let primary = columns[0].add(map);
 
-                // Draw the map widget.
-                ui.add(map);
+                        let map = Map::new(Some(&mut *tiles), &mut self.secondary_map_memory, home)
+                            .with_plugin(self.trace_set.layer(true))
+                            .with_plugin(self.measure.layer())
+                            .with_plugin(self.geofences.draft_layer());
+                        (primary, columns[1].add(map))
+                    });
 
-                // Draw utility windows.
-                {
+                    if !capturing {
+                        self.magnifier.show(ui, &primary, &self.map_memory, home, tiles, &self.trace_set, false);
+                        self.magnifier.show(ui, &secondary, &self.secondary_map_memory, home, tiles, &self.trace_set, true);
+                    }
+                } else {
+                    // In egui, widgets are constructed and consumed in each frame.
+                    let map = Map::new(Some(&mut *tiles), &mut self.map_memory, home);
+
+                    // Attach the trace plugin instead of click watcher
+                    let map = map.with_plugin(self.trace_set.layer(false)).with_plugin(self.measure.layer())
+                            .with_plugin(self.geofences.draft_layer());
+
+                    // Draw the map widget.
+                    let response = ui.add(map);
+
+                    if !capturing {
+                        self.magnifier.show(ui, &response, &self.map_memory, home, tiles, &self.trace_set, false);
+                    }
+                }
+
+                // Draw utility windows, unless the map is being saved as an image
+                if !capturing {
                     use windows::*;
 
-                    zoom(ui, &mut self.map_memory);
-                    enter_ip(ui, &mut self.trace_path, &self.runtime);
-                    controls(
+                    zoom(ui, &mut self.map_memory, &mut self.capture, &mut self.places, &mut self.show_help);
+                    places(ui, &mut self.places, &mut self.map_memory, &self.runtime);
+                    quick_launch(ui, &mut self.quick_launch, &mut self.trace_set, &mut self.config);
+                    help(ui, &mut self.show_help);
+                    enter_ip(
+                        ui,
+                        &mut self.ip_input,
+                        &mut self.trace_set,
+                        &mut self.config,
+                        &mut self.ping,
+                        &self.enrichment,
+                        &self.runtime,
+                    );
+                    trace_errors(ui, &mut self.trace_set);
+                    history(ui, &mut self.history_panel, &mut self.history, &mut self.trace_set);
+                    timeline(
+                        ui,
+                        &mut self.timeline,
+                        &mut self.route_diff,
+                        &self.history,
+                        &mut self.config,
+                        &self.runtime,
+                    );
+                    if let Some((before, after)) = self.trace_set.route_diff_request.take() {
+                        self.route_diff.open(&self.history.entries[before], &self.history.entries[after]);
+                    }
+                    route_diff(ui, &mut self.route_diff, &mut self.trace_set);
+                    hops(ui, &mut self.trace_set);
+                    selected_hop(ui, &mut self.trace_set);
+                    compliance(ui, &mut self.compliance, &self.trace_set, &mut self.config.concerns);
+                    geofences(ui, &mut self.geofences, &mut self.config, &mut self.trace_set, &self.schedule);
+                    policies(ui, &mut self.policies, &mut self.config, &self.trace_set, &self.schedule);
+                    if let Some(ip) = self.trace_set.whois_request.take() {
+                        self.whois.open(ip, &self.runtime);
+                    }
+                    whois(ui, &mut self.whois);
+                    if let Some(ip) = self.trace_set.snmp_request.take() {
+                        self.snmp.open(ip, &self.config.snmp, &self.runtime);
+                    }
+                    snmp(ui, &mut self.snmp);
+                    if let Some(ip) = self.trace_set.watch_request.take() {
+                        let toast = if self.schedule.watch(ip.clone(), &mut self.config, &self.enrichment, &self.runtime) {
+                            toasts::Toast::success(trf("Watching {}, traced every 5 minutes", &[&ip]))
+                        } else {
+                            toasts::Toast::info(trf("{} is already scheduled", &[&ip]))
+                        };
+                        self.bus.toasts.publish(toast);
+                    }
+                    if let Some(target) = self.trace_set.bookmark_request.take() {
+                        if !self.config.is_favorite(&target) {
+                            self.config.toggle_favorite(&target);
+                        }
+                        self.bus.toasts.publish(toasts::Toast::success(trf("{} is among the favorites", &[&target])));
+                    }
+                    if let Some(id) = self.trace_set.export_request.take() {
+                        self.export.open(id, &self.trace_set);
+                    }
+                    export(ui, &mut self.export, &self.trace_set);
+                    if let Some(id) = self.trace_set.flows_request.take() {
+                        self.flows.open(id, &self.trace_set);
+                    }
+                    flows(ui, &mut self.flows, &mut self.trace_set);
+                    if let Some(ip) = self.trace_set.ping_request.take() {
+                        self.ping.start(
+                            ip,
+                            self.config.trace,
+                            self.config.geolocation,
+                            self.enrichment.sites.clone(),
+                            &self.runtime,
+                        );
+                    }
+                    ping(ui, &mut self.ping);
+                    planner(ui, &mut self.planner, &mut self.config, &mut self.trace_set);
+                    baseline(ui, &mut self.baseline, &mut self.history, &mut self.trace_set);
+                    profile(ui, &mut self.profile, &mut self.trace_set);
+                    if let Some(id) = self.trace_set.stats_request.take() {
+                        self.stats.open(id, &self.trace_set, self.config.trace.timeout_ms, &self.runtime);
+                    }
+                    stats(ui, &mut self.stats, &mut self.trace_set);
+                    if let Some(id) = self.trace_set.sweep_request.take() {
+                        self.sweep.open(id, &self.trace_set);
+                    }
+                    sweep(ui, &mut self.sweep);
+                    if let Some(id) = self.trace_set.notes_request.take() {
+                        self.notes.open(id, &self.trace_set);
+                    }
+                    notes(ui, &mut self.notes, &mut self.history.notes);
+                    schedule(
                         ui,
-                        &mut self.selected_provider,
-                        &mut self.providers.keys(),
+                        &mut self.schedule,
+                        &mut self.config,
+                        &self.enrichment,
+                        &mut self.trace_set,
+                        &self.runtime,
                     );
+                    split_tunnel(ui, &mut self.split_tunnel, &mut self.config, &self.enrichment, &self.runtime);
+                    network(ui, &mut self.network, &mut self.trace_set, &mut self.config.auto_retrace);
+                    sounds(ui, &mut self.sounds, &mut self.config);
+                    privileges(ui, &mut self.privileges);
+                    ipinfo(ui, &mut self.ipinfo, &mut self.config, &self.runtime);
+                    workspace(
+                        ui,
+                        &mut self.workspace,
+                        &mut self.config,
+                        &mut self.trace_set,
+                        &mut self.history,
+                        &mut self.schedule,
+                        &self.enrichment,
+                        &self.runtime,
+                    );
+                    relocate(
+                        ui,
+                        &mut self.relocate,
+                        &mut self.history,
+                        self.config.geolocation,
+                        &self.enrichment,
+                        &self.runtime,
+                    );
+                    let mut possible_providers: Vec<_> = self.providers.keys().copied().collect();
+                    possible_providers.sort();
+                    let applied = controls(
+                        ui,
+                        &mut self.config,
+                        &possible_providers,
+                        &mut self.magnifier,
+                        &mut self.measure,
+                        &mut self.compliance,
+                        &mut self.geofences,
+                        &mut self.policies,
+                        &mut self.planner,
+                        &mut self.baseline,
+                        &mut self.profile,
+                        &mut self.schedule,
+                        &mut self.split_tunnel,
+                        &mut self.sounds,
+                        &mut self.workspace,
+                        &mut self.settings,
+                        &mut self.enrichment,
+                    );
+                    let applied = settings(
+                        ui,
+                        &mut self.settings,
+                        &mut self.config,
+                        &possible_providers,
+                        &mut self.trace_set,
+                        &mut self.ipinfo,
+                        &mut self.enrichment,
+                    )
+                    .or(applied);
+                    if let Some(provider) = applied {
+                        // Rebuild the user-configured source with the new settings
+                        let tiles = match provider {
+                            Provider::FloorPlan => self.config.floor_plan.tiles(ui.ctx().clone()),
+                            _ => self.config.custom_tiles.tiles(ui.ctx().clone()),
+                        };
+                        match tiles {
+                            Some(tiles) => {
+                                self.providers.insert(provider, tiles);
+                                self.config.provider = provider;
+                            }
+                            None => {
+                                self.providers.remove(&provider);
+                                if self.config.provider == provider {
+                                    self.config.provider = Provider::OpenStreetMap;
+                                }
+                            }
+                        }
+                    }
                     acknowledge(ui, attribution);
+                    self.toasts.show(ui.ctx());
                 }
             });
+        self.capture.update(ctx);
+        // Asked for by the windows just drawn, started right away instead of with the next input
+        let trace_set = &self.trace_set;
+        if !trace_set.start_requests.is_empty()
+            || !trace_set.resume_requests.is_empty()
+            || !trace_set.lookup_requests.is_empty()
+        {
+            ctx.request_repaint();
+        }
     }
 }
@@ -0,0 +1,155 @@
+//! Sounds for alerts, so finished traces and route changes can be heard from another screen.
+
+use std::{fs::File, io::BufReader, sync::mpsc::Receiver, time::Duration};
+
+use log::{debug, warn};
+use rodio::{
+    source::{self, SineWave},
+    Decoder, OutputStream, OutputStreamHandle, Source,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    alert::{Alert, AlertKind},
+    bus::Bus,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Sound {
+    Off,
+    Chime,
+    Beep,
+    Alarm,
+    /// A WAV, FLAC, Vorbis or MP3 file
+    File(String),
+}
+
+impl Sound {
+    pub const BUILT_IN: [Sound; 4] = [Sound::Off, Sound::Chime, Sound::Beep, Sound::Alarm];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sound::Off => "Off",
+            Sound::Chime => "Chime",
+            Sound::Beep => "Beep",
+            Sound::Alarm => "Alarm",
+            Sound::File(_) => "File",
+        }
+    }
+
+    /// Frequency in Hz and length in ms of each tone of a built-in sound.
+    fn tones(&self) -> &'static [(f32, u64)] {
+        match self {
+            Sound::Chime => &[(880.0, 120), (1320.0, 220)],
+            Sound::Beep => &[(1000.0, 150)],
+            Sound::Alarm => &[(880.0, 150), (660.0, 150), (880.0, 150), (660.0, 150)],
+            Sound::Off | Sound::File(_) => &[],
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub muted: bool,
+    /// 0 to 1
+    pub volume: f32,
+    pub trace_complete: Sound,
+    pub route_changed: Sound,
+    pub monitor_alert: Sound,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            muted: false,
+            volume: 0.5,
+            trace_complete: Sound::Chime,
+            route_changed: Sound::Beep,
+            monitor_alert: Sound::Alarm,
+        }
+    }
+}
+
+impl AudioConfig {
+    pub fn sound(&self, kind: AlertKind) -> &Sound {
+        match kind {
+            AlertKind::TraceComplete => &self.trace_complete,
+            AlertKind::RouteChanged => &self.route_changed,
+            AlertKind::MonitorAlert => &self.monitor_alert,
+        }
+    }
+
+    pub fn sound_mut(&mut self, kind: AlertKind) -> &mut Sound {
+        match kind {
+            AlertKind::TraceComplete => &mut self.trace_complete,
+            AlertKind::RouteChanged => &mut self.route_changed,
+            AlertKind::MonitorAlert => &mut self.monitor_alert,
+        }
+    }
+}
+
+/// Plays the configured sound for every alert published on the bus.
+pub struct Player {
+    receiver: Receiver<Alert>,
+    /// Opened on first use, and only tried once
+    output: Option<Result<(OutputStream, OutputStreamHandle), String>>,
+}
+
+impl Player {
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            receiver: bus.alerts.subscribe(),
+            output: None,
+        }
+    }
+
+    /// Plays whatever alerts came in since the last call, unless muted.
+    pub fn poll(&mut self, config: &AudioConfig) {
+        let alerts: Vec<Alert> = self.receiver.try_iter().collect();
+        if config.muted {
+            return;
+        }
+        for alert in alerts {
+            debug!("Sounding {}", alert);
+            self.play(config.sound(alert.kind), config.volume);
+        }
+    }
+
+    pub fn play(&mut self, sound: &Sound, volume: f32) {
+        let output = self.output.get_or_insert_with(|| {
+            OutputStream::try_default().map_err(|e| {
+                warn!("No audio output: {}", e);
+                e.to_string()
+            })
+        });
+        let Ok((_, handle)) = output else {
+            return;
+        };
+        let result = match sound {
+            Sound::Off => Ok(()),
+            Sound::File(path) => File::open(path)
+                .map_err(|e| e.to_string())
+                .and_then(|file| Decoder::new(BufReader::new(file)).map_err(|e| e.to_string()))
+                .and_then(|decoder| {
+                    handle
+                        .play_raw(decoder.convert_samples::<f32>().amplify(volume))
+                        .map_err(|e| e.to_string())
+                }),
+            _ => {
+                let tones = sound.tones().iter().map(move |&(freq, ms)| {
+                    SineWave::new(freq).take_duration(Duration::from_millis(ms)).amplify(volume)
+                });
+                handle.play_raw(source::from_iter(tones)).map_err(|e| e.to_string())
+            }
+        };
+        if let Err(e) = result {
+            warn!("Failed to play {} sound: {}", sound.name(), e);
+        }
+    }
+
+    /// Why nothing can be heard, once playing was tried.
+    pub fn error(&self) -> Option<&str> {
+        self.output.as_ref()?.as_ref().err().map(String::as_str)
+    }
+}
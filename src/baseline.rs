@@ -0,0 +1,205 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, history::HistoryEntry, i18n::trf, trace::TraceNode};
+
+/// Tag put on the traces a baseline is captured from.
+pub const TAG: &str = "baseline";
+/// Latency above the envelope by this share plus `LATENCY_SLACK_MS` counts as a deviation.
+const LATENCY_SLACK: f64 = 0.2;
+const LATENCY_SLACK_MS: f64 = 5.0;
+
+/// What a target's path normally looks like.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Baseline {
+    pub captured: DateTime<Local>,
+    /// Traces the baseline was built from
+    pub runs: usize,
+    /// Consensus hops in TTL order
+    pub hops: Vec<BaselineHop>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BaselineHop {
+    pub ttl: usize,
+    pub ip: String,
+    /// Lowest and highest RTT seen from this hop, in ms
+    pub rtt_low: f64,
+    pub rtt_high: f64,
+}
+
+/// How a hop differs from its baseline.
+#[derive(Clone)]
+pub enum Deviation {
+    /// A different router answered at this TTL
+    Path { expected: String },
+    /// Slower than anything seen while capturing
+    Latency { rtt_ms: f64, high_ms: f64 },
+}
+
+impl Deviation {
+    pub fn describe(&self) -> String {
+        match self {
+            Deviation::Path { expected } => trf("Expected {}", &[expected]),
+            Deviation::Latency { rtt_ms, high_ms } => {
+                trf("{} ms, baseline up to {} ms", &[&format!("{:.0}", rtt_ms), &format!("{:.0}", high_ms)])
+            }
+        }
+    }
+}
+
+impl Baseline {
+    /// Builds the consensus of `entries`: at every TTL, the IP that answered in at least
+    /// half of the runs, with the range of its RTTs.
+    pub fn from_entries(entries: &[&HistoryEntry]) -> Self {
+        let mut seen: BTreeMap<usize, HashMap<&str, Vec<f64>>> = BTreeMap::new();
+        for entry in entries {
+            for (ttl, node) in entry.nodes.iter().filter(|(ttl, _)| *ttl > 0) {
+                let rtts = seen.entry(*ttl).or_default().entry(&node.ip).or_default();
+                if let Some(rtt) = node.rtt {
+                    rtts.push(rtt.as_secs_f64() * 1000.0);
+                }
+            }
+        }
+
+        let hops = seen
+            .into_iter()
+            .filter_map(|(ttl, ips)| {
+                let (ip, rtts) = ips.into_iter().max_by_key(|(_, rtts)| rtts.len())?;
+                if rtts.len() * 2 < entries.len() {
+                    return None;
+                }
+                Some(BaselineHop {
+                    ttl,
+                    ip: ip.to_string(),
+                    rtt_low: rtts.iter().copied().fold(f64::INFINITY, f64::min),
+                    rtt_high: rtts.iter().copied().fold(0.0, f64::max),
+                })
+            })
+            .collect();
+        Self {
+            captured: Local::now(),
+            runs: entries.len(),
+            hops,
+        }
+    }
+
+    /// Hops of a trace that differ from the baseline, by TTL.
+    pub fn compare(&self, nodes: &[(usize, TraceNode)]) -> BTreeMap<usize, Deviation> {
+        let mut deviations = BTreeMap::new();
+        for (ttl, node) in nodes {
+            let Some(hop) = self.hops.iter().find(|hop| hop.ttl == *ttl) else {
+                continue;
+            };
+            if node.ip != hop.ip {
+                deviations.insert(*ttl, Deviation::Path { expected: hop.ip.clone() });
+            } else if let Some(rtt) = node.rtt {
+                let rtt_ms = rtt.as_secs_f64() * 1000.0;
+                if rtt_ms > hop.rtt_high * (1.0 + LATENCY_SLACK) + LATENCY_SLACK_MS {
+                    deviations.insert(*ttl, Deviation::Latency { rtt_ms, high_ms: hop.rtt_high });
+                }
+            }
+        }
+        deviations
+    }
+}
+
+/// Baselines by target, kept on disk. With a shared store, several people read and write
+/// the same file, so saving merges in what others changed since it was read.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Baselines {
+    pub targets: BTreeMap<String, Baseline>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Modification time of the file when it was last read or written
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+    /// Targets set or removed here since then, which win over the file
+    #[serde(skip)]
+    touched: BTreeSet<String>,
+}
+
+impl Baselines {
+    /// Loads the baselines in `shared_dir`, or the local ones if it is empty.
+    pub fn load(shared_dir: &str) -> Self {
+        let path = config::store_dir(shared_dir).map(|dir| dir.join("baselines.toml"));
+        let mut baselines = path.as_deref().and_then(Self::read).unwrap_or_default();
+        baselines.modified = path.as_deref().and_then(config::modified);
+        baselines.path = path;
+        baselines
+    }
+
+    fn read(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        toml::from_str(&text)
+            .map_err(|e| warn!("Ignoring broken baselines file {}: {}", path.display(), e))
+            .ok()
+    }
+
+    pub fn set(&mut self, target: &str, baseline: Baseline) {
+        self.targets.insert(target.to_string(), baseline);
+        self.touched.insert(target.to_string());
+    }
+
+    pub fn remove(&mut self, target: &str) {
+        self.targets.remove(target);
+        self.touched.insert(target.to_string());
+    }
+
+    /// Takes the file's baselines if someone else wrote it since it was read, keeping the
+    /// targets changed here. Returns whether anything was read.
+    pub fn refresh(&mut self) -> bool {
+        let Some(path) = &self.path else {
+            return false;
+        };
+        let modified = config::modified(path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        let Some(mut theirs) = Self::read(path) else {
+            return false;
+        };
+        for target in &self.touched {
+            match self.targets.get(target) {
+                Some(baseline) => theirs.targets.insert(target.clone(), baseline.clone()),
+                None => theirs.targets.remove(target),
+            };
+        }
+        info!("Merged baselines changed in {}", path.display());
+        self.targets = theirs.targets;
+        self.modified = modified;
+        true
+    }
+
+    pub fn save(&mut self) {
+        self.refresh();
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let result = toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|text| config::write_replacing(&path, &text).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                info!("Saved {} baselines", self.targets.len());
+                self.modified = config::modified(&path);
+                self.touched.clear();
+            }
+            Err(e) => warn!("Failed to save baselines: {}", e),
+        }
+    }
+
+    /// Deviations of a trace to `target`, none if it has no baseline.
+    pub fn compare(&self, target: &str, nodes: &[(usize, TraceNode)]) -> Option<BTreeMap<usize, Deviation>> {
+        self.targets.get(target).map(|baseline| baseline.compare(nodes))
+    }
+}
@@ -0,0 +1,71 @@
+//! Typed publish/subscribe between the trace engine, lookups, storage and the UI.
+//!
+//! Producers publish on a [`Topic`] without knowing who listens, and every subscriber
+//! gets its own copy of each message, so new consumers can be added next to the UI.
+
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex,
+};
+
+use crate::{alert::Alert, network::NetworkContext, scheduler::Run, snmp, ui::toasts::Toast, trace::TraceEvent};
+
+/// One kind of message and everyone subscribed to it. Clones publish to the same subscribers.
+pub struct Topic<T> {
+    subscribers: Arc<Mutex<Vec<Sender<T>>>>,
+}
+
+impl<T> Default for Topic<T> {
+    fn default() -> Self {
+        Self {
+            subscribers: Default::default(),
+        }
+    }
+}
+
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        Self {
+            subscribers: self.subscribers.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Topic<T> {
+    /// Hands `message` to every subscriber, forgetting the ones that went away.
+    pub fn publish(&self, message: T) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+    }
+
+    /// Receives everything published from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+/// Every topic subsystems talk over.
+#[derive(Clone, Default)]
+pub struct Bus {
+    /// Events of all running traces, tagged with the run they belong to, see `Trace::run`
+    pub traces: Topic<(usize, TraceEvent)>,
+    /// Finished WHOIS lookups by IP
+    pub whois: Topic<(String, Result<String, String>)>,
+    /// Finished SNMP interface lookups by IP
+    pub snmp: Topic<(String, Result<snmp::Interface, String>)>,
+    /// The network the machine is on, whenever it changes
+    pub network: Topic<NetworkContext>,
+    /// Finished scheduled traces
+    pub scheduled: Topic<Run>,
+    /// Events the user may want to hear or be notified about
+    pub alerts: Topic<Alert>,
+    /// Short messages for the corner of the window
+    pub toasts: Topic<Toast>,
+    /// Route changes reported by routers over syslog
+    #[cfg(feature = "syslog")]
+    pub route_events: Topic<crate::syslog::RouteEvent>,
+}
@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use directories::ProjectDirs;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{CustomTiles, Provider},
+    floorplan::FloorPlan,
+    geofence::Geofence,
+    history::SavedFilter,
+    i18n::{trf, Language},
+    planner::PlannerConfig,
+    plugins::MarkerStyle,
+    policy::TransitPolicy,
+    scheduler::ScheduleConfig,
+    snmp::SnmpConfig,
+    splittunnel::SplitTunnelConfig,
+    trace::{GeoProvider, Resolver, TraceOptions},
+};
+
+/// Everything that survives a restart, stored as TOML in the platform config directory.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub provider: Provider,
+    pub custom_tiles: CustomTiles,
+    pub floor_plan: FloorPlan,
+    pub trace: TraceOptions,
+    pub geolocation: GeoProvider,
+    /// Token for ipinfo.io, needed for batch lookups and paid fields like carriers
+    pub ipinfo_token: String,
+    /// Who resolves target names
+    pub resolver: Resolver,
+    /// IP of the DNS server for `Resolver::Server`
+    pub dns_server: String,
+    /// DNSBL zones target domains are looked up in before tracing, empty to skip the check
+    pub blocklists: Vec<String>,
+    pub view: ViewConfig,
+    /// Language of the UI, the system's if there is a translation for it
+    pub language: Language,
+    pub last_target: String,
+    /// Targets traced or pinged before, most recent first
+    pub recent_targets: Vec<String>,
+    /// Targets pinned to the top of the target dropdown
+    pub favorite_targets: Vec<String>,
+    /// Targets traced again when the network changes
+    pub pinned_targets: Vec<String>,
+    /// Options targets were last traced with, where they differ from `trace`
+    pub target_options: BTreeMap<String, TraceOptions>,
+    /// Trace the pinned targets again right away instead of asking
+    pub auto_retrace: bool,
+    pub saved_filters: Vec<SavedFilter>,
+    /// Country codes flagged in the compliance report
+    pub concerns: Vec<String>,
+    /// Areas routes should stay out of
+    pub geofences: Vec<Geofence>,
+    /// ASes routes to a network should and shouldn't go through
+    pub transit_policies: Vec<TransitPolicy>,
+    /// CSV or JSON file locating internal ranges, see `SiteMap`
+    pub site_map: String,
+    pub snmp: SnmpConfig,
+    pub planner: PlannerConfig,
+    pub schedule: ScheduleConfig,
+    pub split_tunnel: SplitTunnelConfig,
+    #[cfg(feature = "syslog")]
+    pub syslog: crate::syslog::SyslogConfig,
+    #[cfg(feature = "audio")]
+    pub audio: crate::audio::AudioConfig,
+    #[cfg(feature = "tray")]
+    pub tray: crate::tray::TrayConfig,
+    /// Named sets of network dependent settings, e.g. home, work and field
+    pub profiles: BTreeMap<String, Profile>,
+    /// Profile the current settings belong to, empty for none
+    pub active_profile: String,
+    /// Threads running lookups and traces in the background, 0 for one per core
+    pub worker_threads: usize,
+    /// Seconds without new hops after which a running trace counts as stalled, 0 to never
+    pub stall_secs: u64,
+    /// Folder shared with the team where baselines and notes are kept, empty to keep them local
+    pub shared_dir: String,
+    /// Why the config file couldn't be used, to tell the user once the window is up
+    #[serde(skip)]
+    pub load_error: Option<String>,
+    /// A broken config file that couldn't be moved aside, which saving mustn't overwrite
+    #[serde(skip)]
+    keep_file: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            provider: Provider::OpenStreetMap,
+            custom_tiles: CustomTiles::from_env(),
+            floor_plan: Default::default(),
+            trace: Default::default(),
+            geolocation: Default::default(),
+            ipinfo_token: std::env::var("IPINFO_TOKEN").unwrap_or_default(),
+            resolver: Default::default(),
+            dns_server: String::new(),
+            blocklists: crate::dnsbl::default_zones(),
+            view: Default::default(),
+            language: Language::detect(),
+            last_target: String::new(),
+            recent_targets: Vec::new(),
+            favorite_targets: Vec::new(),
+            pinned_targets: Vec::new(),
+            target_options: BTreeMap::new(),
+            auto_retrace: false,
+            saved_filters: Vec::new(),
+            concerns: Vec::new(),
+            geofences: Vec::new(),
+            transit_policies: Vec::new(),
+            site_map: String::new(),
+            snmp: Default::default(),
+            planner: Default::default(),
+            schedule: Default::default(),
+            split_tunnel: Default::default(),
+            #[cfg(feature = "syslog")]
+            syslog: Default::default(),
+            #[cfg(feature = "audio")]
+            audio: Default::default(),
+            #[cfg(feature = "tray")]
+            tray: Default::default(),
+            profiles: BTreeMap::new(),
+            active_profile: String::new(),
+            worker_threads: 0,
+            stall_secs: 60,
+            shared_dir: String::new(),
+            load_error: None,
+            keep_file: false,
+        }
+    }
+}
+
+/// The settings that depend on the network the machine is on, switched as a whole.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub provider: Provider,
+    pub custom_tiles: CustomTiles,
+    pub trace: TraceOptions,
+    pub geolocation: GeoProvider,
+    pub resolver: Resolver,
+    pub dns_server: String,
+    pub site_map: String,
+    pub snmp: SnmpConfig,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::capture(&Config::default())
+    }
+}
+
+impl Profile {
+    fn capture(config: &Config) -> Self {
+        Self {
+            provider: config.provider,
+            custom_tiles: config.custom_tiles.clone(),
+            trace: config.trace,
+            geolocation: config.geolocation,
+            resolver: config.resolver,
+            dns_server: config.dns_server.clone(),
+            site_map: config.site_map.clone(),
+            snmp: config.snmp.clone(),
+        }
+    }
+
+    fn apply(self, config: &mut Config) {
+        config.provider = self.provider;
+        config.custom_tiles = self.custom_tiles;
+        config.trace = self.trace;
+        config.geolocation = self.geolocation;
+        config.resolver = self.resolver;
+        config.dns_server = self.dns_server;
+        config.site_map = self.site_map;
+        config.snmp = self.snmp;
+    }
+}
+
+/// Layout of the map area.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewConfig {
+    pub split_view: bool,
+    pub separate_selection: bool,
+    pub simplify_path: bool,
+    pub cluster_nodes: bool,
+    pub color_by_as: bool,
+    pub show_private_hops: bool,
+    pub city_labels: bool,
+    pub border_ticks: bool,
+    pub sparklines: bool,
+    pub magnifier_boost: f64,
+    /// Port used when copying a hop as host:port
+    pub copy_port: u16,
+    pub style: MarkerStyle,
+    /// Tool windows that were open on exit, reopened on start. Where windows are and whether
+    /// they are collapsed is kept by eframe
+    pub open_windows: Vec<String>,
+    /// Zoom on top of the display's own scale, by that scale, so every monitor of a mixed-DPI
+    /// setup keeps its own. Displays without one aren't zoomed
+    pub ui_scales: BTreeMap<String, f32>,
+    /// Where the map opens, as latitude and longitude. Without one it opens where the public
+    /// address was last located
+    pub home: Option<[f64; 2]>,
+    /// Where the public address was located on the last launch
+    pub located: Option<[f64; 2]>,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        Self {
+            split_view: false,
+            separate_selection: false,
+            simplify_path: false,
+            cluster_nodes: true,
+            color_by_as: false,
+            show_private_hops: false,
+            city_labels: false,
+            border_ticks: false,
+            sparklines: false,
+            magnifier_boost: 3.0,
+            copy_port: 443,
+            style: Default::default(),
+            open_windows: Vec::new(),
+            ui_scales: BTreeMap::new(),
+            home: None,
+            located: None,
+        }
+    }
+}
+
+const MAX_RECENT_TARGETS: usize = 20;
+const MAX_COMPLETIONS: usize = 8;
+
+fn config_path() -> Option<PathBuf> {
+    ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.config_dir().join("config.toml"))
+}
+
+/// Where baselines and notes are kept: `shared_dir` if set, else the local data directory.
+pub fn store_dir(shared_dir: &str) -> Option<PathBuf> {
+    match shared_dir.trim() {
+        "" => ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.data_dir().to_path_buf()),
+        dir => Some(PathBuf::from(dir)),
+    }
+}
+
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Writes a temporary file next to `path` and renames it over, so others reading a shared
+/// file never see it half written.
+pub fn write_replacing(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(format!(".{}.tmp", std::process::id()));
+    fs::write(&temporary, text)?;
+    fs::rename(&temporary, path).inspect_err(|_| {
+        fs::remove_file(&temporary).ok();
+    })
+}
+
+impl Config {
+    /// Makes `target` the last and most recent target.
+    pub fn remember_target(&mut self, target: &str) {
+        self.last_target = target.to_string();
+        self.recent_targets.retain(|t| t != target);
+        self.recent_targets.insert(0, target.to_string());
+        self.recent_targets.truncate(MAX_RECENT_TARGETS);
+    }
+
+    pub fn toggle_favorite(&mut self, target: &str) {
+        if self.is_favorite(target) {
+            self.favorite_targets.retain(|t| t != target);
+        } else {
+            self.favorite_targets.push(target.to_string());
+        }
+    }
+
+    pub fn is_favorite(&self, target: &str) -> bool {
+        self.favorite_targets.iter().any(|t| t == target)
+    }
+
+    /// Favorite and recent targets containing `input`, favorites first, without `input` itself.
+    pub fn complete(&self, input: &str) -> Vec<&str> {
+        let input = input.trim().to_lowercase();
+        let mut completions: Vec<&str> = Vec::new();
+        for target in self.favorite_targets.iter().chain(&self.recent_targets) {
+            let lower = target.to_lowercase();
+            if lower.contains(&input) && lower != input && !completions.contains(&target.as_str()) {
+                completions.push(target);
+            }
+        }
+        completions.truncate(MAX_COMPLETIONS);
+        completions
+    }
+
+    /// Options to trace `target` with: the ones it was last traced with, else the current ones.
+    pub fn options_for(&self, target: &str) -> TraceOptions {
+        self.target_options.get(target).copied().unwrap_or(self.trace)
+    }
+
+    /// Keeps the options `target` was just traced with, unless they are the current ones anyway.
+    pub fn remember_options(&mut self, target: &str, options: TraceOptions) {
+        if options.differences(&self.trace).is_empty() {
+            self.target_options.remove(target);
+        } else {
+            self.target_options.insert(target.to_string(), options);
+        }
+    }
+
+    /// Saves the current settings as `name` and makes it the active profile.
+    pub fn add_profile(&mut self, name: &str) {
+        info!("Saving settings as profile {}", name);
+        self.profiles.insert(name.to_string(), Profile::capture(self));
+        self.active_profile = name.to_string();
+    }
+
+    /// Keeps changes to the current settings in the active profile, so they are there next time.
+    pub fn store_profile(&mut self) {
+        if self.profiles.contains_key(&self.active_profile) {
+            self.profiles.insert(self.active_profile.clone(), Profile::capture(self));
+        }
+    }
+
+    /// Stores the active profile and switches the settings over to `name`.
+    /// Returns false if there is no such profile.
+    pub fn switch_profile(&mut self, name: &str) -> bool {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            return false;
+        };
+        info!("Switching to profile {}", name);
+        self.store_profile();
+        profile.apply(self);
+        self.active_profile = name.to_string();
+        true
+    }
+
+    /// Forgets a profile, leaving the current settings as they are.
+    pub fn remove_profile(&mut self, name: &str) {
+        self.profiles.remove(name);
+        if self.active_profile == name {
+            self.active_profile.clear();
+        }
+    }
+
+    /// Loads the config file, falling back to defaults if it is missing or broken. A broken
+    /// file is moved to `config.toml.bak` first, or kept from being saved over if that fails.
+    pub fn load() -> Self {
+        let Some(path) = config_path() else {
+            warn!("No config directory available, using defaults");
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => match toml::from_str(&text) {
+                Ok(config) => {
+                    info!("Loaded config from {}", path.display());
+                    config
+                }
+                Err(e) => {
+                    warn!("Invalid config file {}: {}", path.display(), e);
+                    let backup = path.with_extension("toml.bak");
+                    let mut config = Self::default();
+                    config.load_error = Some(match fs::rename(&path, &backup) {
+                        Ok(()) => trf(
+                            "The config file couldn't be read and was moved to {}, the defaults are used",
+                            &[&backup.display()],
+                        ),
+                        Err(e) => {
+                            warn!("Failed to move {} aside: {}", path.display(), e);
+                            config.keep_file = true;
+                            trf(
+                                "The config file {} couldn't be read, the defaults are used and changes aren't saved",
+                                &[&path.display()],
+                            )
+                        }
+                    });
+                    config
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = config_path().filter(|_| !self.keep_file) else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                warn!("Failed to create config directory {}: {}", dir.display(), e);
+                return;
+            }
+        }
+        match toml::to_string_pretty(self) {
+            Ok(text) => match fs::write(&path, text) {
+                Ok(()) => info!("Saved config to {}", path.display()),
+                Err(e) => warn!("Failed to write config {}: {}", path.display(), e),
+            },
+            Err(e) => warn!("Failed to serialize config: {}", e),
+        }
+    }
+}
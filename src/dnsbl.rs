@@ -0,0 +1,101 @@
+//! DNS blocklists of domains, to flag a target as known spam, phishing or malware before
+//! anything is sent to it.
+//!
+//! A domain is listed in a zone when `<domain>.<zone>` has an A record in 127.0.0.0/8,
+//! the last octets telling why. Threat-intel feeds published the same way work as well.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use dns_lookup::lookup_host;
+use log::debug;
+
+use crate::i18n::{tr, trf};
+
+/// Zones checked until the user picks their own.
+pub fn default_zones() -> Vec<String> {
+    vec!["dbl.spamhaus.org".to_string(), "multi.surbl.org".to_string()]
+}
+
+/// A zone listing the target or one of its parent domains.
+pub struct Listing {
+    pub zone: String,
+    pub domain: String,
+    /// Return code, its meaning is up to the zone
+    pub code: Ipv4Addr,
+}
+
+/// What the blocklists say about one target.
+#[derive(Default)]
+pub struct Verdict {
+    pub listings: Vec<Listing>,
+    /// Zones that refused to answer, e.g. Spamhaus for queries through public resolvers
+    pub unavailable: Vec<String>,
+}
+
+impl Verdict {
+    pub fn listed(&self) -> bool {
+        !self.listings.is_empty()
+    }
+
+    /// One line per listing and one for the zones that didn't answer.
+    pub fn describe(&self) -> String {
+        let mut lines: Vec<String> = self
+            .listings
+            .iter()
+            .map(|l| trf("{} is listed by {} ({})", &[&l.domain, &l.zone, &l.code]))
+            .collect();
+        if !self.unavailable.is_empty() {
+            lines.push(trf("No answer from {}", &[&self.unavailable.join(", ")]));
+        }
+        if lines.is_empty() {
+            lines.push(tr("Not on any blocklist").to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// `target` and its parent domains down to two labels, since lists often only carry the
+/// registered domain. Empty for IPs.
+fn candidates(target: &str) -> Vec<String> {
+    let target = target.trim().trim_end_matches('.').to_lowercase();
+    if target.parse::<IpAddr>().is_ok() {
+        return Vec::new();
+    }
+    let labels: Vec<&str> = target.split('.').filter(|l| !l.is_empty()).collect();
+    (0..labels.len().saturating_sub(1)).map(|i| labels[i..].join(".")).collect()
+}
+
+/// Looks up `target` and its parents in every zone. Blocks on DNS, so it belongs off the UI thread.
+pub fn check(target: &str, zones: &[String]) -> Verdict {
+    let mut verdict = Verdict::default();
+    for zone in zones.iter().map(|z| z.trim().trim_matches('.')).filter(|z| !z.is_empty()) {
+        for domain in candidates(target) {
+            // Not found is the usual answer, and means not listed
+            let Ok(answers) = lookup_host(&format!("{}.{}", domain, zone)) else {
+                continue;
+            };
+            let code = answers.into_iter().find_map(|ip| match ip {
+                IpAddr::V4(ip) if ip.octets()[0] == 127 => Some(ip),
+                _ => None,
+            });
+            match code {
+                // 127.0.0.1 and 127.255.255.x are how zones refuse a query, not listings
+                Some(code) if code == Ipv4Addr::LOCALHOST || code.octets()[1..3] == [255, 255] => {
+                    debug!("{} refused the query for {} with {}", zone, domain, code);
+                    verdict.unavailable.push(zone.to_string());
+                    break;
+                }
+                Some(code) => {
+                    verdict.listings.push(Listing {
+                        zone: zone.to_string(),
+                        domain,
+                        code,
+                    });
+                    break;
+                }
+                None => {}
+            }
+        }
+    }
+    verdict
+}
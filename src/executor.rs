@@ -0,0 +1,129 @@
+//! The tokio runtime everything asynchronous runs on, with a known number of workers and
+//! a bounded shutdown.
+//!
+//! Dropping a plain `Runtime` waits for every blocking task, so a trace or lookup stuck on
+//! a socket kept the process alive after the window closed. Tasks go through [`Executor`]
+//! instead, which counts them and gives them [`SHUTDOWN_TIMEOUT`] to finish on exit.
+
+use std::{
+    future::Future,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use log::{info, warn};
+use tokio::{
+    runtime::{Builder, Runtime},
+    task::JoinHandle,
+};
+
+/// How long running tasks get to wind down when the executor shuts down.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counts a task as running until the task is done or dropped.
+struct Running(Arc<AtomicUsize>);
+
+impl Running {
+    fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count.clone())
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A multi-threaded runtime that keeps track of its tasks and shuts down within
+/// [`SHUTDOWN_TIMEOUT`] when dropped.
+pub struct Executor {
+    /// Gone once shut down
+    runtime: Option<Runtime>,
+    running: Arc<AtomicUsize>,
+}
+
+impl Executor {
+    /// Starts `worker_threads` workers, or one per core for 0.
+    pub fn new(worker_threads: usize) -> io::Result<Self> {
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all().thread_name("traced-worker");
+        if worker_threads > 0 {
+            builder.worker_threads(worker_threads);
+        }
+        Ok(Self {
+            runtime: Some(builder.build()?),
+            running: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime.as_ref().expect("executor used after shutdown")
+    }
+
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let running = Running::new(&self.running);
+        self.runtime().spawn(async move {
+            let _running = running;
+            future.await
+        })
+    }
+
+    pub fn spawn_blocking<F, R>(&self, f: F) -> JoinHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let running = Running::new(&self.running);
+        self.runtime().spawn_blocking(move || {
+            let _running = running;
+            f()
+        })
+    }
+
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime().block_on(future)
+    }
+
+    /// Makes the runtime current, for code that spawns through `tokio::spawn` itself.
+    #[cfg(feature = "syslog")]
+    pub fn enter(&self) -> tokio::runtime::EnterGuard<'_> {
+        self.runtime().enter()
+    }
+
+    /// Tasks spawned through the executor that haven't finished yet.
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Cancels async tasks at their next await and waits up to `timeout` for blocking ones.
+    /// Does nothing the second time.
+    pub fn shutdown(&mut self, timeout: Duration) {
+        let Some(runtime) = self.runtime.take() else {
+            return;
+        };
+        let running = self.running();
+        info!("Shutting down with {} tasks running", running);
+        runtime.shutdown_timeout(timeout);
+        // Blocking tasks can't be cancelled, the ones left over are abandoned
+        let left = self.running();
+        if left > 0 {
+            warn!("{} tasks were still running after {:?}", left, timeout);
+        }
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.shutdown(SHUTDOWN_TIMEOUT);
+    }
+}
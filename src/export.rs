@@ -0,0 +1,559 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use directories::ProjectDirs;
+use handlebars::Handlebars;
+use log::warn;
+use serde::Serialize;
+
+use crate::{ixp, plugins::Trace, summary};
+
+/// An output format for a single trace.
+#[derive(Clone, PartialEq)]
+pub enum Format {
+    Text,
+    Csv,
+    Json,
+    /// Host and ping service definitions for Nagios and Icinga 1, chained by `parents`
+    Nagios,
+    /// Hosts with the ICMP Ping template, as a Zabbix 6.0 import
+    Zabbix,
+    /// Single printable HTML page with a mini-map, key facts and the hops, see [`card`]
+    Card,
+    /// A user template, see [`templates`]
+    Template { name: String, path: PathBuf },
+}
+
+impl Format {
+    pub fn name(&self) -> &str {
+        match self {
+            Format::Text => "Text",
+            Format::Csv => "CSV",
+            Format::Json => "JSON",
+            Format::Nagios => "Nagios/Icinga",
+            Format::Zabbix => "Zabbix",
+            Format::Card => "Route card",
+            Format::Template { name, .. } => name,
+        }
+    }
+
+    /// File extension for saved exports. Templates named like `ticket.xml.hbs` use `xml`.
+    pub fn extension(&self) -> String {
+        match self {
+            Format::Text => "txt".to_string(),
+            Format::Csv => "csv".to_string(),
+            Format::Json => "json".to_string(),
+            Format::Nagios => "cfg".to_string(),
+            Format::Zabbix => "yaml".to_string(),
+            Format::Card => "html".to_string(),
+            Format::Template { path, .. } => path
+                .file_stem()
+                .and_then(|stem| PathBuf::from(stem).extension().map(|e| e.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "txt".to_string()),
+        }
+    }
+}
+
+pub fn templates_dir() -> Option<PathBuf> {
+    ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.config_dir().join("templates"))
+}
+
+/// Built-in formats followed by every `*.hbs` Handlebars template in the templates directory.
+///
+/// Templates get `target`, `started`, `tags`, `summary`, `fingerprint` and `hops`, where each hop has
+/// `index`, `ip`, `hostname`, `isp`, `asn`, `as_name`, `prefix`, `country`, `country_code`,
+/// `region`, `city`, `rtt_ms`, `lat`, `lon` and the script `fields`.
+pub fn formats() -> Vec<Format> {
+    let mut formats = vec![Format::Text, Format::Csv, Format::Json, Format::Nagios, Format::Zabbix, Format::Card];
+    let Some(entries) = templates_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return formats;
+    };
+    let mut templates: Vec<Format> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "hbs"))
+        .map(|path| Format::Template {
+            name: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            path,
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name().cmp(b.name()));
+    formats.extend(templates);
+    formats
+}
+
+#[derive(Serialize)]
+struct Context<'a> {
+    target: &'a str,
+    started: String,
+    tags: &'a [String],
+    summary: &'a [String],
+    /// See [`summary::fingerprint`]
+    fingerprint: String,
+    /// Internet exchanges traversed, in order
+    ixps: Vec<&'static str>,
+    hops: Vec<Hop<'a>>,
+}
+
+#[derive(Serialize)]
+struct Hop<'a> {
+    index: usize,
+    ip: &'a str,
+    hostname: &'a str,
+    isp: &'a str,
+    asn: Option<u32>,
+    as_name: &'a str,
+    prefix: &'a str,
+    country: &'a str,
+    country_code: &'a str,
+    region: &'a str,
+    city: &'a str,
+    rtt_ms: Option<f64>,
+    /// Exchange whose peering LAN the hop is on
+    ixp: Option<&'static str>,
+    lat: f64,
+    lon: f64,
+    fields: BTreeMap<&'a str, &'a str>,
+}
+
+fn context(trace: &Trace) -> Context<'_> {
+    Context {
+        target: &trace.target,
+        started: trace.started.to_rfc3339(),
+        tags: &trace.tags,
+        summary: &trace.summary,
+        fingerprint: summary::route_fingerprint(&trace.nodes),
+        ixps: ixp::traversed(&trace.nodes),
+        hops: trace
+            .nodes
+            .iter()
+            .map(|(index, node)| Hop {
+                index: *index,
+                ip: &node.ip,
+                hostname: &node.hostname,
+                isp: &node.isp,
+                asn: node.asn,
+                as_name: &node.as_name,
+                prefix: &node.prefix,
+                country: &node.country,
+                country_code: &node.country_code,
+                region: &node.region,
+                city: &node.city,
+                rtt_ms: node.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+                ixp: ixp::lookup(&node.ip),
+                lat: node.position.lat(),
+                lon: node.position.lon(),
+                fields: node.fields.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect(),
+            })
+            .collect(),
+    }
+}
+
+pub fn render(format: &Format, trace: &Trace) -> Result<String, String> {
+    let context = context(trace);
+    match format {
+        Format::Text => Ok(text(&context)),
+        Format::Csv => Ok(csv(&context)),
+        Format::Json => serde_json::to_string_pretty(&context).map_err(|e| e.to_string()),
+        Format::Nagios => Ok(nagios(&context)),
+        Format::Zabbix => Ok(zabbix(&context)),
+        Format::Card => Ok(card(trace, &context)),
+        Format::Template { path, .. } => {
+            let template = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            Handlebars::new()
+                .render_template(&template, &context)
+                .map_err(|e| {
+                    warn!("Template {} failed: {}", path.display(), e);
+                    e.to_string()
+                })
+        }
+    }
+}
+
+fn text(context: &Context) -> String {
+    let mut out = format!("Trace to {} at {}\n", context.target, context.started);
+    if !context.tags.is_empty() {
+        out.push_str(&format!("Tags: {}\n", context.tags.join(", ")));
+    }
+    for hop in &context.hops {
+        let rtt = hop.rtt_ms.map(|rtt| format!("{:.1} ms", rtt)).unwrap_or_default();
+        let asn = hop.asn.map(|asn| format!("AS{}", asn)).unwrap_or_default();
+        out.push_str(&format!(
+            "{:>3}  {:<39}  {:<10}  {:<8}  {}  {}\n",
+            hop.index, hop.ip, rtt, asn, hop.hostname, hop.country
+        ));
+    }
+    for line in context.summary {
+        out.push_str(line);
+        out.push('\n');
+    }
+    if !context.ixps.is_empty() {
+        out.push_str(&format!("Exchanges: {}\n", context.ixps.join(", ")));
+    }
+    out.push_str(&format!("Route fingerprint: {}\n", context.fingerprint));
+    out
+}
+
+/// Classic traceroute style table of `trace`, for pasting into chats and tickets.
+/// Unlike the text export it lists the hops that never answered, and leaves out tags and summaries.
+pub fn traceroute_text(trace: &Trace) -> String {
+    let mut rows: Vec<[String; 5]> = trace
+        .nodes
+        .iter()
+        .filter(|(index, _)| *index > 0)
+        .map(|(index, node)| {
+            [
+                index.to_string(),
+                node.ip.clone(),
+                if node.hostname == node.ip { String::new() } else { node.hostname.clone() },
+                node.rtt.map(|rtt| format!("{:.1} ms", rtt.as_secs_f64() * 1000.0)).unwrap_or_default(),
+                node.place(),
+            ]
+        })
+        .chain(trace.timeouts.iter().map(|index| {
+            [index.to_string(), "*".to_string(), String::new(), String::new(), String::new()]
+        }))
+        .collect();
+    rows.sort_by_key(|row| row[0].parse::<usize>().unwrap_or_default());
+
+    let header = ["Hop", "IP", "Hostname", "RTT", "Location"].map(str::to_string);
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = format!("traceroute to {}, {} hops max\n", trace.target, trace.settings.options.max_hops);
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = format!(
+            "{:>w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn csv(context: &Context) -> String {
+    let mut out = String::from("index,ip,hostname,isp,asn,as_name,prefix,country,country_code,rtt_ms,lat,lon,fields\n");
+    for hop in &context.hops {
+        let fields = hop
+            .fields
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(";");
+        let row = [
+            hop.index.to_string(),
+            hop.ip.to_string(),
+            hop.hostname.to_string(),
+            hop.isp.to_string(),
+            hop.asn.map(|asn| asn.to_string()).unwrap_or_default(),
+            hop.as_name.to_string(),
+            hop.prefix.to_string(),
+            hop.country.to_string(),
+            hop.country_code.to_string(),
+            hop.rtt_ms.map(|rtt| format!("{:.3}", rtt)).unwrap_or_default(),
+            hop.lat.to_string(),
+            hop.lon.to_string(),
+            fields,
+        ];
+        out.push_str(&row.map(|cell| csv_cell(&cell)).join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Monitoring host names for the hops past the local node, unique within the trace.
+fn monitored<'a>(context: &'a Context) -> Vec<(String, &'a Hop<'a>)> {
+    context
+        .hops
+        .iter()
+        .filter(|hop| hop.index > 0)
+        .map(|hop| {
+            let name = if hop.hostname.is_empty() || hop.hostname == hop.ip {
+                format!("hop{}-{}", hop.index, hop.ip)
+            } else {
+                hop.hostname.to_string()
+            };
+            // Neither allows spaces, and Nagios forbids a few more
+            let name: String = name
+                .chars()
+                .map(|c| if c.is_whitespace() || "~!$%^&*|'\"<>?,()=".contains(c) { '-' } else { c })
+                .collect();
+            (name, hop)
+        })
+        .collect()
+}
+
+/// Ping warning and critical RTTs in ms, with room above what the trace saw.
+fn ping_thresholds(hop: &Hop) -> (u64, u64) {
+    let warning = hop.rtt_ms.map_or(100, |rtt| ((rtt * 2.0).ceil() as u64).max(100));
+    (warning, warning * 2)
+}
+
+fn nagios(context: &Context) -> String {
+    let group = format!("traced-{}", context.target).replace(char::is_whitespace, "-");
+    let mut out = format!("# Hops of the trace to {} at {}\n\n", context.target, context.started);
+    out.push_str(&format!(
+        "define hostgroup {{\n    hostgroup_name  {}\n    alias           Route to {}\n}}\n\n",
+        group, context.target
+    ));
+    let mut parent: Option<&str> = None;
+    let hosts = monitored(context);
+    for (name, hop) in &hosts {
+        let asn = hop.asn.map(|asn| format!(", AS{} {}", asn, hop.as_name)).unwrap_or_default();
+        out.push_str("define host {\n");
+        out.push_str("    use             generic-host\n");
+        out.push_str(&format!("    host_name       {}\n", name));
+        out.push_str(&format!("    alias           Hop {} to {}{}\n", hop.index, context.target, asn));
+        out.push_str(&format!("    address         {}\n", hop.ip));
+        if let Some(parent) = parent {
+            out.push_str(&format!("    parents         {}\n", parent));
+        }
+        out.push_str(&format!("    hostgroups      {}\n", group));
+        out.push_str("}\n\n");
+
+        let (warning, critical) = ping_thresholds(hop);
+        out.push_str("define service {\n");
+        out.push_str("    use                  generic-service\n");
+        out.push_str(&format!("    host_name            {}\n", name));
+        out.push_str("    service_description  PING\n");
+        out.push_str(&format!("    check_command        check_ping!{}.0,20%!{}.0,60%\n", warning, critical));
+        out.push_str("}\n\n");
+        parent = Some(name);
+    }
+    out
+}
+
+/// A YAML scalar, JSON strings are valid YAML.
+fn yaml(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn zabbix(context: &Context) -> String {
+    let group = yaml(&format!("traced/{}", context.target));
+    let mut out = String::from("zabbix_export:\n  version: '6.0'\n  groups:\n");
+    out.push_str(&format!("    - name: {}\n", group));
+    out.push_str("  hosts:\n");
+    for (name, hop) in monitored(context) {
+        let asn = hop.asn.map(|asn| format!(", AS{} {}", asn, hop.as_name)).unwrap_or_default();
+        // Technical names are limited to these, which rules out IPv6 colons
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '-' })
+            .collect();
+        out.push_str(&format!("    - host: {}\n", yaml(&name)));
+        out.push_str(&format!("      name: {}\n", yaml(&format!("Hop {} to {} ({}){}", hop.index, context.target, hop.ip, asn))));
+        out.push_str("      templates:\n        - name: 'ICMP Ping'\n");
+        out.push_str(&format!("      groups:\n        - name: {}\n", group));
+        out.push_str("      interfaces:\n");
+        out.push_str(&format!("        - ip: {}\n          interface_ref: if1\n", yaml(hop.ip)));
+        if !hop.country_code.is_empty() {
+            out.push_str(&format!("      tags:\n        - tag: country\n          value: {}\n", yaml(hop.country_code)));
+        }
+    }
+    out
+}
+
+/// Escapes text for HTML element content and attribute values.
+fn html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const CARD_MAP_WIDTH: f64 = 360.0;
+const CARD_MAP_HEIGHT: f64 = 200.0;
+
+/// Outline of the route over a lat/lon grid, without tiles so the card works offline and prints small.
+fn card_map(context: &Context) -> String {
+    let points: Vec<(f64, f64)> = context
+        .hops
+        .iter()
+        // Hops that were never located sit at null island
+        .filter(|hop| hop.lat != 0.0 || hop.lon != 0.0)
+        .map(|hop| (hop.lon, hop.lat))
+        .collect();
+    let mut svg = format!(
+        "<svg class=\"map\" viewBox=\"0 0 {w} {h}\" width=\"{w}\" height=\"{h}\" xmlns=\"http://www.w3.org/2000/svg\">\n",
+        w = CARD_MAP_WIDTH,
+        h = CARD_MAP_HEIGHT
+    );
+    svg.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"#f4f6f8\" stroke=\"#999\"/>\n", CARD_MAP_WIDTH, CARD_MAP_HEIGHT));
+    if points.is_empty() {
+        svg.push_str("<text x=\"50%\" y=\"50%\" text-anchor=\"middle\" fill=\"#777\">No located hops</text>\n</svg>");
+        return svg;
+    }
+
+    // Fit the route with a margin, keeping degrees square so shapes aren't squashed
+    let (mut west, mut east, mut south, mut north) = (180.0f64, -180.0f64, 90.0f64, -90.0f64);
+    for (lon, lat) in &points {
+        west = west.min(*lon);
+        east = east.max(*lon);
+        south = south.min(*lat);
+        north = north.max(*lat);
+    }
+    let span = ((east - west) / CARD_MAP_WIDTH).max((north - south) / CARD_MAP_HEIGHT).max(0.05) * 1.2;
+    let (center_lon, center_lat) = ((west + east) / 2.0, (south + north) / 2.0);
+    let project = |lon: f64, lat: f64| {
+        (
+            CARD_MAP_WIDTH / 2.0 + (lon - center_lon) / span,
+            CARD_MAP_HEIGHT / 2.0 - (lat - center_lat) / span,
+        )
+    };
+
+    // A graticule line every power of ten degrees that gives a handful of lines
+    let step = [30.0, 10.0, 5.0, 1.0, 0.5, 0.1]
+        .into_iter()
+        .find(|step| CARD_MAP_WIDTH * span / step >= 3.0)
+        .unwrap_or(0.1);
+    let (left, top) = (center_lon - CARD_MAP_WIDTH / 2.0 * span, center_lat + CARD_MAP_HEIGHT / 2.0 * span);
+    let mut lon = (left / step).ceil() * step;
+    while lon < left + CARD_MAP_WIDTH * span {
+        let (x, _) = project(lon, 0.0);
+        svg.push_str(&format!("<line x1=\"{x:.1}\" y1=\"0\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"#dde\"/>\n", CARD_MAP_HEIGHT));
+        lon += step;
+    }
+    let mut lat = (top / step).floor() * step;
+    while lat > top - CARD_MAP_HEIGHT * span {
+        let (_, y) = project(0.0, lat);
+        svg.push_str(&format!("<line x1=\"0\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" stroke=\"#dde\"/>\n", CARD_MAP_WIDTH));
+        lat -= step;
+    }
+
+    let path: Vec<String> = points
+        .iter()
+        .map(|(lon, lat)| {
+            let (x, y) = project(*lon, *lat);
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect();
+    svg.push_str(&format!("<polyline points=\"{}\" fill=\"none\" stroke=\"#1f6fd1\" stroke-width=\"2\"/>\n", path.join(" ")));
+    for (i, (lon, lat)) in points.iter().enumerate() {
+        let (x, y) = project(*lon, *lat);
+        let fill = if i == 0 || i == points.len() - 1 { "#d13b1f" } else { "#1f6fd1" };
+        svg.push_str(&format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3.5\" fill=\"{}\"/>\n", x, y, fill));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Compact one page summary of a trace for tickets: target and date, a mini-map, the key facts
+/// and the hop table. Unlike the other exports it is meant to be printed or saved as a PDF.
+fn card(trace: &Trace, context: &Context) -> String {
+    let stats = summary::PathStats::new(&trace.nodes);
+    let mut facts: Vec<(&str, String)> = vec![
+        ("Hops", stats.hops.to_string()),
+        ("RTT", stats.rtt.map(|rtt| format!("{:.1} ms", rtt.as_secs_f64() * 1000.0)).unwrap_or("-".to_string())),
+        ("Distance", format!("{:.0} km", stats.distance_km)),
+    ];
+    if let Some(detour) = stats.detour_factor() {
+        facts.push(("Detour", format!("{:.1}× the direct line", detour)));
+    }
+    let countries: Vec<String> = summary::countries(&trace.nodes)
+        .iter()
+        .map(|segment| format!("{} {}", summary::flag(&segment.country_code), segment.country_code))
+        .collect();
+    if !countries.is_empty() {
+        facts.push(("Countries", countries.join(" → ")));
+    }
+    facts.push(("Networks", format!("{} AS{}", stats.ases, if stats.ases == 1 { "" } else { "es" })));
+    if !stats.ixps.is_empty() {
+        facts.push(("Exchanges", stats.ixps.join(", ")));
+    }
+    if !trace.timeouts.is_empty() {
+        facts.push(("Silent hops", trace.timeouts.len().to_string()));
+    }
+    facts.push(("Fingerprint", stats.fingerprint.clone()));
+
+    let mut out = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<style>\n",
+        "@page { size: A4; margin: 12mm; }\n",
+        "body { font: 10pt sans-serif; color: #222; max-width: 180mm; margin: auto; }\n",
+        "h1 { font-size: 14pt; margin: 0; }\n",
+        ".when { color: #666; margin-bottom: 6pt; }\n",
+        ".top { display: flex; gap: 12pt; align-items: flex-start; }\n",
+        ".facts th { text-align: left; padding-right: 8pt; color: #555; font-weight: normal; }\n",
+        ".hops { border-collapse: collapse; width: 100%; margin-top: 8pt; font-size: 8.5pt; }\n",
+        ".hops th, .hops td { border-bottom: 1px solid #ddd; padding: 1.5pt 4pt; text-align: left; }\n",
+        ".hops td.num { text-align: right; font-variant-numeric: tabular-nums; }\n",
+        ".silent { color: #999; }\n",
+        "</style>\n",
+    ));
+    out.push_str(&format!("<title>Route to {}</title>\n</head>\n<body>\n", html(context.target)));
+    out.push_str(&format!("<h1>Route to {}</h1>\n", html(context.target)));
+    let mut when = trace.started.format("%Y-%m-%d %H:%M:%S %Z").to_string();
+    if !context.tags.is_empty() {
+        when.push_str(&format!(" · {}", context.tags.join(", ")));
+    }
+    out.push_str(&format!("<div class=\"when\">{}</div>\n", html(&when)));
+
+    out.push_str("<div class=\"top\">\n");
+    out.push_str(&card_map(context));
+    out.push_str("\n<table class=\"facts\">\n");
+    for (name, value) in &facts {
+        out.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, html(value)));
+    }
+    out.push_str("</table>\n</div>\n");
+
+    out.push_str("<table class=\"hops\">\n<tr><th>#</th><th>IP</th><th>Hostname</th><th>RTT</th><th>Network</th><th>Location</th></tr>\n");
+    let mut rows: Vec<(usize, String)> = context
+        .hops
+        .iter()
+        .zip(&trace.nodes)
+        .filter(|(hop, _)| hop.index > 0)
+        .map(|(hop, (_, node))| {
+            let network = match (hop.asn, hop.ixp) {
+                (_, Some(ixp)) => ixp.to_string(),
+                (Some(asn), None) => format!("AS{} {}", asn, hop.as_name),
+                (None, None) => hop.isp.to_string(),
+            };
+            let row = format!(
+                "<tr><td class=\"num\">{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td><td>{}</td><td>{}</td></tr>\n",
+                hop.index,
+                html(hop.ip),
+                if hop.hostname == hop.ip { String::new() } else { html(hop.hostname) },
+                hop.rtt_ms.map(|rtt| format!("{:.1} ms", rtt)).unwrap_or_default(),
+                html(&network),
+                html(&node.place()),
+            );
+            (hop.index, row)
+        })
+        .chain(trace.timeouts.iter().map(|index| {
+            let row = format!("<tr class=\"silent\"><td class=\"num\">{}</td><td>*</td><td></td><td></td><td></td><td></td></tr>\n", index);
+            (*index, row)
+        }))
+        .collect();
+    rows.sort_by_key(|(index, _)| *index);
+    for (_, row) in rows {
+        out.push_str(&row);
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,109 @@
+use egui::{pos2, Color32, ColorImage, Context, Rect};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use walkers::{sources::Attribution, Texture, TextureWithUv, TileId, Tiles};
+
+use crate::{geo, trace::Position};
+
+/// A georeferenced image, e.g. a campus map or WAN diagram, used instead of map tiles.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FloorPlan {
+    /// PNG or JPEG file
+    pub path: String,
+    /// Latitude of the top edge
+    pub north: f64,
+    /// Longitude of the left edge
+    pub west: f64,
+    /// Latitude of the bottom edge
+    pub south: f64,
+    /// Longitude of the right edge
+    pub east: f64,
+}
+
+impl FloorPlan {
+    pub fn tiles(&self, egui_ctx: Context) -> Option<Box<dyn Tiles + Send>> {
+        if self.path.trim().is_empty() {
+            return None;
+        }
+        if self.north <= self.south || self.east <= self.west {
+            warn!("Floor plan corners are not north-west / south-east of each other");
+            return None;
+        }
+        let image = match image::open(self.path.trim()) {
+            Ok(image) => image.to_rgba8(),
+            Err(e) => {
+                warn!("Failed to load floor plan {}: {}", self.path, e);
+                return None;
+            }
+        };
+        info!("Loaded {}x{} floor plan from {}", image.width(), image.height(), self.path);
+
+        // A transparent border, so tiles reaching past the image edge show nothing
+        // instead of the edge pixels smeared outwards
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mut padded = ColorImage::new([width + 2, height + 2], Color32::TRANSPARENT);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            padded[(x as usize + 1, y as usize + 1)] = Color32::from_rgba_unmultiplied(r, g, b, a);
+        }
+
+        let (min_x, min_y) = geo::project(&Position::from_lat_lon(self.north, self.west));
+        let (max_x, max_y) = geo::project(&Position::from_lat_lon(self.south, self.east));
+        Some(Box::new(ImageTiles {
+            texture: Texture::from_color_image(padded, &egui_ctx),
+            bounds: [min_x, min_y, max_x, max_y],
+            size: (width as f64, height as f64),
+        }))
+    }
+}
+
+/// Serves parts of a single image as tiles.
+struct ImageTiles {
+    texture: Texture,
+    /// Image extent in web mercator coordinates, as min x, min y, max x, max y
+    bounds: [f64; 4],
+    /// Image size in pixels, without the border
+    size: (f64, f64),
+}
+
+impl Tiles for ImageTiles {
+    fn at(&mut self, tile_id: TileId) -> Option<TextureWithUv> {
+        // Mercator coordinates lose too much precision as f32 at street level zooms
+        let scale = 2f64.powi(tile_id.zoom as i32);
+        let (x0, y0) = (tile_id.x as f64 / scale, tile_id.y as f64 / scale);
+        let (x1, y1) = (x0 + 1.0 / scale, y0 + 1.0 / scale);
+        let [min_x, min_y, max_x, max_y] = self.bounds;
+        if x1 <= min_x || x0 >= max_x || y1 <= min_y || y0 >= max_y {
+            return None;
+        }
+
+        // Position within the image in 0..1, then shifted into the padded texture
+        let (width, height) = self.size;
+        let uv = |x: f64, y: f64| {
+            let u = (x - min_x) / (max_x - min_x);
+            let v = (y - min_y) / (max_y - min_y);
+            pos2(
+                ((1.0 + u * width) / (width + 2.0)) as f32,
+                ((1.0 + v * height) / (height + 2.0)) as f32,
+            )
+        };
+        Some(TextureWithUv {
+            texture: self.texture.clone(),
+            uv: Rect::from_min_max(uv(x0, y0), uv(x1, y1)),
+        })
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "Floor plan",
+            url: "",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+
+    fn tile_size(&self) -> u32 {
+        256
+    }
+}
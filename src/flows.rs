@@ -0,0 +1,124 @@
+use std::{collections::HashMap, fs, net::IpAddr, path::Path};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use ipnet::IpNet;
+use log::info;
+
+/// One flow record as seen by an exporter, usually a router on the path.
+pub struct FlowRecord {
+    pub start: Option<DateTime<Local>>,
+    pub end: Option<DateTime<Local>>,
+    /// Router or sFlow agent that exported the record
+    pub exporter: IpAddr,
+    pub dst: IpAddr,
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+/// Traffic an exporter saw towards the target prefix.
+#[derive(Clone, Copy, Default)]
+pub struct Volume {
+    pub bytes: u64,
+    pub packets: u64,
+    pub flows: u64,
+}
+
+// Column names of nfdump's `-o csv`, sflowtool / pmacct style exports and plain summaries
+const START: &[&str] = &["ts", "start", "first", "timestamp_start"];
+const END: &[&str] = &["te", "end", "last", "timestamp_end"];
+const EXPORTER: &[&str] = &["ra", "exporter", "agent", "router", "peer_ip_src"];
+const DST: &[&str] = &["da", "dst", "dstaddr", "dst_ip", "ip_dst"];
+const BYTES: &[&str] = &["ibyt", "bytes", "octets", "dOctets"];
+const PACKETS: &[&str] = &["ipkt", "packets", "pkts", "dPkts"];
+
+/// Reads a CSV flow summary with a header row.
+///
+/// Needs exporter, destination and byte columns, start, end and packet columns are optional.
+pub fn load(path: &Path) -> Result<Vec<FlowRecord>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty() && !l.starts_with('#'));
+    let header: Vec<&str> = lines.next().ok_or("Empty flow file")?.split(',').map(str::trim).collect();
+    let column = |names: &[&str]| header.iter().position(|h| names.iter().any(|n| h.eq_ignore_ascii_case(n)));
+    let missing = |what: &str| format!("No {} column in {}", what, path.display());
+    let exporter = column(EXPORTER).ok_or_else(|| missing("exporter"))?;
+    let dst = column(DST).ok_or_else(|| missing("destination"))?;
+    let bytes = column(BYTES).ok_or_else(|| missing("bytes"))?;
+    let (start, end, packets) = (column(START), column(END), column(PACKETS));
+
+    let mut records = Vec::new();
+    for line in lines {
+        let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+        let cell = |i: usize| cells.get(i).copied().unwrap_or_default();
+        // nfdump ends its output with a summary block, just skip what doesn't parse
+        let (Ok(exporter), Ok(dst), Ok(bytes)) = (
+            cell(exporter).parse(),
+            cell(dst).parse(),
+            cell(bytes).parse(),
+        ) else {
+            continue;
+        };
+        records.push(FlowRecord {
+            start: start.and_then(|i| parse_time(cell(i))),
+            end: end.and_then(|i| parse_time(cell(i))),
+            exporter,
+            dst,
+            bytes,
+            packets: packets.and_then(|i| cell(i).parse().ok()).unwrap_or_default(),
+        });
+    }
+    info!("Loaded {} flow records from {}", records.len(), path.display());
+    Ok(records)
+}
+
+fn parse_time(text: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Local));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f") {
+        return Local.from_local_datetime(&time).earliest();
+    }
+    // Unix seconds
+    let secs: f64 = text.parse().ok()?;
+    DateTime::from_timestamp_millis((secs * 1000.0) as i64).map(|t| t.with_timezone(&Local))
+}
+
+/// Sums the traffic towards `prefix` per exporter, for flows overlapping `from..to`.
+/// Records without timestamps always count.
+pub fn correlate(
+    records: &[FlowRecord],
+    prefix: IpNet,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> HashMap<IpAddr, Volume> {
+    let mut volumes: HashMap<IpAddr, Volume> = HashMap::new();
+    for record in records {
+        if !prefix.contains(&record.dst) {
+            continue;
+        }
+        let start = record.start.or(record.end);
+        let end = record.end.or(record.start);
+        if start.is_some_and(|start| start > to) || end.is_some_and(|end| end < from) {
+            continue;
+        }
+        let volume = volumes.entry(record.exporter).or_default();
+        volume.bytes += record.bytes;
+        volume.packets += record.packets;
+        volume.flows += 1;
+    }
+    volumes
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,80 @@
+use crate::trace::Position;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Great-circle distance between two positions, in kilometers.
+pub fn distance_km(a: Position, b: Position) -> f64 {
+    let (lat1, lat2) = (a.lat().to_radians(), b.lat().to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (b.lon() - a.lon()).to_radians();
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+/// Web mercator coordinates in 0..1, with y growing southwards.
+pub fn project(p: &Position) -> (f64, f64) {
+    let x = (p.lon() + 180.0) / 360.0;
+    let lat = p.lat().to_radians();
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0;
+    (x, y)
+}
+
+/// Map center and zoom that fit all `positions` into a viewport of `size` points.
+pub fn fit(positions: &[Position], size: egui::Vec2) -> Option<(walkers::Position, f64)> {
+    let first = positions.first()?;
+    let (mut min_x, mut min_y) = project(first);
+    let (mut max_x, mut max_y) = (min_x, min_y);
+    for (x, y) in positions.iter().map(project) {
+        min_x = min_x.min(x);
+        max_x = max_x.max(x);
+        min_y = min_y.min(y);
+        max_y = max_y.max(y);
+    }
+
+    // Leave some margin around the points, and don't zoom in further than street level
+    let padding = 0.8;
+    let width = (max_x - min_x).max(1e-6) * 256.0;
+    let height = (max_y - min_y).max(1e-6) * 256.0;
+    let zoom = (size.x as f64 * padding / width)
+        .min(size.y as f64 * padding / height)
+        .log2()
+        .clamp(1.0, 16.0);
+
+    let (cx, cy) = ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    let lon = cx * 360.0 - 180.0;
+    let lat = (std::f64::consts::PI * (1.0 - 2.0 * cy)).sinh().atan().to_degrees();
+    Some((walkers::Position::from_lat_lon(lat, lon), zoom))
+}
+
+/// Tiles of `zoom` at most `radius` tiles away from the one `p` is on.
+pub fn tiles_around(p: walkers::Position, zoom: u8, radius: i64) -> Vec<walkers::TileId> {
+    let (x, y) = project(&p.into());
+    let n = 1i64 << zoom;
+    let (cx, cy) = ((x * n as f64) as i64, (y * n as f64) as i64);
+    let mut tiles: Vec<walkers::TileId> = (cy - radius..=cy + radius)
+        .filter(|y| (0..n).contains(y))
+        .flat_map(|y| {
+            (cx - radius..=cx + radius).map(move |x| walkers::TileId {
+                x: x.rem_euclid(n) as u32,
+                y: y as u32,
+                zoom,
+            })
+        })
+        .collect();
+    // Around the antimeridian at low zooms the same tile comes up from both sides
+    tiles.sort_by_key(|t| (t.y, t.x));
+    tiles.dedup();
+    tiles
+}
+
+impl From<Position> for walkers::Position {
+    fn from(p: Position) -> Self {
+        walkers::Position::from_lat_lon(p.lat(), p.lon())
+    }
+}
+
+impl From<walkers::Position> for Position {
+    fn from(p: walkers::Position) -> Self {
+        Position::from_lat_lon(p.lat(), p.lon())
+    }
+}
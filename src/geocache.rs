@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::trace::{GeoProvider, Location, Position};
+
+/// Geolocation results by provider and IP, shared by every trace and kept on disk between runs.
+/// Providers don't agree on names, e.g. ip-api has full country names where ipinfo has codes,
+/// so each one keeps its own answers.
+static CACHE: LazyLock<Mutex<GeoCache>> = LazyLock::new(|| Mutex::new(GeoCache::load()));
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct GeoCache {
+    providers: HashMap<GeoProvider, HashMap<String, Entry>>,
+    /// Caches written before there was more than one provider, all from ip-api
+    #[serde(skip_serializing)]
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    lat: f64,
+    lon: f64,
+    isp: String,
+    asn: Option<u32>,
+    as_name: String,
+    country: String,
+    country_code: String,
+    // Missing in caches written before cities were looked up
+    #[serde(default)]
+    region: String,
+    #[serde(default)]
+    city: String,
+    fetched: DateTime<Utc>,
+}
+
+impl Entry {
+    fn fresh(&self, ttl: Duration) -> bool {
+        chrono::Duration::from_std(ttl).is_ok_and(|ttl| Utc::now() - self.fetched < ttl)
+    }
+}
+
+fn cache_path() -> Option<PathBuf> {
+    ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.cache_dir().join("geocache.toml"))
+}
+
+impl GeoCache {
+    fn load() -> Self {
+        let Some(text) = cache_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return Self::default();
+        };
+        match toml::from_str::<Self>(&text) {
+            Ok(mut cache) => {
+                let old = std::mem::take(&mut cache.entries);
+                cache.providers.entry(GeoProvider::IpApi).or_default().extend(old);
+                info!("Loaded {} cached locations", cache.len());
+                cache
+            }
+            Err(e) => {
+                warn!("Ignoring broken geolocation cache: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.providers.values().map(HashMap::len).sum()
+    }
+}
+
+/// Where `provider` put `ip`, if it was looked up with it less than `ttl` ago.
+pub fn get(provider: GeoProvider, ip: &str, ttl: Duration) -> Option<Location> {
+    let cache = CACHE.lock().unwrap();
+    let entry = cache.providers.get(&provider)?.get(ip).filter(|e| e.fresh(ttl))?;
+    debug!("Geolocation cache hit for {} from {}", ip, provider.name());
+    Some(Location {
+        position: Position::from_lat_lon(entry.lat, entry.lon),
+        isp: entry.isp.clone(),
+        asn: entry.asn,
+        as_name: entry.as_name.clone(),
+        country: entry.country.clone(),
+        country_code: entry.country_code.clone(),
+        region: entry.region.clone(),
+        city: entry.city.clone(),
+    })
+}
+
+pub fn insert(provider: GeoProvider, ip: &str, location: &Location) {
+    CACHE.lock().unwrap().providers.entry(provider).or_default().insert(
+        ip.to_string(),
+        Entry {
+            lat: location.position.lat(),
+            lon: location.position.lon(),
+            isp: location.isp.clone(),
+            asn: location.asn,
+            as_name: location.as_name.clone(),
+            country: location.country.clone(),
+            country_code: location.country_code.clone(),
+            region: location.region.clone(),
+            city: location.city.clone(),
+            fetched: Utc::now(),
+        },
+    );
+}
+
+pub fn len() -> usize {
+    CACHE.lock().unwrap().len()
+}
+
+pub fn clear() {
+    CACHE.lock().unwrap().providers.clear();
+}
+
+/// Writes the cache to disk, dropping entries older than `ttl` on the way.
+pub fn save(ttl: Duration) {
+    let Some(path) = cache_path() else {
+        return;
+    };
+    let mut cache = CACHE.lock().unwrap();
+    for entries in cache.providers.values_mut() {
+        entries.retain(|_, e| e.fresh(ttl));
+    }
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("Failed to create cache directory {}: {}", dir.display(), e);
+            return;
+        }
+    }
+    match toml::to_string(&*cache) {
+        Ok(text) => {
+            if let Err(e) = fs::write(&path, text) {
+                warn!("Failed to write geolocation cache {}: {}", path.display(), e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize geolocation cache: {}", e),
+    }
+}
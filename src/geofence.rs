@@ -0,0 +1,76 @@
+//! Areas routes must stay out of, e.g. countries of concern or regions that add latency.
+
+use serde::{Deserialize, Serialize};
+
+use crate::trace::Position;
+
+/// A named polygon on the map. Routes with a hop inside it raise an alert.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Geofence {
+    pub name: String,
+    /// Corners as latitude and longitude, in order
+    pub points: Vec<[f64; 2]>,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Geofence {
+    pub fn new(name: String, corners: &[Position]) -> Self {
+        Self {
+            name,
+            points: corners.iter().map(|p| [p.lat(), p.lon()]).collect(),
+            enabled: true,
+        }
+    }
+
+    pub fn corners(&self) -> Vec<Position> {
+        self.points.iter().map(|[lat, lon]| Position::from_lat_lon(*lat, *lon)).collect()
+    }
+
+    /// Whether `position` is inside, by ray casting on plain latitude and longitude.
+    /// Good enough for fences that don't cross the antimeridian or a pole.
+    pub fn contains(&self, position: Position) -> bool {
+        let (y, x) = (position.lat(), position.lon());
+        let mut inside = false;
+        let mut j = self.points.len().wrapping_sub(1);
+        for (i, [yi, xi]) in self.points.iter().enumerate() {
+            let [yj, xj] = self.points[j];
+            if (*yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+}
+
+/// A hop inside a geofence.
+pub struct Breach<'a> {
+    pub fence: &'a str,
+    pub ttl: usize,
+}
+
+/// Every hop past the local node that lies inside an enabled fence, in TTL order.
+pub fn breaches(fences: &[Geofence], hops: impl IntoIterator<Item = (usize, Position)>) -> Vec<Breach<'_>> {
+    let mut breaches = Vec::new();
+    for (ttl, position) in hops.into_iter().filter(|(ttl, _)| *ttl > 0) {
+        if let Some(fence) = fences.iter().find(|f| f.enabled && f.points.len() >= 3 && f.contains(position)) {
+            breaches.push(Breach { fence: &fence.name, ttl });
+        }
+    }
+    breaches
+}
+
+/// One line alert text for `breaches`, none if there are none.
+pub fn describe(breaches: &[Breach]) -> Option<String> {
+    let first = breaches.first()?;
+    let more = match breaches.len() {
+        1 => String::new(),
+        n => format!(" and {} more hops", n - 1),
+    };
+    Some(format!("hop {} inside geofence {}{}", first.ttl, first.fence, more))
+}
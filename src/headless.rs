@@ -0,0 +1,64 @@
+use std::process::ExitCode;
+
+use log::{error, info};
+
+use crate::{
+    config::Config,
+    executor::Executor,
+    export::{self, Format},
+    geocache,
+    trace,
+    plugins::TraceSet,
+    summary,
+    trace::{Enrichment, TraceSettings, Tracer},
+};
+
+/// Runs a single trace without a window and prints it to stdout. With `expect_route`, also
+/// fails when the route's fingerprint differs from it.
+pub fn run(target: String, tags: Vec<String>, json: bool, expect_route: Option<String>) -> ExitCode {
+    let config = Config::load();
+    trace::set_ipinfo_token(&config.ipinfo_token);
+    trace::set_resolver(config.resolver, &config.dns_server);
+    let settings = TraceSettings {
+        options: config.trace,
+        geolocation: config.geolocation,
+    };
+    let tracer = Tracer::new(settings.options, settings.geolocation, Enrichment::load(&config.site_map));
+    let runtime = Executor::new(config.worker_threads).expect("Failed to start the async runtime");
+
+    info!("Starting headless trace for {}", target);
+    let mut trace_set = TraceSet::default();
+    let id = trace_set.add(target.clone(), tags, settings);
+    let trace = trace_set.get_mut(id).unwrap();
+    runtime.block_on(async {
+        let mut events = tracer.trace(&target).await;
+        while let Some(event) = events.recv().await {
+            trace.apply(event);
+        }
+    });
+    geocache::save(config.trace.cache_ttl());
+    for error in &trace.errors {
+        error!("{}", error);
+    }
+
+    let format = if json { Format::Json } else { Format::Text };
+    match export::render(&format, trace) {
+        Ok(output) => println!("{}", output.trim_end()),
+        Err(e) => {
+            error!("Failed to format trace: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    // Only the local node means nothing answered
+    if trace.nodes.len() <= 1 {
+        return ExitCode::FAILURE;
+    }
+    if let Some(expected) = expect_route {
+        let fingerprint = summary::route_fingerprint(&trace.nodes);
+        if !fingerprint.eq_ignore_ascii_case(expected.trim()) {
+            error!("Route changed: fingerprint {} instead of {}", fingerprint, expected.trim());
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    baseline::Baselines,
+    network::NetworkContext,
+    notes::Notes,
+    store::{self, Store, TargetStats},
+    summary,
+    trace::{Location, TraceNode, TraceSettings},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// At least one hop past the local node was located
+    Completed,
+    /// Nothing but (at most) the local node came back
+    Failed,
+}
+
+impl Outcome {
+    pub fn from_nodes(nodes: &[(usize, TraceNode)]) -> Self {
+        if nodes.len() > 1 {
+            Outcome::Completed
+        } else {
+            Outcome::Failed
+        }
+    }
+}
+
+/// A finished trace, kept around so it can be filtered and shown again.
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub target: String,
+    pub started: DateTime<Local>,
+    pub outcome: Outcome,
+    pub tags: Vec<String>,
+    /// Settings the trace ran with, reused when it's run again
+    pub settings: TraceSettings,
+    /// Network the trace was taken from, if known
+    pub network: Option<NetworkContext>,
+    pub nodes: Vec<(usize, TraceNode)>,
+    pub timeouts: Vec<usize>,
+}
+
+impl HistoryEntry {
+    /// IPs of the hops past the local node, in TTL order.
+    pub fn path(&self) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|(ttl, _)| *ttl > 0)
+            .map(|(_, node)| node.ip.as_str())
+            .collect()
+    }
+
+    /// See [`summary::fingerprint`].
+    pub fn fingerprint(&self) -> String {
+        summary::route_fingerprint(&self.nodes)
+    }
+}
+
+/// Two consecutive completed traces to the same target that took different routes.
+pub struct PathChange<'a> {
+    pub before: &'a HistoryEntry,
+    pub after: &'a HistoryEntry,
+}
+
+/// Criteria over the history. Empty fields match everything.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    pub tag: Option<String>,
+    pub target: String,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub outcome: Option<Outcome>,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        let day = entry.started.date_naive();
+        self.tag.as_ref().is_none_or(|tag| entry.tags.contains(tag))
+            && (self.target.is_empty()
+                || entry.target.to_lowercase().contains(&self.target.to_lowercase()))
+            && self.from.is_none_or(|from| day >= from)
+            && self.to.is_none_or(|to| day <= to)
+            && self.outcome.is_none_or(|outcome| entry.outcome == outcome)
+    }
+}
+
+/// A filter preset the user saved under a name, shown as a quick filter.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedFilter {
+    pub name: String,
+    pub filter: Filter,
+}
+
+#[derive(Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+    pub saved_filters: Vec<SavedFilter>,
+    pub baselines: Baselines,
+    pub notes: Notes,
+    /// Last time baselines and notes were checked for changes by others
+    pub synced_at: Option<Instant>,
+    /// Where finished traces are kept, none if it couldn't be opened
+    pub store: Option<Store>,
+}
+
+/// How often baselines and notes are checked for changes by others.
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+impl History {
+    /// Switches to the baselines and notes in `shared_dir`, or the local ones if it is empty.
+    pub fn open_shared(&mut self, shared_dir: &str) {
+        self.baselines = Baselines::load(shared_dir);
+        self.notes = Notes::load(shared_dir);
+        self.synced_at = Some(Instant::now());
+    }
+
+    /// Picks up baselines and notes others saved, at most every [`SYNC_INTERVAL`].
+    pub fn sync(&mut self) {
+        if self.synced_at.is_some_and(|at| at.elapsed() < SYNC_INTERVAL) {
+            return;
+        }
+        self.synced_at = Some(Instant::now());
+        self.baselines.refresh();
+        self.notes.reload();
+    }
+
+    /// Opens the trace store and loads the latest runs from it.
+    pub fn open_store(&mut self) {
+        let store = match Store::open() {
+            Ok(store) => store,
+            Err(e) => {
+                warn!("Not storing traces: {}", e);
+                return;
+            }
+        };
+        match store.recent(store::RECENT_RUNS) {
+            Ok(entries) => {
+                info!("Loaded {} stored traces", entries.len());
+                self.entries = entries;
+            }
+            Err(e) => warn!("Failed to load stored traces: {}", e),
+        }
+        self.store = Some(store);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &mut self,
+        target: String,
+        started: DateTime<Local>,
+        tags: Vec<String>,
+        settings: TraceSettings,
+        network: Option<NetworkContext>,
+        nodes: Vec<(usize, TraceNode)>,
+        timeouts: Vec<usize>,
+    ) {
+        self.entries.push(HistoryEntry {
+            target,
+            started,
+            outcome: Outcome::from_nodes(&nodes),
+            tags,
+            settings,
+            network,
+            nodes,
+            timeouts,
+        });
+        if let Some(store) = &mut self.store {
+            if let Err(e) = store.record(&self.entries[self.entries.len() - 1]) {
+                warn!("Failed to store trace: {}", e);
+            }
+        }
+    }
+
+    /// Stores the tags of the entry at `index` after they were edited.
+    pub fn save_tags(&self, index: usize) {
+        let (Some(store), Some(entry)) = (&self.store, self.entries.get(index)) else {
+            return;
+        };
+        if let Err(e) = store.update_tags(entry) {
+            warn!("Failed to store tags: {}", e);
+        }
+    }
+
+    /// Every hop IP in the history, including runs no longer loaded.
+    pub fn ips(&self) -> Vec<String> {
+        if let Some(store) = &self.store {
+            match store.ips() {
+                Ok(ips) => return ips,
+                Err(e) => warn!("Failed to read stored hops: {}", e),
+            }
+        }
+        let mut ips: Vec<String> = self
+            .entries
+            .iter()
+            .flat_map(|e| e.nodes.iter().map(|(_, node)| node.ip.clone()))
+            .collect();
+        ips.sort();
+        ips.dedup();
+        ips
+    }
+
+    /// Moves every hop with one of the given IPs to its new location, in memory and on disk.
+    pub fn relocate(&mut self, located: &[(String, Location)]) {
+        let by_ip: HashMap<&str, &Location> = located.iter().map(|(ip, l)| (ip.as_str(), l)).collect();
+        for (_, node) in self.entries.iter_mut().flat_map(|e| e.nodes.iter_mut()) {
+            if let Some(location) = by_ip.get(node.ip.as_str()) {
+                node.locate(location);
+            }
+        }
+        if let Some(store) = &mut self.store {
+            if let Err(e) = store.relocate(located) {
+                warn!("Failed to store new locations: {}", e);
+            }
+        }
+    }
+
+    /// Statistics over every stored run to `target`, including ones no longer loaded.
+    pub fn stats(&self, target: &str) -> Result<Option<TargetStats>, String> {
+        let store = self.store.as_ref().ok_or("Traces aren't stored")?;
+        store.stats(target).map_err(|e| e.to_string())
+    }
+
+    /// Path changes of every target, oldest first.
+    pub fn path_changes(&self) -> Vec<PathChange<'_>> {
+        let mut entries: Vec<&HistoryEntry> = self
+            .entries
+            .iter()
+            .filter(|e| e.outcome == Outcome::Completed)
+            .collect();
+        entries.sort_by_key(|e| e.started);
+
+        let mut last: HashMap<&str, &HistoryEntry> = HashMap::new();
+        let mut changes = Vec::new();
+        for entry in entries {
+            if let Some(before) = last.insert(&entry.target, entry) {
+                if before.fingerprint() != entry.fingerprint() {
+                    changes.push(PathChange { before, after: entry });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Index of the latest completed trace to the same target before the entry at `index`.
+    pub fn previous(&self, index: usize) -> Option<usize> {
+        let target = &self.entries.get(index)?.target;
+        self.entries[..index]
+            .iter()
+            .rposition(|e| e.target == *target && e.outcome == Outcome::Completed)
+    }
+
+    /// Every tag used anywhere in the history, sorted and deduplicated.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .entries
+            .iter()
+            .flat_map(|e| e.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    pub fn save_filter(&mut self, name: String, filter: Filter) {
+        self.saved_filters.retain(|f| f.name != name);
+        self.saved_filters.push(SavedFilter { name, filter });
+    }
+}
+
+/// Splits user input like "incident-1234, baseline" into individual tags.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
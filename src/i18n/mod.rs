@@ -0,0 +1,136 @@
+//! Translations of the UI.
+//!
+//! Strings are looked up by their English text in a rust-i18n backend filled from the
+//! `<locale>.toml` catalogs next to this file, which are bundled with the binary. Anything
+//! not translated yet shows in English.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock,
+    },
+};
+
+use log::{info, warn};
+use rust_i18n::{Backend, SimpleBackend};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    #[default]
+    English,
+    Spanish,
+    German,
+    Chinese,
+}
+
+impl Language {
+    pub const ALL: [Language; 4] = [Language::English, Language::Spanish, Language::German, Language::Chinese];
+
+    /// The rust-i18n locale, which is also the name of its catalog.
+    fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::German => "de",
+            Language::Chinese => "zh",
+        }
+    }
+
+    /// Name of the language in itself, so it can be found without reading the current one.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Español",
+            Language::German => "Deutsch",
+            Language::Chinese => "中文",
+        }
+    }
+
+    /// The language of the system locale if there is a catalog for it, else English.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+            .unwrap_or_default();
+        Language::ALL
+            .into_iter()
+            .find(|language| locale.get(..2) == Some(language.code()))
+            .unwrap_or_default()
+    }
+}
+
+/// Catalogs by locale. English has none, its strings are the keys.
+const CATALOGS: [(&str, &str); 3] =
+    [("es", include_str!("es.toml")), ("de", include_str!("de.toml")), ("zh", include_str!("zh.toml"))];
+
+static BACKEND: LazyLock<SimpleBackend> = LazyLock::new(|| {
+    let mut backend = SimpleBackend::new();
+    for (locale, text) in CATALOGS {
+        match toml::from_str::<HashMap<String, String>>(text) {
+            Ok(strings) => {
+                let strings = strings.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+                backend.add_translations(locale, &strings);
+            }
+            Err(e) => warn!("Invalid {} catalog, showing English instead: {}", locale, e),
+        }
+    }
+    backend
+});
+
+pub fn set_language(language: Language) {
+    info!("Showing the UI in {:?}", language);
+    rust_i18n::set_locale(language.code());
+}
+
+/// `text` in the current language, or as it is if it has no translation.
+pub fn tr(text: &'static str) -> &'static str {
+    BACKEND.translate(&rust_i18n::locale(), text).unwrap_or(text)
+}
+
+/// [`tr`] of a text with `{}` placeholders, filled with `args` in order.
+pub fn trf(text: &'static str, args: &[&dyn Display]) -> String {
+    let mut parts = tr(text).split("{}");
+    let mut filled = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            filled.push_str(&arg.to_string());
+        }
+        filled.push_str(part);
+    }
+    filled
+}
+
+/// Fonts with Chinese glyphs that come with the common systems, egui's own have none.
+const CJK_FONTS: [&str; 7] = [
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/STHeiti Light.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+
+/// Adds a system font as fallback for the glyphs `language` needs that egui's fonts lack.
+/// Fonts stay once added, so switching back and forth doesn't load them again.
+pub fn install_fonts(ctx: &egui::Context, language: Language) {
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+    if language != Language::Chinese || INSTALLED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let Some((path, bytes)) = CJK_FONTS.iter().find_map(|path| fs::read(path).ok().map(|bytes| (path, bytes))) else {
+        warn!("No font with Chinese glyphs found, install Noto Sans CJK to see them");
+        return;
+    };
+    info!("Using {} for Chinese text", path);
+    let mut fonts = egui::FontDefinitions::default();
+    fonts.font_data.insert("cjk".to_string(), egui::FontData::from_owned(bytes));
+    for family in [egui::FontFamily::Proportional, egui::FontFamily::Monospace] {
+        fonts.families.entry(family).or_default().push("cjk".to_string());
+    }
+    ctx.set_fonts(fonts);
+}
@@ -0,0 +1,44 @@
+//! Internet exchange fabrics, whose addresses show up as hops where networks peer.
+
+use std::{net::IpAddr, sync::LazyLock};
+
+use ipnet::IpNet;
+use log::warn;
+
+use crate::trace::TraceNode;
+
+/// Peering LANs and the exchange each belongs to, bundled with the binary.
+static IXPS: LazyLock<Vec<(IpNet, &'static str)>> = LazyLock::new(|| {
+    include_str!("ixps.csv")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (prefix, name) = line.split_once(',')?;
+            match prefix.trim().parse() {
+                Ok(prefix) => Some((prefix, name.trim())),
+                Err(e) => {
+                    warn!("Invalid IXP prefix {}: {}", prefix, e);
+                    None
+                }
+            }
+        })
+        .collect()
+});
+
+/// Name of the exchange `ip` is on the peering LAN of.
+pub fn lookup(ip: &str) -> Option<&'static str> {
+    let ip: IpAddr = ip.parse().ok()?;
+    IXPS.iter().find(|(prefix, _)| prefix.contains(&ip)).map(|(_, name)| *name)
+}
+
+/// Exchanges a route goes through, in order, each once.
+pub fn traversed(nodes: &[(usize, TraceNode)]) -> Vec<&'static str> {
+    let mut ixps = Vec::new();
+    for name in nodes.iter().filter_map(|(_, node)| lookup(&node.ip)) {
+        if !ixps.contains(&name) {
+            ixps.push(name);
+        }
+    }
+    ixps
+}
@@ -0,0 +1,74 @@
+//! The mini-syntax of the quick launcher: a target followed by flags, e.g.
+//! `example.com --hops 20 --size 1400 --tag incident-1234`.
+
+use crate::{
+    i18n::{tr, trf},
+    trace::TraceOptions,
+};
+
+/// Flags the launcher understands, shown as a hint and in errors.
+pub const FLAGS: &str = "--ping, --hops N, --timeout MS, --parallel N, --size BYTES, --tag TAG";
+
+/// What to do with a launcher line.
+pub struct Launch {
+    pub target: String,
+    pub tags: Vec<String>,
+    /// Ping the target instead of tracing it
+    pub ping: bool,
+    /// The options given, on top of the current ones
+    pub options: TraceOptions,
+    /// Whether any flag changed `options`
+    pub custom: bool,
+}
+
+/// Parses `line`, starting from `options` for whatever the flags don't set.
+/// Errors say which token is wrong and what was expected instead.
+pub fn parse(line: &str, options: TraceOptions) -> Result<Launch, String> {
+    let mut launch = Launch {
+        target: String::new(),
+        tags: Vec::new(),
+        ping: false,
+        options,
+        custom: false,
+    };
+    let mut tokens = line.split_whitespace();
+    while let Some(token) = tokens.next() {
+        let Some(flag) = token.strip_prefix("--") else {
+            if !launch.target.is_empty() {
+                return Err(trf("Only one target at a time, got {} and {}", &[&launch.target, &token]));
+            }
+            launch.target = token.to_string();
+            continue;
+        };
+        let mut value = |what: &'static str| tokens.next().ok_or_else(|| trf("--{} needs {}", &[&flag, &tr(what)]));
+        match flag {
+            "ping" => launch.ping = true,
+            "tag" => launch.tags.push(value("a tag")?.to_string()),
+            "hops" => launch.options.max_hops = number(flag, value("a hop count")?, 1, 64)? as u8,
+            "timeout" => launch.options.timeout_ms = number(flag, value("milliseconds")?, 100, 5000)?,
+            "parallel" => launch.options.parallel_probes = number(flag, value("a probe count")?, 1, 32)? as u8,
+            "size" => launch.options.probe_size = number(flag, value("a size in bytes")?, 28, 1500)? as u16,
+            "tcp" | "udp" => {
+                return Err(trf(
+                    "--{} isn't supported, probes are ICMP, or UDP when raw sockets aren't available",
+                    &[&flag],
+                ))
+            }
+            "v4" | "v6" => return Err(trf("--{} isn't supported, enter the address to trace instead", &[&flag])),
+            _ => return Err(trf("Unknown flag --{}, try {}", &[&flag, &FLAGS])),
+        }
+        launch.custom |= matches!(flag, "hops" | "timeout" | "parallel" | "size");
+    }
+    if launch.target.is_empty() {
+        return Err(tr("Enter an IP or domain to trace").to_string());
+    }
+    Ok(launch)
+}
+
+fn number(flag: &str, value: &str, min: u64, max: u64) -> Result<u64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|n| (min..=max).contains(n))
+        .ok_or_else(|| trf("--{} takes a number from {} to {}, not {}", &[&flag, &min, &max, &value]))
+}
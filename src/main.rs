@@ -1,18 +1,104 @@
+mod accessibility;
+mod address;
+mod alert;
 mod app;
+#[cfg(feature = "audio")]
+mod audio;
+mod baseline;
+mod bus;
+mod config;
+mod dnsbl;
+mod executor;
+mod export;
+mod floorplan;
+mod flows;
+mod geo;
+mod geocache;
+mod geofence;
+mod headless;
+mod history;
+mod i18n;
+mod ixp;
+mod launcher;
+mod network;
+mod notes;
+mod notify;
+mod places;
+mod planner;
 mod plugins;
+mod policy;
+mod ratelimit;
+mod routediff;
+mod scheduler;
+mod scripts;
+mod sites;
+mod snmp;
+mod sources;
+mod splittunnel;
+mod store;
+mod summary;
+#[cfg(feature = "syslog")]
+mod syslog;
+mod trace;
+#[cfg(feature = "tray")]
+mod tray;
+mod ui;
+mod whois;
 mod windows;
+mod workspace;
 
+use std::process::ExitCode;
+
+use clap::Parser;
 use eframe::epaint::Vec2;
 use egui::ViewportBuilder;
 use env_logger::Builder;
 use log::LevelFilter;
 
-fn main() -> Result<(), eframe::Error> {
+#[derive(Parser)]
+#[command(version, about = "Visual traceroute")]
+struct Cli {
+    /// Host or IP to start tracing right away
+    target: Option<String>,
+    /// Trace without opening a window and print the result to stdout
+    #[arg(long, requires = "target")]
+    headless: bool,
+    /// Print JSON instead of a text table
+    #[arg(long, requires = "headless")]
+    json: bool,
+    /// Fail unless the route has this fingerprint, as shown in the path statistics
+    #[arg(long, requires = "headless", value_name = "FINGERPRINT")]
+    expect_route: Option<String>,
+    /// Comma separated tags for the trace
+    #[arg(long, default_value = "")]
+    tags: String,
+    /// Run as the privileged probe helper of the traced listening on this port
+    #[arg(long, hide = true)]
+    helper_port: Option<u16>,
+    /// File with the helper's secret, read from stdin without it
+    #[arg(long, hide = true, requires = "helper_port")]
+    helper_secret: Option<std::path::PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
     Builder::new()
         .filter(None, LevelFilter::Info)
         .filter_module("wgpu_core", LevelFilter::Warn)
         .init();
 
+    if let Some(port) = cli.helper_port {
+        return trace::helper::serve(port, cli.helper_secret.as_deref());
+    }
+
+    let tags = history::parse_tags(&cli.tags);
+    if cli.headless {
+        // Checked by clap
+        let target = cli.target.unwrap_or_default();
+        return headless::run(target, tags, cli.json, cli.expect_route);
+    }
+
     log::info!("Starting Visual Trace application");
 
     let options = eframe::NativeOptions {
@@ -22,9 +108,17 @@ fn main() -> Result<(), eframe::Error> {
         ..Default::default()
     };
 
-    eframe::run_native(
+    let start = cli.target.map(|target| (target, tags));
+    let result = eframe::run_native(
         "Visual Trace",
         options,
-        Box::new(|cc| Ok(Box::new(app::App::new(cc.egui_ctx.clone())))),
-    )
+        Box::new(|cc| Ok(Box::new(app::App::new(cc, start)))),
+    );
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
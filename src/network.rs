@@ -0,0 +1,88 @@
+//! Noticing when the machine moves to another network, e.g. a wifi switch or a VPN connecting.
+
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{bus::Topic, executor::Executor, trace};
+
+/// How often the local address is checked.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Every this many polls the public IP is asked for even if the local address stayed the same.
+const PUBLIC_IP_EVERY: u32 = 20;
+
+/// The network a trace was taken from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkContext {
+    /// Source address of the default route
+    pub local_ip: Option<IpAddr>,
+    /// As seen from the internet, none if it couldn't be looked up
+    pub public_ip: Option<String>,
+}
+
+impl fmt::Display for NetworkContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.local_ip, &self.public_ip) {
+            (Some(local), Some(public)) => write!(f, "{} via {}", local, public),
+            (Some(local), None) => write!(f, "{}", local),
+            (None, Some(public)) => write!(f, "via {}", public),
+            (None, None) => write!(f, "offline"),
+        }
+    }
+}
+
+/// Source address the default route would use. Connecting a UDP socket sends nothing.
+fn local_ip() -> Option<IpAddr> {
+    let probes = [
+        (SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0), SocketAddr::new(Ipv4Addr::new(1, 1, 1, 1).into(), 53)),
+        (
+            SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::new(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111).into(), 53),
+        ),
+    ];
+    probes.into_iter().find_map(|(bind, remote)| {
+        let socket = UdpSocket::bind(bind).ok()?;
+        socket.connect(remote).ok()?;
+        socket.local_addr().ok().map(|addr| addr.ip())
+    })
+}
+
+/// Publishes the network context once at start and again whenever it changes.
+pub fn watch(topic: Topic<NetworkContext>, runtime: &Executor) {
+    runtime.spawn(async move {
+        let client = reqwest::Client::new();
+        let mut current: Option<NetworkContext> = None;
+        let mut polls = 0;
+        loop {
+            let local_ip = local_ip();
+            let moved = current.as_ref().is_none_or(|c| c.local_ip != local_ip);
+            let public_ip = if moved || polls % PUBLIC_IP_EVERY == 0 {
+                match trace::get_my_ip(&client).await {
+                    Some(ip) => Some(ip),
+                    // A failed lookup on the same network doesn't mean the public IP changed
+                    None if !moved => current.as_ref().and_then(|c| c.public_ip.clone()),
+                    None => None,
+                }
+            } else {
+                current.as_ref().and_then(|c| c.public_ip.clone())
+            };
+            polls += 1;
+
+            let context = NetworkContext { local_ip, public_ip };
+            if current.as_ref() != Some(&context) {
+                match &current {
+                    Some(previous) => info!("Network changed from {} to {}", previous, context),
+                    None => debug!("On network {}", context),
+                }
+                topic.publish(context.clone());
+                current = Some(context);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
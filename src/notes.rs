@@ -0,0 +1,166 @@
+//! Notes on targets, kept where the baselines are so a team pointing at the same shared
+//! folder sees everyone's.
+//!
+//! Every author writes only their own `notes/<author>.toml`, so two people never write the
+//! same file. The same author on two machines can, so saving merges in what the file gained
+//! since it was read.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub target: String,
+    pub text: String,
+    /// Also tells an author's notes apart
+    pub written: DateTime<Local>,
+}
+
+/// Contents of one author's file.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct NoteFile {
+    author: String,
+    notes: Vec<Note>,
+}
+
+impl NoteFile {
+    fn read(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        toml::from_str(&text)
+            .map_err(|e| warn!("Ignoring broken notes file {}: {}", path.display(), e))
+            .ok()
+    }
+}
+
+/// Name notes are signed with, the login name.
+pub fn author() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Everyone's notes.
+#[derive(Default)]
+pub struct Notes {
+    /// Folder with a file per author, none without a data directory
+    dir: Option<PathBuf>,
+    pub author: String,
+    /// Notes by the name of the file they are in
+    files: BTreeMap<String, NoteFile>,
+    /// Modification times of the files when they were read, to only read changed ones again
+    modified: HashMap<String, SystemTime>,
+}
+
+impl Notes {
+    /// Loads the notes in `shared_dir`, or the local ones if it is empty.
+    pub fn load(shared_dir: &str) -> Self {
+        let mut notes = Self {
+            dir: config::store_dir(shared_dir).map(|dir| dir.join("notes")),
+            author: author(),
+            ..Default::default()
+        };
+        notes.reload();
+        notes
+    }
+
+    fn file_name(&self) -> String {
+        self.author
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect()
+    }
+
+    /// Reads the files that were added or changed since the last time. Returns whether any were.
+    pub fn reload(&mut self) -> bool {
+        let Some(entries) = self.dir.as_deref().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return false;
+        };
+        let mut changed = false;
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let Some(name) = path
+                .file_stem()
+                .filter(|_| path.extension().is_some_and(|ext| ext == "toml"))
+                .map(|name| name.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            let modified = config::modified(&path);
+            if modified.is_none() || self.modified.get(&name) == modified.as_ref() {
+                continue;
+            }
+            if let (Some(file), Some(modified)) = (NoteFile::read(&path), modified) {
+                self.files.insert(name.clone(), file);
+                self.modified.insert(name, modified);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Notes on `target` and who wrote them, oldest first.
+    pub fn for_target(&self, target: &str) -> Vec<(&str, &Note)> {
+        let mut notes: Vec<(&str, &Note)> = self
+            .files
+            .iter()
+            .flat_map(|(name, file)| {
+                let author = if file.author.is_empty() { name.as_str() } else { file.author.as_str() };
+                file.notes.iter().filter(|note| note.target == target).map(move |note| (author, note))
+            })
+            .collect();
+        notes.sort_by_key(|(_, note)| note.written);
+        notes
+    }
+
+    pub fn add(&mut self, target: &str, text: &str) {
+        let note = Note {
+            target: target.to_string(),
+            text: text.to_string(),
+            written: Local::now(),
+        };
+        self.save(Some(note), None);
+    }
+
+    /// Removes one of the user's own notes, others' can't be.
+    pub fn remove(&mut self, written: DateTime<Local>) {
+        self.save(None, Some(written));
+    }
+
+    /// Writes the user's file with `add` and without `remove`, on top of what it holds now.
+    fn save(&mut self, add: Option<Note>, remove: Option<DateTime<Local>>) {
+        let Some(dir) = self.dir.clone() else {
+            return;
+        };
+        let name = self.file_name();
+        let path = dir.join(format!("{}.toml", name));
+        let mut file = NoteFile::read(&path)
+            .or_else(|| self.files.remove(&name))
+            .unwrap_or_default();
+        file.author = self.author.clone();
+        file.notes.retain(|note| Some(note.written) != remove);
+        file.notes.extend(add);
+        let result = toml::to_string(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|text| config::write_replacing(&path, &text).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                info!("Saved {} notes to {}", file.notes.len(), path.display());
+                if let Some(modified) = config::modified(&path) {
+                    self.modified.insert(name.clone(), modified);
+                }
+            }
+            Err(e) => warn!("Failed to save notes: {}", e),
+        }
+        self.files.insert(name, file);
+    }
+}
@@ -0,0 +1,18 @@
+//! Desktop notifications for alerts of scheduled traces.
+
+use log::warn;
+use notify_rust::Notification;
+
+use crate::alert::Alert;
+
+/// Shows `alert` with the target in the summary. Blocks until the notification server answers.
+pub fn show(alert: &Alert) {
+    let result = Notification::new()
+        .appname("traced")
+        .summary(&format!("{}: {}", alert.kind.name(), alert.target))
+        .body(&alert.detail)
+        .show();
+    if let Err(e) = result {
+        warn!("Failed to show notification: {}", e);
+    }
+}
@@ -0,0 +1,70 @@
+//! Looking up places by name with OpenStreetMap's Nominatim, to move the map there.
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::{i18n::trf, trace::Position};
+
+const NOMINATIM: &str = "https://nominatim.openstreetmap.org/search";
+/// Results per search, Nominatim's usage policy asks to keep requests light
+const MAX_RESULTS: usize = 5;
+
+/// A place matching a search.
+pub struct Place {
+    pub name: String,
+    pub position: Position,
+    /// Opposite corners of the area it covers, when known
+    pub bounds: Option<[Position; 2]>,
+}
+
+impl Place {
+    /// Points the map has to show to take in the whole place.
+    pub fn extent(&self) -> Vec<Position> {
+        match self.bounds {
+            Some(corners) => corners.to_vec(),
+            None => vec![self.position],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NominatimPlace {
+    display_name: String,
+    lat: String,
+    lon: String,
+    /// South, north, west and east edges, as strings like the coordinates
+    #[serde(default)]
+    boundingbox: Vec<String>,
+}
+
+/// Places whose name matches `query`, best match first.
+pub async fn search(client: &reqwest::Client, query: &str) -> Result<Vec<Place>, String> {
+    debug!("Searching Nominatim for {}", query);
+    let response = client
+        .get(NOMINATIM)
+        .query(&[("q", query), ("format", "json"), ("limit", &MAX_RESULTS.to_string())])
+        .header(reqwest::header::USER_AGENT, concat!("traced/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| trf("Search failed: {}", &[&e]))?;
+    let places: Vec<NominatimPlace> = response.json().await.map_err(|e| trf("Invalid search response: {}", &[&e]))?;
+    Ok(places
+        .into_iter()
+        .filter_map(|place| {
+            let position = Position::from_lat_lon(place.lat.parse().ok()?, place.lon.parse().ok()?);
+            let edges: Vec<f64> = place.boundingbox.iter().filter_map(|edge| edge.parse().ok()).collect();
+            let bounds = match edges[..] {
+                [south, north, west, east] => {
+                    Some([Position::from_lat_lon(south, west), Position::from_lat_lon(north, east)])
+                }
+                _ => None,
+            };
+            Some(Place {
+                name: place.display_name,
+                position,
+                bounds,
+            })
+        })
+        .collect())
+}
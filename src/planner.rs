@@ -0,0 +1,166 @@
+use std::{
+    net::IpAddr,
+    sync::mpsc::Sender,
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::trace;
+
+/// Loss above this pushes a region behind all the ones below it, however fast.
+const MAX_LOSS: f64 = 0.05;
+/// Time between probes to the same region.
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A candidate deployment region and a host inside it to measure against.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub host: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlannerConfig {
+    pub regions: Vec<Region>,
+    /// Round trip budget the p95 has to stay under
+    pub budget_ms: f64,
+    /// Probes sent to each region per run
+    pub probes: u32,
+}
+
+impl Default for PlannerConfig {
+    fn default() -> Self {
+        Self {
+            regions: Vec::new(),
+            budget_ms: 100.0,
+            probes: 20,
+        }
+    }
+}
+
+/// Parses "name = host" lines, a bare host is its own name.
+pub fn parse_regions(text: &str) -> Vec<Region> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once('=') {
+            Some((name, host)) => Region {
+                name: name.trim().to_string(),
+                host: host.trim().to_string(),
+            },
+            None => Region {
+                name: line.to_string(),
+                host: line.to_string(),
+            },
+        })
+        .collect()
+}
+
+pub fn format_regions(regions: &[Region]) -> String {
+    regions
+        .iter()
+        .map(|r| format!("{} = {}", r.name, r.host))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Latency distribution of one region, in ms.
+#[derive(Clone, Copy)]
+pub struct Stats {
+    pub min: f64,
+    pub q1: f64,
+    pub p50: f64,
+    pub q3: f64,
+    pub p95: f64,
+    pub max: f64,
+    /// Mean difference between consecutive replies
+    pub jitter: f64,
+    /// Share of probes without a reply, 0..1
+    pub loss: f64,
+}
+
+impl Stats {
+    /// None if nothing came back at all.
+    pub fn new(samples: &[Option<f64>]) -> Option<Self> {
+        let replies: Vec<f64> = samples.iter().flatten().copied().collect();
+        if replies.is_empty() {
+            return None;
+        }
+        let mut sorted = replies.clone();
+        sorted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+        let jitter = if replies.len() > 1 {
+            replies.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (replies.len() - 1) as f64
+        } else {
+            0.0
+        };
+        Some(Self {
+            min: sorted[0],
+            q1: percentile(0.25),
+            p50: percentile(0.5),
+            q3: percentile(0.75),
+            p95: percentile(0.95),
+            max: sorted[sorted.len() - 1],
+            jitter,
+            loss: 1.0 - replies.len() as f64 / samples.len() as f64,
+        })
+    }
+}
+
+/// Indices of `stats` best first: lossy regions after the rest, then by p95.
+/// Regions without any replies come last.
+pub fn rank(stats: &[Option<Stats>]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..stats.len()).collect();
+    order.sort_by(|&a, &b| match (&stats[a], &stats[b]) {
+        (Some(a), Some(b)) => (a.loss > MAX_LOSS)
+            .cmp(&(b.loss > MAX_LOSS))
+            .then(a.p95.total_cmp(&b.p95)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    order
+}
+
+pub enum Event {
+    Resolved(usize, IpAddr),
+    /// RTT in ms, none for a lost probe
+    Sample(usize, Option<f64>),
+    /// The region gave up early, no more events follow for it
+    Error(usize, String),
+}
+
+/// Probes every region at once, reporting back by region index.
+/// The channel disconnects once every region is done.
+pub fn run(regions: &[Region], probes: u32, timeout: Duration, sender: Sender<Event>) {
+    info!("Measuring {} candidate regions", regions.len());
+    for (i, region) in regions.iter().enumerate() {
+        let host = region.host.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let Some(ip) = trace::resolve(&host) else {
+                sender.send(Event::Error(i, format!("Could not resolve {}", host))).ok();
+                return;
+            };
+            sender.send(Event::Resolved(i, ip)).ok();
+            for _ in 0..probes {
+                let rtt = match trace::ping(ip, timeout) {
+                    Ok(rtt) => rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+                    Err(e) => {
+                        sender.send(Event::Error(i, e)).ok();
+                        return;
+                    }
+                };
+                if sender.send(Event::Sample(i, rtt)).is_err() {
+                    return;
+                }
+                thread::sleep(PROBE_INTERVAL);
+            }
+            debug!("Done measuring {}", host);
+        });
+    }
+}
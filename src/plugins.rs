@@ -1,195 +1,1160 @@
-use egui::{vec2, Align2, Color32, FontId, Response, Stroke, Ui};
-use crate::windows::TraceNode;
-use walkers::{Plugin, Projector};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
 
-#[derive(Default)]
-pub struct TracePath {
+use chrono::{DateTime, Local};
+use egui::{vec2, Align2, Color32, FontId, Pos2, Response, RichText, Stroke, Ui, Vec2};
+use serde::{Deserialize, Serialize};
+use crate::{accessibility::{self, Described}, address::CopyFormat, baseline::Deviation, flows::{self, Volume}, bus::Topic, geo, geofence::{self, Geofence}, i18n::{tr, trf}, ixp, network::NetworkContext, scheduler::HopHistory, summary, ui::toasts::Toast, trace::{self, TraceEvent, TraceNode, TraceSettings}};
+use walkers::{Map, MapMemory, Plugin, Position, Projector, Tiles};
+
+/// Colors handed out to new traces, cycled in order.
+const TRACE_COLORS: [Color32; 6] = [
+    Color32::RED,
+    Color32::from_rgb(30, 144, 255),
+    Color32::from_rgb(255, 140, 0),
+    Color32::from_rgb(186, 85, 211),
+    Color32::from_rgb(0, 170, 170),
+    Color32::from_rgb(220, 20, 120),
+];
+/// Okabe-Ito colors, which stay apart for deuteranopia and protanopia.
+const OKABE_ITO: [Color32; 5] = [
+    Color32::from_rgb(230, 159, 0),
+    Color32::from_rgb(86, 180, 233),
+    Color32::from_rgb(0, 158, 115),
+    Color32::from_rgb(240, 228, 66),
+    Color32::from_rgb(204, 121, 167),
+];
+
+/// Colors of the source, destination and trace markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Classic,
+    /// Okabe-Ito, safe for red-green color blindness
+    Deuteranopia,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 2] = [Palette::Classic, Palette::Deuteranopia];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Classic => "Classic",
+            Palette::Deuteranopia => "Color-blind safe",
+        }
+    }
+
+    /// Fill and outline of the local node.
+    fn source(&self) -> (Color32, Color32) {
+        match self {
+            Palette::Classic => (Color32::GREEN, Color32::DARK_GREEN),
+            Palette::Deuteranopia => (Color32::from_rgb(0, 114, 178), Color32::from_rgb(0, 70, 110)),
+        }
+    }
+
+    /// Fill and outline of the last hop.
+    fn destination(&self) -> (Color32, Color32) {
+        match self {
+            Palette::Classic => (Color32::RED, Color32::DARK_RED),
+            Palette::Deuteranopia => (Color32::from_rgb(213, 94, 0), Color32::from_rgb(130, 57, 0)),
+        }
+    }
+
+    fn trace_color(&self, id: usize) -> Color32 {
+        let colors: &[Color32] = match self {
+            Palette::Classic => &TRACE_COLORS,
+            Palette::Deuteranopia => &OKABE_ITO,
+        };
+        colors[id % colors.len()]
+    }
+}
+
+/// How traces are drawn on the map.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkerStyle {
+    pub palette: Palette,
+    /// Marker radius, in points
+    pub marker_size: f32,
+    pub line_width: f32,
+    /// Direction arrows per 100 points of path, 0 for none
+    pub arrow_density: f32,
+    /// Thicker outlined lines, bigger markers and labels, and dimmed tiles underneath
+    pub high_contrast: bool,
+    /// How much the tiles are darkened in high contrast mode, 0 to 1
+    pub tile_dim: f32,
+}
+
+impl Default for MarkerStyle {
+    fn default() -> Self {
+        Self {
+            palette: Palette::Classic,
+            marker_size: 5.0,
+            line_width: 2.0,
+            arrow_density: 3.3,
+            high_contrast: false,
+            tile_dim: 0.5,
+        }
+    }
+}
+
+impl MarkerStyle {
+    /// The style actually drawn, with sizes boosted in high contrast mode.
+    fn effective(&self) -> Self {
+        if !self.high_contrast {
+            return *self;
+        }
+        Self {
+            marker_size: (self.marker_size * 1.5).max(8.0),
+            line_width: (self.line_width * 2.0).max(4.0),
+            ..*self
+        }
+    }
+
+    fn label_size(&self) -> f32 {
+        if self.high_contrast {
+            16.0
+        } else {
+            12.0
+        }
+    }
+}
+
+/// Consecutive hops closer than this are drawn as one marker when simplifying.
+const SIMPLIFY_KM: f64 = 25.0;
+/// Marks hops that differ from the baseline.
+pub const DEVIATION_COLOR: Color32 = Color32::from_rgb(255, 165, 0);
+/// Marks hops where two routes to the same target part ways.
+pub const ROUTE_CHANGE_COLOR: Color32 = Color32::from_rgb(255, 0, 255);
+/// Where private and pending hops are drawn relative to the hop before them
+const PRIVATE_OFFSET: Vec2 = vec2(14.0, 14.0);
+/// Consecutive markers closer than this on screen are drawn as one cluster when clustering.
+const CLUSTER_PIXELS: f32 = 12.0;
+/// Traces that stopped finding hops, and the last hop that answered.
+pub const STALLED_COLOR: Color32 = Color32::from_rgb(230, 190, 0);
+/// Geofences and the hops inside them.
+pub const GEOFENCE_COLOR: Color32 = Color32::from_rgb(220, 30, 30);
+/// Opacity of hops that don't match the hop filter.
+const DIMMED: f32 = 0.25;
+
+/// A single traced route to one target.
+pub struct Trace {
+    pub id: usize,
+    /// What the events of the run in progress are sent under, a new one for every resume so
+    /// late events of a stopped run can't mix in
+    pub run: usize,
+    pub target: String,
+    pub started: DateTime<Local>,
+    pub tags: Vec<String>,
+    /// Settings it was started with
+    pub settings: TraceSettings,
+    /// Network it was taken from, if known
+    pub network: Option<NetworkContext>,
+    pub color: Color32,
+    pub visible: bool,
+    /// Visibility in the second map view when it has its own selection
+    pub visible_secondary: bool,
+    /// Hops by TTL, 0 being the local node
     pub nodes: Vec<(usize, TraceNode)>,
+    /// TTLs that never answered
+    pub timeouts: Vec<usize>,
     pub tracing: bool,
-    copy_anim_time: Option<f64>,
+    /// The target name is still being looked up
+    pub resolving: bool,
+    /// When the trace was started or last heard of a hop, see [`Trace::stalled`]
+    pub progress_at: Instant,
+    /// When probing was started or resumed
+    pub probing_since: Instant,
+    /// TTL being probed, none until the engine starts probing
+    pub probing: Option<usize>,
+    /// Address the target resolved to, once it has
+    pub address: Option<IpAddr>,
+    /// Output of the scripts' trace hooks
+    pub summary: Vec<String>,
+    /// What went wrong along the way, cleared when dismissed
+    pub errors: Vec<String>,
+    /// Probing stopped before the target, so the trace can be resumed
+    pub interrupted: bool,
+    /// Its pending hops are being looked up again
+    pub relocating: bool,
+    /// Traffic towards the target seen by each hop, from an imported flow summary
+    pub flows: HashMap<IpAddr, Volume>,
+    /// Hops that differ from the target's baseline by TTL, none without a baseline
+    pub deviations: Option<BTreeMap<usize, Deviation>>,
+    /// TTLs of hops that aren't on the route it was last diffed against
+    pub route_changes: BTreeSet<usize>,
+}
+
+/// All traces shown on the map, overlaid on top of each other.
+#[derive(Default)]
+pub struct TraceSet {
+    pub traces: Vec<Trace>,
+    /// Whether the second map view picks its visible traces independently
+    pub separate_selection: bool,
+    /// Collapse runs of co-located hops into a single marker
+    pub simplify: bool,
+    /// Draw consecutive markers that overlap at the current zoom as a single one
+    pub cluster: bool,
+    /// Trace id and first TTL of the cluster whose hops are listed on the map
+    expanded: Option<(usize, usize)>,
+    /// Trace id and TTL of the hop clicked on the map, its details stay up until deselected
+    pub selected: Option<(usize, usize)>,
+    /// Color path segments by the AS of the hop they lead to
+    pub color_by_as: bool,
+    /// Draw hops in private ranges, which sit on top of the previous hop
+    pub show_private: bool,
+    /// Label map nodes with their city, where known, instead of the hop number
+    pub city_labels: bool,
+    /// Mark segments where the path enters another country
+    pub border_ticks: bool,
+    /// Draw the recent RTTs of hops next to their markers
+    pub sparklines: bool,
+    /// RTTs of hops from the scheduled runs, fed by the schedule window
+    pub hop_rtts: HopHistory,
+    /// Set through `set_style` so trace colors follow the palette
+    style: MarkerStyle,
+    next_id: usize,
+    /// IP and host name of the node the context menu was opened on, and the target of its trace
+    context_node: Option<(String, String, String)>,
+    /// Port used when copying a hop as host:port
+    pub copy_port: u16,
+    /// Where copies and other feedback are announced, `bus.toasts` in the app
+    pub toasts: Topic<Toast>,
+    /// Set when the user asks for a WHOIS lookup of a node
+    pub whois_request: Option<String>,
+    /// Set when the user asks to export a trace
+    pub export_request: Option<usize>,
+    /// Set when the user asks to import flows for a trace
+    pub flows_request: Option<usize>,
+    /// Set when a trace finishes or the user asks for its path statistics
+    pub stats_request: Option<usize>,
+    /// Set when the user asks for a probe size sweep towards a trace's target
+    pub sweep_request: Option<usize>,
+    /// Set when the user asks for the notes on a trace's target
+    pub notes_request: Option<usize>,
+    /// History indices of an earlier and a later route to the same target to diff
+    pub route_diff_request: Option<(usize, usize)>,
+    /// Set when the user asks for the SNMP interface of a node
+    pub snmp_request: Option<String>,
+    /// IP of a node the user wants to ping
+    pub ping_request: Option<String>,
+    /// IP of a node the user wants to watch with scheduled traces
+    pub watch_request: Option<String>,
+    /// Target whose route the user wants to keep among the favorites
+    pub bookmark_request: Option<String>,
+    /// Traces to start from outside the Enter IP window
+    pub start_requests: Vec<StartRequest>,
+    /// Ids of interrupted traces to pick up where they stopped
+    pub resume_requests: Vec<usize>,
+    /// Ids of finished traces whose pending hops to look up again
+    pub lookup_requests: Vec<usize>,
+    /// The network the machine is on now, new traces are marked with it
+    pub network: Option<NetworkContext>,
+    /// Targets re-run when the network changes
+    pub pinned: BTreeSet<String>,
+    /// Trace whose target is being edited in the trace list, and the new target
+    pub editing: Option<(usize, String)>,
+    /// Trace id and TTL of a hop hovered outside the map, ringed on it
+    pub highlight: Option<(usize, usize)>,
+    /// Where to move a map to show the hop picked with the keyboard, and whether it's the
+    /// second view
+    pub center_request: Option<(Position, bool)>,
+    /// Text typed into the hop list filter, hops not matching it are dimmed on the map
+    pub hop_filter: String,
+    /// Copy of the configured geofences, drawn on the map with the hops inside them ringed
+    pub geofences: Vec<Geofence>,
+    /// How long a trace may go without new hops before it counts as stalled, zero for never
+    pub stall_after: Duration,
 }
 
-impl TracePath {
-    pub fn set_path(&mut self, nodes: Vec<TraceNode>) {
-        self.nodes = nodes.into_iter().enumerate().collect();
+/// A trace to start from outside the Enter IP window.
+pub struct StartRequest {
+    pub target: String,
+    pub tags: Vec<String>,
+    /// Settings to run with instead of the current ones, when re-running an earlier trace
+    pub settings: Option<TraceSettings>,
+}
+
+impl StartRequest {
+    pub fn new(target: String, tags: Vec<String>) -> Self {
+        Self { target, tags, settings: None }
     }
 }
 
-impl Plugin for &mut TracePath {
-    fn run(self: Box<Self>, ui: &mut Ui, _response: &Response, projector: &Projector) {
-        if self.nodes.is_empty() {
+impl TraceSet {
+    /// Adds a new, empty trace for `target` and returns its id.
+    pub fn add(&mut self, target: String, tags: Vec<String>, settings: TraceSettings) -> usize {
+        let network = self.network.clone();
+        self.push(target, Local::now(), tags, settings, network, Vec::new(), Vec::new(), true)
+    }
+
+    /// Adds an already finished trace, e.g. one loaded from history.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show(
+        &mut self,
+        target: String,
+        started: DateTime<Local>,
+        tags: Vec<String>,
+        settings: TraceSettings,
+        network: Option<NetworkContext>,
+        nodes: Vec<(usize, TraceNode)>,
+        timeouts: Vec<usize>,
+    ) -> usize {
+        self.push(target, started, tags, settings, network, nodes, timeouts, false)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push(
+        &mut self,
+        target: String,
+        started: DateTime<Local>,
+        tags: Vec<String>,
+        settings: TraceSettings,
+        network: Option<NetworkContext>,
+        nodes: Vec<(usize, TraceNode)>,
+        timeouts: Vec<usize>,
+        tracing: bool,
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.traces.push(Trace {
+            id,
+            run: id,
+            target,
+            started,
+            tags,
+            settings,
+            network,
+            color: self.style.palette.trace_color(id),
+            visible: true,
+            visible_secondary: true,
+            nodes,
+            timeouts,
+            tracing,
+            resolving: false,
+            progress_at: Instant::now(),
+            probing_since: Instant::now(),
+            probing: None,
+            address: None,
+            summary: Vec::new(),
+            errors: Vec::new(),
+            interrupted: false,
+            relocating: false,
+            flows: HashMap::new(),
+            deviations: None,
+            route_changes: BTreeSet::new(),
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        self.traces.retain(|t| t.id != id);
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Trace> {
+        self.traces.iter_mut().find(|t| t.id == id)
+    }
+
+    /// The trace whose current run sends events as `run`.
+    pub fn by_run(&mut self, run: usize) -> Option<&mut Trace> {
+        self.traces.iter_mut().find(|t| t.run == run)
+    }
+
+    pub fn style(&self) -> MarkerStyle {
+        self.style
+    }
+
+    /// Changes how traces are drawn, recoloring the existing ones for a new palette.
+    pub fn set_style(&mut self, style: MarkerStyle) {
+        if style.palette != self.style.palette {
+            for trace in &mut self.traces {
+                trace.color = style.palette.trace_color(trace.id);
+            }
+        }
+        self.style = style;
+    }
+
+    /// Stops a single trace where it is, so it can be resumed after its last hop.
+    pub fn stop(&mut self, id: usize) {
+        let Some(trace) = self.get_mut(id).filter(|t| t.tracing) else {
             return;
+        };
+        trace.tracing = false;
+        trace.resolving = false;
+        trace.interrupted = true;
+        trace.errors.push(trf("Stopped after {} s without new hops", &[&trace.progress_at.elapsed().as_secs()]));
+    }
+
+    /// Stops waiting for running traces, whatever they still find is dropped.
+    pub fn cancel(&mut self) {
+        for trace in self.traces.iter_mut().filter(|t| t.tracing) {
+            trace.tracing = false;
         }
+    }
 
-        let painter = ui.painter();
-        let mut last_screen_pos = None;
-        let screen_rect = ui.clip_rect();
-        let mut arrow_segments = Vec::new();
+    /// Starts every pinned target again, with the settings of its latest trace.
+    pub fn rerun_pinned(&mut self) {
+        for target in &self.pinned {
+            let latest = self.traces.iter().rev().find(|t| t.target == *target);
+            self.start_requests.push(StartRequest {
+                target: target.clone(),
+                tags: latest.map(|t| t.tags.clone()).unwrap_or_default(),
+                settings: latest.map(|t| t.settings),
+            });
+        }
+    }
 
-        for (idx, node) in &self.nodes {
-            let screen_pos = projector.project(node.position).to_pos2();
-            
-            // Determine node color based on position
-            let (fill_color, stroke_color) = if *idx == 0 {
-                (Color32::GREEN, Color32::DARK_GREEN)
-            } else if *idx == self.nodes.len() - 1 {
-                (Color32::RED, Color32::DARK_RED)
-            } else {
-                (Color32::YELLOW, Color32::from_rgb(180, 180, 0))
+    /// Replaces a trace with a new one to the same target, started the same way.
+    pub fn retry(&mut self, id: usize) {
+        let Some(trace) = self.traces.iter().find(|t| t.id == id) else {
+            return;
+        };
+        self.start_requests.push(StartRequest {
+            target: trace.target.clone(),
+            tags: trace.tags.clone(),
+            settings: Some(trace.settings),
+        });
+        self.remove(id);
+    }
+
+    /// Probes an interrupted trace again after its last hop, keeping the hops it has.
+    pub fn resume(&mut self, id: usize) {
+        let Some(trace) = self.traces.iter_mut().find(|t| t.id == id && t.interrupted && !t.tracing) else {
+            return;
+        };
+        trace.interrupted = false;
+        trace.errors.clear();
+        trace.tracing = true;
+        // Ids are never reused, so a new one from the same counter can't clash with a trace
+        trace.run = self.next_id;
+        self.next_id += 1;
+        trace.probing_since = Instant::now();
+        trace.probing = None;
+        self.resume_requests.push(id);
+    }
+
+    /// Looks up the hops of a finished trace the provider had no location for, once more.
+    pub fn retry_lookups(&mut self, id: usize) {
+        let Some(trace) = self.get_mut(id).filter(|t| !t.tracing && !t.relocating && t.pending() > 0) else {
+            return;
+        };
+        trace.relocating = true;
+        self.lookup_requests.push(id);
+    }
+
+    pub fn tracing(&self) -> bool {
+        self.traces.iter().any(|t| t.tracing)
+    }
+
+    /// Map plugin drawing the traces visible in the given view.
+    pub fn layer(&mut self, secondary: bool) -> TraceLayer<'_> {
+        let second_view = secondary;
+        let secondary = secondary && self.separate_selection;
+        TraceLayer { set: self, secondary, second_view }
+    }
+
+    /// Selects the next hop of the shown traces on Tab and the right and down arrows, the
+    /// previous one with Shift and the others, and moves the map to it if it's out of view.
+    fn step(&mut self, ui: &Ui, response: &Response, secondary: bool, second_view: bool, projector: &Projector) {
+        use egui::{Key, Modifiers};
+
+        // Keep Tab and the arrows on the map, Esc still leaves it
+        let filter = egui::EventFilter {
+            tab: true,
+            horizontal_arrows: true,
+            vertical_arrows: true,
+            escape: false,
+        };
+        ui.memory_mut(|m| m.set_focus_lock_filter(response.id, filter));
+        let (previous, next) = ui.input_mut(|i| {
+            // Shift+Tab first, plain Tab would take it too
+            let previous = i.consume_key(Modifiers::SHIFT, Key::Tab)
+                || i.consume_key(Modifiers::NONE, Key::ArrowLeft)
+                || i.consume_key(Modifiers::NONE, Key::ArrowUp);
+            let next = i.consume_key(Modifiers::NONE, Key::Tab)
+                || i.consume_key(Modifiers::NONE, Key::ArrowRight)
+                || i.consume_key(Modifiers::NONE, Key::ArrowDown);
+            (previous, next)
+        });
+        if !previous && !next {
+            return;
+        }
+
+        let show_private = self.show_private;
+        let stops: Vec<(usize, usize, Position)> = self
+            .traces
+            .iter()
+            .filter(|t| t.shown(secondary))
+            .flat_map(|t| {
+                t.nodes
+                    .iter()
+                    .filter(move |(_, node)| show_private || !node.private)
+                    .map(move |(ttl, node)| (t.id, *ttl, node.position.into()))
+            })
+            .collect();
+        if stops.is_empty() {
+            return;
+        }
+        let current = self
+            .selected
+            .and_then(|selected| stops.iter().position(|(id, ttl, _)| (*id, *ttl) == selected));
+        let index = match (current, next) {
+            (Some(i), true) => (i + 1) % stops.len(),
+            (Some(i), false) => (i + stops.len() - 1) % stops.len(),
+            (None, true) => 0,
+            (None, false) => stops.len() - 1,
+        };
+        let (id, ttl, position) = stops[index];
+        self.selected = Some((id, ttl));
+        self.expanded = None;
+        if !response.rect.contains(projector.project(position).to_pos2()) {
+            self.center_request = Some((position, second_view));
+        }
+    }
+
+    /// What screen readers call a map, the selected hop while there is one.
+    fn map_name(&self) -> String {
+        let selected = self.selected.and_then(|(id, ttl)| {
+            let trace = self.traces.iter().find(|t| t.id == id)?;
+            let (_, node) = trace.nodes.iter().find(|(idx, _)| *idx == ttl)?;
+            Some(accessibility::describe_hop(&trace.target, ttl, node))
+        });
+        selected.unwrap_or_else(|| tr("Map. Tab and the arrow keys go through the hops, Esc leaves").to_string())
+    }
+}
+
+impl Trace {
+    pub fn apply(&mut self, event: TraceEvent) {
+        if matches!(
+            event,
+            TraceEvent::Resolved(_)
+                | TraceEvent::Node(..)
+                | TraceEvent::Timeout(_)
+                | TraceEvent::Probing(_)
+                | TraceEvent::Hostname(..)
+        ) {
+            self.progress_at = Instant::now();
+        }
+        match event {
+            TraceEvent::Resolving => self.resolving = true,
+            TraceEvent::Resolved(address) => {
+                self.resolving = false;
+                self.address = Some(address);
+            }
+            TraceEvent::Node(hop, node) => self.nodes.push((hop, *node)),
+            TraceEvent::Timeout(hop) => self.timeouts.push(hop),
+            TraceEvent::Probing(ttl) => self.probing = Some(ttl),
+            TraceEvent::Hostname(hop, name) => {
+                if let Some((_, node)) = self.nodes.iter_mut().find(|(idx, _)| *idx == hop) {
+                    node.resolving = false;
+                    if let Some(name) = name {
+                        node.hostname = name;
+                    }
+                }
+            }
+            TraceEvent::Summary(lines) => self.summary = lines,
+            TraceEvent::Located(located) => {
+                self.relocating = false;
+                for (_, node) in self.nodes.iter_mut().filter(|(_, node)| node.pending) {
+                    if let Some((_, location)) = located.iter().find(|(ip, _)| *ip == node.ip) {
+                        node.locate(location);
+                    }
+                }
+            }
+            TraceEvent::Error(error) => self.errors.push(error),
+            TraceEvent::Interrupted(error) => {
+                self.errors.push(error);
+                self.interrupted = true;
+            }
+            TraceEvent::Finish => {
+                self.tracing = false;
+                self.resolving = false;
+            }
+        }
+    }
+
+    /// Hops still waiting for a location.
+    pub fn pending(&self) -> usize {
+        self.nodes.iter().filter(|(_, node)| node.pending).count()
+    }
+
+    /// Still running but nothing new came in for `after`, never for a zero `after`.
+    /// Resolving has a timeout of its own, so only probing can stall.
+    pub fn stalled(&self, after: Duration) -> bool {
+        self.tracing && !self.resolving && !after.is_zero() && self.progress_at.elapsed() >= after
+    }
+
+    /// Hops grouped into the markers drawn on the map.
+    fn markers(&self, simplify: bool, show_private: bool) -> Vec<Vec<&(usize, TraceNode)>> {
+        let nodes: Vec<_> = self.nodes.iter().filter(|(_, n)| show_private || !n.private).collect();
+        if simplify {
+            nodes
+                .chunk_by(|(_, a), (_, b)| geo::distance_km(a.position, b.position) < SIMPLIFY_KM)
+                .map(<[_]>::to_vec)
+                .collect()
+        } else {
+            nodes.into_iter().map(|node| vec![node]).collect()
+        }
+    }
+
+    /// Markers with their screen positions. Private hops have the position of the hop
+    /// before them, so they're pushed off to the side to stay visible. When clustering,
+    /// markers that land on top of the one before them join it.
+    fn marker_positions(
+        &self,
+        simplify: bool,
+        show_private: bool,
+        cluster: bool,
+        projector: &Projector,
+    ) -> Vec<(Vec<&(usize, TraceNode)>, Pos2)> {
+        let mut markers: Vec<(Vec<&(usize, TraceNode)>, Pos2)> = Vec::new();
+        let mut last = None;
+        for group in self.markers(simplify, show_private) {
+            let node = &group[0].1;
+            let pos = match last {
+                Some(last) if node.guessed() => last + PRIVATE_OFFSET,
+                _ => projector.project(node.position.into()).to_pos2(),
             };
-            
-            // Draw point with position-based colors
-            painter.circle_filled(
-                screen_pos,
-                5.0,
-                fill_color,
-            );
-            painter.circle_stroke(
-                screen_pos,
-                5.0,
-                Stroke::new(1.0, stroke_color),
-            );
-            painter.text(
-                screen_pos + vec2(7.0, -7.0),
-                Align2::LEFT_TOP,
-                idx.to_string(),
-                FontId::monospace(12.0),
-                Color32::RED,
-            );
-
-            // Draw line to previous point
-            if let Some(last_pos) = last_screen_pos {
-                // Draw full line segment always
-                painter.line_segment(
-                    [last_pos, screen_pos],
-                    Stroke::new(2.0, Color32::RED),
+            last = Some(pos);
+            match markers.last_mut() {
+                Some((hops, at)) if cluster && at.distance(pos) < CLUSTER_PIXELS => hops.extend(group),
+                _ => markers.push((group, pos)),
+            }
+        }
+        markers
+    }
+
+    /// Flow volume seen by any of the hops in a marker.
+    fn volume(&self, group: &[&(usize, TraceNode)]) -> Option<Volume> {
+        group
+            .iter()
+            .filter_map(|(_, node)| self.flows.get(&node.ip.parse().ok()?))
+            .fold(None, |total: Option<Volume>, volume| {
+                let total = total.unwrap_or_default();
+                Some(Volume {
+                    bytes: total.bytes + volume.bytes,
+                    packets: total.packets + volume.packets,
+                    flows: total.flows + volume.flows,
+                })
+            })
+    }
+
+    fn shown(&self, secondary: bool) -> bool {
+        if secondary {
+            self.visible_secondary
+        } else {
+            self.visible
+        }
+    }
+}
+
+pub struct TraceLayer<'a> {
+    set: &'a mut TraceSet,
+    /// Whether to draw the traces picked for the second view
+    secondary: bool,
+    /// Whether this is the second view, which it can be with one selection for both
+    second_view: bool,
+}
+
+impl Plugin for TraceLayer<'_> {
+    fn run(self: Box<Self>, ui: &mut Ui, response: &Response, projector: &Projector) {
+        let (secondary, second_view) = (self.secondary, self.second_view);
+        let set = self.set;
+        // Clip to the map itself, there may be more than one on screen
+        let screen_rect = response.rect.intersect(ui.clip_rect());
+        let painter = ui.painter_at(screen_rect);
+        if response.has_focus() {
+            set.step(ui, response, secondary, second_view, projector);
+            painter.rect_stroke(screen_rect.shrink(1.0), 0.0, Stroke::new(2.0, ui.visuals().selection.bg_fill));
+        }
+        accessibility::live(response, &set.map_name());
+        let style = set.style.effective();
+        if style.high_contrast {
+            // Filters the tiles of any provider, so paths stand out from them
+            painter.rect_filled(screen_rect, 0.0, Color32::BLACK.gamma_multiply(style.tile_dim));
+        }
+        for fence in set.geofences.iter().filter(|f| f.enabled && f.points.len() >= 3) {
+            draw_fence(&painter, projector, &fence.corners(), Some(&fence.name));
+        }
+        if set.traces.iter().all(|t| !t.shown(secondary) || t.nodes.is_empty()) {
+            return;
+        }
+        let radius = style.marker_size;
+        let outline = if style.high_contrast { 2.5 } else { 1.0 };
+
+        for trace in set.traces.iter().filter(|t| t.shown(secondary)) {
+            let mut last_screen_pos = None;
+            let mut arrow_segments = Vec::new();
+
+            let mut last_guessed = false;
+            let mut last_hop = None;
+            let mut last_volume: Option<Volume> = None;
+            // Last country code seen, private hops have none and are skipped over
+            let mut last_country: Option<&str> = None;
+            for (group, screen_pos) in trace.marker_positions(set.simplify, set.show_private, set.cluster, projector) {
+                let (idx, node) = group[0];
+                let last_idx = group[group.len() - 1].0;
+                // Hops that timed out between this marker and the previous one
+                let gap = last_hop.is_some_and(|last| trace.timeouts.iter().any(|t| (last..*idx).contains(t)));
+
+                // Determine node color based on position
+                let (fill_color, stroke_color) = if *idx == 0 {
+                    style.palette.source()
+                } else if Some(last_idx) == trace.nodes.last().map(|(idx, _)| *idx) {
+                    style.palette.destination()
+                } else {
+                    (trace.color, trace.color.gamma_multiply(0.7))
+                };
+                let dimmed = !set.hop_filter.trim().is_empty()
+                    && !group.iter().any(|(_, node)| node.matches(&set.hop_filter));
+                let dim = |color: Color32| if dimmed { color.gamma_multiply(DIMMED) } else { color };
+                let (fill_color, stroke_color) = (dim(fill_color), dim(stroke_color));
+
+                // Draw point with position-based colors
+                painter.circle_filled(
+                    screen_pos,
+                    radius,
+                    fill_color,
                 );
-                
-                // Early culling - check if line segment is completely outside view
-                let line_rect = egui::Rect::from_two_pos(last_pos, screen_pos);
-                if screen_rect.intersects(line_rect) {
-                    let direction = screen_pos - last_pos;
-                    if direction.length() > 0.0 {
-                        // Find intersection points with screen rect
-                        let start = line_rect_intersection(last_pos, screen_pos, screen_rect);
-                        
-                        if let Some((vis_start, vis_end)) = start {
-                            let vis_direction = vis_end - vis_start;
-                            let vis_length = vis_direction.length();
-                            
-                            if vis_length > 0.0 {
-                                let dir_normalized = vis_direction.normalized();
-                                let arrow_size = 5.0;
-                                let arrow_spacing = 30.0;
-                                let num_arrows = (vis_length / arrow_spacing).floor() as i32;
-
-                                // Precalculate arrow properties
-                                let arrow_dir = dir_normalized * arrow_size;
-                                let perp = arrow_dir.rot90();
-
-                                // Calculate all arrow positions along visible segment
-                                for i in 0..num_arrows {
-                                    let t = (i as f32 + 1.0) / (num_arrows + 1) as f32;
-                                    let arrow_pos = vis_start + vis_direction * t;
-                                    
-                                    // Define arrow polygon points
-                                    arrow_segments.push(vec![
-                                        arrow_pos - arrow_dir + perp,  // Left wing
-                                        arrow_pos,                     // Tip
-                                        arrow_pos - arrow_dir - perp,  // Right wing
-                                    ]);
+                let stroke_color = if style.high_contrast { dim(Color32::WHITE) } else { stroke_color };
+                painter.circle_stroke(
+                    screen_pos,
+                    radius,
+                    Stroke::new(outline, stroke_color),
+                );
+                // Ring around hops that left the baseline
+                let deviates = trace
+                    .deviations
+                    .as_ref()
+                    .is_some_and(|d| group.iter().any(|(idx, _)| d.contains_key(idx)));
+                if deviates {
+                    painter.circle_stroke(screen_pos, radius + 4.0, Stroke::new(2.0, DEVIATION_COLOR));
+                }
+                // Outside the baseline ring, a hop can be both
+                if group.iter().any(|(idx, _)| trace.route_changes.contains(idx)) {
+                    painter.circle_stroke(screen_pos, radius + 6.5, Stroke::new(2.0, ROUTE_CHANGE_COLOR));
+                }
+                let fenced = !geofence::breaches(&set.geofences, group.iter().map(|(idx, node)| (*idx, node.position))).is_empty();
+                if fenced {
+                    painter.circle_stroke(screen_pos, radius + 9.0, Stroke::new(2.0, GEOFENCE_COLOR));
+                }
+                let stalled = trace.stalled(set.stall_after) && Some(last_idx) == trace.nodes.last().map(|(idx, _)| *idx);
+                if stalled {
+                    painter.circle_stroke(screen_pos, radius + 5.0, Stroke::new(2.5, STALLED_COLOR));
+                }
+                let highlighted = set
+                    .highlight
+                    .is_some_and(|(id, ttl)| id == trace.id && group.iter().any(|(idx, _)| *idx == ttl));
+                if highlighted {
+                    painter.circle_stroke(
+                        screen_pos,
+                        radius + 7.0,
+                        Stroke::new(3.0, ui.visuals().strong_text_color()),
+                    );
+                }
+                let selected = set
+                    .selected
+                    .is_some_and(|(id, ttl)| id == trace.id && group.iter().any(|(idx, _)| *idx == ttl));
+                if selected {
+                    painter.circle_stroke(screen_pos, radius + 3.0, Stroke::new(2.0, ui.visuals().selection.bg_fill));
+                }
+                let city = &group[0].1.city;
+                let label = if set.city_labels && !city.is_empty() {
+                    city.clone()
+                } else if group.len() > 1 {
+                    format!("{}-{}", idx, last_idx)
+                } else {
+                    idx.to_string()
+                };
+                let label_pos = screen_pos + vec2(radius + 2.0, -radius - 2.0);
+                if style.high_contrast {
+                    // Drop shadow, so labels stay legible over any tile
+                    painter.text(
+                        label_pos + vec2(1.5, 1.5),
+                        Align2::LEFT_TOP,
+                        &label,
+                        FontId::monospace(style.label_size()),
+                        Color32::BLACK,
+                    );
+                }
+                painter.text(
+                    label_pos,
+                    Align2::LEFT_TOP,
+                    label,
+                    FontId::monospace(style.label_size()),
+                    dim(trace.color),
+                );
+
+                if set.sparklines {
+                    if let Some(rtts) = set.hop_rtts.rtts(&node.ip).filter(|rtts| rtts.len() > 1) {
+                        let rect = egui::Rect::from_min_size(
+                            screen_pos + vec2(radius + 2.0, radius + 2.0),
+                            SPARKLINE_SIZE,
+                        );
+                        sparkline(&painter, rect, rtts, trace.color);
+                    }
+                }
+
+                // Count badge for collapsed hops
+                if group.len() > 1 {
+                    let badge_pos = screen_pos + vec2(-radius - 2.0, -radius - 2.0);
+                    painter.circle_filled(badge_pos, 6.0, Color32::BLACK);
+                    painter.text(
+                        badge_pos,
+                        Align2::CENTER_CENTER,
+                        group.len().to_string(),
+                        FontId::proportional(9.0),
+                        Color32::WHITE,
+                    );
+                }
+
+                // Draw line to previous point
+                if let Some(last_pos) = last_screen_pos {
+                    let segment_color = match node.asn {
+                        Some(asn) if set.color_by_as => as_color(asn),
+                        _ => trace.color,
+                    };
+
+                    if style.high_contrast {
+                        painter.line_segment(
+                            [last_pos, screen_pos],
+                            Stroke::new(style.line_width + 2.0 * outline, Color32::BLACK),
+                        );
+                    }
+                    // Draw full line segment always, dashed where we only guess the location
+                    // or hops in between didn't answer
+                    if node.guessed() || last_guessed || gap {
+                        painter.extend(egui::Shape::dashed_line(
+                            &[last_pos, screen_pos],
+                            Stroke::new(style.line_width, segment_color),
+                            4.0,
+                            3.0,
+                        ));
+                    } else {
+                        painter.line_segment(
+                            [last_pos, screen_pos],
+                            Stroke::new(style.line_width, segment_color),
+                        );
+                    }
+
+                    // Tick across the middle of the segment, labeled with the country it enters
+                    let entered = match last_country {
+                        Some(last) if set.border_ticks && !node.country_code.is_empty() => {
+                            (last != node.country_code).then_some(&node.country_code)
+                        }
+                        _ => None,
+                    };
+                    if let Some(code) = entered.filter(|_| last_pos != screen_pos) {
+                        let middle = last_pos.lerp(screen_pos, 0.5);
+                        let across = (screen_pos - last_pos).normalized().rot90() * (style.line_width + 4.0);
+                        painter.line_segment(
+                            [middle - across, middle + across],
+                            Stroke::new(style.line_width.max(1.5), ui.visuals().strong_text_color()),
+                        );
+                        painter.text(
+                            middle + across,
+                            Align2::LEFT_CENTER,
+                            code,
+                            FontId::monospace(style.label_size()),
+                            ui.visuals().strong_text_color(),
+                        );
+                    }
+
+                    // Segments into an exchange fabric are where the networks peer
+                    let exchange = group.iter().find_map(|(_, node)| ixp::lookup(&node.ip));
+                    if let Some(name) = exchange.filter(|_| last_pos != screen_pos) {
+                        painter.text(
+                            last_pos.lerp(screen_pos, 0.5),
+                            Align2::CENTER_TOP,
+                            trf("via {}", &[&name]),
+                            FontId::proportional(style.label_size()),
+                            segment_color,
+                        );
+                    }
+
+                    // Traffic the previous hop forwarded along this segment
+                    if let Some(volume) = last_volume {
+                        painter.text(
+                            last_pos.lerp(screen_pos, 0.5),
+                            Align2::CENTER_BOTTOM,
+                            flows::format_bytes(volume.bytes),
+                            FontId::proportional(11.0),
+                            segment_color,
+                        );
+                    }
+
+                    // Early culling - check if line segment is completely outside view
+                    let line_rect = egui::Rect::from_two_pos(last_pos, screen_pos);
+                    if style.arrow_density > 0.0 && screen_rect.intersects(line_rect) {
+                        let direction = screen_pos - last_pos;
+                        if direction.length() > 0.0 {
+                            // Find intersection points with screen rect
+                            let start = line_rect_intersection(last_pos, screen_pos, screen_rect);
+
+                            if let Some((vis_start, vis_end)) = start {
+                                let vis_direction = vis_end - vis_start;
+                                let vis_length = vis_direction.length();
+
+                                if vis_length > 0.0 {
+                                    let dir_normalized = vis_direction.normalized();
+                                    let arrow_size = 1.5 + style.line_width * 1.75;
+                                    let arrow_spacing = 100.0 / style.arrow_density;
+                                    let num_arrows = (vis_length / arrow_spacing).floor() as i32;
+
+                                    // Precalculate arrow properties
+                                    let arrow_dir = dir_normalized * arrow_size;
+                                    let perp = arrow_dir.rot90();
+
+                                    // Calculate all arrow positions along visible segment
+                                    for i in 0..num_arrows {
+                                        let t = (i as f32 + 1.0) / (num_arrows + 1) as f32;
+                                        let arrow_pos = vis_start + vis_direction * t;
+
+                                        // Define arrow polygon points
+                                        arrow_segments.push(vec![
+                                            arrow_pos - arrow_dir + perp,  // Left wing
+                                            arrow_pos,                     // Tip
+                                            arrow_pos - arrow_dir - perp,  // Right wing
+                                        ]);
+                                    }
                                 }
                             }
                         }
                     }
                 }
+
+                last_screen_pos = Some(screen_pos);
+                last_guessed = node.guessed();
+                last_hop = Some(last_idx);
+                last_volume = trace.volume(&group);
+                if let Some((_, node)) = group.iter().rev().find(|(_, node)| !node.country_code.is_empty()) {
+                    last_country = Some(&node.country_code);
+                }
             }
-            
-            last_screen_pos = Some(screen_pos);
-        }
 
-        // Batch draw all arrow polygons at once
-        if !arrow_segments.is_empty() {
-            painter.add(egui::Shape::Vec(
-                arrow_segments.into_iter()
-                    .map(|points| egui::Shape::convex_polygon(
-                        points,
-                        Color32::RED,
-                        Stroke::NONE,
-                    ))
-                    .collect()
-            ));
+            // Batch draw all arrow polygons at once
+            if !arrow_segments.is_empty() {
+                painter.add(egui::Shape::Vec(
+                    arrow_segments.into_iter()
+                        .map(|points| egui::Shape::convex_polygon(
+                            points,
+                            trace.color,
+                            Stroke::NONE,
+                        ))
+                        .collect()
+                ));
+            }
         }
 
         // Handle hover tooltips
-        let hover_pos = ui.input(|i| i.pointer.hover_pos());
+        let hover_pos = ui
+            .input(|i| i.pointer.hover_pos())
+            .filter(|pos| screen_rect.contains(*pos))
+            // Not through the cluster list or windows on top of the map
+            .filter(|_| response.contains_pointer());
         if let Some(mouse_pos) = hover_pos {
-            for (idx, node) in &self.nodes {
-                let screen_pos = projector.project(node.position).to_pos2();
-                if mouse_pos.distance(screen_pos) < 10.0 {
-                    let tooltip_id = egui::Id::new("trace_tooltip");
-                    let layer_id = egui::LayerId::new(egui::Order::Tooltip, tooltip_id);
-                    
-                    // Get simple timer state
-                    let show_copied = if let Some(start_time) = self.copy_anim_time {
-                        let now = ui.input(|i| i.time);
-                        let age = (now - start_time) as f32;
-                        if age > 1.0 {
-                            self.copy_anim_time = None;
-                            false
-                        } else {
-                            true
-                        }
-                    } else {
-                        false
-                    };
-                    
-                    egui::show_tooltip(
-                        ui.ctx(),
-                        layer_id,
-                        tooltip_id,
-                        |ui| {
-                            ui.set_min_width(0.0);
-                            ui.spacing_mut().item_spacing.y = 2.0;
-                            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-                                let heading = egui::RichText::new(format!("#{}", idx))
-                                    .heading()
-                                    .size(16.0);
-                                ui.label(heading);
-                                ui.add_space(2.0);
-                                
-                                let text_style = egui::TextStyle::Body;
-                                ui.style_mut().text_styles.get_mut(&text_style)
-                                    .map(|font| font.size = 13.0);
-                                
-                                ui.label(format!("Host: {}", node.hostname));
-                                ui.label(format!("IP: {}", node.ip));
-                                ui.label(format!("ISP: {}", node.isp));
-                                
-                                // Show copy feedback with simple timer
-                                let copy_text = if show_copied {
-                                    egui::RichText::new("Copied!")
-                                        .color(Color32::GREEN)
-                                        .size(14.0)
-                                } else {
-                                    egui::RichText::new("Click to copy IP")
+            let show_target = set.traces.iter().filter(|t| t.shown(secondary)).count() > 1;
+            let (simplify, show_private, cluster) = (set.simplify, set.show_private, set.cluster);
+            let hovered = set
+                .traces
+                .iter()
+                .filter(|t| t.shown(secondary))
+                .flat_map(|t| {
+                    t.marker_positions(simplify, show_private, cluster, projector)
+                        .into_iter()
+                        .map(move |marker| (t, marker))
+                })
+                .find(|(_, (_, pos))| mouse_pos.distance(*pos) < (radius * 2.0).max(10.0));
+
+            if let Some((trace, (group, _))) = hovered {
+                let (idx, node) = group[0];
+                let tooltip_id = egui::Id::new("trace_tooltip");
+                let layer_id = egui::LayerId::new(egui::Order::Tooltip, tooltip_id);
+
+                egui::show_tooltip(
+                    ui.ctx(),
+                    layer_id,
+                    tooltip_id,
+                    |ui| {
+                        ui.set_min_width(0.0);
+                        ui.spacing_mut().item_spacing.y = 2.0;
+                        ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                            let heading = if group.len() > 1 {
+                                format!("#{}-{}", idx, group[group.len() - 1].0)
+                            } else {
+                                format!("#{}", idx)
+                            };
+                            ui.label(egui::RichText::new(heading).heading().size(16.0));
+                            if show_target {
+                                ui.label(egui::RichText::new(&trace.target).color(trace.color));
+                            }
+                            ui.add_space(2.0);
+
+                            let text_style = egui::TextStyle::Body;
+                            if let Some(font) = ui.style_mut().text_styles.get_mut(&text_style) {
+                                font.size = 13.0;
+                            }
+
+                            if group.len() > 1 {
+                                ui.label(trf("{} hops here", &[&group.len()]));
+                                cluster_list(ui, &group);
+                                ui.label(
+                                    RichText::new(tr("Click to keep the list open"))
                                         .color(Color32::GRAY)
-                                        .size(14.0)
-                                };
-                                ui.label(copy_text);
+                                        .size(14.0),
+                                );
+                                return;
+                            }
+
+                            if node.resolving {
+                                ui.label(RichText::new(tr("Host: resolving…")).italics().weak());
+                            } else {
+                                ui.label(trf("Host: {}", &[&node.hostname]));
+                            }
+                            ui.label(trf("IP: {}", &[&node.ip]));
+                            let place = node.place();
+                            if !place.is_empty() {
+                                let flag = summary::flag(&node.country_code);
+                                ui.label(format!("{} {}", flag, place).trim());
+                            }
+                            ui.label(trf("ISP: {}", &[&node.isp]));
+                            if let Some(asn) = node.asn {
+                                ui.label(format!("AS: AS{} {}", asn, node.as_name));
+                            }
+                            if !node.prefix.is_empty() {
+                                ui.label(trf("Prefix: {}", &[&node.prefix]));
+                            }
+
+                            ui.label(
+                                RichText::new(tr("Click for details, right-click to watch or bookmark"))
+                                    .color(Color32::GRAY)
+                                    .size(14.0),
+                            );
+                        });
+                    }
+                );
+
+                if ui.input(|i| i.pointer.primary_clicked()) {
+                    let clicked = (trace.id, *idx);
+                    if group.len() > 1 {
+                        set.expanded = (set.expanded != Some(clicked)).then_some(clicked);
+                    } else {
+                        set.selected = (set.selected != Some(clicked)).then_some(clicked);
+                    }
+                }
+                if response.secondary_clicked() {
+                    set.context_node = Some((node.ip.clone(), node.hostname.clone(), trace.target.clone()));
+                }
+            } else {
+                if response.clicked() {
+                    set.expanded = None;
+                    set.selected = None;
+                }
+                if response.secondary_clicked() {
+                    set.context_node = None;
+                }
+            }
+        }
+
+        // Hops of the cluster clicked on, until clicking elsewhere on the map. Zooming in
+        // far enough to split the cluster closes it too.
+        if let Some((id, first)) = set.expanded {
+            let expanded = set
+                .traces
+                .iter()
+                .filter(|t| t.id == id && t.shown(secondary))
+                .flat_map(|t| t.marker_positions(set.simplify, set.show_private, set.cluster, projector))
+                .find(|(group, _)| group.len() > 1 && group[0].0 == first);
+            match expanded {
+                Some((group, pos)) if screen_rect.contains(pos) => {
+                    let mut picked = None;
+                    egui::Area::new(egui::Id::new("trace_cluster").with(secondary))
+                        .fixed_pos(pos + vec2(radius + 4.0, radius + 4.0))
+                        .order(egui::Order::Foreground)
+                        .show(ui.ctx(), |ui| {
+                            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.strong(trf("{} hops", &[&group.len()]));
+                                    if ui.small_button("✕").described(tr("Close")).clicked() {
+                                        set.expanded = None;
+                                    }
+                                });
+                                picked = cluster_list(ui, &group);
                             });
-                        }
-                    );
-                    
-                    if ui.input(|i| i.pointer.any_click()) {
-                        ui.output_mut(|o| o.copied_text = node.ip.clone());
-                        self.copy_anim_time = Some(ui.input(|i| i.time));
-                        ui.ctx().request_repaint();
+                        });
+                    if let Some(ttl) = picked {
+                        set.selected = Some((id, ttl));
                     }
-                    break;
                 }
+                _ => set.expanded = None,
             }
         }
+
+        response.context_menu(|ui| {
+            let Some((ip, hostname, target)) = set.context_node.clone() else {
+                ui.close_menu();
+                return;
+            };
+            ui.label(RichText::new(&ip).strong());
+            copy_menu(ui, &ip, &hostname, &mut set.copy_port, &set.toasts);
+            ui.separator();
+            if ui.button(tr("WHOIS…")).clicked() {
+                set.whois_request = Some(ip.clone());
+                ui.close_menu();
+            }
+            if ui.button(tr("Interface (SNMP)…")).clicked() {
+                set.snmp_request = Some(ip.clone());
+                ui.close_menu();
+            }
+            ui.separator();
+            if ui.button(tr("👁 Watch this hop")).on_hover_text(tr("Trace it on a schedule")).clicked() {
+                set.watch_request = Some(ip);
+                ui.close_menu();
+            }
+            if ui.button(trf("★ Bookmark route to {}", &[&target])).clicked() {
+                set.bookmark_request = Some(target);
+                ui.close_menu();
+            }
+        });
+    }
+}
+
+/// One row per hop of a cluster, returns the TTL of the one clicked.
+fn cluster_list(ui: &mut Ui, group: &[&(usize, TraceNode)]) -> Option<usize> {
+    let mut clicked = None;
+    egui::Grid::new("cluster_hops").num_columns(3).show(ui, |ui| {
+        for (idx, node) in group {
+            ui.label(format!("#{}", idx));
+            if ui.link(&node.ip).on_hover_text(tr("Show details")).clicked() {
+                clicked = Some(*idx);
+            }
+            ui.label(RichText::new(&node.hostname).weak());
+            ui.end_row();
+        }
+    });
+    clicked
+}
+
+/// Copy buttons for every `CopyFormat` of a hop, for context menus.
+pub fn copy_menu(ui: &mut Ui, ip: &str, hostname: &str, port: &mut u16, toasts: &Topic<Toast>) {
+    for format in CopyFormat::ALL {
+        let text = format.render(ip, hostname, *port);
+        ui.horizontal(|ui| {
+            let button = ui.add_enabled(text.is_some(), egui::Button::new(tr(format.name())));
+            if let Some(text) = text {
+                if button.on_hover_text(&text).clicked() {
+                    toasts.publish(Toast::success(trf("Copied {}", &[&text])));
+                    ui.output_mut(|o| o.copied_text = text);
+                    ui.close_menu();
+                }
+            }
+            if format == CopyFormat::HostPort {
+                ui.add(egui::DragValue::new(port).range(1..=65535));
+            }
+        });
     }
 }
 
@@ -263,4 +1228,332 @@ fn line_rect_intersection(start: egui::Pos2, end: egui::Pos2, rect: egui::Rect)
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Size of the RTT sparklines next to markers.
+const SPARKLINE_SIZE: Vec2 = vec2(36.0, 12.0);
+
+/// RTTs as a line filling `rect`, from the fastest at the bottom to the slowest at the top.
+/// The latest one is dotted so the trend reads left to right.
+fn sparkline(painter: &egui::Painter, rect: egui::Rect, rtts: &VecDeque<f64>, color: Color32) {
+    let (min, max) = rtts.iter().fold((f64::MAX, f64::MIN), |(min, max), &rtt| (min.min(rtt), max.max(rtt)));
+    let range = (max - min).max(1.0);
+    let step = rect.width() / (rtts.len() - 1) as f32;
+    let points: Vec<Pos2> = rtts
+        .iter()
+        .enumerate()
+        .map(|(i, &rtt)| Pos2::new(rect.left() + i as f32 * step, rect.bottom() - ((rtt - min) / range) as f32 * rect.height()))
+        .collect();
+    painter.rect_filled(rect.expand(2.0), 2.0, Color32::BLACK.gamma_multiply(0.5));
+    if let Some(last) = points.last() {
+        painter.circle_filled(*last, 1.5, color);
+    }
+    painter.add(egui::Shape::line(points, Stroke::new(1.0, color)));
+}
+
+/// Stable, well spread color for an AS number.
+fn as_color(asn: u32) -> Color32 {
+    // Golden ratio steps keep neighbouring ASNs apart on the hue wheel
+    let hue = (asn as f32 * 0.618_034).fract();
+    egui::ecolor::Hsva::new(hue, 0.85, 0.9, 1.0).into()
+}
+
+/// Radius of the magnifier lens in points.
+const LENS_RADIUS: f32 = 90.0;
+
+/// Circular zoomed-in inset that follows the cursor, leaving the map zoom alone.
+pub struct Magnifier {
+    pub enabled: bool,
+    /// Zoom levels added on top of the magnified map's own zoom
+    pub boost: f64,
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            boost: 3.0,
+        }
+    }
+}
+
+impl Magnifier {
+    /// Draws the lens over `map` if the cursor is on it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show(
+        &self,
+        ui: &Ui,
+        map: &Response,
+        map_memory: &MapMemory,
+        my_position: Position,
+        tiles: &mut dyn Tiles,
+        trace_set: &TraceSet,
+        secondary: bool,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let Some(pointer) = map.hover_pos() else {
+            return;
+        };
+
+        let center = Projector::new(map.rect, map_memory, my_position)
+            .unproject(pointer - map.rect.center());
+        let mut memory = MapMemory::default();
+        memory.set_zoom((map_memory.zoom() + self.boost).min(19.0)).ok();
+
+        let rect = egui::Rect::from_center_size(pointer, vec2(LENS_RADIUS, LENS_RADIUS) * 2.0);
+        let layer_id = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("magnifier"));
+        let start = ui.ctx().graphics_mut(|g| g.entry(layer_id).next_idx());
+
+        egui::Area::new(layer_id.id)
+            .order(egui::Order::Foreground)
+            .fixed_pos(rect.min)
+            .interactable(false)
+            .show(ui.ctx(), |ui| {
+                ui.add_sized(
+                    rect.size(),
+                    Map::new(Some(tiles), &mut memory, center)
+                        .drag_gesture(false)
+                        .zoom_gesture(false)
+                        .double_click_to_zoom(false),
+                );
+            });
+
+        // The tiles come out as square meshes, cut them down to the lens
+        ui.ctx().graphics_mut(|g| {
+            let list = g.entry(layer_id);
+            for idx in start.0..list.next_idx().0 {
+                list.mutate_shape(egui::layers::ShapeIdx(idx), |clipped| {
+                    if let egui::Shape::Mesh(mesh) = &clipped.shape {
+                        clipped.shape = egui::Shape::Mesh(clip_mesh_to_circle(mesh, pointer, LENS_RADIUS));
+                    }
+                });
+            }
+        });
+
+        let painter = ui.ctx().layer_painter(layer_id).with_clip_rect(rect);
+        let projector = Projector::new(rect, &memory, center);
+        let secondary = secondary && trace_set.separate_selection;
+        for trace in trace_set.traces.iter().filter(|t| t.shown(secondary)) {
+            let points: Vec<_> = trace
+                .nodes
+                .iter()
+                .filter(|(_, node)| !node.guessed())
+                .map(|(_, node)| projector.project(node.position.into()).to_pos2())
+                .collect();
+            for segment in points.windows(2) {
+                if let Some(visible) = clip_segment_to_circle(segment[0], segment[1], pointer, LENS_RADIUS) {
+                    painter.line_segment(visible, Stroke::new(3.0, trace.color));
+                }
+            }
+            for point in points.iter().filter(|p| p.distance(pointer) < LENS_RADIUS - 6.0) {
+                painter.circle(*point, 6.0, trace.color, Stroke::new(1.5, Color32::BLACK));
+            }
+        }
+        painter.circle_stroke(pointer, LENS_RADIUS, Stroke::new(2.0, ui.visuals().strong_text_color()));
+    }
+}
+
+/// Clips every triangle of `mesh` against a polygon approximating the circle.
+fn clip_mesh_to_circle(mesh: &egui::Mesh, center: egui::Pos2, radius: f32) -> egui::Mesh {
+    const SIDES: usize = 64;
+    let circle: Vec<egui::Pos2> = (0..SIDES)
+        .map(|i| {
+            let angle = i as f32 / SIDES as f32 * std::f32::consts::TAU;
+            center + radius * vec2(angle.cos(), angle.sin())
+        })
+        .collect();
+
+    let mut out = egui::Mesh::with_texture(mesh.texture_id);
+    for triangle in mesh.indices.chunks_exact(3) {
+        let mut polygon: Vec<egui::epaint::Vertex> =
+            triangle.iter().map(|&i| mesh.vertices[i as usize]).collect();
+
+        // Sutherland-Hodgman against each edge of the (convex) circle polygon
+        for (i, &a) in circle.iter().enumerate() {
+            let b = circle[(i + 1) % SIDES];
+            let inside = |p: egui::Pos2| (b - a).x * (p - a).y - (b - a).y * (p - a).x >= 0.0;
+            let input = std::mem::take(&mut polygon);
+            for (j, &current) in input.iter().enumerate() {
+                let previous = input[(j + input.len() - 1) % input.len()];
+                let intersection = || {
+                    let edge = b - a;
+                    let d = current.pos - previous.pos;
+                    let denom = edge.x * d.y - edge.y * d.x;
+                    let t = if denom == 0.0 {
+                        0.0
+                    } else {
+                        (edge.y * (previous.pos.x - a.x) - edge.x * (previous.pos.y - a.y)) / denom
+                    };
+                    egui::epaint::Vertex {
+                        pos: previous.pos.lerp(current.pos, t),
+                        uv: previous.uv.lerp(current.uv, t),
+                        color: current.color,
+                    }
+                };
+                match (inside(previous.pos), inside(current.pos)) {
+                    (true, true) => polygon.push(current),
+                    (true, false) => polygon.push(intersection()),
+                    (false, true) => {
+                        polygon.push(intersection());
+                        polygon.push(current);
+                    }
+                    (false, false) => {}
+                }
+            }
+            if polygon.is_empty() {
+                break;
+            }
+        }
+
+        if polygon.len() >= 3 {
+            let base = out.vertices.len() as u32;
+            out.vertices.extend(polygon.iter().copied());
+            for k in 1..polygon.len() as u32 - 1 {
+                out.add_triangle(base, base + k, base + k + 1);
+            }
+        }
+    }
+    out
+}
+
+/// Part of the segment `a`-`b` that lies inside the circle, if any.
+fn clip_segment_to_circle(
+    a: egui::Pos2,
+    b: egui::Pos2,
+    center: egui::Pos2,
+    radius: f32,
+) -> Option<[egui::Pos2; 2]> {
+    let d = b - a;
+    let f = a - center;
+    let qa = d.dot(d);
+    if qa == 0.0 {
+        return None;
+    }
+    let qb = 2.0 * f.dot(d);
+    let qc = f.dot(f) - radius * radius;
+    let discriminant = qb * qb - 4.0 * qa * qc;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let t0 = ((-qb - root) / (2.0 * qa)).max(0.0);
+    let t1 = ((-qb + root) / (2.0 * qa)).min(1.0);
+    if t0 >= t1 {
+        return None;
+    }
+    Some([a + d * t0, a + d * t1])
+}
+
+/// Great-circle distances along points clicked on the map, e.g. to hold against the
+/// distance a trace covers.
+#[derive(Default)]
+pub struct Measure {
+    pub enabled: bool,
+    pub points: Vec<trace::Position>,
+}
+
+impl Measure {
+    /// Distance along all the points, in kilometers.
+    pub fn total_km(&self) -> f64 {
+        self.points.windows(2).map(|pair| geo::distance_km(pair[0], pair[1])).sum()
+    }
+
+    /// Map plugin taking clicks as points and drawing the measured path.
+    pub fn layer(&mut self) -> MeasureLayer<'_> {
+        MeasureLayer { measure: self }
+    }
+}
+
+/// Kilometers with a decimal for short distances.
+pub fn format_km(km: f64) -> String {
+    if km < 100.0 {
+        format!("{:.1} km", km)
+    } else {
+        format!("{:.0} km", km)
+    }
+}
+
+pub struct MeasureLayer<'a> {
+    measure: &'a mut Measure,
+}
+
+impl Plugin for MeasureLayer<'_> {
+    fn run(self: Box<Self>, ui: &mut Ui, response: &Response, projector: &Projector) {
+        let measure = self.measure;
+        if !measure.enabled {
+            return;
+        }
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+            measure.points.push(projector.unproject(pos - response.rect.center()).into());
+        }
+
+        let painter = ui.painter_at(response.rect.intersect(ui.clip_rect()));
+        let color = ui.visuals().warn_fg_color;
+        let screen: Vec<Pos2> = measure.points.iter().map(|p| projector.project((*p).into()).to_pos2()).collect();
+        painter.add(egui::Shape::dashed_line(&screen, Stroke::new(2.0, color), 6.0, 4.0));
+        let mut km = 0.0;
+        for (i, pos) in screen.iter().enumerate() {
+            painter.circle(*pos, 4.0, color, Stroke::new(1.0, Color32::BLACK));
+            if i > 0 {
+                km += geo::distance_km(measure.points[i - 1], measure.points[i]);
+                painter.text(
+                    *pos + vec2(6.0, -6.0),
+                    Align2::LEFT_BOTTOM,
+                    format_km(km),
+                    FontId::proportional(12.0),
+                    color,
+                );
+            }
+        }
+    }
+}
+
+/// Outlines the polygon with `corners`, with `name` at its first corner.
+/// Not filled, egui only fills convex shapes.
+fn draw_fence(painter: &egui::Painter, projector: &Projector, corners: &[trace::Position], name: Option<&str>) {
+    let screen: Vec<Pos2> = corners.iter().map(|p| projector.project((*p).into()).to_pos2()).collect();
+    painter.add(egui::Shape::closed_line(screen.clone(), Stroke::new(2.0, GEOFENCE_COLOR)));
+    if let (Some(name), Some(first)) = (name, screen.first()) {
+        painter.text(*first, Align2::LEFT_BOTTOM, name, FontId::proportional(12.0), GEOFENCE_COLOR);
+    }
+}
+
+/// A geofence being drawn, one corner per click on the map.
+#[derive(Default)]
+pub struct FenceDraft {
+    pub drawing: bool,
+    pub corners: Vec<trace::Position>,
+}
+
+impl FenceDraft {
+    pub fn layer(&mut self) -> FenceDraftLayer<'_> {
+        FenceDraftLayer { draft: self }
+    }
+}
+
+pub struct FenceDraftLayer<'a> {
+    draft: &'a mut FenceDraft,
+}
+
+impl Plugin for FenceDraftLayer<'_> {
+    fn run(self: Box<Self>, ui: &mut Ui, response: &Response, projector: &Projector) {
+        let draft = self.draft;
+        if !draft.drawing {
+            return;
+        }
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+            draft.corners.push(projector.unproject(pos - response.rect.center()).into());
+        }
+        let painter = ui.painter_at(response.rect.intersect(ui.clip_rect()));
+        if draft.corners.len() >= 3 {
+            draw_fence(&painter, projector, &draft.corners, None);
+        }
+        let screen: Vec<Pos2> = draft.corners.iter().map(|p| projector.project((*p).into()).to_pos2()).collect();
+        painter.add(egui::Shape::dashed_line(&screen, Stroke::new(2.0, GEOFENCE_COLOR), 6.0, 4.0));
+        for pos in &screen {
+            painter.circle(*pos, 4.0, GEOFENCE_COLOR, Stroke::new(1.0, Color32::BLACK));
+        }
+    }
+}
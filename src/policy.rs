@@ -0,0 +1,108 @@
+//! Expected transit towards a network, e.g. traffic to AS15169 should go via AS1299 and
+//! never through AS174.
+
+use serde::{Deserialize, Serialize};
+
+/// How routes to one destination AS should look. Applies to routes whose last hop with a
+/// known AS is in `destination`, routes that don't get there aren't judged.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransitPolicy {
+    pub destination: u32,
+    /// ASes the route has to go through, any one of them will do
+    pub via: Vec<u32>,
+    /// ASes the route must stay out of
+    pub never: Vec<u32>,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl TransitPolicy {
+    /// E.g. "via AS1299, never AS174".
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.via.is_empty() {
+            parts.push(format!("via {}", format_asns(&self.via)));
+        }
+        if !self.never.is_empty() {
+            parts.push(format!("never {}", format_asns(&self.never)));
+        }
+        parts.join(", ")
+    }
+}
+
+/// ASNs separated by commas or spaces, with or without the AS prefix. Skips anything else.
+pub fn parse_asns(text: &str) -> Vec<u32> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|part| {
+            let part = part.trim();
+            let number = part.strip_prefix("AS").or_else(|| part.strip_prefix("as")).unwrap_or(part);
+            number.parse().ok()
+        })
+        .collect()
+}
+
+pub fn format_asns(asns: &[u32]) -> String {
+    asns.iter().map(|asn| format!("AS{}", asn)).collect::<Vec<_>>().join(", ")
+}
+
+/// A route breaking a policy.
+pub enum Violation {
+    /// None of the required ASes is on the route
+    Bypassed { destination: u32, via: Vec<u32> },
+    /// A forbidden AS answered at `ttl`
+    Forbidden { destination: u32, asn: u32, ttl: usize },
+}
+
+impl Violation {
+    pub fn describe(&self) -> String {
+        match self {
+            Violation::Bypassed { destination, via } => {
+                format!("not via {} to AS{}", format_asns(via), destination)
+            }
+            Violation::Forbidden { destination, asn, ttl } => {
+                format!("hop {} in AS{} on the way to AS{}", ttl, asn, destination)
+            }
+        }
+    }
+}
+
+/// How a route breaks the enabled policies, given the AS of every hop in TTL order.
+pub fn violations(policies: &[TransitPolicy], hops: impl IntoIterator<Item = (usize, Option<u32>)>) -> Vec<Violation> {
+    let hops: Vec<(usize, u32)> = hops
+        .into_iter()
+        .filter(|(ttl, _)| *ttl > 0)
+        .filter_map(|(ttl, asn)| Some((ttl, asn?)))
+        .collect();
+    let Some(&(_, destination)) = hops.last() else {
+        return Vec::new();
+    };
+    let transit: Vec<(usize, u32)> = hops.into_iter().filter(|(_, asn)| *asn != destination).collect();
+
+    let mut violations = Vec::new();
+    for policy in policies.iter().filter(|p| p.enabled && p.destination == destination) {
+        if let Some(&(ttl, asn)) = transit.iter().find(|(_, asn)| policy.never.contains(asn)) {
+            violations.push(Violation::Forbidden { destination, asn, ttl });
+        }
+        if !policy.via.is_empty() && !transit.iter().any(|(_, asn)| policy.via.contains(asn)) {
+            violations.push(Violation::Bypassed {
+                destination,
+                via: policy.via.clone(),
+            });
+        }
+    }
+    violations
+}
+
+/// One line alert text for `violations`, none if there are none.
+pub fn describe(violations: &[Violation]) -> Option<String> {
+    let first = violations.first()?;
+    let more = match violations.len() {
+        1 => String::new(),
+        n => format!(" and {} more violations", n - 1),
+    };
+    Some(format!("transit policy: {}{}", first.describe(), more))
+}
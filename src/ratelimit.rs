@@ -0,0 +1,48 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Token bucket shared by everything that talks to one rate limited API.
+pub struct TokenBucket {
+    capacity: f64,
+    /// Tokens added per second
+    rate: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// Allows bursts of `capacity` requests and `capacity` requests per `period` on average.
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            rate: capacity as f64 / period.as_secs_f64(),
+            state: Mutex::new((capacity as f64, Instant::now())),
+        }
+    }
+
+    /// Waits until a token is available and takes it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let (tokens, last) = &mut *state;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.capacity);
+                *last = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.rate)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Empties the bucket, e.g. after the server said we're over the limit anyway.
+    pub fn drain(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = (0.0, Instant::now());
+    }
+}
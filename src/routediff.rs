@@ -0,0 +1,187 @@
+//! Hop by hop differences between two routes to the same target.
+
+use std::collections::BTreeSet;
+
+use crate::trace::TraceNode;
+
+#[derive(Clone)]
+pub struct Hop {
+    pub ttl: usize,
+    pub ip: String,
+    pub asn: Option<u32>,
+}
+
+impl Hop {
+    fn describe(&self) -> String {
+        match self.asn {
+            Some(asn) => format!("{:>2} {} (AS{})", self.ttl, self.ip, asn),
+            None => format!("{:>2} {}", self.ttl, self.ip),
+        }
+    }
+}
+
+/// One line of the diff, by position along the routes.
+#[derive(Clone)]
+pub enum Change {
+    Same(Hop),
+    /// Only on the later route
+    Added(Hop),
+    /// Only on the earlier route
+    Removed(Hop),
+    /// A different router in the same place, earlier hop first
+    Changed(Hop, Hop),
+}
+
+/// How a later route to a target differs from an earlier one.
+#[derive(Clone)]
+pub struct RouteDiff {
+    pub changes: Vec<Change>,
+    /// AS numbers along each route, consecutive repeats collapsed
+    pub as_before: Vec<u32>,
+    pub as_after: Vec<u32>,
+}
+
+fn hops(nodes: &[(usize, TraceNode)]) -> Vec<Hop> {
+    nodes
+        .iter()
+        .filter(|(ttl, _)| *ttl > 0)
+        .map(|(ttl, node)| Hop {
+            ttl: *ttl,
+            ip: node.ip.clone(),
+            asn: node.asn,
+        })
+        .collect()
+}
+
+/// AS numbers a route passes through, in order.
+pub fn as_path(nodes: &[(usize, TraceNode)]) -> Vec<u32> {
+    let mut path: Vec<u32> = nodes.iter().filter_map(|(_, node)| node.asn).collect();
+    path.dedup();
+    path
+}
+
+pub fn format_as_path(path: &[u32]) -> String {
+    if path.is_empty() {
+        return "unknown".to_string();
+    }
+    path.iter().map(|asn| format!("AS{}", asn)).collect::<Vec<_>>().join(" → ")
+}
+
+impl RouteDiff {
+    /// Lines up both routes on their longest common run of IPs. Hops removed and added
+    /// between the same two common hops are paired up as changed.
+    pub fn between(before: &[(usize, TraceNode)], after: &[(usize, TraceNode)]) -> Self {
+        let (old, new) = (hops(before), hops(after));
+
+        // Longest common subsequence lengths of the suffixes
+        let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                common[i][j] = if old[i].ip == new[j].ip {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+
+        let mut changes = Vec::new();
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i].ip == new[j].ip {
+                pair(&mut changes, &mut removed, &mut added);
+                changes.push(Change::Same(new[j].clone()));
+                i += 1;
+                j += 1;
+            } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+                added.push(new[j].clone());
+                j += 1;
+            } else {
+                removed.push(old[i].clone());
+                i += 1;
+            }
+        }
+        pair(&mut changes, &mut removed, &mut added);
+
+        Self {
+            changes,
+            as_before: as_path(before),
+            as_after: as_path(after),
+        }
+    }
+
+    /// Whether the routes differ at all.
+    pub fn differs(&self) -> bool {
+        self.changes.iter().any(|c| !matches!(c, Change::Same(_)))
+    }
+
+    pub fn as_path_changed(&self) -> bool {
+        self.as_before != self.as_after
+    }
+
+    /// TTLs of the earlier route's hops that aren't on the later one.
+    pub fn ttls_before(&self) -> BTreeSet<usize> {
+        self.changes
+            .iter()
+            .filter_map(|c| match c {
+                Change::Removed(hop) | Change::Changed(hop, _) => Some(hop.ttl),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// TTLs of the later route's hops that weren't on the earlier one.
+    pub fn ttls_after(&self) -> BTreeSet<usize> {
+        self.changes
+            .iter()
+            .filter_map(|c| match c {
+                Change::Added(hop) | Change::Changed(_, hop) => Some(hop.ttl),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// e.g. "1 added, 2 changed"
+    pub fn summary(&self) -> String {
+        if !self.differs() {
+            return "Same route".to_string();
+        }
+        let count = |f: fn(&Change) -> bool| self.changes.iter().filter(|c| f(c)).count();
+        [
+            (count(|c| matches!(c, Change::Added(_))), "added"),
+            (count(|c| matches!(c, Change::Removed(_))), "removed"),
+            (count(|c| matches!(c, Change::Changed(..))), "changed"),
+        ]
+        .into_iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, what)| format!("{} {}", n, what))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    /// The diff as text, one hop per line prefixed like a unified diff, with `~` for changed hops.
+    pub fn lines(&self) -> Vec<String> {
+        self.changes
+            .iter()
+            .map(|c| match c {
+                Change::Same(hop) => format!("  {}", hop.describe()),
+                Change::Added(hop) => format!("+ {}", hop.describe()),
+                Change::Removed(hop) => format!("- {}", hop.describe()),
+                Change::Changed(before, after) => format!("~ {} → {}", before.describe(), after.describe().trim_start()),
+            })
+            .collect()
+    }
+}
+
+/// Flushes the hops removed and added since the last common hop, pairing them up in order.
+fn pair(changes: &mut Vec<Change>, removed: &mut Vec<Hop>, added: &mut Vec<Hop>) {
+    let mut added = added.drain(..);
+    for before in removed.drain(..) {
+        changes.push(match added.next() {
+            Some(after) => Change::Changed(before, after),
+            None => Change::Removed(before),
+        });
+    }
+    changes.extend(added.map(Change::Added));
+}
@@ -0,0 +1,373 @@
+//! Tracing a fixed set of targets in the background on a schedule, logging every run.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use directories::ProjectDirs;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use crate::{
+    alert::{Alert, AlertKind},
+    bus::Bus,
+    executor::Executor,
+    geofence::{self, Geofence},
+    notify,
+    policy::{self, TransitPolicy},
+    summary,
+    trace::{Enrichment, Position, TraceEvent, TraceSettings, Tracer},
+};
+
+/// When a job's runs show a desktop notification.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyRule {
+    pub route_change: bool,
+    /// Any hop inside an enabled geofence
+    pub geofence: bool,
+    /// Routes breaking a transit policy
+    pub policy: bool,
+    /// Any hop slower than this, in ms
+    pub latency_ms: Option<f64>,
+    /// Hops that answered before going quiet on this many runs in a row
+    pub loss_runs: Option<u32>,
+}
+
+impl NotifyRule {
+    pub fn any(&self) -> bool {
+        self.route_change || self.geofence || self.policy || self.latency_ms.is_some() || self.loss_runs.is_some()
+    }
+}
+
+/// A target traced every `every_minutes`, aligned to the clock like cron's `*/n`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub target: String,
+    pub every_minutes: u32,
+    #[serde(default)]
+    pub notify: NotifyRule,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    pub enabled: bool,
+    pub jobs: Vec<Job>,
+}
+
+/// A hop as kept in the log.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LoggedHop {
+    pub ttl: usize,
+    pub ip: String,
+    pub hostname: String,
+    pub asn: Option<u32>,
+    /// Missing in runs logged by older versions
+    #[serde(default)]
+    pub prefix: String,
+    pub rtt_ms: Option<f64>,
+    /// Latitude and longitude, missing in runs logged by older versions
+    #[serde(default)]
+    pub position: Option<[f64; 2]>,
+}
+
+/// One scheduled trace, as appended to the log.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Run {
+    pub target: String,
+    pub started: DateTime<Local>,
+    /// Hops past the local node, in TTL order
+    pub hops: Vec<LoggedHop>,
+    pub timeouts: Vec<usize>,
+    /// Why the trace couldn't run at all
+    pub error: Option<String>,
+}
+
+impl Run {
+    /// IPs of the hops in TTL order.
+    pub fn path(&self) -> Vec<&str> {
+        self.hops.iter().map(|hop| hop.ip.as_str()).collect()
+    }
+
+    /// See [`summary::fingerprint`].
+    pub fn fingerprint(&self) -> String {
+        summary::fingerprint(self.hops.iter().map(|hop| (hop.ip.as_str(), hop.asn, hop.prefix.as_str())))
+    }
+
+    pub fn completed(&self) -> bool {
+        self.error.is_none() && !self.hops.is_empty()
+    }
+}
+
+/// Runs kept per hop for its RTT trend.
+const HOP_HISTORY_RUNS: usize = 30;
+
+/// Recent RTTs of the hops scheduled runs went through, by IP, oldest first.
+#[derive(Default)]
+pub struct HopHistory(HashMap<String, VecDeque<f64>>);
+
+impl HopHistory {
+    pub fn record(&mut self, run: &Run) {
+        for hop in &run.hops {
+            let Some(rtt) = hop.rtt_ms else {
+                continue;
+            };
+            let rtts = self.0.entry(hop.ip.clone()).or_default();
+            if rtts.len() == HOP_HISTORY_RUNS {
+                rtts.pop_front();
+            }
+            rtts.push_back(rtt);
+        }
+    }
+
+    pub fn rtts(&self, ip: &str) -> Option<&VecDeque<f64>> {
+        self.0.get(ip)
+    }
+}
+
+fn log_path() -> Option<PathBuf> {
+    ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.data_dir().join("scheduled.jsonl"))
+}
+
+/// Every logged run, oldest first. Lines that don't parse are skipped.
+pub fn load() -> Vec<Run> {
+    let Some(text) = log_path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    text.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+fn append(run: &Run) {
+    let Some(path) = log_path() else {
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::to_string(run).map_err(|e| e.to_string()))
+        .and_then(|line| {
+            let mut file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| e.to_string())?;
+            writeln!(file, "{}", line).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        warn!("Failed to log scheduled run: {}", e);
+    }
+}
+
+/// Time until the next multiple of `every_minutes` since the epoch.
+fn until_next(every_minutes: u32, now: DateTime<Local>) -> Duration {
+    let period = every_minutes.max(1) as i64 * 60_000;
+    let wait = period - now.timestamp_millis().rem_euclid(period);
+    Duration::from_millis(wait as u64)
+}
+
+async fn run(tracer: &Tracer, target: &str) -> Run {
+    let mut run = Run {
+        target: target.to_string(),
+        started: Local::now(),
+        hops: Vec::new(),
+        timeouts: Vec::new(),
+        error: None,
+    };
+    let mut events = tracer.trace(target).await;
+    let mut error = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            TraceEvent::Node(ttl, node) if ttl > 0 => run.hops.push(LoggedHop {
+                ttl,
+                ip: node.ip,
+                hostname: node.hostname,
+                asn: node.asn,
+                prefix: node.prefix,
+                rtt_ms: node.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+                position: Some([node.position.lat(), node.position.lon()]),
+            }),
+            TraceEvent::Timeout(ttl) => run.timeouts.push(ttl),
+            TraceEvent::Error(e) => error = Some(e),
+            TraceEvent::Hostname(ttl, Some(name)) => {
+                if let Some(hop) = run.hops.iter_mut().find(|hop| hop.ttl == ttl) {
+                    hop.hostname = name;
+                }
+            }
+            _ => {}
+        }
+    }
+    // Errors along the way don't fail a run that got somewhere, e.g. a hop that couldn't be located
+    if run.hops.is_empty() {
+        run.error = error;
+    }
+    run
+}
+
+/// What a job remembers between runs to tell what changed.
+#[derive(Default)]
+struct Monitor {
+    /// Path and fingerprint of the previous completed run
+    last_route: Option<(Vec<String>, String)>,
+    /// TTLs that answered on the previous completed run
+    last_ttls: Vec<usize>,
+    /// Runs in a row that lost hops
+    lossy_runs: u32,
+}
+
+impl Monitor {
+    /// What's wrong with a run, each alert with whether the rule asks for a notification.
+    fn check(
+        &mut self,
+        run: &Run,
+        rule: &NotifyRule,
+        geofences: &[Geofence],
+        policies: &[TransitPolicy],
+    ) -> Vec<(Alert, bool)> {
+        let mut alerts = Vec::new();
+
+        // Hops that replied last time but not now, or the whole run when nothing came back
+        let lost = if run.completed() {
+            run.timeouts.iter().copied().find(|ttl| self.last_ttls.contains(ttl))
+        } else {
+            let detail = run.error.clone().unwrap_or_else(|| "No reply".to_string());
+            alerts.push((Alert::new(AlertKind::MonitorAlert, &run.target, detail), false));
+            Some(0)
+        };
+        self.lossy_runs = if lost.is_some() { self.lossy_runs + 1 } else { 0 };
+        if let (Some(ttl), Some(runs)) = (lost, rule.loss_runs) {
+            if self.lossy_runs == runs.max(1) {
+                let hop = if ttl == 0 { "no reply".to_string() } else { format!("hop {} lost", ttl) };
+                let detail = format!("{} for {} runs in a row", hop, self.lossy_runs);
+                alerts.push((Alert::new(AlertKind::MonitorAlert, &run.target, detail), true));
+            }
+        }
+        if !run.completed() {
+            return alerts;
+        }
+
+        if let Some(threshold) = rule.latency_ms {
+            let slow = run.hops.iter().find(|hop| hop.rtt_ms.is_some_and(|rtt| rtt > threshold));
+            if let Some(hop) = slow {
+                let detail = format!(
+                    "hop {} ({}) at {:.0} ms, over {:.0} ms",
+                    hop.ttl,
+                    hop.ip,
+                    hop.rtt_ms.unwrap_or_default(),
+                    threshold
+                );
+                alerts.push((Alert::new(AlertKind::MonitorAlert, &run.target, detail), true));
+            }
+        }
+
+        let positions = run
+            .hops
+            .iter()
+            .filter_map(|hop| hop.position.map(|[lat, lon]| (hop.ttl, Position::from_lat_lon(lat, lon))));
+        if let Some(detail) = geofence::describe(&geofence::breaches(geofences, positions)) {
+            alerts.push((Alert::new(AlertKind::MonitorAlert, &run.target, detail), rule.geofence));
+        }
+        let asns = run.hops.iter().map(|hop| (hop.ttl, hop.asn));
+        if let Some(detail) = policy::describe(&policy::violations(policies, asns)) {
+            alerts.push((Alert::new(AlertKind::MonitorAlert, &run.target, detail), rule.policy));
+        }
+
+        let path: Vec<String> = run.path().into_iter().map(str::to_string).collect();
+        self.last_ttls = run.hops.iter().map(|hop| hop.ttl).collect();
+        let fingerprint = run.fingerprint();
+        if let Some((previous, last_fingerprint)) = self.last_route.replace((path.clone(), fingerprint.clone())) {
+            if last_fingerprint != fingerprint {
+                let first = previous.iter().zip(&path).take_while(|(a, b)| a == b).count();
+                let now = path.get(first).map_or("missing", String::as_str);
+                let detail = format!("hop {} now {}", first + 1, now);
+                alerts.push((Alert::new(AlertKind::RouteChanged, &run.target, detail), rule.route_change));
+            }
+        }
+        alerts
+    }
+}
+
+/// Background tasks running the configured jobs while the app is open.
+#[derive(Default)]
+pub struct Scheduler {
+    tasks: Vec<JoinHandle<()>>,
+    /// Checked by every run, shared so changes reach running jobs
+    geofences: Arc<RwLock<Vec<Geofence>>>,
+    policies: Arc<RwLock<Vec<TransitPolicy>>>,
+}
+
+impl Scheduler {
+    /// Stops the running jobs and starts the configured ones, if enabled.
+    /// Finished runs are logged and published on the bus, with alerts for failed runs,
+    /// route changes, hops inside `geofences`, breaches of `policies` and the thresholds of
+    /// the job. Alerts the job's rule asks for are also shown as desktop notifications.
+    /// Later changes to either are handed over with [`Scheduler::set_geofences`] and
+    /// [`Scheduler::set_policies`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn restart(
+        &mut self,
+        config: &ScheduleConfig,
+        settings: TraceSettings,
+        geofences: &[Geofence],
+        policies: &[TransitPolicy],
+        enrichment: &Enrichment,
+        bus: &Bus,
+        runtime: &Executor,
+    ) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+        self.set_geofences(geofences);
+        self.set_policies(policies);
+        if !config.enabled {
+            return;
+        }
+        info!("Scheduling {} targets", config.jobs.len());
+        for job in config.jobs.iter().filter(|job| !job.target.trim().is_empty()) {
+            let job = job.clone();
+            let tracer = Tracer::new(settings.options, settings.geolocation, enrichment.clone());
+            let bus = bus.clone();
+            let geofences = self.geofences.clone();
+            let policies = self.policies.clone();
+            self.tasks.push(runtime.spawn(async move {
+                let mut monitor = Monitor::default();
+                loop {
+                    tokio::time::sleep(until_next(job.every_minutes, Local::now())).await;
+                    info!("Running scheduled trace to {}", job.target);
+                    let run = run(&tracer, job.target.trim()).await;
+                    append(&run);
+                    let alerts = {
+                        let (geofences, policies) = (geofences.read().unwrap(), policies.read().unwrap());
+                        monitor.check(&run, &job.notify, &geofences, &policies)
+                    };
+                    for (alert, notify) in alerts {
+                        if notify {
+                            let alert = alert.clone();
+                            tokio::task::spawn_blocking(move || notify::show(&alert));
+                        }
+                        bus.alerts.publish(alert);
+                    }
+                    bus.scheduled.publish(run);
+                }
+            }));
+        }
+    }
+
+    /// Has the next runs of all jobs check `geofences`, without restarting them.
+    pub fn set_geofences(&self, geofences: &[Geofence]) {
+        *self.geofences.write().unwrap() = geofences.to_vec();
+    }
+
+    /// Has the next runs of all jobs check `policies`, without restarting them.
+    pub fn set_policies(&self, policies: &[TransitPolicy]) {
+        *self.policies.write().unwrap() = policies.to_vec();
+    }
+
+    pub fn running(&self) -> usize {
+        self.tasks.len()
+    }
+}
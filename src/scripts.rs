@@ -0,0 +1,165 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use directories::ProjectDirs;
+use log::{info, warn};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::trace::TraceNode;
+
+/// User scripts from `<config dir>/scripts/*.rhai`.
+///
+/// A script can define `fn hop(hop)`, returning a map of extra fields for each hop,
+/// and `fn trace(target, hops)`, returning a summary line for a finished trace.
+/// Both get hops as maps with the same keys as the hop table, plus `rtt_ms`, `reply_ttl`, `lat` and `lon`.
+/// `http_get(url)` is available for asking internal APIs.
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<Script>,
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self {
+            engine: engine(),
+            scripts: Vec::new(),
+        }
+    }
+}
+
+pub fn scripts_dir() -> Option<PathBuf> {
+    ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.config_dir().join("scripts"))
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    // Keep a runaway loop from hanging a trace forever
+    engine.set_max_operations(1_000_000);
+    engine.register_fn("http_get", http_get);
+    engine
+}
+
+fn http_get(url: &str) -> Result<String, Box<EvalAltResult>> {
+    reqwest::blocking::Client::new()
+        .get(url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .and_then(|response| response.text())
+        .map_err(|e| e.to_string().into())
+}
+
+impl Scripts {
+    /// Compiles every script in the scripts directory, skipping the broken ones.
+    pub fn load() -> Self {
+        let mut scripts = Self::default();
+        let Some(dir) = scripts_dir() else {
+            return scripts;
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return scripts;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            match scripts.engine.compile_file(path.clone()) {
+                Ok(ast) => {
+                    info!("Loaded script {}", name);
+                    scripts.scripts.push(Script { name, ast });
+                }
+                Err(e) => warn!("Failed to compile script {}: {}", path.display(), e),
+            }
+        }
+        scripts
+    }
+
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// Extra fields for a hop from every script with a `hop` hook.
+    pub fn hop(&self, node: &TraceNode) -> Vec<(String, String)> {
+        let hop = hop_map(node);
+        let mut fields = Vec::new();
+        for script in self.with_hook("hop", 1) {
+            match self.engine.call_fn::<Dynamic>(&mut Scope::new(), &script.ast, "hop", (hop.clone(),)) {
+                Ok(result) => {
+                    if let Some(map) = result.try_cast::<Map>() {
+                        fields.extend(map.into_iter().map(|(k, v)| (k.to_string(), v.to_string())));
+                    }
+                }
+                Err(e) => warn!("{}: hop hook failed: {}", script.name, e),
+            }
+        }
+        fields
+    }
+
+    /// Summary lines for a finished trace from every script with a `trace` hook.
+    pub fn trace(&self, target: &str, nodes: &[TraceNode]) -> Vec<String> {
+        let hops: Array = nodes.iter().map(|n| Dynamic::from_map(hop_map(n))).collect();
+        let mut lines = Vec::new();
+        for script in self.with_hook("trace", 2) {
+            match self.engine.call_fn::<Dynamic>(
+                &mut Scope::new(),
+                &script.ast,
+                "trace",
+                (target.to_string(), hops.clone()),
+            ) {
+                Ok(result) if !result.is_unit() => lines.push(result.to_string()),
+                Ok(_) => {}
+                Err(e) => warn!("{}: trace hook failed: {}", script.name, e),
+            }
+        }
+        lines
+    }
+
+    fn with_hook<'a>(&'a self, hook: &'a str, params: usize) -> impl Iterator<Item = &'a Script> {
+        self.scripts.iter().filter(move |s| {
+            s.ast
+                .iter_functions()
+                .any(|f| f.name == hook && f.params.len() == params)
+        })
+    }
+}
+
+fn hop_map(node: &TraceNode) -> Map {
+    let mut map = Map::new();
+    map.insert("ip".into(), node.ip.clone().into());
+    map.insert("hostname".into(), node.hostname.clone().into());
+    map.insert("isp".into(), node.isp.clone().into());
+    map.insert(
+        "asn".into(),
+        node.asn.map(|asn| Dynamic::from_int(asn as i64)).unwrap_or(Dynamic::UNIT),
+    );
+    map.insert("as_name".into(), node.as_name.clone().into());
+    map.insert("prefix".into(), node.prefix.clone().into());
+    map.insert("country".into(), node.country.clone().into());
+    map.insert("country_code".into(), node.country_code.clone().into());
+    map.insert("region".into(), node.region.clone().into());
+    map.insert("city".into(), node.city.clone().into());
+    map.insert(
+        "rtt_ms".into(),
+        node.rtt
+            .map(|rtt| Dynamic::from_float(rtt.as_secs_f64() * 1000.0))
+            .unwrap_or(Dynamic::UNIT),
+    );
+    map.insert(
+        "reply_ttl".into(),
+        node.reply_ttl.map(|ttl| Dynamic::from_int(ttl as i64)).unwrap_or(Dynamic::UNIT),
+    );
+    map.insert("lat".into(), node.position.lat().into());
+    map.insert("lon".into(), node.position.lon().into());
+    map
+}
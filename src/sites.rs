@@ -0,0 +1,139 @@
+use std::{fs, net::IpAddr, path::Path};
+
+use ipnet::IpNet;
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::trace::{Location, Position};
+
+/// A private network range pinned to a building or site.
+#[derive(Clone, Deserialize)]
+pub struct Site {
+    pub cidr: IpNet,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// ISO 3166-1 alpha-2, optional
+    #[serde(default)]
+    pub country_code: String,
+}
+
+/// User-supplied locations for internal ranges that public geolocation knows nothing about.
+///
+/// Loaded from either a JSON array of `{cidr, name, lat, lon, country_code}` objects,
+/// or a CSV file with `cidr,name,lat,lon[,country_code]` rows.
+#[derive(Default)]
+pub struct SiteMap {
+    sites: Vec<Site>,
+}
+
+impl SiteMap {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut sites = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            serde_json::from_str::<Vec<Site>>(&text).map_err(|e| format!("Invalid site map: {}", e))?
+        } else {
+            parse_csv(&text)?
+        };
+        // Most specific range first, so lookups can take the first match
+        sites.sort_by_key(|s| std::cmp::Reverse(s.cidr.prefix_len()));
+        info!("Loaded {} sites from {}", sites.len(), path.display());
+        Ok(Self { sites })
+    }
+
+    /// Loads `path` if set, logging instead of failing so a broken file doesn't stop a trace.
+    pub fn load_or_default(path: &str) -> Self {
+        if path.trim().is_empty() {
+            return Self::default();
+        }
+        Self::load(Path::new(path.trim())).unwrap_or_else(|e| {
+            warn!("{}", e);
+            Self::default()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<&Site> {
+        self.sites.iter().find(|s| s.cidr.contains(&ip))
+    }
+
+    pub fn location(&self, ip: &str) -> Option<Location> {
+        let site = self.lookup(ip.parse().ok()?)?;
+        Some(Location {
+            position: Position::from_lat_lon(site.lat, site.lon),
+            isp: site.name.clone(),
+            asn: None,
+            as_name: String::new(),
+            country: site.country_code.clone(),
+            country_code: site.country_code.clone(),
+            region: String::new(),
+            city: String::new(),
+        })
+    }
+}
+
+/// Private, shared, link-local and otherwise reserved addresses public geolocation can't place.
+pub fn is_bogon(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+                // Benchmarking, 198.18.0.0/15
+                || (a == 198 && (b & 0xfe) == 18)
+                // Reserved, 240.0.0.0/4
+                || a >= 240
+        }
+        IpAddr::V6(ip) => {
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                // Documentation, 2001:db8::/32
+                || (ip.segments()[0] == 0x2001 && ip.segments()[1] == 0xdb8)
+        }
+    }
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Site>, String> {
+    let mut sites = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+        // Allow a header row
+        if i == 0 && cells[0].eq_ignore_ascii_case("cidr") {
+            continue;
+        }
+        let invalid = || format!("Invalid site map line {}: {}", i + 1, line);
+        let [cidr, name, lat, lon, rest @ ..] = cells.as_slice() else {
+            return Err(invalid());
+        };
+        sites.push(Site {
+            // A bare address is a /32 or /128
+            cidr: cidr
+                .parse()
+                .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| invalid())?,
+            name: name.to_string(),
+            lat: lat.parse().map_err(|_| invalid())?,
+            lon: lon.parse().map_err(|_| invalid())?,
+            country_code: rest.first().map(|c| c.to_uppercase()).unwrap_or_default(),
+        });
+    }
+    Ok(sites)
+}
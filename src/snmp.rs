@@ -0,0 +1,231 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use snmp2::{
+    v3::{Auth, AuthProtocol, Cipher, Security},
+    AsyncSession, Oid, Value,
+};
+
+/// How long each SNMP request may take.
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// Time between the two counter samples used for utilization.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Version {
+    #[default]
+    V2c,
+    V3,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthHash {
+    Md5,
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Privacy {
+    Des,
+    #[default]
+    Aes128,
+}
+
+/// Which hops are ours to query, and how to log in to them.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnmpConfig {
+    pub enabled: bool,
+    /// CIDR ranges of routers we manage, one per entry
+    pub managed: Vec<String>,
+    pub version: Version,
+    pub community: String,
+    pub username: String,
+    /// Empty for noAuthNoPriv
+    pub auth_password: String,
+    pub auth_hash: AuthHash,
+    /// Empty for authNoPriv
+    pub privacy_password: String,
+    pub privacy: Privacy,
+}
+
+impl SnmpConfig {
+    pub fn is_managed(&self, ip: IpAddr) -> bool {
+        self.enabled
+            && self
+                .managed
+                .iter()
+                .filter_map(|range| range.trim().parse::<IpNet>().ok())
+                .any(|net| net.contains(&ip))
+    }
+
+    async fn session(&self, ip: IpAddr) -> Result<AsyncSession, String> {
+        let addr = SocketAddr::new(ip, 161);
+        match self.version {
+            Version::V2c => AsyncSession::new_v2c(addr, self.community.as_bytes(), 0)
+                .await
+                .map_err(|e| e.to_string()),
+            Version::V3 => {
+                let auth = if self.auth_password.is_empty() {
+                    Auth::NoAuthNoPriv
+                } else if self.privacy_password.is_empty() {
+                    Auth::AuthNoPriv
+                } else {
+                    Auth::AuthPriv {
+                        cipher: match self.privacy {
+                            Privacy::Des => Cipher::Des,
+                            Privacy::Aes128 => Cipher::Aes128,
+                        },
+                        privacy_password: self.privacy_password.as_bytes().to_vec(),
+                    }
+                };
+                let security = Security::new(self.username.as_bytes(), self.auth_password.as_bytes())
+                    .with_auth(auth)
+                    .with_auth_protocol(match self.auth_hash {
+                        AuthHash::Md5 => AuthProtocol::Md5,
+                        AuthHash::Sha1 => AuthProtocol::Sha1,
+                        AuthHash::Sha256 => AuthProtocol::Sha256,
+                    });
+                let mut session = AsyncSession::new_v3(addr, 0, security)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                // Discovers the engine id and time
+                timeout(session.init()).await?;
+                Ok(session)
+            }
+        }
+    }
+}
+
+/// The interface a hop's address lives on.
+#[derive(Clone)]
+pub struct Interface {
+    pub system: String,
+    pub name: String,
+    pub description: String,
+    pub speed_mbps: u64,
+    /// Percent of the interface speed, in and out
+    pub utilization: Option<(f64, f64)>,
+}
+
+// IF-MIB and IP-MIB objects
+const SYS_NAME: &[u64] = &[1, 3, 6, 1, 2, 1, 1, 5, 0];
+const IP_AD_ENT_IF_INDEX: &[u64] = &[1, 3, 6, 1, 2, 1, 4, 20, 1, 2];
+const IF_DESCR: &[u64] = &[1, 3, 6, 1, 2, 1, 2, 2, 1, 2];
+const IF_NAME: &[u64] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 1];
+const IF_ALIAS: &[u64] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 18];
+const IF_HIGH_SPEED: &[u64] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 15];
+const IF_HC_IN_OCTETS: &[u64] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 6];
+const IF_HC_OUT_OCTETS: &[u64] = &[1, 3, 6, 1, 2, 1, 31, 1, 1, 1, 10];
+
+/// Owned copy of the values we care about, the PDU borrows the session.
+enum Reply {
+    Text(String),
+    Number(u64),
+    Missing,
+}
+
+fn oid(base: &[u64], index: &[u64]) -> Oid<'static> {
+    let id: Vec<u64> = base.iter().chain(index).copied().collect();
+    Oid::from(&id).expect("valid OID")
+}
+
+async fn timeout<T>(request: impl std::future::Future<Output = Result<T, snmp2::Error>>) -> Result<T, String> {
+    match tokio::time::timeout(TIMEOUT, request).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err("No SNMP response".to_string()),
+    }
+}
+
+async fn get(session: &mut AsyncSession, oids: &[Oid<'static>]) -> Result<Vec<Reply>, String> {
+    let refs: Vec<&Oid> = oids.iter().collect();
+    let pdu = timeout(session.get_many(&refs)).await?;
+    Ok(pdu
+        .varbinds
+        .map(|(_, value)| match value {
+            Value::OctetString(bytes) => Reply::Text(String::from_utf8_lossy(bytes).into_owned()),
+            Value::Integer(n) => Reply::Number(n.max(0) as u64),
+            Value::Counter32(n) | Value::Unsigned32(n) | Value::Timeticks(n) => Reply::Number(n as u64),
+            Value::Counter64(n) => Reply::Number(n),
+            _ => Reply::Missing,
+        })
+        .collect())
+}
+
+fn text(reply: Option<&Reply>) -> String {
+    match reply {
+        Some(Reply::Text(text)) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+fn number(reply: Option<&Reply>) -> Option<u64> {
+    match reply {
+        Some(Reply::Number(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+/// Finds the interface `ip` is configured on and samples its traffic counters.
+pub async fn interface(config: &SnmpConfig, ip: IpAddr) -> Result<Interface, String> {
+    // IP-MIB's address table is indexed by the IPv4 address itself
+    let IpAddr::V4(v4) = ip else {
+        return Err("Interface lookup only supports IPv4 hops".to_string());
+    };
+    let mut session = config.session(ip).await?;
+
+    let address: Vec<u64> = v4.octets().iter().map(|&b| b as u64).collect();
+    let replies = get(&mut session, &[oid(SYS_NAME, &[]), oid(IP_AD_ENT_IF_INDEX, &address)]).await?;
+    let system = text(replies.first());
+    let index = number(replies.get(1)).ok_or("Address not found in the router's IP table")?;
+
+    let index = [index];
+    let counters = [oid(IF_HC_IN_OCTETS, &index), oid(IF_HC_OUT_OCTETS, &index)];
+    let replies = get(
+        &mut session,
+        &[
+            oid(IF_NAME, &index),
+            oid(IF_DESCR, &index),
+            oid(IF_ALIAS, &index),
+            oid(IF_HIGH_SPEED, &index),
+            counters[0].clone(),
+            counters[1].clone(),
+        ],
+    )
+    .await?;
+    let first_sample = Instant::now();
+    let name = text(replies.first());
+    let description = [text(replies.get(2)), text(replies.get(1))]
+        .into_iter()
+        .find(|d| !d.is_empty())
+        .unwrap_or_default();
+    let speed_mbps = number(replies.get(3)).unwrap_or_default();
+    let before = number(replies.get(4)).zip(number(replies.get(5)));
+
+    let mut utilization = None;
+    if let (Some((in_before, out_before)), true) = (before, speed_mbps > 0) {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        let replies = get(&mut session, &counters).await?;
+        if let Some((in_after, out_after)) = number(replies.first()).zip(number(replies.get(1))) {
+            let secs = first_sample.elapsed().as_secs_f64();
+            let percent = |before: u64, after: u64| {
+                after.wrapping_sub(before) as f64 * 8.0 / secs / (speed_mbps as f64 * 1e6) * 100.0
+            };
+            utilization = Some((percent(in_before, in_after), percent(out_before, out_after)));
+        }
+    }
+
+    Ok(Interface {
+        system,
+        name,
+        description,
+        speed_mbps,
+        utilization,
+    })
+}
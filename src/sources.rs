@@ -0,0 +1,140 @@
+use std::{collections::BTreeSet, sync::Mutex};
+
+use walkers::{
+    sources::{Attribution, TileSource},
+    TileId,
+};
+
+/// <https://opentopomap.org/about>
+pub struct OpenTopoMap;
+
+impl TileSource for OpenTopoMap {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        format!(
+            "https://tile.opentopomap.org/{}/{}/{}.png",
+            tile_id.zoom, tile_id.x, tile_id.y
+        )
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "OpenTopoMap (CC-BY-SA), OpenStreetMap contributors",
+            url: "https://opentopomap.org/about",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+
+    fn max_zoom(&self) -> u8 {
+        17
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CartoStyle {
+    DarkMatter,
+    Positron,
+}
+
+/// <https://carto.com/basemaps>
+pub struct Carto {
+    pub style: CartoStyle,
+}
+
+impl TileSource for Carto {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        let style = match self.style {
+            CartoStyle::DarkMatter => "dark_all",
+            CartoStyle::Positron => "light_all",
+        };
+        format!(
+            "https://basemaps.cartocdn.com/{}/{}/{}/{}.png",
+            style, tile_id.zoom, tile_id.x, tile_id.y
+        )
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "CARTO, OpenStreetMap contributors",
+            url: "https://carto.com/attributions",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+
+    fn max_zoom(&self) -> u8 {
+        20
+    }
+}
+
+/// Satellite imagery, <https://www.arcgis.com/home/item.html?id=10df2279f9684e4a9f6a7f08febac2a9>
+pub struct EsriWorldImagery;
+
+impl TileSource for EsriWorldImagery {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        // Esri orders the tile coordinates as z/y/x
+        format!(
+            "https://server.arcgisonline.com/ArcGIS/rest/services/World_Imagery/MapServer/tile/{}/{}/{}",
+            tile_id.zoom, tile_id.y, tile_id.x
+        )
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "Esri, Maxar, Earthstar Geographics",
+            url: "https://www.esri.com",
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+}
+
+/// Any XYZ tile server, given a URL template such as
+/// `https://tile.example.com/{z}/{x}/{y}.png?apikey={key}`.
+pub struct CustomUrl {
+    template: String,
+    api_key: String,
+    // Attribution wants static strings, so each distinct template is leaked once
+    attribution_url: &'static str,
+}
+
+/// Templates leaked so far, reused when the source is rebuilt with one of them.
+static ATTRIBUTION_URLS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+impl CustomUrl {
+    pub fn new(template: String, api_key: String) -> Self {
+        let mut leaked = ATTRIBUTION_URLS.lock().unwrap();
+        let attribution_url = match leaked.get(template.as_str()) {
+            Some(url) => *url,
+            None => {
+                let url: &'static str = Box::leak(template.clone().into_boxed_str());
+                leaked.insert(url);
+                url
+            }
+        };
+        Self {
+            template,
+            api_key,
+            attribution_url,
+        }
+    }
+}
+
+impl TileSource for CustomUrl {
+    fn tile_url(&self, tile_id: TileId) -> String {
+        self.template
+            .replace("{z}", &tile_id.zoom.to_string())
+            .replace("{x}", &tile_id.x.to_string())
+            .replace("{y}", &tile_id.y.to_string())
+            .replace("{key}", &self.api_key)
+    }
+
+    fn attribution(&self) -> Attribution {
+        Attribution {
+            text: "Custom tile server",
+            url: self.attribution_url,
+            logo_light: None,
+            logo_dark: None,
+        }
+    }
+}
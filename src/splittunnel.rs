@@ -0,0 +1,166 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::mpsc::Sender,
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executor::Executor,
+    sites,
+    trace::{self, Enrichment, TraceEvent, TraceSettings, Tracer},
+};
+
+/// Interface name prefixes VPN clients commonly use, for tunnels that don't flag themselves
+/// as point-to-point.
+const VPN_PREFIXES: [&str; 8] = ["tun", "tap", "wg", "utun", "ppp", "ipsec", "nordlynx", "tailscale"];
+
+/// Which way traffic to a target leaves the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Egress {
+    Vpn,
+    Direct,
+}
+
+impl Egress {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Egress::Vpn => "VPN",
+            Egress::Direct => "direct",
+        }
+    }
+}
+
+/// A target and which way it should go, none if either is fine.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Check {
+    pub target: String,
+    pub expect: Option<Egress>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitTunnelConfig {
+    pub checks: Vec<Check>,
+}
+
+/// Parses "target = vpn" and "target = direct" lines, a bare target only gets reported.
+pub fn parse_checks(text: &str) -> Vec<Check> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once('=') {
+            Some((target, expect)) => Check {
+                target: target.trim().to_string(),
+                expect: match expect.trim().to_lowercase().as_str() {
+                    "vpn" | "tunnel" => Some(Egress::Vpn),
+                    "direct" | "local" => Some(Egress::Direct),
+                    _ => None,
+                },
+            },
+            None => Check {
+                target: line.to_string(),
+                expect: None,
+            },
+        })
+        .collect()
+}
+
+pub fn format_checks(checks: &[Check]) -> String {
+    checks
+        .iter()
+        .map(|c| match c.expect {
+            Some(Egress::Vpn) => format!("{} = vpn", c.target),
+            Some(Egress::Direct) => format!("{} = direct", c.target),
+            None => c.target.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Source address the routing table picks for `ip`. Connecting a UDP socket sends nothing.
+fn source_for(ip: IpAddr) -> Option<IpAddr> {
+    let bind: SocketAddr = match ip {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind).ok()?;
+    socket.connect((ip, 53)).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// The interface traffic to `ip` leaves through, and whether it's a tunnel.
+fn egress_interface(ip: IpAddr) -> Option<(String, Egress)> {
+    let source = source_for(ip)?;
+    let interface = netdev::get_interfaces().into_iter().find(|interface| match source {
+        IpAddr::V4(v4) => interface.ipv4.iter().any(|net| net.addr == v4),
+        IpAddr::V6(v6) => interface.ipv6.iter().any(|net| net.addr == v6),
+    })?;
+    let name = interface.name.to_lowercase();
+    let vpn = interface.is_tun() || VPN_PREFIXES.iter().any(|prefix| name.starts_with(prefix));
+    debug!("{} leaves through {} from {}", ip, interface.name, source);
+    Some((interface.name, if vpn { Egress::Vpn } else { Egress::Direct }))
+}
+
+pub enum Event {
+    /// Interface and way out the routing table picked
+    Egress(usize, String, Egress),
+    /// First hop outside private and bogon ranges, and who runs it
+    FirstPublic(usize, String, String),
+    /// The check gave up, no more events follow for it
+    Error(usize, String),
+}
+
+/// Checks every target at once, reporting back by check index.
+/// The channel disconnects once every check is done.
+pub fn run(checks: &[Check], settings: TraceSettings, enrichment: &Enrichment, runtime: &Executor, sender: Sender<Event>) {
+    info!("Checking the egress of {} targets", checks.len());
+    for (i, check) in checks.iter().enumerate() {
+        let target = check.target.clone();
+        let sender = sender.clone();
+        let tracer = Tracer::new(settings.options, settings.geolocation, enrichment.clone());
+        runtime.spawn(async move {
+            let Some(ip) = trace::resolve(&target) else {
+                sender.send(Event::Error(i, format!("Could not resolve {}", target))).ok();
+                return;
+            };
+            match egress_interface(ip) {
+                Some((interface, egress)) => {
+                    sender.send(Event::Egress(i, interface, egress)).ok();
+                }
+                None => {
+                    sender.send(Event::Error(i, "No route".to_string())).ok();
+                    return;
+                }
+            }
+
+            let mut events = tracer.trace(&ip.to_string()).await;
+            // Only worth showing if no public hop turns up after it
+            let mut error = None;
+            while let Some(event) = events.recv().await {
+                let (ttl, node) = match event {
+                    TraceEvent::Node(ttl, node) => (ttl, node),
+                    TraceEvent::Error(e) => {
+                        error = Some(e);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                let public = node.ip.parse().is_ok_and(|ip| !sites::is_bogon(ip));
+                if ttl > 0 && public {
+                    let owner = match node.asn {
+                        Some(asn) => format!("AS{} {}", asn, node.isp),
+                        None => node.isp.clone(),
+                    };
+                    sender.send(Event::FirstPublic(i, node.ip, owner)).ok();
+                    // Nothing past the first public hop matters
+                    return;
+                }
+            }
+            if let Some(error) = error {
+                sender.send(Event::Error(i, error)).ok();
+            }
+        });
+    }
+}
@@ -0,0 +1,323 @@
+//! Every finished trace kept in SQLite, so the history outlives the app without
+//! all of it sitting in memory.
+
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Local};
+use directories::ProjectDirs;
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    history::{HistoryEntry, Outcome},
+    trace::{Location, Position, TraceNode, TraceSettings},
+};
+
+/// Runs loaded into the history at start, older ones stay on disk.
+pub const RECENT_RUNS: usize = 500;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        target TEXT NOT NULL,
+        started TEXT NOT NULL,
+        completed INTEGER NOT NULL,
+        tags TEXT NOT NULL,
+        options TEXT NOT NULL,
+        geolocation TEXT NOT NULL,
+        network TEXT,
+        timeouts TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_target ON runs (target);
+    CREATE TABLE IF NOT EXISTS hops (
+        run INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+        ttl INTEGER NOT NULL,
+        ip TEXT NOT NULL,
+        hostname TEXT NOT NULL,
+        isp TEXT NOT NULL,
+        asn INTEGER,
+        as_name TEXT NOT NULL,
+        prefix TEXT NOT NULL,
+        country TEXT NOT NULL,
+        country_code TEXT NOT NULL,
+        region TEXT NOT NULL,
+        city TEXT NOT NULL,
+        lat REAL NOT NULL,
+        lon REAL NOT NULL,
+        rtt_ms REAL,
+        reply_ttl INTEGER,
+        private INTEGER NOT NULL,
+        fields TEXT NOT NULL,
+        PRIMARY KEY (run, ttl)
+    );
+";
+
+/// Everything on record about one target.
+pub struct TargetStats {
+    pub runs: usize,
+    pub completed: usize,
+    pub first: DateTime<Local>,
+    pub last: DateTime<Local>,
+    /// Different hop sequences seen over all completed runs
+    pub paths: usize,
+    /// RTT to the last hop over all completed runs, in ms
+    pub rtt_mean: Option<f64>,
+    pub rtt_min: Option<f64>,
+    pub rtt_max: Option<f64>,
+}
+
+fn store_path() -> Option<PathBuf> {
+    ProjectDirs::from("org", "dragynfruit", "traced").map(|dirs| dirs.data_dir().join("traces.sqlite"))
+}
+
+fn json<T: serde::Serialize>(value: &T) -> rusqlite::Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(column: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, e.into()))
+}
+
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    pub fn open() -> Result<Self, String> {
+        let path = store_path().ok_or("No data directory")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let connection = Connection::open(&path).map_err(|e| e.to_string())?;
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .and_then(|_| connection.execute_batch(SCHEMA))
+            .map_err(|e| e.to_string())?;
+        info!("Storing traces in {}", path.display());
+        Ok(Self { connection })
+    }
+
+    /// Adds a finished trace with all its hops.
+    pub fn record(&mut self, entry: &HistoryEntry) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (target, started, completed, tags, options, geolocation, network, timeouts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                entry.target,
+                entry.started,
+                entry.outcome == Outcome::Completed,
+                json(&entry.tags)?,
+                json(&entry.settings.options)?,
+                json(&entry.settings.geolocation)?,
+                entry.network.as_ref().map(json).transpose()?,
+                json(&entry.timeouts)?,
+            ],
+        )?;
+        let run = transaction.last_insert_rowid();
+        {
+            let mut insert = transaction.prepare(
+                "INSERT OR REPLACE INTO hops (run, ttl, ip, hostname, isp, asn, as_name, prefix, country,
+                 country_code, region, city, lat, lon, rtt_ms, reply_ttl, private, fields)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            )?;
+            for (ttl, node) in &entry.nodes {
+                insert.execute(params![
+                    run,
+                    ttl,
+                    node.ip,
+                    node.hostname,
+                    node.isp,
+                    node.asn,
+                    node.as_name,
+                    node.prefix,
+                    node.country,
+                    node.country_code,
+                    node.region,
+                    node.city,
+                    node.position.lat(),
+                    node.position.lon(),
+                    node.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+                    node.reply_ttl,
+                    node.private,
+                    json(&node.fields)?,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// The latest `limit` runs, oldest first.
+    pub fn recent(&self, limit: usize) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let mut runs = self.connection.prepare(
+            "SELECT * FROM (
+                 SELECT id, target, started, tags, options, geolocation, network, timeouts
+                 FROM runs ORDER BY id DESC LIMIT ?1
+             ) ORDER BY id",
+        )?;
+        let mut hops = self.connection.prepare(
+            "SELECT ttl, ip, hostname, isp, asn, as_name, prefix, country, country_code, region, city,
+             lat, lon, rtt_ms, reply_ttl, private, fields
+             FROM hops WHERE run = ?1 ORDER BY ttl",
+        )?;
+        let rows = runs.query_map([limit], |row| {
+            let id: i64 = row.get(0)?;
+            let tags: String = row.get(3)?;
+            let options: String = row.get(4)?;
+            let geolocation: String = row.get(5)?;
+            let network: Option<String> = row.get(6)?;
+            let timeouts: String = row.get(7)?;
+            Ok((
+                id,
+                HistoryEntry {
+                    target: row.get(1)?,
+                    started: row.get(2)?,
+                    outcome: Outcome::Failed,
+                    tags: from_json(3, &tags)?,
+                    settings: TraceSettings {
+                        options: from_json(4, &options)?,
+                        geolocation: from_json(5, &geolocation)?,
+                    },
+                    network: network.map(|n| from_json(6, &n)).transpose()?,
+                    nodes: Vec::new(),
+                    timeouts: from_json(7, &timeouts)?,
+                },
+            ))
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (id, mut entry) = row?;
+            entry.nodes = hops
+                .query_map([id], |row| {
+                    let fields: String = row.get(16)?;
+                    Ok((
+                        row.get(0)?,
+                        TraceNode {
+                            position: Position::from_lat_lon(row.get(11)?, row.get(12)?),
+                            hostname: row.get(2)?,
+                            isp: row.get(3)?,
+                            ip: row.get(1)?,
+                            asn: row.get(4)?,
+                            as_name: row.get(5)?,
+                            prefix: row.get(6)?,
+                            country: row.get(7)?,
+                            country_code: row.get(8)?,
+                            region: row.get(9)?,
+                            city: row.get(10)?,
+                            rtt: row
+                                .get::<_, Option<f64>>(13)?
+                                .map(|ms| Duration::from_secs_f64(ms / 1000.0)),
+                            reply_ttl: row.get(14)?,
+                            fields: from_json(16, &fields)?,
+                            private: row.get(15)?,
+                            pending: false,
+                            resolving: false,
+                        },
+                    ))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            entry.outcome = Outcome::from_nodes(&entry.nodes);
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Saves tags edited after the trace was recorded.
+    pub fn update_tags(&self, entry: &HistoryEntry) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "UPDATE runs SET tags = ?1 WHERE target = ?2 AND started = ?3",
+            params![json(&entry.tags)?, entry.target, entry.started],
+        )?;
+        Ok(())
+    }
+
+    /// Every hop IP on record, once each.
+    pub fn ips(&self) -> rusqlite::Result<Vec<String>> {
+        let mut ips = self.connection.prepare("SELECT DISTINCT ip FROM hops ORDER BY ip")?;
+        let ips = ips.query_map([], |row| row.get(0))?.collect();
+        ips
+    }
+
+    /// Replaces the location of every hop with one of the given IPs.
+    pub fn relocate(&mut self, located: &[(String, Location)]) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        {
+            let mut update = transaction.prepare(
+                "UPDATE hops SET lat = ?2, lon = ?3, isp = ?4, asn = ?5, as_name = ?6, country = ?7,
+                 country_code = ?8, region = ?9, city = ?10, private = 0 WHERE ip = ?1",
+            )?;
+            for (ip, location) in located {
+                update.execute(params![
+                    ip,
+                    location.position.lat(),
+                    location.position.lon(),
+                    location.isp,
+                    location.asn,
+                    location.as_name,
+                    location.country,
+                    location.country_code,
+                    location.region,
+                    location.city,
+                ])?;
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Statistics over every run to `target`, none if it was never traced.
+    pub fn stats(&self, target: &str) -> rusqlite::Result<Option<TargetStats>> {
+        let counts = self
+            .connection
+            .query_row(
+                "SELECT COUNT(*), SUM(completed),
+                     (SELECT started FROM runs WHERE target = ?1 ORDER BY id LIMIT 1),
+                     (SELECT started FROM runs WHERE target = ?1 ORDER BY id DESC LIMIT 1)
+                 FROM runs WHERE target = ?1",
+                [target],
+                |row| {
+                    Ok((
+                        row.get::<_, usize>(0)?,
+                        row.get::<_, Option<usize>>(1)?,
+                        row.get::<_, Option<DateTime<Local>>>(2)?,
+                        row.get::<_, Option<DateTime<Local>>>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((runs, completed, Some(first), Some(last))) = counts else {
+            return Ok(None);
+        };
+
+        let mut paths = self.connection.prepare(
+            "SELECT group_concat(ip, ' ') FROM (
+                 SELECT run, ip FROM hops JOIN runs ON runs.id = hops.run
+                 WHERE target = ?1 AND completed AND ttl > 0 ORDER BY run, ttl
+             ) GROUP BY run",
+        )?;
+        let paths = paths
+            .query_map([target], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<HashSet<_>>>()?
+            .len();
+
+        // The last hop of a run is where it got to, usually the target itself
+        let (rtt_mean, rtt_min, rtt_max) = self.connection.query_row(
+            "SELECT AVG(rtt_ms), MIN(rtt_ms), MAX(rtt_ms) FROM hops AS h JOIN runs ON runs.id = h.run
+             WHERE target = ?1 AND completed AND ttl = (SELECT MAX(ttl) FROM hops WHERE run = h.run)",
+            [target],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        Ok(Some(TargetStats {
+            runs,
+            completed: completed.unwrap_or(0),
+            first,
+            last,
+            paths,
+            rtt_mean,
+            rtt_min,
+            rtt_max,
+        }))
+    }
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashSet, time::Duration};
+
+use crate::{
+    geo, ixp,
+    trace::{Position, TraceNode},
+};
+
+/// Flag emoji of an ISO 3166-1 alpha-2 country code, empty for anything else.
+pub fn flag(country_code: &str) -> String {
+    if country_code.len() != 2 || !country_code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return String::new();
+    }
+    country_code
+        .to_ascii_uppercase()
+        .chars()
+        .filter_map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+        .collect()
+}
+
+/// A run of consecutive hops located in the same country.
+pub struct CountrySegment {
+    pub country: String,
+    pub country_code: String,
+    pub hops: usize,
+    /// RTT added while the route was inside this country
+    pub latency: Duration,
+    pub positions: Vec<Position>,
+}
+
+/// Countries traversed in order. A country appears again if the route leaves and comes back.
+pub fn countries(nodes: &[(usize, TraceNode)]) -> Vec<CountrySegment> {
+    let mut segments: Vec<CountrySegment> = Vec::new();
+    let mut last_rtt = Duration::ZERO;
+    let mut entry_rtt = Duration::ZERO;
+
+    for (_, node) in nodes {
+        if node.country_code.is_empty() {
+            continue;
+        }
+        let rtt = node.rtt.unwrap_or(last_rtt);
+        match segments.last_mut() {
+            Some(segment) if segment.country_code == node.country_code => {
+                segment.hops += 1;
+                segment.positions.push(node.position);
+            }
+            _ => {
+                entry_rtt = last_rtt;
+                segments.push(CountrySegment {
+                    country: node.country.clone(),
+                    country_code: node.country_code.clone(),
+                    hops: 1,
+                    latency: Duration::ZERO,
+                    positions: vec![node.position],
+                });
+            }
+        }
+        if let Some(segment) = segments.last_mut() {
+            // RTTs of individual probes are noisy, so never let a segment go negative
+            segment.latency = rtt.saturating_sub(entry_rtt);
+        }
+        last_rtt = rtt.max(last_rtt);
+    }
+    segments
+}
+
+/// Short hash of the networks a route goes through, equal for two routes that take the
+/// same way. Hops count by their announced prefix, else their AS, else their IP, and hops
+/// in a row on the same network count once. Stable across runs and versions, so it can be
+/// compared with fingerprints from earlier runs or other machines.
+pub fn fingerprint<'a>(hops: impl IntoIterator<Item = (&'a str, Option<u32>, &'a str)>) -> String {
+    // FNV-1a, std's hashers may change between releases
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut last = String::new();
+    for (ip, asn, prefix) in hops {
+        let key = if !prefix.is_empty() {
+            prefix.to_string()
+        } else if let Some(asn) = asn {
+            format!("AS{}", asn)
+        } else {
+            ip.to_string()
+        };
+        if key == last {
+            continue;
+        }
+        for byte in key.bytes().chain([b'|']) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        last = key;
+    }
+    format!("{:016x}", hash)
+}
+
+/// [`fingerprint`] of the hops past the local node.
+pub fn route_fingerprint(nodes: &[(usize, TraceNode)]) -> String {
+    fingerprint(
+        nodes
+            .iter()
+            .filter(|(ttl, _)| *ttl > 0)
+            .map(|(_, node)| (node.ip.as_str(), node.asn, node.prefix.as_str())),
+    )
+}
+
+/// Overall shape of a route.
+pub struct PathStats {
+    /// Hops past the local node that answered
+    pub hops: usize,
+    /// Great-circle distance along the located hops, in km
+    pub distance_km: f64,
+    /// Great-circle distance from the local node to the last hop, in km
+    pub direct_km: f64,
+    pub countries: usize,
+    pub ases: usize,
+    /// RTT to the last hop
+    pub rtt: Option<Duration>,
+    /// See [`fingerprint`]
+    pub fingerprint: String,
+    /// Internet exchanges the route goes through, in order
+    pub ixps: Vec<&'static str>,
+}
+
+impl PathStats {
+    pub fn new(nodes: &[(usize, TraceNode)]) -> Self {
+        let distance_km = nodes
+            .windows(2)
+            .map(|pair| geo::distance_km(pair[0].1.position, pair[1].1.position))
+            .sum();
+        let direct_km = match (nodes.first(), nodes.last()) {
+            (Some((_, first)), Some((_, last))) => geo::distance_km(first.position, last.position),
+            _ => 0.0,
+        };
+        let countries: HashSet<&str> = nodes
+            .iter()
+            .map(|(_, node)| node.country_code.as_str())
+            .filter(|code| !code.is_empty())
+            .collect();
+        let ases: HashSet<u32> = nodes.iter().filter_map(|(_, node)| node.asn).collect();
+        Self {
+            hops: nodes.iter().filter(|(ttl, _)| *ttl > 0).count(),
+            distance_km,
+            direct_km,
+            countries: countries.len(),
+            ases: ases.len(),
+            rtt: nodes.last().and_then(|(_, node)| node.rtt),
+            fingerprint: route_fingerprint(nodes),
+            ixps: ixp::traversed(nodes),
+        }
+    }
+
+    /// How much longer the route is than a straight line, none when both ends are in the same place.
+    pub fn detour_factor(&self) -> Option<f64> {
+        (self.direct_km >= 1.0).then(|| self.distance_km / self.direct_km)
+    }
+}
+
+/// A country on the route and who operates the hops inside it.
+pub struct Jurisdiction {
+    pub country: String,
+    pub country_code: String,
+    pub hops: usize,
+    pub organizations: Vec<String>,
+    /// The user listed this country as a jurisdiction of concern
+    pub flagged: bool,
+}
+
+/// Every country and organization a route transits, for compliance checks.
+pub fn jurisdictions(nodes: &[(usize, TraceNode)], concerns: &[String]) -> Vec<Jurisdiction> {
+    let mut result: Vec<Jurisdiction> = Vec::new();
+    for (_, node) in nodes {
+        if node.country_code.is_empty() {
+            continue;
+        }
+        let organization = if node.as_name.is_empty() { &node.isp } else { &node.as_name };
+        let entry = match result.iter_mut().position(|j| j.country_code == node.country_code) {
+            Some(i) => &mut result[i],
+            None => {
+                result.push(Jurisdiction {
+                    country: node.country.clone(),
+                    country_code: node.country_code.clone(),
+                    hops: 0,
+                    organizations: Vec::new(),
+                    flagged: concerns.iter().any(|c| c.eq_ignore_ascii_case(&node.country_code)),
+                });
+                result.last_mut().unwrap()
+            }
+        };
+        entry.hops += 1;
+        if !organization.is_empty() && !entry.organizations.contains(organization) {
+            entry.organizations.push(organization.clone());
+        }
+    }
+    result
+}
+
+/// Plain text version of the jurisdiction report, for pasting into tickets.
+pub fn jurisdiction_report(target: &str, jurisdictions: &[Jurisdiction]) -> String {
+    let mut report = format!("Jurisdiction report for {}\n\n", target);
+    for j in jurisdictions {
+        report.push_str(&format!(
+            "{}{} ({}) - {} hop{}\n",
+            if j.flagged { "[!] " } else { "" },
+            j.country,
+            j.country_code,
+            j.hops,
+            if j.hops == 1 { "" } else { "s" }
+        ));
+        for organization in &j.organizations {
+            report.push_str(&format!("    {}\n", organization));
+        }
+    }
+    let flagged: Vec<_> = jurisdictions.iter().filter(|j| j.flagged).map(|j| j.country_code.as_str()).collect();
+    if flagged.is_empty() {
+        report.push_str("\nNo jurisdictions of concern on this route.\n");
+    } else {
+        report.push_str(&format!("\nJurisdictions of concern on this route: {}\n", flagged.join(", ")));
+    }
+    report
+}
@@ -0,0 +1,177 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::mpsc::Receiver,
+};
+
+use chrono::{DateTime, Local};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+use crate::{
+    bus::{Bus, Topic},
+    executor::Executor,
+};
+
+/// How many events are kept, oldest dropped first.
+const MAX_EVENTS: usize = 5000;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyslogConfig {
+    pub enabled: bool,
+    /// Address to listen on, 514 needs root so the default is 5514
+    pub bind: String,
+    /// How far around a path change events still count as related, in seconds
+    pub match_window_secs: u64,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "0.0.0.0:5514".to_string(),
+            match_window_secs: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bgp,
+    /// OSPF or IS-IS adjacency changes
+    Igp,
+    Interface,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Bgp => "BGP",
+            Kind::Igp => "IGP",
+            Kind::Interface => "Interface",
+        }
+    }
+}
+
+/// A routing related syslog message.
+#[derive(Clone)]
+pub struct RouteEvent {
+    /// When we received it, router clocks can't be trusted to be in sync with ours
+    pub at: DateTime<Local>,
+    pub source: IpAddr,
+    pub kind: Kind,
+    pub message: String,
+}
+
+/// Strips the priority and picks out BGP, IGP and interface state changes. Everything else is dropped.
+pub fn parse(source: IpAddr, datagram: &[u8]) -> Option<RouteEvent> {
+    let text = String::from_utf8_lossy(datagram);
+    let text = text.trim();
+    let message = match text.strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+        Some((priority, rest)) if priority.chars().all(|c| c.is_ascii_digit()) => rest.trim(),
+        _ => text,
+    };
+
+    let upper = message.to_uppercase();
+    let kind = if upper.contains("BGP") {
+        Kind::Bgp
+    } else if upper.contains("OSPF") || upper.contains("ISIS") || upper.contains("IS-IS") {
+        Kind::Igp
+    } else if upper.contains("UPDOWN")
+        || upper.contains("LINEPROTO")
+        || upper.contains("CHANGED STATE TO")
+        || upper.contains("LINK DOWN")
+        || upper.contains("LINK UP")
+        || upper.contains("SNMP_TRAP_LINK")
+    {
+        Kind::Interface
+    } else {
+        return None;
+    };
+    Some(RouteEvent {
+        at: Local::now(),
+        source,
+        kind,
+        message: message.to_string(),
+    })
+}
+
+/// UDP syslog receiver, publishing route events on the bus in the background.
+pub struct Listener {
+    /// Events received so far, for the timeline
+    pub events: Vec<RouteEvent>,
+    /// Where we're listening, or why we aren't
+    pub status: Result<Option<SocketAddr>, String>,
+    task: Option<JoinHandle<()>>,
+    topic: Topic<RouteEvent>,
+    receiver: Receiver<RouteEvent>,
+}
+
+impl Listener {
+    pub fn new(config: &SyslogConfig, bus: &Bus, runtime: &Executor) -> Self {
+        let mut listener = Self {
+            events: Vec::new(),
+            status: Ok(None),
+            task: None,
+            topic: bus.route_events.clone(),
+            receiver: bus.route_events.subscribe(),
+        };
+        listener.restart(config, runtime);
+        listener
+    }
+
+    /// Stops listening and starts again with the new settings, if enabled.
+    pub fn restart(&mut self, config: &SyslogConfig, runtime: &Executor) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.status = Ok(None);
+        if !config.enabled {
+            return;
+        }
+
+        // Bind right away so a bad address or port in use shows up immediately
+        let socket = std::net::UdpSocket::bind(config.bind.trim()).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            let _guard = runtime.enter();
+            UdpSocket::from_std(socket)
+        });
+        let socket = match socket {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("Failed to listen for syslog on {}: {}", config.bind, e);
+                self.status = Err(format!("Failed to listen on {}: {}", config.bind, e));
+                return;
+            }
+        };
+        self.status = Ok(socket.local_addr().ok());
+        info!("Listening for syslog on {}", config.bind);
+
+        let topic = self.topic.clone();
+        self.task = Some(runtime.spawn(async move {
+            let mut buf = vec![0; 8192];
+            loop {
+                let (len, from) = match socket.recv_from(&mut buf).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        debug!("Syslog receive failed: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(event) = parse(from.ip(), &buf[..len]) {
+                    debug!("{} event from {}: {}", event.kind.name(), event.source, event.message);
+                    topic.publish(event);
+                }
+            }
+        }));
+    }
+
+    /// Moves events published since the last call into `events`.
+    pub fn poll(&mut self) {
+        self.events.extend(self.receiver.try_iter());
+        if self.events.len() > MAX_EVENTS {
+            self.events.drain(..self.events.len() - MAX_EVENTS);
+        }
+    }
+}
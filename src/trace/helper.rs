@@ -0,0 +1,440 @@
+//! A second copy of traced, started once with raw socket privileges, that probes on behalf
+//! of the unprivileged GUI.
+//!
+//! The GUI listens on a loopback port and starts the helper elevated with that port. A
+//! one-time secret of two tokens goes to the helper over its stdin, or where the password
+//! prompt can't pass that on, in a file only the user can read, never on the command line
+//! where other users could see it. The helper connects back and proves itself with the first
+//! token, the GUI answers with the second so the helper knows it reached the GUI and not
+//! whatever else listens on the port. Then it answers trace requests, one JSON message per
+//! line. It can't do anything but trace, and it exits as soon as the GUI goes away.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tracert::node::{Node, NodeType};
+
+use super::{tracer, TraceOptions};
+
+/// How long the user has to answer the password prompt.
+const START_TIMEOUT: Duration = Duration::from_secs(120);
+/// How long either side waits for the other's token once connected.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    id: u64,
+    ip: IpAddr,
+    options: TraceOptions,
+    first_ttl: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Kind {
+    Gateway,
+    Relay,
+    Destination,
+}
+
+/// A [`Node`] on the wire.
+#[derive(Serialize, Deserialize)]
+struct Hop {
+    seq: u8,
+    ip: IpAddr,
+    host_name: String,
+    ttl: Option<u8>,
+    hop: Option<u8>,
+    kind: Kind,
+    rtt: Duration,
+}
+
+impl From<Node> for Hop {
+    fn from(node: Node) -> Self {
+        Self {
+            seq: node.seq,
+            ip: node.ip_addr,
+            host_name: node.host_name,
+            ttl: node.ttl,
+            hop: node.hop,
+            kind: match node.node_type {
+                NodeType::DefaultGateway => Kind::Gateway,
+                NodeType::Relay => Kind::Relay,
+                NodeType::Destination => Kind::Destination,
+            },
+            rtt: node.rtt,
+        }
+    }
+}
+
+impl From<Hop> for Node {
+    fn from(hop: Hop) -> Self {
+        Self {
+            seq: hop.seq,
+            ip_addr: hop.ip,
+            host_name: hop.host_name,
+            ttl: hop.ttl,
+            hop: hop.hop,
+            node_type: match hop.kind {
+                Kind::Gateway => NodeType::DefaultGateway,
+                Kind::Relay => NodeType::Relay,
+                Kind::Destination => NodeType::Destination,
+            },
+            rtt: hop.rtt,
+        }
+    }
+}
+
+/// What the helper sends back for a request, by its id.
+#[derive(Serialize, Deserialize)]
+enum Reply {
+    Hop(u64, Hop),
+    Error(u64, String),
+    Done(u64),
+}
+
+fn send(stream: &Mutex<TcpStream>, message: &impl Serialize) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.lock().unwrap().write_all(line.as_bytes())
+}
+
+/// The connection to a running helper.
+struct Helper {
+    writer: Mutex<TcpStream>,
+    /// Traces waiting for replies, by request id
+    pending: Mutex<HashMap<u64, Sender<Reply>>>,
+    next_id: AtomicU64,
+}
+
+static HELPER: Mutex<Option<Arc<Helper>>> = Mutex::new(None);
+
+/// Whether a helper is connected and traces go through it.
+pub fn running() -> bool {
+    HELPER.lock().unwrap().is_some()
+}
+
+/// 32 bytes from the OS's CSPRNG, hex-encoded, so other local users can't guess it.
+fn token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Starts this executable with `args` through the UAC prompt, hidden unless `visible`.
+#[cfg(windows)]
+pub fn run_elevated(args: &[String], visible: bool) -> Result<(), String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::UI::{
+        Shell::ShellExecuteW,
+        WindowsAndMessaging::{SW_HIDE, SW_SHOWNORMAL},
+    };
+
+    let wide = |s: &std::ffi::OsStr| s.encode_wide().chain([0]).collect::<Vec<u16>>();
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let (verb, file) = (wide("runas".as_ref()), wide(exe.as_os_str()));
+    let parameters = wide(args.join(" ").as_ref());
+    // SAFETY: all strings are NUL terminated and outlive the call
+    let result = unsafe {
+        ShellExecuteW(
+            std::ptr::null_mut(),
+            verb.as_ptr(),
+            file.as_ptr(),
+            parameters.as_ptr(),
+            std::ptr::null(),
+            if visible { SW_SHOWNORMAL } else { SW_HIDE },
+        )
+    };
+    // Anything up to 32 is an error code, e.g. when the prompt was declined
+    if result as usize <= 32 {
+        return Err(format!("Not started ({})", result as usize));
+    }
+    Ok(())
+}
+
+/// Writes `secret` to a new file only this user, and so the elevated helper, can read, for
+/// password prompts that don't pass stdin on.
+#[cfg(any(windows, target_os = "macos"))]
+fn secret_file(secret: &str) -> Result<PathBuf, String> {
+    // The temporary directory is already private to the user on both
+    let path = std::env::temp_dir().join(format!("traced-helper-{}", std::process::id()));
+    fs::remove_file(&path).ok();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(secret.as_bytes()))
+        .map_err(|e| format!("Couldn't write the helper secret: {}", e))?;
+    Ok(path)
+}
+
+/// Starts the helper for `port` through the platform's password prompt and hands it
+/// `secret`. Gives the process to watch for an early exit, where there is one, and the
+/// secret file to remove once the helper is in, where there is one.
+fn spawn(port: u16, secret: &str) -> Result<(Option<std::process::Child>, Option<PathBuf>), String> {
+    let args = vec!["--helper-port".to_string(), port.to_string()];
+    #[cfg(windows)]
+    {
+        let (path, mut args) = (secret_file(secret)?, args);
+        args.extend(["--helper-secret".to_string(), format!("\"{}\"", path.display())]);
+        run_elevated(&args, false).map(|()| (None, Some(path)))
+    }
+    #[cfg(target_os = "linux")]
+    {
+        // pkexec keeps stdin, so the secret never touches the disk
+        let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+        let mut child = std::process::Command::new("pkexec")
+            .arg(exe)
+            .args(&args)
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Couldn't run pkexec: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(secret.as_bytes()).map_err(|e| e.to_string())?;
+        }
+        Ok((Some(child), None))
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let (path, mut args) = (secret_file(secret)?, args);
+        args.extend(["--helper-secret".to_string(), format!("'{}'", path.display())]);
+        let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+        let exe = exe.display().to_string().replace('\\', "\\\\").replace('"', "\\\"");
+        let script = format!(
+            "do shell script (quoted form of \"{}\") & \" {}\" with administrator privileges",
+            exe,
+            args.join(" ")
+        );
+        std::process::Command::new("osascript")
+            .args(["-e", &script])
+            .spawn()
+            .map(|child| (Some(child), Some(path)))
+            .map_err(|e| format!("Couldn't run osascript: {}", e))
+    }
+    #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+    {
+        let _ = (args, secret);
+        Err("Starting a helper isn't supported here, run traced as root instead".to_string())
+    }
+}
+
+/// Waits for the helper to connect and show the right token, then proves this is the GUI
+/// with `reply`.
+fn accept(
+    listener: &TcpListener,
+    token: &str,
+    reply: &str,
+    mut child: Option<std::process::Child>,
+) -> Result<(TcpStream, Option<std::process::Child>), String> {
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let deadline = Instant::now() + START_TIMEOUT;
+    while Instant::now() < deadline {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+                stream.set_read_timeout(Some(TOKEN_TIMEOUT)).map_err(|e| e.to_string())?;
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).ok();
+                if line.trim() == token {
+                    stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+                    (&stream).write_all(format!("{}\n", reply).as_bytes()).map_err(|e| e.to_string())?;
+                    return Ok((stream, child));
+                }
+                warn!("Dropped a helper connection with the wrong token");
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // Declining the prompt ends the process before it ever connects
+                if let Some(status) = child.as_mut().and_then(|c| c.try_wait().ok().flatten()) {
+                    return Err(format!("The helper didn't start ({})", status));
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Err("The helper didn't connect in time".to_string())
+}
+
+/// Starts the helper and waits for it to connect, which includes the user answering the
+/// password prompt. Blocks for up to a couple of minutes.
+pub fn start() -> Result<(), String> {
+    if running() {
+        return Ok(());
+    }
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let (token, reply) = (token()?, token()?);
+    let (child, secret_file) = spawn(port, &format!("{} {}\n", token, reply))?;
+    let accepted = accept(&listener, &token, &reply, child);
+    // The helper read it before connecting, or it isn't coming
+    if let Some(path) = secret_file {
+        fs::remove_file(path).ok();
+    }
+    let (stream, child) = accepted?;
+    info!("Probe helper connected");
+
+    let reader = stream.try_clone().map_err(|e| e.to_string())?;
+    let helper = Arc::new(Helper {
+        writer: Mutex::new(stream),
+        pending: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(0),
+    });
+    *HELPER.lock().unwrap() = Some(helper.clone());
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let reply: Reply = match serde_json::from_str(&line) {
+                Ok(reply) => reply,
+                Err(e) => {
+                    warn!("Invalid reply from the helper: {}", e);
+                    continue;
+                }
+            };
+            let (Reply::Hop(id, _) | Reply::Error(id, _) | Reply::Done(id)) = &reply;
+            if let Some(pending) = helper.pending.lock().unwrap().get(id) {
+                pending.send(reply).ok();
+            }
+        }
+        warn!("Probe helper went away");
+        // Dropping the senders ends the traces still waiting on it
+        helper.pending.lock().unwrap().clear();
+        let mut current = HELPER.lock().unwrap();
+        if current.as_ref().is_some_and(|current| Arc::ptr_eq(current, &helper)) {
+            *current = None;
+        }
+        if let Some(mut child) = child {
+            child.wait().ok();
+        }
+    });
+    Ok(())
+}
+
+/// Traces `ip` through the helper from `first_ttl` on, handing hops to `report` in TTL order
+/// until it returns false.
+pub fn trace(ip: IpAddr, options: TraceOptions, first_ttl: u8, mut report: impl FnMut(Node) -> bool) -> io::Result<()> {
+    let helper = HELPER
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "The helper isn't running"))?;
+    let id = helper.next_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = channel();
+    helper.pending.lock().unwrap().insert(id, tx);
+    let result = send(&helper.writer, &Request { id, ip, options, first_ttl }).and_then(|()| loop {
+        match rx.recv() {
+            Ok(Reply::Hop(_, hop)) => {
+                if !report(hop.into()) {
+                    break Ok(());
+                }
+            }
+            Ok(Reply::Error(_, e)) => break Err(io::Error::other(e)),
+            Ok(Reply::Done(_)) => break Ok(()),
+            Err(_) => break Err(io::Error::new(io::ErrorKind::ConnectionAborted, "The helper stopped")),
+        }
+    });
+    helper.pending.lock().unwrap().remove(&id);
+    result
+}
+
+/// The two tokens [`start`] handed over, from the file at `path` or else from stdin.
+fn read_secret(path: Option<&Path>) -> io::Result<(String, String)> {
+    let text = match path {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            line
+        }
+    };
+    text.split_once(' ')
+        .map(|(token, reply)| (token.trim().to_string(), reply.trim().to_string()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed helper secret"))
+}
+
+/// Runs as the helper: connects to the GUI on `port`, with the secret from the file at
+/// `secret` or else stdin, and traces whatever it asks for.
+pub fn serve(port: u16, secret: Option<&Path>) -> ExitCode {
+    let (token, reply) = match read_secret(secret) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            error!("Couldn't read the helper secret: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let stream = match TcpStream::connect(SocketAddr::from((Ipv4Addr::LOCALHOST, port))) {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Couldn't reach traced: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    let answered = (&stream)
+        .write_all(format!("{}\n", token).as_bytes())
+        .and_then(|()| stream.set_read_timeout(Some(TOKEN_TIMEOUT)))
+        .and_then(|()| reader.read_line(&mut line))
+        .and_then(|_| stream.set_read_timeout(None));
+    if let Err(e) = answered {
+        error!("{}", e);
+        return ExitCode::FAILURE;
+    }
+    // Anything else could have taken the port once the GUI let go of it
+    if line.trim() != reply {
+        error!("Whatever listens on port {} isn't traced, not serving it", port);
+        return ExitCode::FAILURE;
+    }
+    let writer = Arc::new(Mutex::new(stream));
+    info!("Serving traces for port {}", port);
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let Request { id, ip, options, first_ttl } = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("Invalid request: {}", e);
+                continue;
+            }
+        };
+        debug!("Tracing {} for request {}", ip, id);
+        let writer = writer.clone();
+        thread::spawn(move || {
+            let result = tracert::trace::Tracer::new(ip)
+                .and_then(|tracer| tracer::trace_raw(tracer, ip, options, first_ttl, |node| send(&writer, &Reply::Hop(id, node.into())).is_ok()));
+            let reply = match result {
+                Ok(()) => Reply::Done(id),
+                Err(e) => Reply::Error(id, e),
+            };
+            send(&writer, &reply).ok();
+        });
+    }
+    // The GUI closed, so nobody needs traces any more
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,135 @@
+//! Traceroute through the ICMP helper API, which Windows offers without administrator rights.
+//!
+//! One probe per TTL and one TTL after another, the way tracert.exe does it. Only used
+//! when raw sockets can't be opened.
+
+use std::{
+    ffi::c_void,
+    io,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use tracert::node::{Node, NodeType};
+use windows_sys::Win32::{
+    Foundation::{HANDLE, INVALID_HANDLE_VALUE},
+    NetworkManagement::IpHelper::{
+        IcmpCloseHandle, IcmpCreateFile, IcmpSendEcho, ICMP_ECHO_REPLY, IP_DEST_HOST_UNREACHABLE,
+        IP_DEST_PORT_UNREACHABLE, IP_DEST_PROT_UNREACHABLE, IP_OPTION_INFORMATION, IP_SUCCESS,
+        IP_TTL_EXPIRED_TRANSIT,
+    },
+};
+
+/// Payload of pings, the size tracert.exe and ping.exe send.
+const PAYLOAD: [u8; 32] = [0; 32];
+/// IPv4 and ICMP headers, which the API adds to the payload.
+const HEADERS: usize = 28;
+
+/// An ICMP handle, closed when dropped.
+struct Icmp(HANDLE);
+
+impl Icmp {
+    fn open() -> io::Result<Self> {
+        // SAFETY: no arguments, the handle is checked before use
+        let handle = unsafe { IcmpCreateFile() };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self(handle))
+    }
+
+    /// Sends one echo request with `ttl` carrying `payload` and waits for whatever answers it.
+    /// Gives the replying address, the TTL of the reply, and whether it came from `dst`.
+    fn echo(&self, dst: Ipv4Addr, ttl: u8, payload: &[u8], timeout: Duration) -> Option<(Ipv4Addr, u8, bool)> {
+        let options = IP_OPTION_INFORMATION {
+            Ttl: ttl,
+            ..Default::default()
+        };
+        // Room for one reply, its payload and an ICMP error on top, as the API asks for
+        let mut reply = vec![0u8; size_of::<ICMP_ECHO_REPLY>() + payload.len() + 8 + 64];
+        // SAFETY: all pointers are valid for the sizes passed along with them
+        let count = unsafe {
+            IcmpSendEcho(
+                self.0,
+                u32::from_ne_bytes(dst.octets()),
+                payload.as_ptr() as *const c_void,
+                payload.len() as u16,
+                &options,
+                reply.as_mut_ptr() as *mut c_void,
+                reply.len() as u32,
+                timeout.as_millis().min(u32::MAX as u128) as u32,
+            )
+        };
+        if count == 0 {
+            debug!("No reply for TTL {}: {}", ttl, io::Error::last_os_error());
+            return None;
+        }
+        // SAFETY: a non-zero count means the buffer starts with a reply, which may not be aligned
+        let reply = unsafe { std::ptr::read_unaligned(reply.as_ptr() as *const ICMP_ECHO_REPLY) };
+        let reached = match reply.Status {
+            IP_SUCCESS | IP_DEST_HOST_UNREACHABLE | IP_DEST_PORT_UNREACHABLE | IP_DEST_PROT_UNREACHABLE => true,
+            IP_TTL_EXPIRED_TRANSIT => false,
+            status => {
+                debug!("TTL {} failed with status {}", ttl, status);
+                return None;
+            }
+        };
+        let source = Ipv4Addr::from(reply.Address.to_ne_bytes());
+        Some((source, reply.Options.Ttl, reached || source == dst))
+    }
+}
+
+impl Drop for Icmp {
+    fn drop(&mut self) {
+        // SAFETY: the handle came from IcmpCreateFile and is closed only here
+        unsafe { IcmpCloseHandle(self.0) };
+    }
+}
+
+/// Traces `dst` from `first_ttl` on, handing hops to `report` in TTL order until it returns false.
+/// TTLs that never answered are skipped, like the tracert crate does. Probes are `size` bytes
+/// long including the IP header.
+pub fn trace(
+    dst: Ipv4Addr,
+    first_ttl: u8,
+    max_hops: u8,
+    size: u16,
+    timeout: Duration,
+    mut report: impl FnMut(Node) -> bool,
+) -> io::Result<()> {
+    let icmp = Icmp::open()?;
+    let payload = vec![0; (size as usize).saturating_sub(HEADERS)];
+    for ttl in first_ttl.max(1)..=max_hops {
+        let sent = Instant::now();
+        let Some((ip, reply_ttl, reached)) = icmp.echo(dst, ttl, &payload, timeout) else {
+            continue;
+        };
+        let node = Node {
+            seq: ttl,
+            ip_addr: IpAddr::V4(ip),
+            host_name: ip.to_string(),
+            ttl: Some(reply_ttl),
+            hop: Some(ttl),
+            node_type: if reached {
+                NodeType::Destination
+            } else if ttl == 1 {
+                NodeType::DefaultGateway
+            } else {
+                NodeType::Relay
+            },
+            rtt: sent.elapsed(),
+        };
+        if !report(node) || reached {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Round trip time of one echo request to `dst`, none when it got lost.
+pub fn ping(dst: Ipv4Addr, timeout: Duration) -> io::Result<Option<Duration>> {
+    let icmp = Icmp::open()?;
+    let sent = Instant::now();
+    Ok(icmp.echo(dst, 128, &PAYLOAD, timeout).filter(|(_, _, reached)| *reached).map(|_| sent.elapsed()))
+}
@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::Position;
+use crate::{address, geocache, ratelimit::TokenBucket, sites::SiteMap, whois};
+
+/// Where hop locations are looked up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GeoProvider {
+    #[default]
+    IpApi,
+    /// Needs a token for batch lookups, see [`set_ipinfo_token`]
+    IpInfo,
+}
+
+impl GeoProvider {
+    pub const ALL: [GeoProvider; 2] = [GeoProvider::IpApi, GeoProvider::IpInfo];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeoProvider::IpApi => "ip-api.com",
+            GeoProvider::IpInfo => "ipinfo.io",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct IpApiResponse {
+    // Missing when the lookup failed, e.g. for private addresses
+    #[serde(default)]
+    lat: f64,
+    #[serde(default)]
+    lon: f64,
+    status: String,
+    isp: String,
+    /// e.g. "AS15169 Google LLC"
+    #[serde(rename = "as", default)]
+    as_info: String,
+    #[serde(default)]
+    asname: String,
+    #[serde(default)]
+    country: String,
+    #[serde(rename = "countryCode", default)]
+    country_code: String,
+    #[serde(rename = "regionName", default)]
+    region: String,
+    #[serde(default)]
+    city: String,
+    /// The IP this answer is for
+    #[serde(default)]
+    query: String,
+}
+
+impl IpApiResponse {
+    fn location(self) -> Option<Location> {
+        if self.status != "success" {
+            return None;
+        }
+        let asn = self
+            .as_info
+            .strip_prefix("AS")
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|asn| asn.parse().ok());
+        Some(Location {
+            position: Position::from_lat_lon(self.lat, self.lon),
+            isp: self.isp,
+            asn,
+            as_name: self.asname,
+            country: self.country,
+            country_code: self.country_code,
+            region: self.region,
+            city: self.city,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct IpInfoName {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct IpInfoAsn {
+    /// e.g. "AS15169"
+    #[serde(default)]
+    asn: String,
+    #[serde(default)]
+    name: String,
+}
+
+/// The parts of an ipinfo.io answer used, `asn`, `company` and `carrier` need a paid plan.
+#[derive(Deserialize, Debug)]
+struct IpInfoResponse {
+    #[serde(default)]
+    bogon: bool,
+    /// "lat,lon"
+    #[serde(default)]
+    loc: String,
+    /// e.g. "AS15169 Google LLC"
+    #[serde(default)]
+    org: String,
+    #[serde(default)]
+    city: String,
+    #[serde(default)]
+    region: String,
+    /// ISO 3166-1 alpha-2, there are no country names
+    #[serde(default)]
+    country: String,
+    asn: Option<IpInfoAsn>,
+    company: Option<IpInfoName>,
+    carrier: Option<IpInfoName>,
+}
+
+impl IpInfoResponse {
+    fn location(self) -> Option<Location> {
+        if self.bogon {
+            return None;
+        }
+        let (lat, lon) = self.loc.split_once(',')?;
+        let position = Position::from_lat_lon(lat.trim().parse().ok()?, lon.trim().parse().ok()?);
+        let (asn, as_name) = match self.asn {
+            Some(asn) => (asn.asn, asn.name),
+            None => match self.org.split_once(' ') {
+                Some((asn, name)) => (asn.to_string(), name.to_string()),
+                None => (self.org.clone(), String::new()),
+            },
+        };
+        // Mobile carriers and companies say more about who runs the hop than the AS does
+        let isp = [self.carrier, self.company]
+            .into_iter()
+            .flatten()
+            .map(|n| n.name)
+            .find(|name| !name.is_empty())
+            .unwrap_or_else(|| as_name.clone());
+        Some(Location {
+            position,
+            isp,
+            asn: asn.strip_prefix("AS").and_then(|asn| asn.parse().ok()),
+            as_name,
+            country: self.country.clone(),
+            country_code: self.country,
+            region: self.region,
+            city: self.city,
+        })
+    }
+}
+
+/// What a geolocation provider knows about an IP.
+#[derive(Clone)]
+pub struct Location {
+    pub position: Position,
+    pub isp: String,
+    pub asn: Option<u32>,
+    pub as_name: String,
+    pub country: String,
+    pub country_code: String,
+    /// State or province
+    pub region: String,
+    pub city: String,
+}
+
+/// Public IP of this machine, as ipify sees it.
+pub async fn get_my_ip(client: &reqwest::Client) -> Option<String> {
+    match client.get("https://api.ipify.org").send().await {
+        Ok(resp) => {
+            if let Ok(my_ip) = resp.text().await {
+                debug!("Retrieved IP: {}", my_ip);
+                Some(address::canonical(&my_ip))
+            } else {
+                None
+            }
+        }
+        Err(e) => {
+            warn!("Failed to get IP: {}", e);
+            None
+        }
+    }
+}
+
+pub async fn get_location(
+    client: &reqwest::Client,
+    provider: GeoProvider,
+    sites: &SiteMap,
+    ip: &str,
+    ttl: Duration,
+) -> Option<Location> {
+    // Internal ranges from the site map win over anything public geolocation says
+    if let Some(location) = sites.location(ip) {
+        return Some(location);
+    }
+    if let Some(location) = geocache::get(provider, ip, ttl) {
+        return Some(location);
+    }
+    let location = match provider {
+        GeoProvider::IpApi => ip_api_location(client, ip).await,
+        GeoProvider::IpInfo => ipinfo_location(client, ip).await,
+    }?;
+    geocache::insert(provider, ip, &location);
+    Some(location)
+}
+
+/// Locations for many IPs at once, in the same order as `ips`.
+pub async fn get_locations(
+    client: &reqwest::Client,
+    provider: GeoProvider,
+    sites: &SiteMap,
+    ips: &[String],
+    ttl: Duration,
+) -> Vec<Option<Location>> {
+    let mut locations: Vec<Option<Location>> = ips
+        .iter()
+        .map(|ip| sites.location(ip).or_else(|| geocache::get(provider, ip, ttl)))
+        .collect();
+    let mut missing: Vec<String> = ips
+        .iter()
+        .zip(&locations)
+        .filter(|(_, location)| location.is_none())
+        .map(|(ip, _)| ip.clone())
+        .collect();
+    missing.sort();
+    missing.dedup();
+    if missing.is_empty() {
+        return locations;
+    }
+
+    let fetched: HashMap<String, Location> = match provider {
+        GeoProvider::IpApi => ip_api_batch(client, &missing).await,
+        GeoProvider::IpInfo => ipinfo_batch(client, &missing).await,
+    };
+    for (ip, location) in ips.iter().zip(&mut locations) {
+        if location.is_none() {
+            if let Some(found) = fetched.get(ip) {
+                geocache::insert(provider, ip, found);
+                *location = Some(found.clone());
+            }
+        }
+    }
+    locations
+}
+
+const IP_API_FIELDS: &str = "status,lat,lon,isp,as,asname,country,countryCode,regionName,city,query";
+
+// ip-api's free tier allows 45 single and 15 batch requests a minute per client IP
+static IP_API_SINGLE: LazyLock<TokenBucket> = LazyLock::new(|| TokenBucket::new(45, Duration::from_secs(60)));
+static IP_API_BATCH: LazyLock<TokenBucket> = LazyLock::new(|| TokenBucket::new(15, Duration::from_secs(60)));
+
+/// Sends a request to ip-api once the rate limiter allows it, waiting out a 429 once.
+async fn ip_api_send(bucket: &TokenBucket, request: reqwest::RequestBuilder) -> Option<reqwest::Response> {
+    for _ in 0..2 {
+        bucket.acquire().await;
+        let response = request.try_clone()?.send().await.ok()?;
+        if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Some(response);
+        }
+        // X-Ttl is the number of seconds until the limit resets
+        let wait = response
+            .headers()
+            .get("X-Ttl")
+            .and_then(|ttl| ttl.to_str().ok()?.parse().ok())
+            .unwrap_or(60);
+        warn!("ip-api rate limit reached, waiting {}s", wait);
+        bucket.drain();
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+    }
+    None
+}
+
+async fn ip_api_location(client: &reqwest::Client, ip: &str) -> Option<Location> {
+    let request = client.get(format!("http://ip-api.com/json/{}?fields={}", ip, IP_API_FIELDS));
+    let response = ip_api_send(&IP_API_SINGLE, request).await?;
+    response.json::<IpApiResponse>().await.ok()?.location()
+}
+
+/// Looks up to 100 IPs per request with ip-api's batch endpoint.
+async fn ip_api_batch(client: &reqwest::Client, ips: &[String]) -> HashMap<String, Location> {
+    let mut locations = HashMap::new();
+    for chunk in ips.chunks(100) {
+        debug!("Batch locating {} IPs", chunk.len());
+        let request = client
+            .post(format!("http://ip-api.com/batch?fields={}", IP_API_FIELDS))
+            .json(chunk);
+        let Some(response) = ip_api_send(&IP_API_BATCH, request).await else {
+            warn!("ip-api batch request failed");
+            continue;
+        };
+        match response.json::<Vec<IpApiResponse>>().await {
+            Ok(answers) => {
+                for answer in answers {
+                    let ip = answer.query.clone();
+                    if let Some(location) = answer.location() {
+                        locations.insert(ip, location);
+                    }
+                }
+            }
+            Err(e) => warn!("Invalid ip-api batch response: {}", e),
+        }
+    }
+    locations
+}
+
+/// Access token for ipinfo.io, shared by every trace. Empty for anonymous lookups.
+static IPINFO_TOKEN: RwLock<String> = RwLock::new(String::new());
+
+pub fn set_ipinfo_token(token: &str) {
+    *IPINFO_TOKEN.write().unwrap() = token.trim().to_string();
+}
+
+fn ipinfo_request(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    let token = IPINFO_TOKEN.read().unwrap().clone();
+    if token.is_empty() {
+        request
+    } else {
+        request.bearer_auth(token)
+    }
+}
+
+async fn ipinfo_location(client: &reqwest::Client, ip: &str) -> Option<Location> {
+    let response = ipinfo_request(client.get(format!("https://ipinfo.io/{}/json", ip))).send().await.ok()?;
+    if !response.status().is_success() {
+        warn!("ipinfo.io lookup failed: {}", response.status());
+        return None;
+    }
+    response.json::<IpInfoResponse>().await.ok()?.location()
+}
+
+/// Looks up to 1000 IPs per request with the batch endpoint, which needs a token.
+/// Without one, IPs are looked up one by one.
+async fn ipinfo_batch(client: &reqwest::Client, ips: &[String]) -> HashMap<String, Location> {
+    let mut locations = HashMap::new();
+    if IPINFO_TOKEN.read().unwrap().is_empty() {
+        for ip in ips {
+            if let Some(location) = ipinfo_location(client, ip).await {
+                locations.insert(ip.clone(), location);
+            }
+        }
+        return locations;
+    }
+    for chunk in ips.chunks(1000) {
+        debug!("Batch locating {} IPs with ipinfo.io", chunk.len());
+        let request = ipinfo_request(client.post("https://ipinfo.io/batch")).json(chunk);
+        let response = match request.send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                warn!("ipinfo.io batch request failed: {}", response.status());
+                continue;
+            }
+            Err(e) => {
+                warn!("ipinfo.io batch request failed: {}", e);
+                continue;
+            }
+        };
+        // Answers by IP, IPs it couldn't look up get an error object instead
+        match response.json::<HashMap<String, serde_json::Value>>().await {
+            Ok(answers) => {
+                for (ip, answer) in answers {
+                    let location = serde_json::from_value::<IpInfoResponse>(answer).ok().and_then(|a| a.location());
+                    if let Some(location) = location {
+                        locations.insert(ip, location);
+                    }
+                }
+            }
+            Err(e) => warn!("Invalid ipinfo.io batch response: {}", e),
+        }
+    }
+    locations
+}
+
+/// Requests a token has left on ipinfo.io.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct IpInfoQuota {
+    #[serde(default)]
+    pub month: u64,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub remaining: u64,
+}
+
+/// Checks `token` with ipinfo.io, giving what it has left this month.
+pub async fn ipinfo_quota(client: &reqwest::Client, token: &str) -> Result<IpInfoQuota, String> {
+    #[derive(Deserialize)]
+    struct Me {
+        requests: IpInfoQuota,
+    }
+
+    let response = client
+        .get("https://ipinfo.io/me")
+        .bearer_auth(token.trim())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        status if status.is_success() => {}
+        reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::UNAUTHORIZED => return Err("Invalid token".to_string()),
+        status => return Err(format!("ipinfo.io answered {}", status)),
+    }
+    response.json::<Me>().await.map(|me| me.requests).map_err(|e| e.to_string())
+}
+
+/// Looks up the announced prefix for `ip` in Team Cymru's IP-to-ASN whois service.
+pub(super) async fn bgp_prefix(ip: IpAddr) -> Option<String> {
+    let response = match tokio::time::timeout(
+        Duration::from_secs(3),
+        whois::query("whois.cymru.com", &format!(" -v {}", ip)),
+    )
+    .await
+    {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => {
+            debug!("Prefix lookup for {} failed: {}", ip, e);
+            return None;
+        }
+        Err(_) => {
+            debug!("Prefix lookup for {} timed out", ip);
+            return None;
+        }
+    };
+
+    // AS | IP | BGP Prefix | CC | Registry | Allocated | AS Name
+    let line = response.lines().rfind(|l| !l.trim().is_empty())?;
+    let prefix = line.split('|').nth(2)?.trim();
+    (!prefix.is_empty() && prefix != "NA").then(|| prefix.to_string())
+}
+
+/// Best-effort PTR lookup, giving up after a couple of seconds.
+pub(super) async fn reverse_lookup(ip: IpAddr) -> Option<String> {
+    let lookup = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&ip));
+    match tokio::time::timeout(Duration::from_secs(2), lookup).await {
+        Ok(Ok(Ok(name))) if name != ip.to_string() => Some(name),
+        Ok(Ok(Err(e))) => {
+            debug!("No PTR record for {}: {}", ip, e);
+            None
+        }
+        Err(_) => {
+            debug!("PTR lookup for {} timed out", ip);
+            None
+        }
+        _ => None,
+    }
+}
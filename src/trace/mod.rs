@@ -0,0 +1,354 @@
+//! Resolving, tracing and locating hops, without any UI.
+//!
+//! The GUI and headless mode both drive a [`Tracer`] and consume its [`TraceEvent`]s.
+
+pub mod helper;
+#[cfg(windows)]
+mod icmpapi;
+mod locate;
+mod mtu;
+mod probe;
+mod resolver;
+mod sweep;
+mod tracer;
+#[cfg(target_os = "linux")]
+mod udp;
+
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{i18n::{tr, trf}, scripts::Scripts, sites::SiteMap};
+
+pub use locate::{
+    get_location, get_locations, get_my_ip, ipinfo_quota, set_ipinfo_token, GeoProvider, IpInfoQuota, Location,
+};
+pub use mtu::{MtuReport, MSS_PORT};
+pub use resolver::{set_resolver, Resolver};
+pub use sweep::{size_sweep, sweep_sizes};
+pub use tracer::Tracer;
+
+/// A point on the globe, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    lat: f64,
+    lon: f64,
+}
+
+impl Position {
+    pub fn from_lat_lon(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn lon(&self) -> f64 {
+        self.lon
+    }
+}
+
+/// Probe size the tracert crate and most traceroutes send, 60 bytes over IPv4.
+pub const DEFAULT_PROBE_SIZE: u16 = 60;
+
+/// Knobs passed to the tracer for every new trace.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TraceOptions {
+    pub max_hops: u8,
+    /// How long to wait for each hop to answer
+    pub timeout_ms: u64,
+    /// Look up PTR names for hops the tracer didn't resolve
+    pub reverse_dns: bool,
+    /// How long geolocation results are reused, 0 to always ask the provider
+    pub cache_ttl_hours: u64,
+    /// TTLs probed at once over IPv4, 1 to probe them one after another
+    pub parallel_probes: u8,
+    /// Wait `adaptive_factor` times the slowest reply so far instead of `timeout_ms`, IPv4 only
+    pub adaptive_timeout: bool,
+    pub adaptive_factor: f64,
+    /// Adaptive timeouts never go below this
+    pub adaptive_min_ms: u64,
+    /// Bytes per probe including the IP header, for finding hops that treat big packets
+    /// differently. The tracert crate fallback ignores it
+    pub probe_size: u16,
+}
+
+impl TraceOptions {
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl_hours * 60 * 60)
+    }
+
+    /// Short descriptions of the probing knobs that differ from `base`, e.g. "20 hops".
+    pub fn differences(&self, base: &TraceOptions) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.max_hops != base.max_hops {
+            differences.push(trf("{} hops", &[&self.max_hops]));
+        }
+        if self.timeout_ms != base.timeout_ms {
+            differences.push(trf("{} ms timeout", &[&self.timeout_ms]));
+        }
+        if self.parallel_probes != base.parallel_probes {
+            differences.push(trf("{} parallel probes", &[&self.parallel_probes]));
+        }
+        if self.probe_size != base.probe_size {
+            differences.push(trf("{} B probes", &[&self.probe_size]));
+        }
+        if self.adaptive_timeout != base.adaptive_timeout {
+            let adaptive = if self.adaptive_timeout { "adaptive timeout on" } else { "adaptive timeout off" };
+            differences.push(tr(adaptive).to_string());
+        }
+        if self.reverse_dns != base.reverse_dns {
+            let reverse_dns = if self.reverse_dns { "reverse DNS on" } else { "reverse DNS off" };
+            differences.push(tr(reverse_dns).to_string());
+        }
+        differences
+    }
+
+    /// How long to wait for the next probe given the slowest reply so far.
+    /// `timeout_ms` is both the fixed timeout and the cap for adaptive ones.
+    pub fn probe_timeout(&self, slowest: Option<Duration>) -> Duration {
+        let max = Duration::from_millis(self.timeout_ms);
+        match slowest {
+            Some(slowest) if self.adaptive_timeout => slowest
+                .mul_f64(self.adaptive_factor)
+                .clamp(Duration::from_millis(self.adaptive_min_ms).min(max), max),
+            _ => max,
+        }
+    }
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            max_hops: 30,
+            timeout_ms: 1000,
+            reverse_dns: true,
+            cache_ttl_hours: 24,
+            parallel_probes: 1,
+            adaptive_timeout: false,
+            adaptive_factor: 3.0,
+            adaptive_min_ms: 100,
+            probe_size: DEFAULT_PROBE_SIZE,
+        }
+    }
+}
+
+/// What a trace was started with, so it can be run again the same way.
+#[derive(Clone, Copy)]
+pub struct TraceSettings {
+    pub options: TraceOptions,
+    pub geolocation: GeoProvider,
+}
+
+/// Local data used to fill in hops, shared with the trace tasks.
+#[derive(Clone, Default)]
+pub struct Enrichment {
+    pub scripts: Arc<Scripts>,
+    pub sites: Arc<SiteMap>,
+}
+
+impl Enrichment {
+    /// Loads the user scripts and the site map at `site_map`, if set.
+    pub fn load(site_map: &str) -> Self {
+        Self {
+            scripts: Arc::new(Scripts::load()),
+            sites: Arc::new(SiteMap::load_or_default(site_map)),
+        }
+    }
+}
+
+/// A located hop.
+#[derive(Clone)]
+pub struct TraceNode {
+    pub position: Position,
+    pub hostname: String,
+    pub isp: String,
+    /// Always in `address::canonical` form
+    pub ip: String,
+    pub asn: Option<u32>,
+    pub as_name: String,
+    /// Announced BGP prefix covering the IP
+    pub prefix: String,
+    pub country: String,
+    /// ISO 3166-1 alpha-2
+    pub country_code: String,
+    /// State or province, empty when unknown
+    pub region: String,
+    /// Empty when unknown
+    pub city: String,
+    /// Round trip time to this hop, none for the local node
+    pub rtt: Option<Duration>,
+    /// TTL left in the IP header of the reply, none for the local node and IPv6 hops
+    pub reply_ttl: Option<u8>,
+    /// Extra fields added by user scripts
+    pub fields: Vec<(String, String)>,
+    /// In a private or bogon range nobody could locate, so placed at the previous hop
+    pub private: bool,
+    /// Public, but the provider had no location for it yet, e.g. after a timeout or a 429. Placed
+    /// at the previous hop until looked up again
+    pub pending: bool,
+    /// The PTR lookup for `hostname` is still queued, see [`TraceEvent::Hostname`]
+    pub resolving: bool,
+}
+
+/// Initial TTLs operating systems commonly send with, lowest first.
+const INITIAL_TTLS: [(u8, &str); 3] = [
+    (64, "Linux, macOS or BSD"),
+    (128, "Windows"),
+    (255, "Router or network OS"),
+];
+
+impl TraceNode {
+    /// "City, Country", or whichever of the two is known.
+    pub fn place(&self) -> String {
+        match (self.city.is_empty(), self.country.is_empty()) {
+            (false, false) => format!("{}, {}", self.city, self.country),
+            (false, true) => self.city.clone(),
+            _ => self.country.clone(),
+        }
+    }
+
+    /// Whether any of the IP, hostname, ISP, AS or location contains `query`, ignoring case.
+    /// Both "AS3320" and "3320" match by AS number.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        let asn = self.asn.map(|asn| format!("as{}", asn)).unwrap_or_default();
+        [&self.ip, &self.hostname, &self.isp, &self.as_name, &asn, &self.country, &self.country_code, &self.city]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+
+    /// Takes over what a geolocation source says about the hop.
+    pub fn locate(&mut self, location: &Location) {
+        self.position = location.position;
+        self.isp = location.isp.clone();
+        self.asn = location.asn;
+        self.as_name = location.as_name.clone();
+        self.country = location.country.clone();
+        self.country_code = location.country_code.clone();
+        self.region = location.region.clone();
+        self.city = location.city.clone();
+        self.private = false;
+        self.pending = false;
+    }
+
+    /// Placed at the previous hop for want of a location of its own, or at the next one
+    /// with a location when nothing came before it.
+    pub fn guessed(&self) -> bool {
+        self.private || self.pending
+    }
+
+    /// The TTL the reply most likely started out with, and what usually sends that.
+    pub fn initial_ttl(&self) -> Option<(u8, &'static str)> {
+        let reply_ttl = self.reply_ttl?;
+        INITIAL_TTLS.into_iter().find(|(initial, _)| *initial >= reply_ttl)
+    }
+}
+
+#[derive(Clone)]
+pub enum TraceEvent {
+    /// The target is a name being looked up, sent before anything else about it
+    Resolving,
+    /// The address the target resolved to, or is, which the hops lead to
+    Resolved(IpAddr),
+    /// A located hop and its TTL, 0 for the local node
+    Node(usize, Box<TraceNode>),
+    /// No reply came back for this TTL
+    Timeout(usize),
+    /// Probing moved on to this TTL, after a reply from the one before it. Sent as soon as
+    /// the reply is in, ahead of the hop it came from, which still has to be located
+    Probing(usize),
+    /// Result of the deferred PTR lookup for the hop at this TTL, none if it has no name.
+    /// All of them arrive before `Finish`.
+    Hostname(usize, Option<String>),
+    /// Lines from the scripts' trace hooks, sent right before `Finish`
+    Summary(Vec<String>),
+    /// Locations found for pending hops by IP, when they're looked up again after `Finish`
+    Located(Vec<(String, Location)>),
+    /// Something went wrong, e.g. the target didn't resolve. The trace still ends with `Finish`
+    /// and keeps whatever it found.
+    Error(String),
+    /// Probing stopped before the target, e.g. on a socket error or after a suspend. The hops
+    /// found so far stand and the trace can pick up after them with `Tracer::resume`.
+    Interrupted(String),
+    Finish,
+}
+
+/// Whether this process may open raw sockets, which every trace method but the Windows
+/// ICMP API and Linux UDP probes needs.
+pub fn raw_sockets() -> bool {
+    Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).is_ok()
+}
+
+/// Sends one echo request and waits for the reply, none when it got lost.
+/// Errors only when pinging isn't possible at all, e.g. without raw socket privileges.
+pub fn ping(ip: IpAddr, timeout: Duration) -> Result<Option<Duration>, String> {
+    #[cfg(windows)]
+    if !raw_sockets() {
+        return match ip {
+            IpAddr::V4(ip) => icmpapi::ping(ip, timeout).map_err(|e| e.to_string()),
+            IpAddr::V6(_) => Err("Pinging IPv6 needs administrator rights".to_string()),
+        };
+    }
+    let mut pinger = tracert::ping::Pinger::new(ip)?;
+    pinger.set_count(1);
+    pinger.set_receive_timeout(timeout);
+    // Only replies are reported, so an empty result is a lost probe
+    match pinger.ping() {
+        Ok(result) => Ok(result.results.first().map(|node| node.rtt)),
+        Err(e) => {
+            debug!("Ping to {} failed: {}", ip, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Every address `target` stands for: itself when it's an IP, else all its A and AAAA records.
+/// CDNs and anycast services answer with several, of which [`resolve`] only takes the first.
+pub fn resolve_all(target: &str) -> Result<Vec<IpAddr>, String> {
+    if let Ok(ip) = target.parse() {
+        return Ok(vec![ip]);
+    }
+    let ips = resolver::lookup(target).map_err(|e| format!("Could not resolve {}: {}", target, e))?;
+    // The system resolver repeats addresses once per socket type
+    let mut unique = Vec::new();
+    for ip in ips {
+        if !unique.contains(&ip) {
+            unique.push(ip);
+        }
+    }
+    Ok(unique)
+}
+
+/// Parses `target` as an IP, or resolves it as a domain name through the resolver set with
+/// [`set_resolver`].
+pub fn resolve(target: &str) -> Option<IpAddr> {
+    match target.parse::<IpAddr>() {
+        Ok(ip) => {
+            debug!("Parsed direct IP: {}", ip);
+            Some(ip)
+        }
+        Err(_) => {
+            debug!("Attempting DNS lookup for: {}", target);
+            match resolver::lookup(target) {
+                Ok(ips) => {
+                    if let Some(ip) = ips.first() {
+                        debug!("DNS lookup successful: {}", ip);
+                        Some(*ip)
+                    } else {
+                        error!("DNS lookup returned no results");
+                        None
+                    }
+                }
+                Err(e) => {
+                    error!("DNS lookup failed: {}", e);
+                    None
+                }
+            }
+        }
+    }
+}
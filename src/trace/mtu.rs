@@ -0,0 +1,223 @@
+//! Path MTU and TCP MSS towards a target, for spotting tunnels that need MSS clamping.
+
+use std::{
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::probe::checksum;
+
+const ECHO_REPLY: u8 = 0;
+const DESTINATION_UNREACHABLE: u8 = 3;
+/// Destination unreachable code for "fragmentation needed and DF set"
+const FRAGMENTATION_NEEDED: u8 = 4;
+const ECHO_REQUEST: u8 = 8;
+/// IPv4 and ICMP headers in front of the echo payload.
+const ICMP_OVERHEAD: usize = 28;
+/// IPv4 and TCP headers in front of a segment.
+const TCP_OVERHEAD: u16 = 40;
+/// Every IPv4 host has to accept datagrams of this size.
+const MIN_MTU: u16 = 576;
+const ETHERNET_MTU: u16 = 1500;
+/// Tries before a size that got no answer counts as too big.
+const ATTEMPTS: usize = 2;
+/// Where the TCP MSS is read from, HTTPS being the port most targets answer on.
+pub const MSS_PORT: u16 = 443;
+
+/// What came back for one probe.
+enum Probe {
+    Fits,
+    /// Too big somewhere on the path, with the next hop MTU if the router said
+    TooBig(Option<u16>),
+    Lost,
+}
+
+/// Largest IPv4 packet that reaches `dst` unfragmented, between 576 and 1500 bytes.
+/// None if the target never answered pings at all.
+///
+/// Needs raw socket privileges, and Linux to set the don't fragment bit.
+pub fn path_mtu(dst: Ipv4Addr, timeout: Duration) -> io::Result<Option<u16>> {
+    let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
+    set_dont_fragment(&socket)?;
+    let id = std::process::id() as u16 ^ 0x4d54;
+    let mut seq = 0;
+    let mut probe = |size: u16| -> io::Result<Probe> {
+        for _ in 0..ATTEMPTS {
+            seq += 1;
+            match send_probe(&socket, dst, id, seq, size, timeout)? {
+                Probe::Lost => continue,
+                answer => return Ok(answer),
+            }
+        }
+        Ok(Probe::Lost)
+    };
+
+    // Largest size known to fit, and smallest known not to
+    let mut fits = None;
+    let mut too_big = ETHERNET_MTU + 1;
+    let mut size = ETHERNET_MTU;
+    loop {
+        match probe(size)? {
+            Probe::Fits => fits = Some(size),
+            Probe::TooBig(Some(mtu)) if (MIN_MTU..size).contains(&mtu) && fits.is_none_or(|f| mtu > f) => {
+                too_big = size;
+                // Routers usually tell the truth, try exactly that next
+                debug!("{} bytes too big, next hop MTU {}", size, mtu);
+                size = mtu;
+                continue;
+            }
+            Probe::TooBig(_) | Probe::Lost => too_big = size,
+        }
+        let low = fits.unwrap_or(MIN_MTU - 1);
+        if too_big - low <= 1 {
+            break;
+        }
+        size = low + (too_big - low) / 2;
+    }
+    Ok(fits)
+}
+
+fn send_probe(socket: &Socket, dst: Ipv4Addr, id: u16, seq: u16, size: u16, timeout: Duration) -> io::Result<Probe> {
+    let mut packet = vec![0; size as usize - ICMP_OVERHEAD + 8];
+    packet[0] = ECHO_REQUEST;
+    packet[4..6].copy_from_slice(&id.to_be_bytes());
+    packet[6..8].copy_from_slice(&seq.to_be_bytes());
+    let sum = checksum(&packet);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+
+    let target = SocketAddr::new(IpAddr::V4(dst), 0).into();
+    match socket.send_to(&packet, &target) {
+        Ok(_) => {}
+        // Larger than the local interface allows
+        Err(e) if e.raw_os_error() == Some(EMSGSIZE) => return Ok(Probe::TooBig(None)),
+        Err(e) => return Err(e),
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0; 2048];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(Probe::Lost);
+        }
+        socket.set_read_timeout(Some(left))?;
+        let len = match (&*socket).read(&mut buf) {
+            Ok(len) => len,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(answer) = parse_answer(&buf[..len], id, seq) {
+            return Ok(answer);
+        }
+    }
+}
+
+/// Whether an IPv4 packet answers our probe, and how.
+fn parse_answer(packet: &[u8], id: u16, seq: u16) -> Option<Probe> {
+    let header_len = (*packet.first()? & 0x0f) as usize * 4;
+    let icmp = packet.get(header_len..)?;
+    let ours = |probe: &[u8]| -> Option<bool> {
+        Some(probe.get(4..6)? == id.to_be_bytes() && probe.get(6..8)? == seq.to_be_bytes())
+    };
+    match (*icmp.first()?, *icmp.get(1)?) {
+        (ECHO_REPLY, _) => ours(icmp)?.then_some(Probe::Fits),
+        (DESTINATION_UNREACHABLE, FRAGMENTATION_NEEDED) => {
+            let mtu = u16::from_be_bytes([*icmp.get(6)?, *icmp.get(7)?]);
+            // The original IP header and the start of our echo request follow
+            let inner = icmp.get(8..)?;
+            let inner_len = (*inner.first()? & 0x0f) as usize * 4;
+            let probe = inner.get(inner_len..)?;
+            (*probe.first()? == ECHO_REQUEST && ours(probe)?).then_some(Probe::TooBig((mtu > 0).then_some(mtu)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+const EMSGSIZE: i32 = libc::EMSGSIZE;
+// Never reached without DF support, but it's the same number on macOS and the BSDs
+#[cfg(not(target_os = "linux"))]
+const EMSGSIZE: i32 = 40;
+
+/// Sets DF on everything sent, ignoring what the kernel already learned about the path.
+#[cfg(target_os = "linux")]
+fn set_dont_fragment(socket: &Socket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let value: libc::c_int = libc::IP_PMTUDISC_PROBE;
+    // SAFETY: the socket is open and value outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_MTU_DISCOVER,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_dont_fragment(_: &Socket) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "path MTU discovery is only supported on Linux"))
+}
+
+/// MSS of a TCP connection to `ip`, the smaller of ours and the one the target advertised.
+#[cfg(unix)]
+pub fn tcp_mss(ip: IpAddr, port: u16, timeout: Duration) -> io::Result<u16> {
+    let socket = Socket::new(Domain::for_address(SocketAddr::new(ip, port)), Type::STREAM, Some(Protocol::TCP))?;
+    socket.connect_timeout(&SocketAddr::new(ip, port).into(), timeout)?;
+    Ok(socket.mss()? as u16)
+}
+
+#[cfg(not(unix))]
+pub fn tcp_mss(_: IpAddr, _: u16, _: Duration) -> io::Result<u16> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reading the TCP MSS is only supported on Unix"))
+}
+
+/// Path MTU and TCP MSS to a target, with errors kept for display.
+#[derive(Clone)]
+pub struct MtuReport {
+    pub path_mtu: Result<Option<u16>, String>,
+    pub mss: Result<u16, String>,
+}
+
+impl MtuReport {
+    /// Measures both, blocking for a few seconds at most.
+    pub fn measure(ip: IpAddr, port: u16, timeout: Duration) -> Self {
+        let path_mtu = match ip {
+            IpAddr::V4(v4) => path_mtu(v4, timeout).map_err(|e| e.to_string()),
+            IpAddr::V6(_) => Err("IPv4 only".to_string()),
+        };
+        Self {
+            path_mtu,
+            mss: tcp_mss(ip, port, timeout).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// One line of advice, none when there's nothing to go on.
+    pub fn advice(&self) -> Option<String> {
+        let mtu = (*self.path_mtu.as_ref().ok()?)?;
+        let fitting = mtu - TCP_OVERHEAD;
+        Some(match self.mss {
+            Ok(mss) if mss > fitting => format!(
+                "Path MTU {}, but TCP MSS {} needs {}; clamp the MSS to {}",
+                mtu,
+                mss,
+                mss + TCP_OVERHEAD,
+                fitting
+            ),
+            _ if mtu < ETHERNET_MTU => format!("Path MTU {}, likely a tunnel; consider an MSS clamp of {}", mtu, fitting),
+            _ => format!("Path MTU {}, no MSS clamping needed", mtu),
+        })
+    }
+}
@@ -0,0 +1,183 @@
+//! ICMP echo traceroute that keeps several TTLs in flight at once.
+//!
+//! The tracert crate waits for every TTL before sending the next, so a path with a few
+//! silent hops takes tens of seconds. This sends up to `concurrency` probes at a time and
+//! reports hops in TTL order as soon as everything before them is settled.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicU16, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use socket2::{Domain, Protocol, Socket, Type};
+use tracert::node::{Node, NodeType};
+
+const ECHO_REPLY: u8 = 0;
+const DESTINATION_UNREACHABLE: u8 = 3;
+const ECHO_REQUEST: u8 = 8;
+const TIME_EXCEEDED: u8 = 11;
+const IPV4_HEADER: usize = 20;
+/// How long a single receive blocks before checking for timed out probes.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Distinguishes concurrent traces, a raw socket sees every ICMP packet on the host.
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// An answered probe.
+struct Reply {
+    ip: IpAddr,
+    rtt: Duration,
+    reply_ttl: u8,
+    /// The target itself answered, or refused the probe
+    reached: bool,
+}
+
+/// Traces `dst` over IPv4 from `first_ttl` on, handing hops to `report` in TTL order until it
+/// returns false. TTLs that never answered are skipped, like the tracert crate does.
+///
+/// Each probe waits for `timeout` of the slowest reply seen when it was sent, and is `size`
+/// bytes long including the IP header. Errors when the raw socket can't be opened, e.g. without privileges.
+pub fn trace(
+    dst: Ipv4Addr,
+    first_ttl: u8,
+    max_hops: u8,
+    size: u16,
+    timeout: impl Fn(Option<Duration>) -> Duration,
+    concurrency: u8,
+    mut report: impl FnMut(Node) -> bool,
+) -> io::Result<()> {
+    let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    let target = SocketAddr::new(IpAddr::V4(dst), 0).into();
+    let id = (std::process::id() as u16).wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+    // u16 so stepping past a max_hops of 255 doesn't overflow
+    let mut sent: HashMap<u16, (Instant, Duration)> = HashMap::new();
+    let mut replies: HashMap<u16, Reply> = HashMap::new();
+    let mut next_send = first_ttl.max(1) as u16;
+    let mut next_report = next_send;
+    // Lowest TTL the target answered at, nothing past it is interesting
+    let mut last = max_hops as u16;
+    let mut slowest: Option<Duration> = None;
+    let mut buf = [0; 1500];
+
+    while next_report <= last {
+        // Keep the window full
+        while next_send <= last && next_send < next_report + concurrency.max(1) as u16 {
+            socket.set_ttl(next_send as u32)?;
+            socket.send_to(&echo_request(id, next_send, size), &target)?;
+            sent.insert(next_send, (Instant::now(), timeout(slowest)));
+            next_send += 1;
+        }
+
+        match (&socket).read(&mut buf) {
+            Ok(len) => {
+                if let Some((ttl, ip, reply_ttl, reached)) = parse_reply(&buf[..len], id, dst) {
+                    if let (Some((at, _)), false) = (sent.get(&ttl), replies.contains_key(&ttl)) {
+                        debug!("TTL {} answered by {}", ttl, ip);
+                        let rtt = at.elapsed();
+                        slowest = slowest.max(Some(rtt));
+                        replies.insert(ttl, Reply { ip, rtt, reply_ttl, reached });
+                        if reached {
+                            last = last.min(ttl);
+                        }
+                    }
+                }
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e),
+        }
+
+        // Report everything that is settled, in order
+        while next_report <= last {
+            let settled = replies.contains_key(&next_report)
+                || sent.get(&next_report).is_some_and(|(at, timeout)| at.elapsed() >= *timeout);
+            if !settled {
+                break;
+            }
+            if let Some(reply) = replies.remove(&next_report) {
+                let ttl = next_report as u8;
+                let node = Node {
+                    seq: ttl,
+                    ip_addr: reply.ip,
+                    host_name: reply.ip.to_string(),
+                    ttl: Some(reply.reply_ttl),
+                    hop: Some(ttl),
+                    node_type: if reply.reached {
+                        NodeType::Destination
+                    } else if next_report == 1 {
+                        NodeType::DefaultGateway
+                    } else {
+                        NodeType::Relay
+                    },
+                    rtt: reply.rtt,
+                };
+                if !report(node) {
+                    return Ok(());
+                }
+            }
+            next_report += 1;
+        }
+    }
+    Ok(())
+}
+
+/// An echo request that makes an IPv4 packet of `size` bytes, padded with zeros.
+fn echo_request(id: u16, seq: u16, size: u16) -> Vec<u8> {
+    let mut packet = vec![0; (size as usize).saturating_sub(IPV4_HEADER).max(8)];
+    packet[0] = ECHO_REQUEST;
+    packet[4..6].copy_from_slice(&id.to_be_bytes());
+    packet[6..8].copy_from_slice(&seq.to_be_bytes());
+    let checksum = checksum(&packet);
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// Internet checksum, RFC 1071.
+pub(super) fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Picks the TTL, sender and reply TTL out of an IPv4 packet answering one of our probes,
+/// and whether it came from the target.
+fn parse_reply(packet: &[u8], id: u16, dst: Ipv4Addr) -> Option<(u16, IpAddr, u8, bool)> {
+    let header_len = (*packet.first()? & 0x0f) as usize * 4;
+    let reply_ttl = *packet.get(8)?;
+    let source = Ipv4Addr::new(*packet.get(12)?, packet[13], packet[14], packet[15]);
+    let icmp = packet.get(header_len..)?;
+
+    let (probe, reached) = match *icmp.first()? {
+        ECHO_REPLY => (icmp, true),
+        TIME_EXCEEDED | DESTINATION_UNREACHABLE => {
+            // The original IP header and the first bytes of our echo request follow
+            let inner = icmp.get(8..)?;
+            let inner_len = (*inner.first()? & 0x0f) as usize * 4;
+            if *inner.get(9)? != 1 {
+                return None;
+            }
+            let probe = inner.get(inner_len..)?;
+            if *probe.first()? != ECHO_REQUEST {
+                return None;
+            }
+            (probe, source == dst)
+        }
+        _ => return None,
+    };
+    let probe_id = u16::from_be_bytes([*probe.get(4)?, *probe.get(5)?]);
+    let seq = u16::from_be_bytes([*probe.get(6)?, *probe.get(7)?]);
+    if probe_id != id || seq == 0 || seq > u8::MAX as u16 {
+        return None;
+    }
+    Some((seq, IpAddr::V4(source), reply_ttl, reached))
+}
@@ -0,0 +1,222 @@
+//! Resolving target names, through the system or a DNS server of the user's choice.
+//!
+//! Captive portals and corporate networks like to rewrite answers from the system resolver,
+//! so it can be swapped for a plain DNS server or DNS over HTTPS.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::RwLock,
+    thread,
+    time::Duration,
+};
+
+use dns_lookup::lookup_host;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+/// Who answers name lookups for targets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resolver {
+    #[default]
+    System,
+    /// A DNS server asked directly over UDP, see [`set_resolver`]
+    Server,
+    Cloudflare,
+    Google,
+}
+
+impl Resolver {
+    pub const ALL: [Resolver; 4] = [Resolver::System, Resolver::Server, Resolver::Cloudflare, Resolver::Google];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resolver::System => "System",
+            Resolver::Server => "DNS server",
+            Resolver::Cloudflare => "Cloudflare DoH",
+            Resolver::Google => "Google DoH",
+        }
+    }
+
+    /// JSON API of the DNS over HTTPS services.
+    fn doh_url(&self) -> Option<&'static str> {
+        match self {
+            Resolver::Cloudflare => Some("https://cloudflare-dns.com/dns-query"),
+            Resolver::Google => Some("https://dns.google/resolve"),
+            _ => None,
+        }
+    }
+}
+
+/// The resolver every lookup goes through, and the server for [`Resolver::Server`].
+static RESOLVER: RwLock<(Resolver, Option<IpAddr>)> = RwLock::new((Resolver::System, None));
+
+/// Switches every later lookup to `resolver`. `server` is the IP of the DNS server to ask
+/// for [`Resolver::Server`], lookups stay with the system if it isn't one.
+pub fn set_resolver(resolver: Resolver, server: &str) {
+    let server = server.trim().parse().ok();
+    if resolver == Resolver::Server && server.is_none() {
+        warn!("No valid DNS server set, resolving through the system");
+    }
+    *RESOLVER.write().unwrap() = (resolver, server);
+}
+
+/// Every address `host` resolves to, through the resolver set with [`set_resolver`].
+/// Custom resolvers list IPv4 addresses first.
+pub fn lookup(host: &str) -> io::Result<Vec<IpAddr>> {
+    let (resolver, server) = *RESOLVER.read().unwrap();
+    match (resolver, server) {
+        (Resolver::Server, Some(server)) => {
+            let mut ips = query(server, host, TYPE_A)?;
+            ips.extend(query(server, host, TYPE_AAAA).unwrap_or_default());
+            Ok(ips)
+        }
+        (Resolver::Cloudflare | Resolver::Google, _) => {
+            let url = resolver.doh_url().unwrap_or_default();
+            let host = host.to_string();
+            // The blocking client can't run on a tokio worker, and callers may be on one
+            thread::spawn(move || {
+                let mut ips = doh(url, &host, TYPE_A)?;
+                ips.extend(doh(url, &host, TYPE_AAAA).unwrap_or_default());
+                Ok(ips)
+            })
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("DNS over HTTPS lookup panicked")))
+        }
+        _ => lookup_host(host),
+    }
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u16,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    kind: u16,
+    data: String,
+}
+
+fn doh(url: &str, host: &str, kind: u16) -> io::Result<Vec<IpAddr>> {
+    debug!("Resolving {} over HTTPS at {}", host, url);
+    let response: DohResponse = reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .and_then(|client| {
+            client
+                .get(url)
+                .query(&[("name", host), ("type", &kind.to_string())])
+                .header(reqwest::header::ACCEPT, "application/dns-json")
+                .send()?
+                .error_for_status()?
+                .json()
+        })
+        .map_err(io::Error::other)?;
+    if response.status != 0 {
+        return Err(io::Error::other(format!("{} answered with DNS error {}", url, response.status)));
+    }
+    // CNAMEs come along in the answer, only the addresses they lead to are of interest
+    Ok(response
+        .answer
+        .iter()
+        .filter(|answer| answer.kind == kind)
+        .filter_map(|answer| answer.data.parse().ok())
+        .collect())
+}
+
+/// Asks `server` for the `kind` records of `host` over UDP.
+fn query(server: IpAddr, host: &str, kind: u16) -> io::Result<Vec<IpAddr>> {
+    debug!("Resolving {} at {}", host, server);
+    let bind: SocketAddr = match server {
+        IpAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        IpAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect((server, 53))?;
+    let id = std::process::id() as u16 ^ kind;
+
+    let mut request = Vec::with_capacity(host.len() + 18);
+    // Header: id, recursion desired, one question
+    request.extend_from_slice(&id.to_be_bytes());
+    request.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid name {}", host)));
+        }
+        request.push(label.len() as u8);
+        request.extend_from_slice(label.as_bytes());
+    }
+    request.push(0);
+    request.extend_from_slice(&kind.to_be_bytes());
+    request.extend_from_slice(&1u16.to_be_bytes());
+    socket.send(&request)?;
+
+    let mut buf = [0; 1500];
+    loop {
+        let len = socket.recv(&mut buf)?;
+        // Late answers to an earlier question may still arrive
+        if let Some(ips) = parse_answer(&buf[..len], id, kind)? {
+            return Ok(ips);
+        }
+    }
+}
+
+/// Addresses of `kind` in a DNS response, none if it isn't the response to `id`.
+fn parse_answer(packet: &[u8], id: u16, kind: u16) -> io::Result<Option<Vec<IpAddr>>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Malformed DNS response");
+    let header = packet.get(..12).ok_or_else(invalid)?;
+    if u16::from_be_bytes([header[0], header[1]]) != id {
+        return Ok(None);
+    }
+    match header[3] & 0x0f {
+        0 => {}
+        // No such name
+        3 => return Ok(Some(Vec::new())),
+        rcode => return Err(io::Error::other(format!("DNS server answered with error {}", rcode))),
+    }
+    let questions = u16::from_be_bytes([header[4], header[5]]);
+    let answers = u16::from_be_bytes([header[6], header[7]]);
+
+    let mut at = 12;
+    for _ in 0..questions {
+        at = skip_name(packet, at).ok_or_else(invalid)? + 4;
+    }
+    let mut ips = Vec::new();
+    for _ in 0..answers {
+        at = skip_name(packet, at).ok_or_else(invalid)?;
+        let fixed = packet.get(at..at + 10).ok_or_else(invalid)?;
+        let record_kind = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let data = packet.get(at + 10..at + 10 + len).ok_or_else(invalid)?;
+        match (record_kind, <[u8; 4]>::try_from(data), <[u8; 16]>::try_from(data)) {
+            (TYPE_A, Ok(v4), _) if kind == TYPE_A => ips.push(IpAddr::from(v4)),
+            (TYPE_AAAA, _, Ok(v6)) if kind == TYPE_AAAA => ips.push(IpAddr::from(v6)),
+            _ => {}
+        }
+        at += 10 + len;
+    }
+    Ok(Some(ips))
+}
+
+/// Offset just past the possibly compressed name at `at`.
+fn skip_name(packet: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(at)?;
+        match len {
+            0 => return Some(at + 1),
+            // A pointer ends the name
+            len if len & 0xc0 == 0xc0 => return Some(at + 2),
+            len => at += 1 + len as usize,
+        }
+    }
+}
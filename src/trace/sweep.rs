@@ -0,0 +1,62 @@
+//! Tracing the same target with growing probes, for hops that get slower or drop packets
+//! as they get bigger, e.g. tunnels that fragment or links that rate limit large ICMP.
+
+use std::{net::IpAddr, time::Duration};
+
+use tracert::node::Node;
+
+use super::{probe, TraceOptions};
+
+/// `steps` probe sizes spread evenly from `from` to `to` bytes, both included.
+pub fn sweep_sizes(from: u16, to: u16, steps: usize) -> Vec<u16> {
+    let (from, to) = (from.min(to), from.max(to));
+    if steps < 2 || from == to {
+        return vec![from];
+    }
+    let mut sizes: Vec<u16> = (0..steps)
+        .map(|i| from + ((to - from) as usize * i / (steps - 1)) as u16)
+        .collect();
+    sizes.dedup();
+    sizes
+}
+
+/// Traces `ip` once per size in `sizes`, handing each hop to `report` along with the size it
+/// was probed with, until it returns false. Blocks until every size is done.
+pub fn size_sweep(ip: IpAddr, options: TraceOptions, sizes: &[u16], mut report: impl FnMut(u16, Node) -> bool) -> Result<(), String> {
+    for &size in sizes {
+        let mut stopped = false;
+        trace_sized(ip, options, size, |node| {
+            stopped = !report(size, node);
+            !stopped
+        })?;
+        if stopped {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// One trace with `size` byte probes, through whichever backend this process can use.
+/// Unlike the tracer there is no tracert crate fallback, it can't change its probe size.
+fn trace_sized(ip: IpAddr, options: TraceOptions, size: u16, report: impl FnMut(Node) -> bool) -> Result<(), String> {
+    #[cfg(windows)]
+    if !super::raw_sockets() {
+        let IpAddr::V4(dst) = ip else {
+            return Err("Tracing IPv6 needs administrator rights".to_string());
+        };
+        let timeout = Duration::from_millis(options.timeout_ms);
+        return super::icmpapi::trace(dst, 1, options.max_hops, size, timeout, report).map_err(|e| e.to_string());
+    }
+    #[cfg(target_os = "linux")]
+    if !super::raw_sockets() || ip.is_ipv6() {
+        let timeout = Duration::from_millis(options.timeout_ms);
+        return super::udp::trace(ip, 1, options.max_hops, size, timeout, report).map_err(|e| e.to_string());
+    }
+    match ip {
+        IpAddr::V4(dst) => {
+            let timeout = |slowest: Option<Duration>| options.probe_timeout(slowest);
+            probe::trace(dst, 1, options.max_hops, size, timeout, options.parallel_probes, report).map_err(|e| e.to_string())
+        }
+        IpAddr::V6(_) => Err("Size sweeps over IPv6 need Linux".to_string()),
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use tokio::sync::mpsc;
+use tracert::node::Node;
+
+use super::{
+    locate::{bgp_prefix, get_locations, get_my_ip, get_location, reverse_lookup},
+    probe,
+    resolve, Enrichment, GeoProvider, Location, Position, TraceEvent, TraceNode, TraceOptions, DEFAULT_PROBE_SIZE,
+};
+use crate::{i18n::{tr, trf}, scripts::Scripts, sites};
+
+/// Runs the scripts' hop hooks off the async threads, since they may block on `http_get`.
+async fn enrich(scripts: &Arc<Scripts>, mut node: TraceNode) -> Option<TraceNode> {
+    if scripts.is_empty() {
+        return Some(node);
+    }
+    let scripts = scripts.clone();
+    tokio::task::spawn_blocking(move || {
+        node.fields = scripts.hop(&node);
+        node
+    })
+    .await
+    .ok()
+}
+
+/// Runs the hop hooks on `node` and sends it, then queues its PTR lookup if `ptr` is set.
+async fn send_hop(
+    tx: &mpsc::UnboundedSender<TraceEvent>,
+    scripts: &Arc<Scripts>,
+    ptr_tx: &mpsc::UnboundedSender<(usize, IpAddr)>,
+    nodes: &mut Vec<(usize, TraceNode)>,
+    ttl: usize,
+    node: TraceNode,
+    ptr: Option<IpAddr>,
+) {
+    if let Some(node) = enrich(scripts, node).await {
+        nodes.push((ttl, node.clone()));
+        tx.send(TraceEvent::Node(ttl, Box::new(node))).ok();
+        if let Some(ip) = ptr {
+            ptr_tx.send((ttl, ip)).ok();
+        }
+    }
+}
+
+async fn finish(
+    tx: &mpsc::UnboundedSender<TraceEvent>,
+    scripts: &Arc<Scripts>,
+    target: String,
+    nodes: Vec<TraceNode>,
+) {
+    if !scripts.is_empty() {
+        let scripts = scripts.clone();
+        if let Ok(lines) = tokio::task::spawn_blocking(move || scripts.trace(&target, &nodes)).await {
+            tx.send(TraceEvent::Summary(lines)).ok();
+        }
+    }
+    tx.send(TraceEvent::Finish).ok();
+}
+
+/// How long to wait for more hops before locating the ones already in.
+const LOCATION_BATCH_WINDOW: Duration = Duration::from_millis(500);
+/// How long resolving the target may take before the trace gives up.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Traces `ip` over raw sockets from `first_ttl` on, handing hops to `report` in TTL order
+/// until it returns false.
+pub(super) fn trace_raw(
+    mut tracer: tracert::trace::Tracer,
+    ip: IpAddr,
+    options: TraceOptions,
+    first_ttl: u8,
+    mut report: impl FnMut(Node) -> bool,
+) -> Result<(), String> {
+    // The tracert crate only does one fixed timeout, one TTL at a time, always from the first.
+    // It's also the fallback for resuming, so it at least doesn't probe further than needed
+    let resuming = first_ttl > 1;
+    let custom = options.parallel_probes > 1 || options.adaptive_timeout || options.probe_size != DEFAULT_PROBE_SIZE;
+    if let (IpAddr::V4(dst), true) = (ip, custom || resuming) {
+        let timeout = |slowest| options.probe_timeout(slowest);
+        let size = options.probe_size;
+        match probe::trace(dst, first_ttl, options.max_hops, size, timeout, options.parallel_probes, &mut report) {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!("Probing failed, falling back to the tracert crate: {}", e),
+        }
+    }
+
+    tracer.set_max_hop(options.max_hops);
+    tracer.set_receive_timeout(Duration::from_millis(options.timeout_ms));
+    let progress_receiver = tracer.get_progress_receiver();
+    let trace = thread::spawn(move || tracer.trace());
+
+    while let Ok(node) = progress_receiver.lock().unwrap().recv() {
+        // Hops before `first_ttl` were already there
+        if node.seq >= first_ttl {
+            report(node);
+        }
+    }
+    // It unwraps its socket setup, so missing privileges show up as a panic
+    match trace.join() {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(trf("Tracing failed: {}", &[&e])),
+        Err(panic) => {
+            let reason = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_default();
+            Err(trf("The tracer crashed: {}", &[&reason]))
+        }
+    }
+}
+
+/// Starts traces with fixed options, each one reporting back through its own channel.
+#[derive(Clone)]
+pub struct Tracer {
+    options: TraceOptions,
+    geolocation: GeoProvider,
+    enrichment: Enrichment,
+    client: reqwest::Client,
+}
+
+impl Tracer {
+    pub fn new(options: TraceOptions, geolocation: GeoProvider, enrichment: Enrichment) -> Self {
+        Self {
+            options,
+            geolocation,
+            enrichment,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Traces `target`, a host name or IP. Hops arrive located and enriched, in TTL order,
+    /// and the channel always ends with [`TraceEvent::Finish`], also when the trace couldn't
+    /// get going. Returns right away, resolving and probing happen on the runtime.
+    pub async fn trace(&self, target: &str) -> mpsc::UnboundedReceiver<TraceEvent> {
+        info!("Starting trace for target: {}", target);
+        self.start(target, Vec::new())
+    }
+
+    /// Picks up an interrupted trace of `target` after the last of the hops it already
+    /// found, which aren't probed or located again. Only the new hops arrive, the scripts'
+    /// trace hooks still see all of them.
+    pub async fn resume(&self, target: &str, found: Vec<(usize, TraceNode)>) -> mpsc::UnboundedReceiver<TraceEvent> {
+        info!("Resuming trace for target {} after {} hops", target, found.len());
+        self.start(target, found)
+    }
+
+    fn start(&self, target: &str, found: Vec<(usize, TraceNode)>) -> mpsc::UnboundedReceiver<TraceEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(self.clone().run(target.to_string(), found, tx));
+        rx
+    }
+
+    async fn run(self, target: String, found: Vec<(usize, TraceNode)>, tx: mpsc::UnboundedSender<TraceEvent>) {
+        let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+        let Self { options, geolocation, client, enrichment } = self;
+        let Enrichment { scripts, sites } = enrichment;
+        let first_ttl = found.iter().map(|(ttl, _)| *ttl + 1).max().unwrap_or(1).min(u8::MAX as usize) as u8;
+        let resuming = !found.is_empty();
+        // Everything sent so far, for the scripts' trace hooks
+        let mut nodes = found;
+
+        // Get my ip first, unless it's already there
+        let my_ip = if resuming { None } else { get_my_ip(&client).await };
+        if my_ip.is_none() && !resuming {
+            tx.send(TraceEvent::Error(tr("Couldn't find the public IP of this machine").to_string())).ok();
+        }
+        if let Some(ip) = my_ip {
+            let location = get_location(&client, geolocation, &sites, ip.as_str(), options.cache_ttl()).await;
+            if location.is_none() {
+                let error = trf("Couldn't locate this machine with {}", &[&geolocation.name()]);
+                tx.send(TraceEvent::Error(error)).ok();
+            }
+            if let Some(location) = location {
+                let prefix = match ip.parse() {
+                    Ok(addr) => bgp_prefix(addr).await.unwrap_or_default(),
+                    Err(_) => String::new(),
+                };
+                let node = TraceNode {
+                    position: location.position,
+                    hostname: tr("Local").to_string(),
+                    isp: location.isp,
+                    ip,
+                    asn: location.asn,
+                    as_name: location.as_name,
+                    prefix,
+                    country: location.country,
+                    country_code: location.country_code,
+                    region: location.region,
+                    city: location.city,
+                    rtt: None,
+                    reply_ttl: None,
+                    fields: Vec::new(),
+                    private: false,
+                    pending: false,
+                    resolving: false,
+                };
+                if let Some(node) = enrich(&scripts, node).await {
+                    nodes.push((0, node.clone()));
+                    tx.send(TraceEvent::Node(0, Box::new(node))).ok();
+                }
+            }
+        }
+
+        // Resolve domain name or parse IP, off the async threads since lookups block
+        if target.parse::<IpAddr>().is_err() {
+            tx.send(TraceEvent::Resolving).ok();
+        }
+        let lookup = {
+            let target = target.clone();
+            tokio::task::spawn_blocking(move || resolve(&target))
+        };
+        let ip = match tokio::time::timeout(RESOLVE_TIMEOUT, lookup).await {
+            Ok(Ok(Some(ip))) => ip,
+            result => {
+                let error = match result {
+                    Err(_) => trf("Resolving {} timed out after {} s", &[&target, &RESOLVE_TIMEOUT.as_secs()]),
+                    Ok(_) => trf("Couldn't resolve {}", &[&target]),
+                };
+                tx.send(TraceEvent::Error(error)).ok();
+                finish(&tx, &scripts, target, nodes.into_iter().map(|(_, node)| node).collect()).await;
+                return;
+            }
+        };
+        tx.send(TraceEvent::Resolved(ip)).ok();
+
+        debug!("Starting tracer for IP: {}", ip);
+        let tracer = match tracert::trace::Tracer::new(ip) {
+            Ok(tracer) => tracer,
+            Err(e) => {
+                tx.send(TraceEvent::Error(trf("Failed to start tracer: {}", &[&e]))).ok();
+                finish(&tx, &scripts, target, nodes.into_iter().map(|(_, node)| node).collect()).await;
+                return;
+            }
+        };
+        // Sent before the hops run out, so they always come ahead of `Finish`
+        let errors = tx.clone();
+        // A trace cut short didn't probe the TTLs after its last hop, so they aren't timeouts
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let probe_failed = interrupted.clone();
+        let fail = move |error: String| {
+            warn!("{}", error);
+            probe_failed.store(true, Ordering::Relaxed);
+            errors.send(TraceEvent::Interrupted(error)).ok();
+        };
+        let probing = tx.clone();
+        probing.send(TraceEvent::Probing(first_ttl as usize)).ok();
+        thread::spawn(move || {
+            let report = |node: Node| {
+                debug!("Got hop {}, sending", node.ip_addr);
+                // Nothing comes after the target
+                if node.ip_addr != ip {
+                    probing.send(TraceEvent::Probing(node.seq as usize + 1)).ok();
+                }
+                progress_tx.send(node).is_ok()
+            };
+            // Started by the user to probe with privileges this process doesn't have
+            if super::helper::running() {
+                if let Err(e) = super::helper::trace(ip, options, first_ttl, report) {
+                    fail(trf("Tracing through the helper failed: {}", &[&e]));
+                }
+                return;
+            }
+            // Without administrator rights the tracert crate panics, the ICMP API works regardless
+            #[cfg(windows)]
+            if !super::raw_sockets() {
+                let IpAddr::V4(dst) = ip else {
+                    fail(tr("Tracing IPv6 needs administrator rights").to_string());
+                    return;
+                };
+                let timeout = Duration::from_millis(options.timeout_ms);
+                if let Err(e) = super::icmpapi::trace(dst, first_ttl, options.max_hops, options.probe_size, timeout, report) {
+                    fail(trf("Tracing through the ICMP API failed: {}", &[&e]));
+                }
+                return;
+            }
+            // Without CAP_NET_RAW, UDP probes still get their ICMP errors back
+            #[cfg(target_os = "linux")]
+            if !super::raw_sockets() {
+                let timeout = Duration::from_millis(options.timeout_ms);
+                if let Err(e) = super::udp::trace(ip, first_ttl, options.max_hops, options.probe_size, timeout, report) {
+                    fail(trf("Tracing with UDP probes failed: {}", &[&e]));
+                }
+                return;
+            }
+            if let Err(e) = trace_raw(tracer, ip, options, first_ttl, report) {
+                fail(e);
+            }
+        });
+
+        debug!("Starting location lookup");
+        tokio::spawn(async move {
+            // PTR lookups run one at a time behind the hops, so a slow DNS server never holds up plotting
+            let (ptr_tx, mut ptr_rx) = mpsc::unbounded_channel::<(usize, IpAddr)>();
+            let resolver = {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut names = Vec::new();
+                    while let Some((ttl, ip)) = ptr_rx.recv().await {
+                        let name = reverse_lookup(ip).await;
+                        tx.send(TraceEvent::Hostname(ttl, name.clone())).ok();
+                        if let Some(name) = name {
+                            names.push((ttl, name));
+                        }
+                    }
+                    names
+                })
+            };
+
+            let mut done = false;
+            let mut last_ttl = first_ttl - 1;
+            let mut reached = false;
+            // Public hops the provider had nothing for, kept as pending
+            let mut unlocated = 0;
+            // Hops without a location that came before any other, held back to be placed
+            // at the first hop that has one
+            let mut unplaced = Vec::new();
+            while !done {
+                // Wait for the next hop, then collect whatever else arrives shortly after
+                // so it can be located with a single batch request
+                let Some(first) = progress_rx.recv().await else {
+                    break;
+                };
+                let mut batch = vec![first];
+                let deadline = tokio::time::Instant::now() + LOCATION_BATCH_WINDOW;
+                while batch.len() < 100 {
+                    match tokio::time::timeout_at(deadline, progress_rx.recv()).await {
+                        Ok(Some(node)) => batch.push(node),
+                        Ok(None) => {
+                            done = true;
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                debug!("Processing {} hops", batch.len());
+
+                let ips: Vec<String> = batch.iter().map(|node| node.ip_addr.to_string()).collect();
+                // Prefix lookups don't share the rate limit, so they run per hop alongside
+                let prefixes: Vec<_> = batch.iter().map(|node| tokio::spawn(bgp_prefix(node.ip_addr))).collect();
+                let locations = get_locations(&client, geolocation, &sites, &ips, options.cache_ttl()).await;
+
+                for ((node, location), prefix) in batch.into_iter().zip(locations).zip(prefixes) {
+                    // The tracer only reports hops that answered, the TTLs it skipped timed out
+                    let ttl = node.seq;
+                    for missing in last_ttl + 1..ttl {
+                        tx.send(TraceEvent::Timeout(missing as usize)).ok();
+                    }
+                    last_ttl = last_ttl.max(ttl);
+                    reached |= node.ip_addr == ip;
+
+                    let prefix = prefix.await.unwrap_or_default();
+                    // The tracer only resolves the destination, relays come back as bare IPs
+                    let needs_ptr = options.reverse_dns && node.host_name == node.ip_addr.to_string();
+                    let ip = node.ip_addr;
+                    // Private hops can't be geolocated, but dropping them leaves gaps in the path
+                    let private = location.is_none() && sites::is_bogon(node.ip_addr);
+                    // Public ones the provider failed on are kept the same way, to be looked
+                    // up again once the trace is done
+                    let pending = location.is_none() && !private;
+                    if pending {
+                        unlocated += 1;
+                    }
+                    let location = match location {
+                        Some(location) => location,
+                        None => Location {
+                            // Without a hop before it, this only sticks if no hop gets a location
+                            position: nodes.last().map_or(Position::from_lat_lon(0.0, 0.0), |(_, node)| node.position),
+                            isp: tr(if private { "Private network" } else { "Location pending" }).to_string(),
+                            asn: None,
+                            as_name: String::new(),
+                            country: String::new(),
+                            country_code: String::new(),
+                            region: String::new(),
+                            city: String::new(),
+                        },
+                    };
+                    let node = TraceNode {
+                        position: location.position,
+                        hostname: node.host_name,
+                        isp: location.isp,
+                        ip: node.ip_addr.to_canonical().to_string(),
+                        asn: location.asn,
+                        as_name: location.as_name,
+                        prefix: prefix.unwrap_or_default(),
+                        country: location.country,
+                        country_code: location.country_code,
+                        region: location.region,
+                        city: location.city,
+                        rtt: Some(node.rtt),
+                        reply_ttl: node.ttl,
+                        fields: Vec::new(),
+                        private,
+                        pending,
+                        resolving: needs_ptr,
+                    };
+                    let ptr = needs_ptr.then_some(ip);
+                    if node.guessed() && nodes.is_empty() {
+                        unplaced.push((ttl as usize, node, ptr));
+                        continue;
+                    }
+                    for (ttl, hop, ptr) in unplaced.drain(..) {
+                        let hop = TraceNode { position: node.position, ..hop };
+                        send_hop(&tx, &scripts, &ptr_tx, &mut nodes, ttl, hop, ptr).await;
+                    }
+                    send_hop(&tx, &scripts, &ptr_tx, &mut nodes, ttl as usize, node, ptr).await;
+                }
+            }
+            for (ttl, hop, ptr) in unplaced {
+                send_hop(&tx, &scripts, &ptr_tx, &mut nodes, ttl, hop, ptr).await;
+            }
+            // Every TTL up to the limit was probed without the target answering
+            if !reached && !interrupted.load(Ordering::Relaxed) {
+                for missing in last_ttl as usize + 1..=options.max_hops as usize {
+                    tx.send(TraceEvent::Timeout(missing)).ok();
+                }
+            }
+
+            if unlocated > 0 {
+                let error = trf(
+                    "Couldn't locate {} hops with {}, they're looked up again once the trace is done",
+                    &[&unlocated, &geolocation.name()],
+                );
+                tx.send(TraceEvent::Error(error)).ok();
+            }
+
+            // Wait for the queued names so history and the trace hooks get them too
+            drop(ptr_tx);
+            for (ttl, name) in resolver.await.unwrap_or_default() {
+                if let Some((_, node)) = nodes.iter_mut().find(|(idx, _)| *idx == ttl) {
+                    node.hostname = name;
+                }
+            }
+            let nodes = nodes
+                .into_iter()
+                .map(|(_, node)| TraceNode { resolving: false, ..node })
+                .collect();
+            finish(&tx, &scripts, target, nodes).await;
+        });
+    }
+}
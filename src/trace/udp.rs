@@ -0,0 +1,224 @@
+//! UDP traceroute that works without CAP_NET_RAW, reading the ICMP errors the kernel
+//! queues on an ordinary socket with `IP_RECVERR`.
+//!
+//! One probe per TTL and one TTL after another, to the classic traceroute ports. Only used
+//! when raw sockets can't be opened.
+
+use std::{
+    io,
+    mem::{size_of, MaybeUninit},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::fd::AsRawFd,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use socket2::{Domain, Protocol, Socket, Type};
+use tracert::node::{Node, NodeType};
+
+/// Destination port of the first probe, each TTL adds one.
+const BASE_PORT: u16 = 33434;
+const TIME_EXCEEDED: u8 = 11;
+const TIME_EXCEEDED_V6: u8 = 3;
+const PORT_UNREACHABLE: (u8, u8) = (3, 3);
+const PORT_UNREACHABLE_V6: (u8, u8) = (1, 4);
+
+fn set_option(socket: &Socket, level: libc::c_int, name: libc::c_int) -> io::Result<()> {
+    let value: libc::c_int = 1;
+    // SAFETY: the socket is open and value outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// An ICMP error for one of our probes.
+struct Error {
+    /// Who sent it
+    source: IpAddr,
+    /// Port the probe was sent to
+    port: u16,
+    /// TTL or hop limit of the error when it arrived
+    reply_ttl: Option<u8>,
+    time_exceeded: bool,
+    port_unreachable: bool,
+}
+
+/// Waits up to `timeout` for the next queued error.
+fn next_error(socket: &Socket, timeout: Duration) -> io::Result<Option<Error>> {
+    let fd = socket.as_raw_fd();
+    let mut poll = libc::pollfd {
+        fd,
+        events: 0,
+        revents: 0,
+    };
+    // Errors always wake poll, no events need asking for
+    // SAFETY: one valid pollfd
+    let ready = unsafe { libc::poll(&mut poll, 1, timeout.as_millis().min(i32::MAX as u128) as i32) };
+    if ready < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if ready == 0 || poll.revents & libc::POLLERR == 0 {
+        return Ok(None);
+    }
+
+    let mut name = MaybeUninit::<libc::sockaddr_storage>::zeroed();
+    let mut data = [0u8; 512];
+    let mut control = [0u64; 64];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // SAFETY: zeroed msghdr is valid, all buffers outlive recvmsg
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_name = name.as_mut_ptr() as *mut libc::c_void;
+    message.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = size_of::<[u64; 64]>() as _;
+    // SAFETY: message points at valid buffers of the sizes it claims
+    if unsafe { libc::recvmsg(fd, &mut message, libc::MSG_ERRQUEUE) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the kernel filled in the original destination of the probe
+    let port = match unsafe { socket2::SockAddr::new(name.assume_init(), message.msg_namelen) }.as_socket() {
+        Some(address) => address.port(),
+        None => return Ok(None),
+    };
+
+    let (mut source, mut reply_ttl, mut kind) = (None, None, None);
+    // SAFETY: walking the control messages recvmsg wrote, within msg_controllen
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&message);
+        while !cmsg.is_null() {
+            let (level, kind_of) = ((*cmsg).cmsg_level, (*cmsg).cmsg_type);
+            let payload = libc::CMSG_DATA(cmsg);
+            if (level, kind_of) == (libc::SOL_IP, libc::IP_RECVERR) || (level, kind_of) == (libc::SOL_IPV6, libc::IPV6_RECVERR) {
+                let error = std::ptr::read_unaligned(payload as *const libc::sock_extended_err);
+                if error.ee_origin == libc::SO_EE_ORIGIN_ICMP || error.ee_origin == libc::SO_EE_ORIGIN_ICMP6 {
+                    kind = Some((error.ee_origin, error.ee_type, error.ee_code));
+                    let offender = libc::SO_EE_OFFENDER(payload as *const libc::sock_extended_err);
+                    source = match (*offender).sa_family as libc::c_int {
+                        libc::AF_INET => {
+                            let address = std::ptr::read_unaligned(offender as *const libc::sockaddr_in);
+                            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr))))
+                        }
+                        libc::AF_INET6 => {
+                            let address = std::ptr::read_unaligned(offender as *const libc::sockaddr_in6);
+                            Some(IpAddr::V6(Ipv6Addr::from(address.sin6_addr.s6_addr)))
+                        }
+                        _ => None,
+                    };
+                }
+            } else if (level, kind_of) == (libc::SOL_IP, libc::IP_TTL)
+                || (level, kind_of) == (libc::SOL_IPV6, libc::IPV6_HOPLIMIT)
+            {
+                let ttl = std::ptr::read_unaligned(payload as *const libc::c_int);
+                reply_ttl = u8::try_from(ttl).ok();
+            }
+            cmsg = libc::CMSG_NXTHDR(&message, cmsg);
+        }
+    }
+
+    let (Some(source), Some((origin, icmp_type, code))) = (source, kind) else {
+        return Ok(None);
+    };
+    let v6 = origin == libc::SO_EE_ORIGIN_ICMP6;
+    Ok(Some(Error {
+        source,
+        port,
+        reply_ttl,
+        time_exceeded: icmp_type == if v6 { TIME_EXCEEDED_V6 } else { TIME_EXCEEDED },
+        port_unreachable: (icmp_type, code) == if v6 { PORT_UNREACHABLE_V6 } else { PORT_UNREACHABLE },
+    }))
+}
+
+/// Traces `dst` from `first_ttl` on, handing hops to `report` in TTL order until it returns false.
+/// TTLs that never answered are skipped, like the tracert crate does. Probes are `size` bytes
+/// long including the IP and UDP headers.
+pub fn trace(
+    dst: IpAddr,
+    first_ttl: u8,
+    max_hops: u8,
+    size: u16,
+    timeout: Duration,
+    mut report: impl FnMut(Node) -> bool,
+) -> io::Result<()> {
+    let socket = Socket::new(Domain::for_address(SocketAddr::new(dst, 0)), Type::DGRAM, Some(Protocol::UDP))?;
+    match dst {
+        IpAddr::V4(_) => {
+            set_option(&socket, libc::SOL_IP, libc::IP_RECVERR)?;
+            set_option(&socket, libc::SOL_IP, libc::IP_RECVTTL)?;
+        }
+        IpAddr::V6(_) => {
+            set_option(&socket, libc::SOL_IPV6, libc::IPV6_RECVERR)?;
+            set_option(&socket, libc::SOL_IPV6, libc::IPV6_RECVHOPLIMIT)?;
+        }
+    }
+
+    let headers = match dst {
+        IpAddr::V4(_) => 28,
+        IpAddr::V6(_) => 48,
+    };
+    let payload = vec![0; (size as usize).saturating_sub(headers)];
+    for ttl in first_ttl.max(1)..=max_hops {
+        match dst {
+            IpAddr::V4(_) => socket.set_ttl(ttl as u32)?,
+            IpAddr::V6(_) => socket.set_unicast_hops_v6(ttl as u32)?,
+        }
+        let port = BASE_PORT + ttl as u16;
+        let sent = Instant::now();
+        socket.send_to(&payload, &SocketAddr::new(dst, port).into())?;
+
+        // Skip errors for earlier probes that arrived late
+        let reply = loop {
+            let Some(left) = timeout.checked_sub(sent.elapsed()) else {
+                break None;
+            };
+            match next_error(&socket, left)? {
+                Some(error) if error.port == port && (error.time_exceeded || error.source == dst || error.port_unreachable) => {
+                    break Some(error)
+                }
+                Some(_) => continue,
+                None => break None,
+            }
+        };
+        let Some(reply) = reply else {
+            debug!("No reply for TTL {}", ttl);
+            continue;
+        };
+
+        let reached = !reply.time_exceeded;
+        let node = Node {
+            seq: ttl,
+            ip_addr: reply.source,
+            host_name: reply.source.to_string(),
+            ttl: reply.reply_ttl,
+            hop: Some(ttl),
+            node_type: if reached {
+                NodeType::Destination
+            } else if ttl == 1 {
+                NodeType::DefaultGateway
+            } else {
+                NodeType::Relay
+            },
+            rtt: sent.elapsed(),
+        };
+        if !report(node) || reached {
+            break;
+        }
+    }
+    Ok(())
+}
+
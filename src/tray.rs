@@ -0,0 +1,187 @@
+//! Tray icon listing the favorite targets, for tracing without bringing up the window first.
+
+use std::sync::mpsc::{channel, Receiver};
+
+use egui::{Context, ViewportCommand};
+use serde::{Deserialize, Serialize};
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+const SHOW: &str = "show";
+const QUIT: &str = "quit";
+/// Prefix of the menu ids of favorite targets, followed by the target
+const TRACE: &str = "trace:";
+const ICON_SIZE: u32 = 32;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrayConfig {
+    /// Takes effect on the next start
+    pub enabled: bool,
+    /// Closing the window hides it instead, so scheduled traces keep running
+    pub close_to_tray: bool,
+}
+
+pub enum Action {
+    Trace(String),
+    Show,
+    Quit,
+}
+
+/// A ring on a transparent background, in the colors of the trace markers.
+fn icon() -> Icon {
+    let center = (ICON_SIZE as f32 - 1.0) / 2.0;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = (x as f32 - center).hypot(y as f32 - center);
+            let pixel = if distance < 5.0 {
+                [255, 0, 0, 255]
+            } else if (10.0..15.0).contains(&distance) {
+                [30, 144, 255, 255]
+            } else {
+                [0, 0, 0, 0]
+            };
+            rgba.extend_from_slice(&pixel);
+        }
+    }
+    Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).expect("icon size matches its pixels")
+}
+
+fn menu(favorites: &[String]) -> Menu {
+    let menu = Menu::new();
+    for target in favorites {
+        menu.append(&MenuItem::with_id(format!("{}{}", TRACE, target), format!("Trace {}", target), true, None))
+            .ok();
+    }
+    if favorites.is_empty() {
+        menu.append(&MenuItem::new("No favorite targets", false, None)).ok();
+    }
+    menu.append(&PredefinedMenuItem::separator()).ok();
+    menu.append(&MenuItem::with_id(SHOW, "Show window", true, None)).ok();
+    menu.append(&MenuItem::with_id(QUIT, "Quit", true, None)).ok();
+    menu
+}
+
+fn build(favorites: &[String]) -> Result<TrayIcon, String> {
+    TrayIconBuilder::new()
+        .with_icon(icon())
+        .with_tooltip("traced")
+        .with_menu(Box::new(menu(favorites)))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Brings the window back up, it may have been closed to the tray.
+pub fn show(ctx: &Context) {
+    ctx.send_viewport_cmd(ViewportCommand::Visible(true));
+    ctx.send_viewport_cmd(ViewportCommand::Minimized(false));
+    ctx.send_viewport_cmd(ViewportCommand::Focus);
+}
+
+pub struct Tray {
+    actions: Receiver<Action>,
+    /// Favorites the menu was last built with
+    favorites: Vec<String>,
+    /// Set once the user quit from the tray, so closing isn't turned into hiding
+    pub quitting: bool,
+    /// Menus for the GTK thread the icon lives on
+    #[cfg(target_os = "linux")]
+    updates: std::sync::mpsc::Sender<Vec<String>>,
+    #[cfg(not(target_os = "linux"))]
+    icon: TrayIcon,
+}
+
+impl Tray {
+    pub fn new(favorites: &[String], ctx: &Context) -> Result<Self, String> {
+        let (sender, actions) = channel();
+        // Events arrive on the thread of the icon, wake the UI so it sees them even when hidden
+        let (menu_sender, menu_ctx) = (sender.clone(), ctx.clone());
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            let action = match event.id.0.as_str() {
+                SHOW => Action::Show,
+                QUIT => Action::Quit,
+                id => match id.strip_prefix(TRACE) {
+                    Some(target) => Action::Trace(target.to_string()),
+                    None => return,
+                },
+            };
+            menu_sender.send(action).ok();
+            menu_ctx.request_repaint();
+        }));
+        let click_ctx = ctx.clone();
+        TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                sender.send(Action::Show).ok();
+                click_ctx.request_repaint();
+            }
+        }));
+
+        Ok(Self {
+            actions,
+            favorites: favorites.to_vec(),
+            quitting: false,
+            #[cfg(target_os = "linux")]
+            updates: spawn_gtk(favorites.to_vec())?,
+            #[cfg(not(target_os = "linux"))]
+            icon: build(favorites)?,
+        })
+    }
+
+    /// Rebuilds the menu if the favorites changed since.
+    pub fn update(&mut self, favorites: &[String]) {
+        if self.favorites == favorites {
+            return;
+        }
+        self.favorites = favorites.to_vec();
+        #[cfg(target_os = "linux")]
+        self.updates.send(self.favorites.clone()).ok();
+        #[cfg(not(target_os = "linux"))]
+        self.icon.set_menu(Some(Box::new(menu(favorites))));
+    }
+
+    /// What the user picked since the last call.
+    pub fn poll(&self) -> Vec<Action> {
+        self.actions.try_iter().collect()
+    }
+}
+
+/// On Linux the icon needs a GTK main loop, which can't share the thread with the window.
+#[cfg(target_os = "linux")]
+fn spawn_gtk(favorites: Vec<String>) -> Result<std::sync::mpsc::Sender<Vec<String>>, String> {
+    use gtk::glib;
+    use std::time::Duration;
+
+    let (updates, receiver) = channel::<Vec<String>>();
+    let (started, result) = channel();
+    std::thread::spawn(move || {
+        if let Err(e) = gtk::init() {
+            started.send(Err(e.to_string())).ok();
+            return;
+        }
+        let icon = match build(&favorites) {
+            Ok(icon) => icon,
+            Err(e) => {
+                started.send(Err(e)).ok();
+                return;
+            }
+        };
+        started.send(Ok(())).ok();
+        glib::timeout_add_local(Duration::from_millis(250), move || {
+            for favorites in receiver.try_iter() {
+                icon.set_menu(Some(Box::new(menu(&favorites))));
+            }
+            glib::ControlFlow::Continue
+        });
+        gtk::main();
+    });
+    result.recv().map_err(|e| e.to_string())??;
+    Ok(updates)
+}
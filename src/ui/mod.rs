@@ -0,0 +1,3 @@
+//! Pieces of the UI shared by every window.
+
+pub mod toasts;
@@ -0,0 +1,127 @@
+//! Short messages stacked in a corner of the window that go away on their own.
+//!
+//! Anything with access to the [`Bus`] publishes a [`Toast`], alerts are shown as toasts too.
+
+use std::{sync::mpsc::Receiver, time::Duration};
+
+use egui::{Align2, Color32, Context, RichText};
+
+use crate::{alert::Alert, bus::Bus, i18n::tr};
+
+/// How long a toast stays up, errors stay twice as long.
+const TOAST_SECONDS: f64 = 4.0;
+/// Most toasts on screen at once, the oldest go first.
+const MAX_TOASTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Success,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub level: Level,
+    pub text: String,
+}
+
+impl Toast {
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            level: Level::Info,
+            text: text.into(),
+        }
+    }
+
+    pub fn success(text: impl Into<String>) -> Self {
+        Self {
+            level: Level::Success,
+            text: text.into(),
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            level: Level::Error,
+            text: text.into(),
+        }
+    }
+
+    fn seconds(&self) -> f64 {
+        match self.level {
+            Level::Error => TOAST_SECONDS * 2.0,
+            _ => TOAST_SECONDS,
+        }
+    }
+}
+
+/// The toasts on screen, with the time each one showed up.
+pub struct Toasts {
+    toasts: Receiver<Toast>,
+    alerts: Receiver<Alert>,
+    shown: Vec<(Toast, f64)>,
+}
+
+impl Toasts {
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            toasts: bus.toasts.subscribe(),
+            alerts: bus.alerts.subscribe(),
+            shown: Vec::new(),
+        }
+    }
+
+    /// Takes in new toasts and draws the ones still up. Clicking one dismisses it.
+    pub fn show(&mut self, ctx: &Context) {
+        let now = ctx.input(|i| i.time);
+        let alerts = self.alerts.try_iter().map(|alert| Toast::info(alert.to_string()));
+        self.shown.extend(self.toasts.try_iter().chain(alerts).map(|toast| (toast, now)));
+        self.shown.retain(|(toast, since)| now - since < toast.seconds());
+        let extra = self.shown.len().saturating_sub(MAX_TOASTS);
+        self.shown.drain(..extra);
+        if self.shown.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new(egui::Id::new("toasts"))
+            // Above the tracing spinner in the corner
+            .anchor(Align2::RIGHT_BOTTOM, [-10., -50.])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                for (i, (toast, _)) in self.shown.iter().enumerate().rev() {
+                    let (icon, color) = match toast.level {
+                        Level::Info => ("ℹ", ui.visuals().text_color()),
+                        Level::Success => ("✔", Color32::GREEN),
+                        Level::Error => ("⚠", Color32::RED),
+                    };
+                    let response = egui::Frame::popup(ui.style())
+                        .show(ui, |ui| {
+                            ui.set_max_width(320.);
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new(icon).color(color));
+                                ui.label(&toast.text);
+                            });
+                        })
+                        .response
+                        .interact(egui::Sense::click())
+                        .on_hover_text(tr("Dismiss"));
+                    if response.clicked() {
+                        dismissed = Some(i);
+                    }
+                }
+            });
+        if let Some(i) = dismissed {
+            self.shown.remove(i);
+        }
+
+        // Wake up to take down the next one that runs out
+        let left = self
+            .shown
+            .iter()
+            .map(|(toast, since)| toast.seconds() - (now - since))
+            .fold(f64::MAX, f64::min);
+        ctx.request_repaint_after(Duration::from_secs_f64(left.max(0.0)));
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use log::debug;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Sends `query` to a whois server on port 43 and returns the raw response.
+pub async fn query(server: &str, query: &str) -> Result<String, String> {
+    let request = async {
+        let mut stream = tokio::net::TcpStream::connect((server, 43))
+            .await
+            .map_err(|e| format!("Failed to connect to {}: {}", server, e))?;
+        stream
+            .write_all(format!("{}\r\n", query).as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.map_err(|e| e.to_string())?;
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    };
+    match tokio::time::timeout(Duration::from_secs(10), request).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("{} did not answer in time", server)),
+    }
+}
+
+/// Looks up an IP starting at IANA and following its referral to the responsible RIR.
+pub async fn lookup(ip: &str) -> Result<String, String> {
+    let iana = query("whois.iana.org", ip).await?;
+    let referral = field(&iana, &["refer", "whois"]);
+    match referral {
+        Some(server) => {
+            debug!("Following whois referral to {}", server);
+            // ARIN only returns the network summary when asked for it explicitly
+            let request = if server == "whois.arin.net" {
+                format!("n + {}", ip)
+            } else {
+                ip.to_string()
+            };
+            query(&server, &request).await
+        }
+        None => Ok(iana),
+    }
+}
+
+/// The few fields people usually look for in a whois response.
+#[derive(Default)]
+pub struct Summary {
+    pub registrant: Option<String>,
+    pub netblock: Option<String>,
+    pub abuse: Option<String>,
+}
+
+pub fn summarize(response: &str) -> Summary {
+    // Field names differ between registries (ARIN, RIPE/APNIC/AFRINIC, LACNIC)
+    Summary {
+        registrant: field(response, &["OrgName", "org-name", "owner", "descr", "netname"]),
+        netblock: field(response, &["NetRange", "CIDR", "inetnum", "inet6num"]),
+        abuse: field(response, &["OrgAbuseEmail", "abuse-mailbox", "abuse-c"]).or_else(|| {
+            // RIPE puts the abuse address in a comment
+            response
+                .lines()
+                .find_map(|l| l.strip_prefix("% Abuse contact for"))
+                .and_then(|l| l.split('\'').nth(3))
+                .map(str::to_string)
+        }),
+    }
+}
+
+/// First value of any of `keys` in a `key: value` style response.
+fn field(response: &str, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| {
+        response.lines().find_map(|line| {
+            let (k, v) = line.split_once(':')?;
+            (k.trim().eq_ignore_ascii_case(key) && !v.trim().is_empty()).then(|| v.trim().to_string())
+        })
+    })
+}
@@ -1,59 +1,125 @@
+use crate::accessibility::Described;
+use crate::alert::{Alert, AlertKind};
 use crate::app::Provider;
-use crate::plugins::TracePath;
-use log::{info, warn, error, debug};
+use crate::bus::{Bus, Topic};
+use crate::config::Config;
+use crate::dnsbl;
+use crate::executor::Executor;
+use crate::geofence::{self, Geofence};
+use crate::history::{self, Filter, History, Outcome};
+use crate::i18n::{self, tr, trf};
+use crate::network::NetworkContext;
+use crate::notes::Notes;
+use crate::plugins::{
+    self, Magnifier, Measure, Palette, StartRequest, TraceSet, DEVIATION_COLOR, ROUTE_CHANGE_COLOR, STALLED_COLOR,
+};
+use crate::policy::TransitPolicy;
+use crate::scripts::Scripts;
+use crate::sites::SiteMap;
+use crate::scheduler::{self, HopHistory, Job, NotifyRule, Run, Scheduler};
+use crate::ui::toasts::Toast;
+use crate::trace::{self, Enrichment, GeoProvider, TraceEvent, TraceNode, TraceOptions, TraceSettings, Tracer};
+use crate::{baseline, export, flows, geo, geocache, ixp, launcher, places, planner, policy, routediff, snmp, splittunnel, summary, whois};
+use crate::workspace::Workspace;
+use log::{info, warn, debug};
 
 use egui::{Align2, RichText, Ui, Window};
-use serde::Deserialize;
+use egui_plot::{BoxElem, BoxPlot, BoxSpread, Line, Plot, Points};
 use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
     net::IpAddr,
-    sync::mpsc::{channel, Receiver, Sender}, thread,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
-use tokio::{runtime::Runtime, sync::mpsc};
-use walkers::{sources::Attribution, MapMemory, Position};
-use dns_lookup::lookup_host;
+use walkers::{sources::Attribution, MapMemory};
 
+/// What's typed into the Enter IP window.
 #[derive(Default)]
-struct IpInput {
+pub struct IpInput {
     value: String,
+    tags: String,
+    mode: Mode,
+    addresses: Option<Addresses>,
+    lookup: Option<Receiver<Addresses>>,
+    /// Target the blocklists were checked for, and what they said
+    verdict: Option<(String, dnsbl::Verdict)>,
+    checking: Option<Receiver<(String, dnsbl::Verdict)>>,
+    /// When the target was last typed into, checks wait for the typing to stop
+    edited_at: Option<Instant>,
 }
 
-struct TraceChannel {
-    sender: Sender<TraceEvent>,
-    receiver: Receiver<TraceEvent>,
-}
+/// A target and every address it resolved to.
+type Addresses = (String, Result<Vec<IpAddr>, String>);
 
-impl Default for TraceChannel {
-    fn default() -> Self {
-        let (sender, receiver) = channel();
-        Self { sender, receiver }
+impl IpInput {
+    /// Starts out with the target traced last time.
+    pub fn new(last_target: &str) -> Self {
+        Self {
+            value: last_target.to_string(),
+            ..Default::default()
+        }
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct IpApiResponse {
-    lat: f64,
-    lon: f64,
-    status: String,
-    isp: String,  // Add ISP field
-}
-
-// Add this new struct to store position with hostname
-#[derive(Clone)]
-pub struct TraceNode {
-    pub position: Position,
-    pub hostname: String,
-    pub isp: String,  // Add ISP field
-    pub ip: String,  // Add IP field
+/// What the Enter IP window does with its target.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    #[default]
+    Trace,
+    Ping,
 }
 
-#[derive(Clone)]
-pub enum TraceEvent {
-    Node(TraceNode),
-    Finish,
+/// Profile picker, returns true when the settings were switched to another profile.
+fn profiles(ui: &mut Ui, config: &mut Config) -> bool {
+    let new_name_id = ui.id().with("new_profile");
+    let mut switch = None;
+    let mut remove = false;
+    ui.horizontal(|ui| {
+        let selected = if config.active_profile.is_empty() {
+            tr("No profile")
+        } else {
+            config.active_profile.as_str()
+        };
+        egui::ComboBox::from_id_salt("profile")
+            .selected_text(selected)
+            .width(110.)
+            .show_ui(ui, |ui| {
+                for name in config.profiles.keys() {
+                    if ui.selectable_label(*name == config.active_profile, name).clicked() {
+                        switch = Some(name.clone());
+                    }
+                }
+                ui.separator();
+                let mut new_name = ui.data_mut(|d| d.get_temp::<String>(new_name_id)).unwrap_or_default();
+                let response = ui.add(egui::TextEdit::singleline(&mut new_name).hint_text(tr("New profile"))).named(tr("New profile"));
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) && !new_name.trim().is_empty() {
+                    config.add_profile(new_name.trim());
+                    new_name.clear();
+                }
+                ui.data_mut(|d| d.insert_temp(new_name_id, new_name));
+            })
+            .response
+            .on_hover_text(tr("Tiles, trace, geolocation, site map and SNMP settings, saved per network"));
+        if !config.active_profile.is_empty() && ui.small_button("🗙").described(tr("Delete profile")).clicked() {
+            remove = true;
+        }
+    });
+    if remove {
+        let name = config.active_profile.clone();
+        config.remove_profile(&name);
+    }
+    switch.is_some_and(|name| name != config.active_profile && config.switch_profile(&name))
 }
 
 pub fn acknowledge(ui: &Ui, attribution: Attribution) {
-    Window::new("Acknowledge")
+    Window::new(tr("Acknowledge"))
+        .id(egui::Id::new("Acknowledge"))
         .collapsible(false)
         .resizable(false)
         .title_bar(false)
@@ -68,243 +134,5196 @@ pub fn acknowledge(ui: &Ui, attribution: Attribution) {
         });
 }
 
+/// Where a window pivoted on `align` starts out, `offset` away from that corner of the screen.
+/// Unlike `Window::anchor` this leaves it movable, and egui remembers where it was moved to,
+/// across sessions too since eframe persists its memory.
+fn corner(ctx: &egui::Context, align: Align2, offset: [f32; 2]) -> egui::Pos2 {
+    align.pos_in_rect(&ctx.screen_rect()) + egui::vec2(offset[0], offset[1])
+}
+
+/// Titles of the windows that are always there and whether they start out expanded.
+const LAYOUT: [(&str, bool); 5] = [
+    ("Controls", true),
+    ("Enter IP or Domain", true),
+    ("Hops", false),
+    ("History", false),
+    ("Timeline", false),
+];
+
+/// Puts every window back where it started out and expands or collapses the permanent ones
+/// as they were at first.
+pub fn reset_layout(ctx: &egui::Context) {
+    ctx.memory_mut(|m| m.reset_areas());
+    for (title, open) in LAYOUT {
+        let id = egui::Id::new(title).with("collapsing");
+        if let Some(mut state) = egui::collapsing_header::CollapsingState::load(ctx, id) {
+            state.set_open(open);
+            state.store(ctx);
+        }
+    }
+}
+
+/// Returns the user-configured provider whose settings were applied, if any.
+#[allow(clippy::too_many_arguments)]
 pub fn controls(
     ui: &Ui,
-    selected_provider: &mut Provider,
-    possible_providers: &mut dyn Iterator<Item = &Provider>,
-) {
-    Window::new("Satellite")
-        .collapsible(false)
+    config: &mut Config,
+    possible_providers: &[Provider],
+    magnifier: &mut Magnifier,
+    measure: &mut Measure,
+    compliance: &mut ComplianceWindow,
+    geofences: &mut GeofenceWindow,
+    policies: &mut PolicyWindow,
+    planner: &mut PlannerWindow,
+    baseline: &mut BaselineWindow,
+    profile: &mut ProfileWindow,
+    schedule: &mut ScheduleWindow,
+    split_tunnel: &mut SplitTunnelWindow,
+    sounds: &mut SoundsWindow,
+    workspace: &mut WorkspaceWindow,
+    settings: &mut SettingsWindow,
+    enrichment: &mut Enrichment,
+) -> Option<Provider> {
+    let mut apply = None;
+    let resolver = (config.resolver, config.dns_server.clone());
+    Window::new(tr("Controls"))
+        .id(egui::Id::new("Controls"))
         .resizable(false)
-        .title_bar(false)
-        .anchor(Align2::RIGHT_TOP, [-10., 10.])
+        .pivot(Align2::RIGHT_TOP)
+        .default_pos(corner(ui.ctx(), Align2::RIGHT_TOP, [-10., 10.]))
         .fixed_size([150., 150.])
         .show(ui.ctx(), |ui| {
-            ui.collapsing("Map", |ui| {
-                egui::ComboBox::from_label("Tile Provider")
-                    .selected_text(format!("{:?}", selected_provider))
-                    .show_ui(ui, |ui| {
-                        for p in possible_providers {
-                            ui.selectable_value(selected_provider, *p, format!("{:?}", p));
+            if profiles(ui, config) {
+                // The profile may use a provider that has to be built, or isn't available here
+                if config.provider == Provider::Custom {
+                    apply = Some(Provider::Custom);
+                } else if !possible_providers.contains(&config.provider) {
+                    config.provider = Provider::OpenStreetMap;
+                }
+                enrichment.sites = Arc::new(SiteMap::load_or_default(&config.site_map));
+            }
+            ui.separator();
+            ui.toggle_value(&mut settings.open, tr("⚙ Settings")).on_hover_text("Ctrl+,");
+            ui.collapsing(tr("Tools"), |ui| {
+                ui.checkbox(&mut magnifier.enabled, tr("Magnifier"));
+                ui.add_enabled(
+                    magnifier.enabled,
+                    egui::Slider::new(&mut magnifier.boost, 1.0..=6.0).text(tr("Lens zoom")),
+                );
+                ui.checkbox(&mut measure.enabled, tr("Measure distance"))
+                    .on_hover_text(tr("Click points on the map to measure the great-circle distance along them"));
+                if measure.enabled {
+                    ui.horizontal(|ui| {
+                        ui.label(plugins::format_km(measure.total_km()));
+                        if ui.add_enabled(!measure.points.is_empty(), egui::Button::new(tr("Clear"))).clicked() {
+                            measure.points.clear();
                         }
                     });
+                }
+                ui.toggle_value(&mut compliance.open, tr("Compliance report"));
+                ui.toggle_value(&mut geofences.open, tr("Geofences"));
+                ui.toggle_value(&mut policies.open, tr("Transit policies"));
+                ui.toggle_value(&mut planner.open, tr("Region planner"));
+                ui.toggle_value(&mut baseline.open, tr("Baselines"));
+                ui.toggle_value(&mut profile.open, tr("Latency profile"));
+                ui.toggle_value(&mut schedule.open, tr("Scheduled traces"));
+                ui.toggle_value(&mut split_tunnel.open, tr("Split tunnel check"));
+                ui.toggle_value(&mut sounds.open, tr("Sounds"));
+                ui.toggle_value(&mut workspace.open, tr("Workspace"));
             });
         });
+    // Switching profiles may change it too
+    if resolver != (config.resolver, config.dns_server.clone()) {
+        trace::set_resolver(config.resolver, &config.dns_server);
+    }
+    apply
 }
 
-/// Simple GUI to zoom in and out.
-pub fn zoom(ui: &Ui, map_memory: &mut MapMemory) {
-    Window::new("Map")
-        .collapsible(false)
-        .resizable(false)
-        .title_bar(false)
-        .anchor(Align2::LEFT_BOTTOM, [10., -10.])
+/// A category of the settings window.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum SettingsTab {
+    #[default]
+    Trace,
+    Geolocation,
+    Map,
+    Appearance,
+    Advanced,
+}
+
+impl SettingsTab {
+    const ALL: [SettingsTab; 5] = [
+        SettingsTab::Trace,
+        SettingsTab::Geolocation,
+        SettingsTab::Map,
+        SettingsTab::Appearance,
+        SettingsTab::Advanced,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            SettingsTab::Trace => tr("Trace"),
+            SettingsTab::Geolocation => tr("Geolocation"),
+            SettingsTab::Map => tr("Map"),
+            SettingsTab::Appearance => tr("Appearance"),
+            SettingsTab::Advanced => tr("Advanced"),
+        }
+    }
+}
+
+/// Every persistent option in one place, by category.
+#[derive(Default)]
+pub struct SettingsWindow {
+    /// Toggled from the controls and with Ctrl+,
+    pub open: bool,
+    tab: SettingsTab,
+    /// Set when Reset layout was clicked, for the app to act on
+    pub reset_layout: bool,
+    /// Set when the shared folder was edited, for the app to load what's in it
+    pub reload_shared: bool,
+    /// Set when the map center was picked as home, for the app that knows it
+    pub set_home: bool,
+    /// UI scale while its slider is dragged, applied on release so the slider doesn't move
+    /// under the pointer
+    ui_scale: Option<f32>,
+}
+
+/// The settings window. Changes go straight to the config, which is saved on exit.
+/// Returns the user-configured provider whose settings were applied, if any.
+pub fn settings(
+    ui: &Ui,
+    window: &mut SettingsWindow,
+    config: &mut Config,
+    possible_providers: &[Provider],
+    trace_set: &mut TraceSet,
+    ipinfo: &mut IpInfoWindow,
+    enrichment: &mut Enrichment,
+) -> Option<Provider> {
+    if !window.open {
+        return None;
+    }
+    let mut apply = None;
+    let resolver = (config.resolver, config.dns_server.clone());
+    let mut open = true;
+    Window::new(tr("Settings"))
+        .id(egui::Id::new("Settings"))
+        .open(&mut open)
+        .default_size([340., 420.])
         .show(ui.ctx(), |ui| {
             ui.horizontal(|ui| {
-                if ui.button(RichText::new("➕").heading()).clicked() {
-                    let _ = map_memory.zoom_in();
+                for tab in SettingsTab::ALL {
+                    ui.selectable_value(&mut window.tab, tab, tab.name());
                 }
+            });
+            ui.separator();
 
-                if ui.button(RichText::new("➖").heading()).clicked() {
-                    let _ = map_memory.zoom_out();
+            let Config {
+                provider: selected_provider,
+                custom_tiles,
+                floor_plan,
+                view,
+                trace,
+                geolocation,
+                resolver,
+                dns_server,
+                blocklists,
+                stall_secs,
+                site_map,
+                snmp,
+                worker_threads,
+                shared_dir,
+                language,
+                #[cfg(feature = "tray")]
+                tray,
+                ..
+            } = &mut *config;
+            let split_view = &mut view.split_view;
+            egui::ScrollArea::vertical().show(ui, |ui| match window.tab {
+                SettingsTab::Trace => {
+                    ui.add(egui::Slider::new(&mut trace.max_hops, 1..=64).text(tr("Max hops")));
+                    ui.add(
+                        egui::Slider::new(&mut trace.timeout_ms, 100..=5000)
+                            .text(tr("Hop timeout"))
+                            .suffix(" ms"),
+                    );
+                    ui.add(egui::Slider::new(&mut trace.parallel_probes, 1..=32).text(tr("Parallel probes")))
+                        .on_hover_text(tr("TTLs probed at once over IPv4, faster on long paths with silent hops"));
+                    ui.add(
+                        egui::Slider::new(&mut trace.probe_size, 28..=1500)
+                            .text(tr("Probe size"))
+                            .suffix(" B"),
+                    )
+                    .on_hover_text(tr("Bytes per probe including the IP header, bigger probes find hops that fragment or drop them"));
+                    ui.collapsing(tr("Advanced"), |ui| {
+                        ui.checkbox(&mut trace.adaptive_timeout, tr("Adaptive timeout"))
+                            .on_hover_text(tr("Wait less on fast paths and longer on slow ones, IPv4 only"));
+                        ui.add_enabled_ui(trace.adaptive_timeout, |ui| {
+                            ui.add(egui::Slider::new(&mut trace.adaptive_factor, 1.5..=10.0).text(tr("× slowest reply")));
+                            ui.add(
+                                egui::Slider::new(&mut trace.adaptive_min_ms, 10..=1000)
+                                    .text(tr("Minimum"))
+                                    .suffix(" ms"),
+                            );
+                            ui.label(
+                                RichText::new(trf(
+                                    "Each hop waits {}× the slowest reply so far, between {} and {} ms",
+                                    &[&format!("{:.1}", trace.adaptive_factor), &trace.adaptive_min_ms, &trace.timeout_ms],
+                                ))
+                                .small()
+                                .weak(),
+                            );
+                        });
+                    });
+                    ui.checkbox(&mut trace.reverse_dns, tr("Reverse DNS"))
+                        .on_hover_text(tr("Resolve hop hostnames, slower on some networks"));
+                    ui.collapsing(tr("Blocklists"), |ui| {
+                        let mut text = blocklists.join("\n");
+                        ui.add(
+                            egui::TextEdit::multiline(&mut text)
+                                .hint_text("dbl.spamhaus.org")
+                                .desired_rows(2)
+                                .desired_width(140.),
+                        )
+                        .named(tr("Blocklists"))
+                        .on_hover_text(tr("DNSBL zones target domains are checked against, one per line, none to skip the check"));
+                        if text != blocklists.join("\n") {
+                            // Split on every newline, so the line being started isn't dropped
+                            *blocklists = text.split('\n').map(str::to_string).collect();
+                        }
+                    });
+                    ui.add(
+                        egui::Slider::new(stall_secs, 0..=300)
+                            .text(tr("Stalled after"))
+                            .suffix(" s"),
+                    )
+                    .on_hover_text(tr("Flag traces that find no new hops for this long, 0 never does"));
+                    trace_set.stall_after = Duration::from_secs(*stall_secs);
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("DNS")
+                            .selected_text(resolver.name())
+                            .show_ui(ui, |ui| {
+                                for choice in trace::Resolver::ALL {
+                                    ui.selectable_value(resolver, choice, choice.name());
+                                }
+                            })
+                            .response
+                            .on_hover_text(tr("Who resolves targets, in case the network's DNS rewrites answers"));
+                        if *resolver == trace::Resolver::Server {
+                            ui.add(
+                                egui::TextEdit::singleline(dns_server)
+                                    .hint_text(tr("e.g. 9.9.9.9"))
+                                    .desired_width(90.),
+                            )
+                            .named(tr("DNS server"));
+                        }
+                    });
+                }
+                SettingsTab::Geolocation => {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::Slider::new(&mut trace.cache_ttl_hours, 0..=168)
+                                .text(tr("Cache TTL"))
+                                .suffix(" h"),
+                        )
+                        .on_hover_text(tr("Reuse geolocation results for this long, 0 disables the cache"));
+                        if ui.small_button(tr("Clear")).on_hover_text(trf("{} cached", &[&geocache::len()])).clicked() {
+                            geocache::clear();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label(tr("Geolocation"))
+                            .selected_text(geolocation.name())
+                            .show_ui(ui, |ui| {
+                                for provider in GeoProvider::ALL {
+                                    ui.selectable_value(geolocation, provider, provider.name());
+                                }
+                            });
+                        if *geolocation == GeoProvider::IpInfo && ui.small_button(tr("Token…")).clicked() {
+                            ipinfo.open = true;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Site map"));
+                        ui.add(
+                            egui::TextEdit::singleline(site_map)
+                                .hint_text(tr("sites.csv or sites.json"))
+                                .desired_width(140.),
+                        )
+                        .named(tr("Site map"))
+                        .on_hover_text(tr("Locations for internal ranges: cidr,name,lat,lon[,country_code]"));
+                        if ui.button(tr("Load")).clicked() {
+                            enrichment.sites = Arc::new(SiteMap::load_or_default(site_map));
+                        }
+                    });
+                    if enrichment.sites.len() > 0 {
+                        ui.label(RichText::new(trf("{} sites", &[&enrichment.sites.len()])).small().weak());
+                    }
+                }
+                SettingsTab::Map => {
+                    egui::ComboBox::from_label(tr("Tile Provider"))
+                        .selected_text(selected_provider.name())
+                        .show_ui(ui, |ui| {
+                            for p in possible_providers {
+                                ui.selectable_value(selected_provider, *p, p.name());
+                            }
+                        });
+                    ui.collapsing(tr("Custom tiles"), |ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut custom_tiles.url)
+                                .hint_text("https://…/{z}/{x}/{y}.png?key={key}"),
+                        )
+                        .named(tr("Tile URL"));
+                        ui.add(
+                            egui::TextEdit::singleline(&mut custom_tiles.api_key)
+                                .hint_text(tr("API key (optional)"))
+                                .password(true),
+                        )
+                        .named(tr("API key (optional)"));
+                        if ui.button(tr("Apply")).clicked() {
+                            apply = Some(Provider::Custom);
+                        }
+                    });
+                    ui.collapsing(tr("Floor plan"), |ui| {
+                        ui.add(egui::TextEdit::singleline(&mut floor_plan.path).hint_text("campus.png")).named(tr("Floor plan"));
+                        egui::Grid::new("floor_plan_corners").num_columns(2).show(ui, |ui| {
+                            ui.label(tr("North"));
+                            ui.add(egui::DragValue::new(&mut floor_plan.north).speed(0.0001).range(-85.0..=85.0));
+                            ui.end_row();
+                            ui.label(tr("West"));
+                            ui.add(egui::DragValue::new(&mut floor_plan.west).speed(0.0001).range(-180.0..=180.0));
+                            ui.end_row();
+                            ui.label(tr("South"));
+                            ui.add(egui::DragValue::new(&mut floor_plan.south).speed(0.0001).range(-85.0..=85.0));
+                            ui.end_row();
+                            ui.label(tr("East"));
+                            ui.add(egui::DragValue::new(&mut floor_plan.east).speed(0.0001).range(-180.0..=180.0));
+                            ui.end_row();
+                        });
+                        if ui.button(tr("Apply")).clicked() {
+                            apply = Some(Provider::FloorPlan);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Home"));
+                        match view.home {
+                            Some([lat, lon]) => ui.label(format!("{:.3}, {:.3}", lat, lon)),
+                            None => ui.weak(tr("Where you are")),
+                        }
+                        .on_hover_text(tr("Where the map opens, without one where your public address is located"));
+                        if ui.small_button(tr("Set to map center")).clicked() {
+                            window.set_home = true;
+                        }
+                        if view.home.is_some() && ui.small_button(tr("Clear")).clicked() {
+                            view.home = None;
+                        }
+                    });
+                    ui.separator();
+                    if ui.checkbox(split_view, tr("Split view")).changed() && !*split_view {
+                        trace_set.separate_selection = false;
+                    }
+                    ui.add_enabled(
+                        *split_view,
+                        egui::Checkbox::new(&mut trace_set.separate_selection, tr("Separate trace selection")),
+                    );
+                    ui.checkbox(&mut trace_set.simplify, tr("Simplify path"))
+                        .on_hover_text(tr("Draw co-located hops as a single marker"));
+                    ui.checkbox(&mut trace_set.cluster, tr("Cluster overlapping hops"))
+                        .on_hover_text(tr("Draw hops that overlap at this zoom as one marker, click it to list them"));
+                    ui.checkbox(&mut trace_set.color_by_as, tr("Color by AS"))
+                        .on_hover_text(tr("Color path segments by autonomous system"));
+                    ui.checkbox(&mut trace_set.city_labels, tr("City labels"))
+                        .on_hover_text(tr("Label map nodes with their city instead of the hop number"));
+                    ui.checkbox(&mut trace_set.show_private, tr("Private hops on map"))
+                        .on_hover_text(tr("Draw hops in private ranges next to the previous hop"));
+                    ui.checkbox(&mut trace_set.border_ticks, tr("Country borders"))
+                        .on_hover_text(tr("Mark where the path enters another country"));
+                    ui.checkbox(&mut trace_set.sparklines, tr("RTT sparklines"))
+                        .on_hover_text(tr("Recent RTTs of hops the scheduled traces go through"));
+                }
+                SettingsTab::Appearance => {
+                    let before = *language;
+                    egui::ComboBox::from_label(tr("Language"))
+                        .selected_text(language.name())
+                        .show_ui(ui, |ui| {
+                            for choice in i18n::Language::ALL {
+                                ui.selectable_value(language, choice, choice.name());
+                            }
+                        });
+                    if *language != before {
+                        i18n::install_fonts(ui.ctx(), *language);
+                        i18n::set_language(*language);
+                    }
+                    let native = ui.ctx().native_pixels_per_point().unwrap_or(1.0);
+                    let mut zoom = window.ui_scale.unwrap_or_else(|| ui.ctx().zoom_factor());
+                    ui.horizontal(|ui| {
+                        let response = ui
+                            .add(
+                                egui::Slider::new(&mut zoom, 0.5..=3.0)
+                                    .text(tr("UI scale"))
+                                    .custom_formatter(|v, _| format!("{:.0}%", v * 100.))
+                                    .custom_parser(|s| s.trim().trim_end_matches('%').parse::<f64>().ok().map(|v| v / 100.)),
+                            )
+                            .on_hover_text(trf(
+                                "On top of this display's own {}%, kept for every display. Ctrl + and Ctrl - work too",
+                                &[&format!("{:.0}", native * 100.)],
+                            ));
+                        if response.dragged() {
+                            window.ui_scale = Some(zoom);
+                        } else if response.changed() || response.drag_stopped() {
+                            window.ui_scale = None;
+                            ui.ctx().set_zoom_factor(zoom);
+                        }
+                        if ui.small_button(tr("Reset")).clicked() {
+                            ui.ctx().set_zoom_factor(1.0);
+                        }
+                    });
+                    let mut style = trace_set.style();
+                    egui::ComboBox::from_label(tr("Palette"))
+                        .selected_text(tr(style.palette.name()))
+                        .show_ui(ui, |ui| {
+                            for palette in Palette::ALL {
+                                ui.selectable_value(&mut style.palette, palette, tr(palette.name()));
+                            }
+                        });
+                    ui.add(egui::Slider::new(&mut style.marker_size, 2.0..=12.0).text(tr("Marker size")));
+                    ui.add(egui::Slider::new(&mut style.line_width, 0.5..=6.0).text(tr("Line width")));
+                    ui.add(egui::Slider::new(&mut style.arrow_density, 0.0..=10.0).text(tr("Arrows")))
+                        .on_hover_text(tr("Direction arrows per 100 points of path, 0 hides them"));
+                    ui.checkbox(&mut style.high_contrast, tr("High contrast"))
+                        .on_hover_text(tr("Black and white windows with strong outlines, thicker outlined paths, bigger markers and labels, for low vision and projectors"));
+                    ui.add_enabled(
+                        style.high_contrast,
+                        egui::Slider::new(&mut style.tile_dim, 0.0..=0.9).text(tr("Dim tiles")),
+                    );
+                    if style != trace_set.style() {
+                        trace_set.set_style(style);
+                    }
+                    ui.separator();
+                    if ui
+                        .button(tr("Reset layout"))
+                        .on_hover_text(tr("Move windows back where they started and close the tool windows"))
+                        .clicked()
+                    {
+                        window.reset_layout = true;
+                    }
+                }
+                SettingsTab::Advanced => {
+                    ui.add(egui::Slider::new(worker_threads, 0..=32).text(tr("Worker threads")))
+                        .on_hover_text(tr("Threads for lookups and traces in the background, 0 for one per core. Takes effect on restart"));
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Shared folder"));
+                        let response = ui
+                            .add(egui::TextEdit::singleline(shared_dir).hint_text(tr("Keep baselines and notes local")))
+                            .named(tr("Shared folder"))
+                            .on_hover_text(tr(
+                                "Folder the team shares baselines and notes through, e.g. a network drive. \
+                                 Mount a WebDAV share as a drive to use it here",
+                            ));
+                        if response.lost_focus() {
+                            window.reload_shared = true;
+                        }
+                    });
+                    #[cfg(feature = "tray")]
+                    {
+                        ui.checkbox(&mut tray.enabled, tr("Tray icon"))
+                            .on_hover_text(tr("Favorite targets can be traced from the tray, takes effect on restart"));
+                        ui.add_enabled(
+                            tray.enabled,
+                            egui::Checkbox::new(&mut tray.close_to_tray, tr("Close to tray")),
+                        )
+                        .on_hover_text(tr("Keep running in the tray when the window is closed"));
+                    }
+                    ui.collapsing("SNMP", |ui| {
+                        ui.checkbox(&mut snmp.enabled, tr("Query managed hops"))
+                            .on_hover_text(tr("Right click a hop and pick Interface to look it up"));
+                        let mut managed = snmp.managed.join("\n");
+                        if ui
+                            .add(
+                                egui::TextEdit::multiline(&mut managed)
+                                    .hint_text(tr("Managed ranges, one CIDR per line"))
+                                    .desired_rows(2),
+                            )
+                            .named(tr("Managed ranges, one CIDR per line"))
+                            .changed()
+                        {
+                            snmp.managed = managed.split('\n').map(str::to_string).collect();
+                        }
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut snmp.version, snmp::Version::V2c, "v2c");
+                            ui.selectable_value(&mut snmp.version, snmp::Version::V3, "v3");
+                        });
+                        match snmp.version {
+                            snmp::Version::V2c => {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut snmp.community)
+                                        .hint_text(tr("Community"))
+                                        .password(true),
+                                )
+                                .named(tr("Community"));
+                            }
+                            snmp::Version::V3 => {
+                                ui.add(egui::TextEdit::singleline(&mut snmp.username).hint_text(tr("User"))).named(tr("User"));
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut snmp.auth_password)
+                                        .hint_text(tr("Auth password"))
+                                        .password(true),
+                                )
+                                .named(tr("Auth password"));
+                                egui::ComboBox::from_label(tr("Auth"))
+                                    .selected_text(format!("{:?}", snmp.auth_hash))
+                                    .show_ui(ui, |ui| {
+                                        for hash in [snmp::AuthHash::Md5, snmp::AuthHash::Sha1, snmp::AuthHash::Sha256] {
+                                            ui.selectable_value(&mut snmp.auth_hash, hash, format!("{:?}", hash));
+                                        }
+                                    });
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut snmp.privacy_password)
+                                        .hint_text(tr("Privacy password"))
+                                        .password(true),
+                                )
+                                .named(tr("Privacy password"));
+                                egui::ComboBox::from_label(tr("Privacy"))
+                                    .selected_text(format!("{:?}", snmp.privacy))
+                                    .show_ui(ui, |ui| {
+                                        for privacy in [snmp::Privacy::Des, snmp::Privacy::Aes128] {
+                                            ui.selectable_value(&mut snmp.privacy, privacy, format!("{:?}", privacy));
+                                        }
+                                    });
+                            }
+                        }
+                    });
+                    ui.collapsing(tr("Scripts"), |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(trf("{} loaded", &[&enrichment.scripts.len()]));
+                            if ui.button(tr("Reload")).clicked() {
+                                enrichment.scripts = Arc::new(Scripts::load());
+                            }
+                        });
+                        if let Some(dir) = crate::scripts::scripts_dir() {
+                            ui.label(RichText::new(dir.display().to_string()).small().weak())
+                                .on_hover_text(tr("*.rhai files defining fn hop(hop) and/or fn trace(target, hops)"));
+                        }
+                    });
                 }
             });
         });
+    window.open = open;
+    if resolver != (config.resolver, config.dns_server.clone()) {
+        trace::set_resolver(config.resolver, &config.dns_server);
+    }
+    apply
+}
+
+/// Saving the map area to a PNG, which takes a few frames.
+pub struct MapCapture {
+    state: CaptureState,
+    /// Area the maps were drawn in this frame
+    pub rect: egui::Rect,
+    status: Option<Result<String, String>>,
 }
 
-pub fn enter_ip(ui: &mut Ui, trace_path: &mut TracePath, runtime: &Runtime) {
-    static IP_INPUT: std::sync::OnceLock<std::sync::Mutex<IpInput>> = std::sync::OnceLock::new();
-    static TRACE_CHANNEL: std::sync::OnceLock<std::sync::Mutex<TraceChannel>> = std::sync::OnceLock::new();
+impl Default for MapCapture {
+    fn default() -> Self {
+        Self {
+            state: CaptureState::Idle,
+            rect: egui::Rect::NOTHING,
+            status: None,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum CaptureState {
+    Idle,
+    /// The next frame is drawn without windows and captured
+    Requested,
+    /// Waiting for the screenshot to come back
+    Waiting,
+}
+
+impl MapCapture {
+    /// Whether this frame is the one being captured, so only the map should be drawn.
+    pub fn hide_windows(&self) -> bool {
+        self.state == CaptureState::Requested
+    }
+
+    /// Call at the end of every frame.
+    pub fn update(&mut self, ctx: &egui::Context) {
+        match self.state {
+            CaptureState::Idle => {}
+            CaptureState::Requested => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot);
+                self.state = CaptureState::Waiting;
+            }
+            CaptureState::Waiting => {
+                let image = ctx.input(|i| {
+                    i.events.iter().find_map(|e| match e {
+                        egui::Event::Screenshot { image, .. } => Some(image.clone()),
+                        _ => None,
+                    })
+                });
+                if let Some(image) = image {
+                    self.state = CaptureState::Idle;
+                    self.status = Some(save_capture(&image.region(&self.rect, Some(ctx.pixels_per_point()))));
+                }
+            }
+        }
+    }
+}
 
-    let ip_input = IP_INPUT.get_or_init(|| std::sync::Mutex::new(IpInput::default()));
-    let trace_channel = TRACE_CHANNEL.get_or_init(|| std::sync::Mutex::new(TraceChannel::default()));
+fn save_capture(image: &egui::ColorImage) -> Result<String, String> {
+    let [width, height] = image.size;
+    let pixels = image.pixels.iter().flat_map(|c| c.to_srgba_unmultiplied()).collect();
+    let image = image::RgbaImage::from_raw(width as u32, height as u32, pixels).ok_or(tr("Empty screenshot"))?;
+    let dir = directories::UserDirs::new()
+        .and_then(|dirs| dirs.picture_dir().or(dirs.download_dir()).map(|d| d.to_path_buf()))
+        .unwrap_or_default();
+    let path = dir.join(format!("traced-map-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    image
+        .save(&path)
+        .map_err(|e| trf("Failed to save image: {}", &[&e]))?;
+    info!("Saved map image to {}", path.display());
+    Ok(path.display().to_string())
+}
 
-    Window::new("Enter IP or Domain")
+/// Lists the keyboard shortcuts.
+pub fn help(ui: &Ui, open: &mut bool) {
+    Window::new(tr("Keyboard shortcuts"))
+        .id(egui::Id::new("Keyboard shortcuts"))
+        .open(open)
+        .collapsible(false)
         .resizable(false)
-        .anchor(Align2::RIGHT_CENTER, [-10., 0.])
         .show(ui.ctx(), |ui| {
-            ui.horizontal(|ui| {
-                let mut ip_guard = ip_input.lock().unwrap();
-                let text_edit = ui.add_enabled(
-                    !trace_path.tracing,
-                    egui::TextEdit::singleline(&mut ip_guard.value)
-                );
-                let trace_button = ui.add_enabled(
-                    !trace_path.tracing,
-                    egui::Button::new("Trace")
-                );
-                
-                if !trace_path.tracing && 
-                   ((text_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) 
-                   || trace_button.clicked()) {
-                    let trace_guard = trace_channel.lock().unwrap();
-                    let sender = trace_guard.sender.clone();
-                    let ip = ip_guard.value.clone();
-                    info!("Starting trace for IP: {}", ip);
-                    trace_path.nodes.clear();
-                    trace_path.tracing = true;
-                    runtime.spawn(async move {
-                        match trace(&ip).await {
-                            Ok(mut events) => {
-                                while let Some(event) = events.recv().await {
-                                    sender.send(event).ok();
-                                }
-                            }
-                            Err(e) => error!("Trace failed: {}", e),
-                        }
-                    });
+            egui::Grid::new("shortcuts").striped(true).num_columns(2).show(ui, |ui| {
+                for (keys, action) in crate::app::SHORTCUTS {
+                    ui.strong(keys);
+                    ui.label(tr(action));
+                    ui.end_row();
                 }
             });
+        });
+}
+
+/// Simple GUI to zoom in and out, and to save the map as an image.
+pub fn zoom(ui: &Ui, map_memory: &mut MapMemory, capture: &mut MapCapture, places: &mut PlaceSearch, show_help: &mut bool) {
+    Window::new(tr("Map"))
+        .id(egui::Id::new("Map"))
+        .collapsible(false)
+        .resizable(false)
+        .title_bar(false)
+        .pivot(Align2::LEFT_BOTTOM)
+        .default_pos(corner(ui.ctx(), Align2::LEFT_BOTTOM, [10., -10.]))
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button(RichText::new("➕").heading()).described(tr("Zoom in")).clicked() {
+                    let _ = map_memory.zoom_in();
+                }
+
+                if ui.button(RichText::new("➖").heading()).described(tr("Zoom out")).clicked() {
+                    let _ = map_memory.zoom_out();
+                }
+
+                if ui
+                    .toggle_value(&mut places.open, RichText::new("🔍").heading())
+                    .described(tr("Go to location (Ctrl+G)"))
+                    .clicked()
+                    && places.open
+                {
+                    places.show(ui.ctx());
+                }
 
-            let trace_guard = trace_channel.lock().unwrap();
-            if let Ok(event) = trace_guard.receiver.try_recv() {
-                match event {
-                    TraceEvent::Node(node) => {
-                        trace_path.nodes.push((trace_path.nodes.len(), node));
+                ui.toggle_value(show_help, RichText::new("⌨").heading()).described(tr("Keyboard shortcuts (F1)"));
+
+                if ui.button(RichText::new("📷").heading()).described(tr("Save image")).clicked() {
+                    capture.state = CaptureState::Requested;
+                    capture.status = None;
+                    ui.ctx().request_repaint();
+                }
+                match &capture.status {
+                    Some(Ok(path)) => {
+                        ui.label(RichText::new(trf("Saved to {}", &[path])).small());
                     }
-                    TraceEvent::Finish => {
-                        trace_path.tracing = false;
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, e);
                     }
+                    None => {}
                 }
-            }
+            });
         });
+}
 
-    // Add loading spinner in bottom right
-    if trace_path.tracing {
-        Window::new("Loading")
-            .collapsible(false)
-            .resizable(false)
-            .title_bar(false)
-            .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
-            .show(ui.ctx(), |ui| {
-                ui.horizontal(|ui| {
-                    ui.spinner(); // Built-in spinner widget
-                    ui.label("Tracing route...");
-                });
-            });
-    }
+/// Id source of the place search input, so it can be focused when the window opens.
+const PLACE_INPUT: &str = "place_input";
+
+/// Finding a place by name and moving the map there.
+pub struct PlaceSearch {
+    open: bool,
+    query: String,
+    searching: bool,
+    results: Result<Vec<places::Place>, String>,
+    sender: Sender<Result<Vec<places::Place>, String>>,
+    receiver: Receiver<Result<Vec<places::Place>, String>>,
 }
 
-async fn get_my_ip(client: &reqwest::Client) -> Option<String> {
-    match client.get("https://api.ipify.org").send().await {
-        Ok(resp) => {
-            if let Ok(my_ip) = resp.text().await {
-                debug!("Retrieved IP: {}", my_ip);
-                Some(my_ip)
-            } else {
-                None
-            }
-        }
-        Err(e) => {
-            warn!("Failed to get IP: {}", e);
-            None
+impl Default for PlaceSearch {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            open: false,
+            query: String::new(),
+            searching: false,
+            results: Ok(Vec::new()),
+            sender,
+            receiver,
         }
     }
 }
 
-async fn get_location(client: &reqwest::Client, ip: &str) -> Option<(Position, String)> {
-    if let Ok(resp) = client
-        .get(format!("http://ip-api.com/json/{}", ip))
-        .send()
-        .await
-    {
-        if let Ok(location) = resp.json::<IpApiResponse>().await {
-            if location.status == "success" {
-                return Some((
-                    Position::from_lat_lon(location.lat, location.lon),
-                    location.isp,
-                ));
-            }
+impl PlaceSearch {
+    /// Opens the window with the search input focused.
+    pub fn show(&mut self, ctx: &egui::Context) {
+        self.open = true;
+        ctx.memory_mut(|m| m.request_focus(egui::Id::new(PLACE_INPUT)));
+    }
+
+    fn search(&mut self, runtime: &Executor) {
+        let query = self.query.trim().to_string();
+        if query.is_empty() {
+            return;
         }
+        self.searching = true;
+        let sender = self.sender.clone();
+        runtime.spawn(async move {
+            let result = places::search(&reqwest::Client::new(), &query).await;
+            if let Err(e) = &result {
+                warn!("Place search for {} failed: {}", query, e);
+            }
+            sender.send(result).ok();
+        });
     }
-    None
 }
 
-// Modify trace function to use new helpers
-async fn trace(
-    target: &str,
-) -> Result<mpsc::UnboundedReceiver<TraceEvent>, Box<dyn std::error::Error + Send + Sync>> {
-    info!("Starting trace for target: {}", target);
-    let (tx, rx) = mpsc::unbounded_channel();
-    let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
-    let client = reqwest::Client::new();
-
-    // Get my ip first
-    if let Some(ip) = get_my_ip(&client).await {
-        if let Some((position, isp)) = get_location(&client, ip.as_str()).await {
-            tx.send(TraceEvent::Node(TraceNode {
-                position,
-                hostname: "Local".to_string(),
-                isp,
-                ip,
-            })).ok();
+/// Search box for places, centering the map on the first match and on any result clicked.
+pub fn places(ui: &Ui, window: &mut PlaceSearch, map_memory: &mut MapMemory, runtime: &Executor) {
+    let size = ui.max_rect().size();
+    let mut go_to = |place: &places::Place| {
+        if let Some((center, zoom)) = geo::fit(&place.extent(), size) {
+            map_memory.center_at(center);
+            map_memory.set_zoom(zoom).ok();
+        }
+    };
+    if let Ok(results) = window.receiver.try_recv() {
+        window.searching = false;
+        if let Some(first) = results.as_ref().ok().and_then(|r| r.first()) {
+            go_to(first);
         }
+        window.results = results;
     }
 
-    // Resolve domain name or parse IP
-    let ip = match target.parse::<IpAddr>() {
-        Ok(ip) => {
-            debug!("Parsed direct IP: {}", ip);
-            Some(ip)
-        }
-        Err(_) => {
-            debug!("Attempting DNS lookup for: {}", target);
-            match lookup_host(target) {
-                Ok(ips) => {
-                    if let Some(ip) = ips.first() {
-                        debug!("DNS lookup successful: {}", ip);
-                        Some(*ip)
-                    } else {
-                        error!("DNS lookup returned no results");
-                        None
+    let mut open = window.open;
+    Window::new(tr("Go to location"))
+        .id(egui::Id::new("Go to location"))
+        .open(&mut open)
+        .default_width(320.)
+        .pivot(Align2::LEFT_BOTTOM)
+        .default_pos(corner(ui.ctx(), Align2::LEFT_BOTTOM, [10., -60.]))
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut window.query)
+                        .id(egui::Id::new(PLACE_INPUT))
+                        .hint_text(tr("City, address or landmark"))
+                        .desired_width(220.),
+                )
+                .named(tr("Go to location"));
+                let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                let clicked = ui.add_enabled(!window.searching, egui::Button::new(tr("Search"))).clicked();
+                if (submitted || clicked) && !window.searching {
+                    window.search(runtime);
+                }
+                if window.searching {
+                    ui.spinner();
+                }
+            });
+            match &window.results {
+                Ok(results) => {
+                    for place in results {
+                        if ui.selectable_label(false, &place.name).clicked() {
+                            go_to(place);
+                        }
                     }
                 }
                 Err(e) => {
-                    error!("DNS lookup failed: {}", e);
-                    None
+                    ui.colored_label(egui::Color32::RED, e);
                 }
             }
+            ui.label(RichText::new(tr("Search by OpenStreetMap Nominatim")).small().weak());
+        });
+    window.open = open;
+}
+
+/// Id source of the quick launcher input, focused whenever it opens.
+const LAUNCHER_INPUT: &str = "launcher_input";
+
+/// Keyboard-only overlay that starts a trace from one line, see [`launcher::parse`].
+#[derive(Default)]
+pub struct QuickLaunch {
+    open: bool,
+    line: String,
+}
+
+impl QuickLaunch {
+    /// Opens the overlay with an empty line, or closes it when it's already open.
+    pub fn toggle(&mut self, ctx: &egui::Context) {
+        self.open = !self.open;
+        self.line.clear();
+        if self.open {
+            ctx.memory_mut(|m| m.request_focus(egui::Id::new(LAUNCHER_INPUT)));
         }
-    };
+    }
 
-    // Handle DNS resolution failure
-    let ip = match ip {
-        Some(ip) => ip,
-        None => {
-            tx.send(TraceEvent::Finish).ok();
-            return Ok(rx);
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+/// The quick launcher overlay. Enter starts what the line says, Esc closes it.
+pub fn quick_launch(ui: &Ui, window: &mut QuickLaunch, trace_set: &mut TraceSet, config: &mut Config) {
+    if !window.open {
+        return;
+    }
+    let parsed = launcher::parse(&window.line, config.trace);
+    egui::Area::new(egui::Id::new("quick_launch"))
+        .anchor(Align2::CENTER_TOP, [0., 80.])
+        .order(egui::Order::Foreground)
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_width(420.);
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut window.line)
+                        .id(egui::Id::new(LAUNCHER_INPUT))
+                        .hint_text("example.com --hops 20 --tag incident")
+                        .font(egui::TextStyle::Heading)
+                        .desired_width(f32::INFINITY),
+                )
+                .named(tr("Quick launch"));
+                let (enter, escape) = ui.input(|i| (i.key_pressed(egui::Key::Enter), i.key_pressed(egui::Key::Escape)));
+                // Both keys take the focus away from the input
+                if input.lost_focus() && escape {
+                    window.open = false;
+                    return;
+                }
+                match &parsed {
+                    // Nothing typed yet isn't worth an error
+                    Err(_) if window.line.trim().is_empty() => {
+                        ui.label(RichText::new(launcher::FLAGS).small().weak());
+                    }
+                    Err(e) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    Ok(launch) => {
+                        let hint = if launch.ping {
+                            trf("Enter to ping {}", &[&launch.target])
+                        } else {
+                            trf("Enter to trace {}", &[&launch.target])
+                        };
+                        ui.label(RichText::new(hint).small().weak());
+                    }
+                }
+                if input.lost_focus() && enter {
+                    match parsed {
+                        Ok(launch) if launch.ping => {
+                            config.remember_target(&launch.target);
+                            trace_set.ping_request = Some(launch.target);
+                            window.open = false;
+                        }
+                        Ok(launch) => {
+                            let settings = launch.custom.then_some(TraceSettings {
+                                options: launch.options,
+                                geolocation: config.geolocation,
+                            });
+                            trace_set.start_requests.push(StartRequest {
+                                target: launch.target,
+                                tags: launch.tags,
+                                settings,
+                            });
+                            window.open = false;
+                            ui.ctx().request_repaint();
+                        }
+                        // Keep the line so it can be fixed
+                        Err(_) => input.request_focus(),
+                    }
+                }
+            });
+        });
+}
+
+/// How long typing has to pause before the target is checked against the blocklists.
+const BLOCKLIST_DELAY: Duration = Duration::from_millis(600);
+
+/// Badge saying whether the target domain is on one of the configured DNS blocklists,
+/// checked as soon as it's typed so a known bad target is flagged before it's probed.
+fn blocklists(ui: &mut Ui, ip_input: &mut IpInput, zones: &[String], runtime: &Executor) {
+    if let Some(result) = ip_input.checking.as_ref().and_then(|receiver| receiver.try_recv().ok()) {
+        ip_input.verdict = Some(result);
+        ip_input.checking = None;
+    }
+    let target = ip_input.value.trim().to_lowercase();
+    let off = zones.iter().all(|zone| zone.trim().is_empty());
+    if off || target.is_empty() || target.parse::<IpAddr>().is_ok() {
+        return;
+    }
+    let checked = ip_input.verdict.as_ref().filter(|(checked, _)| *checked == target);
+    let Some((_, verdict)) = checked else {
+        let typing = ip_input.edited_at.is_some_and(|at| at.elapsed() < BLOCKLIST_DELAY);
+        if typing {
+            ui.ctx().request_repaint_after(BLOCKLIST_DELAY);
+        } else if ip_input.checking.is_none() {
+            let (sender, receiver) = channel();
+            ip_input.checking = Some(receiver);
+            let zones = zones.to_vec();
+            let ctx = ui.ctx().clone();
+            runtime.spawn_blocking(move || {
+                let verdict = dnsbl::check(&target, &zones);
+                sender.send((target, verdict)).ok();
+                ctx.request_repaint();
+            });
+        }
+        if ip_input.checking.is_some() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.weak(tr("Checking blocklists…"));
+            });
         }
+        return;
     };
+    if verdict.listed() {
+        ui.colored_label(egui::Color32::RED, tr("⚠ On a blocklist, be careful with this target"))
+            .on_hover_text(verdict.describe());
+    } else {
+        let text = if verdict.unavailable.is_empty() {
+            tr("🛡 Not blocklisted")
+        } else {
+            tr("🛡 Not blocklisted, some lists didn't answer")
+        };
+        ui.label(RichText::new(text).small().weak()).on_hover_text(verdict.describe());
+    }
+}
 
-    debug!("Starting tracer for IP: {}", ip);
-    thread::spawn(move || {
-        let tracer = tracert::trace::Tracer::new(ip).unwrap();
-        let progress_receiver = tracer.get_progress_receiver();
-        thread::spawn(move || tracer.trace());
-
-        while let Ok(node) = progress_receiver.lock().unwrap().recv() {
-            debug!("Got hop {}, sending", node.ip_addr);
-            progress_tx.send(node).ok();
+/// Lists every address a domain target resolves to, to trace one or all of them instead of
+/// whichever the resolver happened to put first.
+fn addresses(ui: &mut Ui, ip_input: &mut IpInput, starts: &mut Vec<StartRequest>, runtime: &Executor) {
+    if let Some(result) = ip_input.lookup.as_ref().and_then(|receiver| receiver.try_recv().ok()) {
+        ip_input.addresses = Some(result);
+        ip_input.lookup = None;
+    }
+    let target = ip_input.value.trim().to_string();
+    if target.is_empty() || target.parse::<IpAddr>().is_ok() {
+        return;
+    }
+    let current = ip_input.addresses.clone().filter(|(looked_up, _)| *looked_up == target);
+    let Some((_, result)) = current else {
+        ui.horizontal(|ui| {
+            if ip_input.lookup.is_some() {
+                ui.spinner();
+                ui.ctx().request_repaint_after(Duration::from_millis(100));
+            } else if ui
+                .small_button(tr("All addresses…"))
+                .on_hover_text(tr("CDNs and anycast services have several, a trace only takes the first"))
+                .clicked()
+            {
+                let (sender, receiver) = channel();
+                ip_input.lookup = Some(receiver);
+                runtime.spawn_blocking(move || {
+                    let result = trace::resolve_all(&target);
+                    sender.send((target, result)).ok();
+                });
+            }
+        });
+        return;
+    };
+    // Traces of single addresses keep the domain as a tag
+    let mut tags = history::parse_tags(&ip_input.tags);
+    tags.push(target.clone());
+    match result {
+        Ok(ips) => {
+            for ip in &ips {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(ip.to_string()).monospace());
+                    if ui.small_button(tr("Trace")).clicked() {
+                        starts.push(StartRequest::new(ip.to_string(), tags.clone()));
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                if ips.len() > 1 && ui.small_button(trf("Trace all {}", &[&ips.len()])).clicked() {
+                    starts.extend(ips.iter().map(|ip| StartRequest::new(ip.to_string(), tags.clone())));
+                }
+                if ui.small_button(tr("Hide")).clicked() {
+                    ip_input.addresses = None;
+                }
+            });
         }
-    });
+        Err(e) => {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+    }
+}
 
-    debug!("Starting location lookup");
-    tokio::spawn(async move {
-        while let Some(node) = progress_rx.recv().await {
-            let ip_str = node.ip_addr.to_string();
-            debug!("Processing hop: {}", ip_str);
-            
-            if let Some((position, isp)) = get_location(&client, &ip_str).await {
-                tx.send(TraceEvent::Node(TraceNode {
-                    position,
-                    hostname: node.host_name,
-                    isp,
-                    ip: ip_str,
-                })).ok();
-            }
-        }
-        tx.send(TraceEvent::Finish).ok();
-    });
+/// Id source of the target input, so shortcuts can focus it.
+pub const TARGET_INPUT: &str = "target_input";
+
+/// Starts, resumes and looks up again the traces asked for since the last frame, from the
+/// target input or elsewhere, e.g. the command line. Runs whether or not the target window
+/// is drawn or collapsed.
+pub fn run_requests(
+    ctx: &egui::Context,
+    ip_input: &mut IpInput,
+    trace_set: &mut TraceSet,
+    config: &mut Config,
+    enrichment: &Enrichment,
+    bus: &Bus,
+    runtime: &Executor,
+) {
+    let starts = std::mem::take(&mut trace_set.start_requests);
+    if let Some(request) = starts.last() {
+        ip_input.value = request.target.clone();
+    }
+    for StartRequest { target: ip, tags, settings } in starts {
+        let topic = bus.traces.clone();
+        info!("Starting trace for IP: {}", ip);
+        config.remember_target(&ip);
+        let settings = settings.unwrap_or(TraceSettings {
+            options: config.options_for(&ip),
+            geolocation: config.geolocation,
+        });
+        config.remember_options(&ip, settings.options);
+        let id = trace_set.add(ip.clone(), tags, settings);
+        let tracer = Tracer::new(settings.options, settings.geolocation, enrichment.clone());
+        forward_trace(id, async move { tracer.trace(&ip).await }, topic, ctx.clone(), runtime);
+    }
+    for id in std::mem::take(&mut trace_set.resume_requests) {
+        let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) else {
+            continue;
+        };
+        let (target, found) = (trace.target.clone(), trace.nodes.clone());
+        let tracer = Tracer::new(trace.settings.options, trace.settings.geolocation, enrichment.clone());
+        let events = async move { tracer.resume(&target, found).await };
+        forward_trace(trace.run, events, bus.traces.clone(), ctx.clone(), runtime);
+    }
+    for id in std::mem::take(&mut trace_set.lookup_requests) {
+        if let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) {
+            locate_pending(trace, enrichment, bus.traces.clone(), ctx.clone(), runtime);
+        }
+    }
+}
 
-    Ok(rx)
+/// Applies what the running traces sent since the last frame, and once one finishes records
+/// it and raises its alerts. Runs whether or not the target window is drawn or collapsed.
+pub fn apply_trace_events(
+    trace_set: &mut TraceSet,
+    history: &mut History,
+    config: &Config,
+    bus: &Bus,
+    trace_events: &Receiver<(usize, TraceEvent)>,
+) {
+    while let Ok((run, event)) = trace_events.try_recv() {
+        // Events for traces that were removed, cancelled or stopped and resumed in the
+        // meantime are dropped
+        if let Some(trace) = trace_set.by_run(run).filter(|t| t.tracing || t.relocating) {
+            let id = trace.id;
+            if let TraceEvent::Located(located) = &event {
+                history.relocate(located);
+            }
+            let finished = matches!(event, TraceEvent::Finish);
+            trace.apply(event);
+            if finished {
+                trace.deviations = history.baselines.compare(&trace.target, &trace.nodes);
+                let completed = trace.nodes.len() > 1;
+                let detail = if completed {
+                    trf("{} hops", &[&(trace.nodes.len() - 1)])
+                } else {
+                    tr("no reply").to_string()
+                };
+                bus.alerts.publish(Alert::new(AlertKind::TraceComplete, &trace.target, detail));
+                if let Some(deviations) = trace.deviations.as_ref().filter(|d| !d.is_empty()) {
+                    let detail = trf("{} hops off the baseline", &[&deviations.len()]);
+                    bus.alerts.publish(Alert::new(AlertKind::MonitorAlert, &trace.target, detail));
+                }
+                let breaches = geofence::breaches(
+                    &config.geofences,
+                    trace.nodes.iter().map(|(ttl, node)| (*ttl, node.position)),
+                );
+                if let Some(detail) = geofence::describe(&breaches) {
+                    bus.alerts.publish(Alert::new(AlertKind::MonitorAlert, &trace.target, detail));
+                }
+                let violations = policy::violations(
+                    &config.transit_policies,
+                    trace.nodes.iter().map(|(ttl, node)| (*ttl, node.asn)),
+                );
+                // Under the trace in the list as well
+                trace.summary
+                    .extend(violations.iter().map(|v| trf("⚠ Transit policy: {}", &[&v.describe()])));
+                if let Some(detail) = policy::describe(&violations) {
+                    bus.alerts.publish(Alert::new(AlertKind::MonitorAlert, &trace.target, detail));
+                }
+                history.record(
+                    trace.target.clone(),
+                    trace.started,
+                    trace.tags.clone(),
+                    trace.settings,
+                    trace.network.clone(),
+                    trace.nodes.clone(),
+                    trace.timeouts.clone(),
+                );
+                if completed {
+                    trace_set.stats_request = Some(id);
+                    let index = history.entries.len() - 1;
+                    if let Some(previous) = history.previous(index) {
+                        let (before, after) = (&history.entries[previous], &history.entries[index]);
+                        if before.fingerprint() != after.fingerprint() {
+                            let (before, after) = (before.path(), after.path());
+                            let first = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+                            let now = after.get(first).copied().unwrap_or(tr("missing"));
+                            let detail = trf("hop {} now {}", &[&(first + 1), &now]);
+                            let target = &history.entries[index].target;
+                            bus.alerts.publish(Alert::new(AlertKind::RouteChanged, target, detail));
+                            trace_set.route_diff_request = Some((previous, index));
+                        }
+                    }
+                }
+                trace_set.retry_lookups(id);
+            }
+        }
+    }
+}
+
+pub fn enter_ip(
+    ui: &mut Ui,
+    ip_input: &mut IpInput,
+    trace_set: &mut TraceSet,
+    config: &mut Config,
+    ping_window: &mut PingWindow,
+    enrichment: &Enrichment,
+    runtime: &Executor,
+) {
+    Window::new(tr("Enter IP or Domain"))
+        .id(egui::Id::new("Enter IP or Domain"))
+        .resizable(false)
+        .pivot(Align2::RIGHT_CENTER)
+        .default_pos(corner(ui.ctx(), Align2::RIGHT_CENTER, [-10., 0.]))
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut ip_input.mode, Mode::Trace, tr("Traceroute"));
+                ui.selectable_value(&mut ip_input.mode, Mode::Ping, tr("Ping"));
+            });
+            ui.horizontal(|ui| {
+                let text_edit = target_input(ui, &mut ip_input.value, config);
+                if text_edit.changed() {
+                    ip_input.edited_at = Some(Instant::now());
+                }
+                let label = match ip_input.mode {
+                    Mode::Trace => tr("Trace"),
+                    Mode::Ping => tr("Ping"),
+                };
+                let trace_button = ui.add_enabled(
+                    !ip_input.value.trim().is_empty(),
+                    egui::Button::new(label)
+                );
+                
+                if !ip_input.value.trim().is_empty() &&
+                   ((text_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) 
+                   || trace_button.clicked()) {
+                    let ip = ip_input.value.trim().to_string();
+                    match ip_input.mode {
+                        // Started with the next frame, see `run_requests`
+                        Mode::Trace => {
+                            trace_set.start_requests.push(StartRequest::new(ip, history::parse_tags(&ip_input.tags)))
+                        }
+                        Mode::Ping => {
+                            config.remember_target(&ip);
+                            ping_window.start(
+                                ip,
+                                config.trace,
+                                config.geolocation,
+                                enrichment.sites.clone(),
+                                runtime,
+                            )
+                        }
+                    }
+                }
+            });
+            blocklists(ui, ip_input, &config.blocklists, runtime);
+            let remembered = config
+                .target_options
+                .get(ip_input.value.trim())
+                .map(|options| options.differences(&config.trace))
+                .filter(|differences| !differences.is_empty());
+            if let Some(differences) = remembered {
+                let mut forget = false;
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(format!("⚙ {}", differences.join(", "))).small())
+                        .on_hover_text(tr("Options this target was traced with last time, used again instead of the current ones"));
+                    forget = ui.small_button(tr("Forget")).clicked();
+                });
+                if forget {
+                    config.target_options.remove(ip_input.value.trim());
+                }
+            }
+            addresses(ui, ip_input, &mut trace_set.start_requests, runtime);
+            ui.horizontal(|ui| {
+                ui.label(tr("Tags"));
+                ui.add(
+                    egui::TextEdit::singleline(&mut ip_input.tags)
+                        .hint_text(tr("e.g. baseline, incident-1234")),
+                )
+                .named(tr("Tags"));
+            });
+
+            if !trace_set.traces.is_empty() {
+                ui.separator();
+                traces(ui, trace_set);
+            }
+        });
+
+    // How far the running traces got, in the bottom right
+    if trace_set.tracing() {
+        let running: Vec<&plugins::Trace> = trace_set.traces.iter().filter(|t| t.tracing).collect();
+        Window::new(tr("Loading"))
+            .id(egui::Id::new("Loading"))
+            .collapsible(false)
+            .resizable(false)
+            .title_bar(false)
+            .anchor(Align2::RIGHT_BOTTOM, [-10., -10.])
+            .show(ui.ctx(), |ui| {
+                for trace in &running {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        if running.len() > 1 {
+                            ui.label(RichText::new(&trace.target).color(trace.color));
+                        }
+                        ui.label(progress(trace));
+                        ui.weak(elapsed(trace.probing_since.elapsed()));
+                    });
+                }
+            });
+        // The elapsed time moves on without any events
+        ui.ctx().request_repaint_after(Duration::from_secs(1));
+    }
+}
+
+/// Where a running trace is, e.g. "Hop 7 / max 30 — probing TTL 8…".
+fn progress(trace: &plugins::Trace) -> String {
+    let max = trace.settings.options.max_hops as usize;
+    let reached = trace
+        .address
+        .map(|address| address.to_canonical().to_string())
+        .and_then(|address| trace.nodes.iter().find(|(_, node)| node.ip == address))
+        .map(|(ttl, _)| *ttl);
+    match (trace.probing, reached) {
+        _ if trace.resolving => tr("Resolving…").to_string(),
+        (_, Some(ttl)) => trf("Reached the target at hop {}, finishing…", &[&ttl]),
+        (None, None) => tr("Locating this machine…").to_string(),
+        (Some(ttl), None) if ttl <= max => trf("Hop {} / max {} — probing TTL {}…", &[&(ttl - 1), &max, &ttl]),
+        (Some(_), None) => trf("Hop {} / max {} — finishing…", &[&max, &max]),
+    }
+}
+
+/// E.g. "42 s" or "3:05".
+fn elapsed(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{} s", secs)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// The target field, completing from favorite and recent targets while typing,
+/// with a dropdown of all of them next to it.
+fn target_input(ui: &mut Ui, value: &mut String, config: &mut Config) -> egui::Response {
+    let response = ui.add(egui::TextEdit::singleline(value).id(egui::Id::new(TARGET_INPUT))).named(tr("Target"));
+    let popup_id = ui.id().with("target_completions");
+    let completions: Vec<String> = config.complete(value).into_iter().map(str::to_string).collect();
+    if response.changed() {
+        let show = !value.trim().is_empty() && !completions.is_empty();
+        ui.memory_mut(|m| match (show, m.is_popup_open(popup_id)) {
+            (true, false) => m.open_popup(popup_id),
+            (false, true) => m.close_popup(),
+            _ => {}
+        });
+    }
+    egui::popup_below_widget(ui, popup_id, &response, egui::PopupCloseBehavior::CloseOnClick, |ui| {
+        ui.set_min_width(160.);
+        for target in &completions {
+            let star = if config.is_favorite(target) { "★ " } else { "" };
+            if ui.selectable_label(false, format!("{}{}", star, target)).clicked() {
+                *value = target.clone();
+            }
+        }
+    });
+
+    let mut toggle = None;
+    ui.menu_button("⏷", |ui| {
+        if config.favorite_targets.is_empty() && config.recent_targets.is_empty() {
+            ui.weak(tr("Nothing traced yet"));
+        }
+        let favorites = config.favorite_targets.iter().map(|t| (t, true));
+        let recent = config
+            .recent_targets
+            .iter()
+            .filter(|t| !config.favorite_targets.contains(t))
+            .map(|t| (t, false));
+        for (i, (target, favorite)) in favorites.chain(recent).enumerate() {
+            if i > 0 && !favorite && i == config.favorite_targets.len() {
+                ui.separator();
+            }
+            ui.horizontal(|ui| {
+                let star = if favorite { "★" } else { "☆" };
+                let hover = if favorite { tr("Remove from favorites") } else { tr("Add to favorites") };
+                if ui.small_button(star).described(hover).clicked() {
+                    toggle = Some(target.clone());
+                }
+                if ui.selectable_label(false, target).clicked() {
+                    *value = target.clone();
+                    ui.close_menu();
+                }
+            });
+        }
+    })
+    .response
+    .described(tr("Favorite and recent targets"));
+    if let Some(target) = toggle {
+        config.toggle_favorite(&target);
+    }
+    response
+}
+
+enum TargetEdit {
+    Begin,
+    Submit(String),
+    Cancel,
+}
+
+/// A trace target that turns into a text field on Alt+click, for running it again
+/// with a different target.
+fn target_label(ui: &mut Ui, target: &str, editing: Option<&mut String>, strong: bool) -> Option<TargetEdit> {
+    match editing {
+        Some(text) => {
+            let response = ui.add(egui::TextEdit::singleline(text).desired_width(140.)).named(tr("Target"));
+            if response.lost_focus() {
+                let submit = ui.input(|i| i.key_pressed(egui::Key::Enter)) && !text.trim().is_empty();
+                Some(if submit { TargetEdit::Submit(text.trim().to_string()) } else { TargetEdit::Cancel })
+            } else {
+                if !response.has_focus() {
+                    response.request_focus();
+                }
+                None
+            }
+        }
+        None => {
+            let text = if strong { RichText::new(target).strong() } else { RichText::new(target) };
+            let response = ui
+                .add(egui::Label::new(text).sense(egui::Sense::click()))
+                .on_hover_text(tr("Alt+click to edit and run again with the same settings"));
+            (response.clicked() && ui.input(|i| i.modifiers.alt)).then_some(TargetEdit::Begin)
+        }
+    }
+}
+
+/// List of traces with a visibility toggle and remove button for each.
+fn traces(ui: &mut Ui, trace_set: &mut TraceSet) {
+    let separate = trace_set.separate_selection;
+    let mut remove = None;
+    let mut export = None;
+    let mut import_flows = None;
+    let mut stats = None;
+    let mut sweep = None;
+    let mut notes = None;
+    let mut rerun = None;
+    let mut retry = None;
+    let mut stop = None;
+    let mut lookups = None;
+    let mut highlight = None;
+    let stall_after = trace_set.stall_after;
+    let editing = &mut trace_set.editing;
+    let pinned = &mut trace_set.pinned;
+    let toasts = &trace_set.toasts;
+    for trace in &mut trace_set.traces {
+        ui.horizontal(|ui| {
+            if separate {
+                ui.checkbox(&mut trace.visible, "").on_hover_text(tr("Show in left view"));
+                ui.checkbox(&mut trace.visible_secondary, "").on_hover_text(tr("Show in right view"));
+            } else {
+                ui.checkbox(&mut trace.visible, "");
+            }
+            let dot = ui.label(RichText::new("⏺").color(trace.color));
+            if let Some(network) = &trace.network {
+                dot.on_hover_text(trf("Taken from {}", &[network]));
+            }
+            let text = editing.as_mut().filter(|(id, _)| *id == trace.id).map(|(_, text)| text);
+            match target_label(ui, &trace.target, text, false) {
+                Some(TargetEdit::Begin) => *editing = Some((trace.id, trace.target.clone())),
+                Some(TargetEdit::Submit(target)) => {
+                    *editing = None;
+                    rerun = Some(StartRequest {
+                        target,
+                        tags: trace.tags.clone(),
+                        settings: Some(trace.settings),
+                    });
+                }
+                Some(TargetEdit::Cancel) => *editing = None,
+                None => {}
+            }
+            if trace.resolving {
+                ui.spinner();
+                ui.weak(tr("Resolving…"));
+            } else if trace.stalled(stall_after) {
+                let last = trace.nodes.iter().map(|(ttl, _)| *ttl).max();
+                let hover = match last {
+                    Some(ttl) if ttl > 0 => trf("Last answer from hop {}, highlighted on the map", &[&ttl]),
+                    _ => tr("No hop has answered yet").to_string(),
+                };
+                let label = ui
+                    .colored_label(STALLED_COLOR, tr("⏸ Stalled"))
+                    .on_hover_text(trf("No new hops for {} s. {}", &[&trace.progress_at.elapsed().as_secs(), &hover]));
+                if label.hovered() {
+                    highlight = last.map(|ttl| (trace.id, ttl));
+                }
+                if ui.small_button("↻").described(tr("Trace again from scratch")).clicked() {
+                    retry = Some(trace.id);
+                }
+                if ui.small_button("⏹").described(tr("Stop, keeping the hops found so far")).clicked() {
+                    stop = Some(trace.id);
+                }
+            } else if trace.tracing {
+                let spinner = ui.spinner();
+                let text = progress(trace);
+                match trace.address.filter(|address| address.to_string() != trace.target) {
+                    Some(address) => spinner.on_hover_text(format!("{}\n{}", trf("Tracing {}", &[&address]), text)),
+                    None => spinner.on_hover_text(text),
+                };
+            } else if trace.relocating {
+                ui.spinner().on_hover_text(tr("Looking up the pending hops again…"));
+            } else if trace.pending() > 0 {
+                ui.weak(trf("{} pending", &[&trace.pending()]))
+                    .on_hover_text(tr("Hops the provider had no location for yet, drawn next to the hop before them"));
+                if ui.small_button(tr("Retry lookups")).clicked() {
+                    lookups = Some(trace.id);
+                }
+            }
+            match &trace.deviations {
+                Some(deviations) if !deviations.is_empty() => {
+                    let text = deviations
+                        .iter()
+                        .map(|(ttl, d)| trf("Hop {}: {}", &[ttl, &d.describe()]))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    ui.colored_label(DEVIATION_COLOR, "⚠").on_hover_text(text);
+                }
+                Some(_) => {
+                    ui.weak("✔").on_hover_text(tr("Matches the baseline"));
+                }
+                None => {}
+            }
+            if !trace.route_changes.is_empty() {
+                ui.colored_label(ROUTE_CHANGE_COLOR, "⇄")
+                    .on_hover_text(trf("{} hops differ from the other route", &[&trace.route_changes.len()]));
+            }
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.small_button("🗙").described(tr("Remove trace")).clicked() {
+                    remove = Some(trace.id);
+                }
+                let is_pinned = pinned.contains(&trace.target);
+                if ui
+                    .selectable_label(is_pinned, "📌")
+                    .described(tr("Pin, to trace it again when the network changes"))
+                    .clicked()
+                {
+                    if is_pinned {
+                        pinned.remove(&trace.target);
+                    } else {
+                        pinned.insert(trace.target.clone());
+                    }
+                }
+                if ui
+                    .add_enabled(!trace.tracing, egui::Button::new("💾").small())
+                    .described(tr("Export"))
+                    .clicked()
+                {
+                    export = Some(trace.id);
+                }
+                if ui
+                    .add_enabled(!trace.tracing, egui::Button::new("📋").small())
+                    .described(tr("Copy as text"))
+                    .clicked()
+                {
+                    ui.ctx().copy_text(export::traceroute_text(trace));
+                    toasts.publish(Toast::success(trf("Copied the trace to {} as text", &[&trace.target])));
+                }
+                if ui
+                    .add_enabled(!trace.tracing, egui::Button::new("📐").small())
+                    .described(tr("Path statistics"))
+                    .clicked()
+                {
+                    stats = Some(trace.id);
+                }
+                if ui
+                    .add_enabled(!trace.tracing, egui::Button::new("📶").small())
+                    .described(tr("Probe size sweep"))
+                    .clicked()
+                {
+                    sweep = Some(trace.id);
+                }
+                if ui.small_button("📝").described(tr("Notes on this target")).clicked() {
+                    notes = Some(trace.id);
+                }
+                if ui
+                    .add_enabled(!trace.tracing, egui::Button::new("📊").small())
+                    .described(tr("Import flows"))
+                    .clicked()
+                {
+                    import_flows = Some(trace.id);
+                }
+            });
+        });
+        for line in &trace.summary {
+            ui.label(RichText::new(line).small().weak());
+        }
+    }
+    if let Some(id) = remove {
+        trace_set.remove(id);
+    }
+    if let Some(id) = retry {
+        trace_set.retry(id);
+    }
+    if let Some(id) = stop {
+        trace_set.stop(id);
+    }
+    if let Some(id) = lookups {
+        trace_set.retry_lookups(id);
+    }
+    if highlight.is_some() {
+        trace_set.highlight = highlight;
+    }
+    if export.is_some() {
+        trace_set.export_request = export;
+    }
+    if import_flows.is_some() {
+        trace_set.flows_request = import_flows;
+    }
+    if stats.is_some() {
+        trace_set.stats_request = stats;
+    }
+    if sweep.is_some() {
+        trace_set.sweep_request = sweep;
+    }
+    if notes.is_some() {
+        trace_set.notes_request = notes;
+    }
+    if rerun.is_some() {
+        trace_set.start_requests.extend(rerun);
+        ui.ctx().request_repaint();
+    }
+}
+
+/// Breadcrumb strip of the countries the latest visible trace passes through.
+/// Returns the hop positions of a country when its crumb is clicked.
+pub fn countries(ctx: &egui::Context, trace_set: &TraceSet) -> Option<Vec<trace::Position>> {
+    let trace = trace_set.traces.iter().rev().find(|t| t.visible)?;
+    let segments = summary::countries(&trace.nodes);
+    if segments.is_empty() {
+        return None;
+    }
+
+    let mut clicked = None;
+    egui::TopBottomPanel::top("countries").show(ctx, |ui| {
+        ui.horizontal_wrapped(|ui| {
+            ui.label(RichText::new(&trace.target).color(trace.color).strong());
+            for (i, segment) in segments.iter().enumerate() {
+                if i > 0 {
+                    ui.weak("›");
+                }
+                let latency = segment.latency.as_millis();
+                let text = if segment.hops == 1 {
+                    trf("{} · 1 hop · +{} ms", &[&segment.country_code, &latency])
+                } else {
+                    trf("{} · {} hops · +{} ms", &[&segment.country_code, &segment.hops, &latency])
+                };
+                if ui.button(text).on_hover_text(&segment.country).clicked() {
+                    clicked = Some(segment.positions.clone());
+                }
+            }
+        });
+    });
+    clicked
+}
+
+/// State of the region planner window.
+pub struct PlannerWindow {
+    pub open: bool,
+    regions_text: Option<String>,
+    /// Regions of the current run, so editing the list doesn't mix up results
+    regions: Vec<planner::Region>,
+    samples: Vec<Vec<Option<f64>>>,
+    ips: Vec<Option<IpAddr>>,
+    errors: Vec<Option<String>>,
+    running: bool,
+    receiver: Receiver<planner::Event>,
+}
+
+impl Default for PlannerWindow {
+    fn default() -> Self {
+        let (_, receiver) = channel();
+        Self {
+            open: false,
+            regions_text: None,
+            regions: Vec::new(),
+            samples: Vec::new(),
+            ips: Vec::new(),
+            errors: Vec::new(),
+            running: false,
+            receiver,
+        }
+    }
+}
+
+impl PlannerWindow {
+    fn start(&mut self, config: &planner::PlannerConfig, timeout: Duration) {
+        let (sender, receiver) = channel();
+        let count = config.regions.len();
+        self.regions = config.regions.clone();
+        self.samples = vec![Vec::new(); count];
+        self.ips = vec![None; count];
+        self.errors = vec![None; count];
+        self.running = true;
+        self.receiver = receiver;
+        planner::run(&self.regions, config.probes, timeout, sender);
+    }
+}
+
+/// Measures candidate deployment regions and ranks them against a latency budget.
+pub fn planner(ui: &Ui, window: &mut PlannerWindow, config: &mut Config, trace_set: &mut TraceSet) {
+    loop {
+        match window.receiver.try_recv() {
+            Ok(planner::Event::Resolved(i, ip)) => window.ips[i] = Some(ip),
+            Ok(planner::Event::Sample(i, rtt)) => window.samples[i].push(rtt),
+            Ok(planner::Event::Error(i, e)) => window.errors[i] = Some(e),
+            Err(TryRecvError::Empty) => break,
+            // Every region finished
+            Err(TryRecvError::Disconnected) => {
+                window.running = false;
+                break;
+            }
+        }
+    }
+    if window.running {
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+
+    let mut open = window.open;
+    Window::new(tr("Region planner"))
+        .id(egui::Id::new("Region planner"))
+        .open(&mut open)
+        .default_width(460.)
+        .show(ui.ctx(), |ui| {
+            let settings = &mut config.planner;
+            let text = window
+                .regions_text
+                .get_or_insert_with(|| planner::format_regions(&settings.regions));
+            if ui
+                .add(
+                    egui::TextEdit::multiline(text)
+                        .hint_text(tr("One candidate per line, e.g. Frankfurt = ec2.eu-central-1.amazonaws.com"))
+                        .desired_rows(4),
+                )
+                .named(tr("Candidate regions"))
+                .changed()
+            {
+                settings.regions = planner::parse_regions(text);
+            }
+            ui.horizontal(|ui| {
+                ui.label(tr("p95 budget"));
+                ui.add(egui::DragValue::new(&mut settings.budget_ms).range(1.0..=1000.0).suffix(" ms"));
+                ui.label(tr("Probes"));
+                ui.add(egui::DragValue::new(&mut settings.probes).range(5..=200));
+                let run = ui.add_enabled(
+                    !window.running && !settings.regions.is_empty(),
+                    egui::Button::new(tr("Measure")),
+                );
+                if run.clicked() {
+                    window.start(settings, Duration::from_millis(config.trace.timeout_ms));
+                }
+                if window.running {
+                    ui.spinner();
+                }
+            });
+            if window.regions.is_empty() {
+                return;
+            }
+            ui.separator();
+
+            let budget = config.planner.budget_ms;
+            let stats: Vec<_> = window.samples.iter().map(|s| planner::Stats::new(s)).collect();
+            let order = planner::rank(&stats);
+            if !window.running {
+                let best = order.first().and_then(|&i| Some((&window.regions[i], stats[i]?)));
+                match best {
+                    Some((region, best)) if best.p95 <= budget => {
+                        ui.label(
+                            RichText::new(trf(
+                                "Deploy in {}: p95 {} ms, within the {} ms budget",
+                                &[&region.name, &format!("{:.0}", best.p95), &format!("{:.0}", budget)],
+                            ))
+                            .color(egui::Color32::GREEN),
+                        );
+                    }
+                    Some((region, best)) => {
+                        ui.colored_label(
+                            egui::Color32::YELLOW,
+                            trf(
+                                "No region meets the {} ms budget, closest is {} at p95 {} ms",
+                                &[&format!("{:.0}", budget), &region.name, &format!("{:.0}", best.p95)],
+                            ),
+                        );
+                    }
+                    None => {
+                        ui.colored_label(egui::Color32::RED, tr("No region answered"));
+                    }
+                }
+            }
+
+            egui::Grid::new("planner").striped(true).num_columns(8).show(ui, |ui| {
+                for header in ["#", "Region", "IP", "p50", "p95", "Jitter", "Loss", ""] {
+                    ui.strong(tr(header));
+                }
+                ui.end_row();
+                for (rank, &i) in order.iter().enumerate() {
+                    let region = &window.regions[i];
+                    ui.label((rank + 1).to_string());
+                    ui.label(&region.name).on_hover_text(&region.host);
+                    ui.label(window.ips[i].map(|ip| ip.to_string()).unwrap_or_default());
+                    match (&stats[i], &window.errors[i]) {
+                        (Some(stats), _) => {
+                            let color = if stats.p95 <= budget { egui::Color32::GREEN } else { egui::Color32::YELLOW };
+                            ui.label(format!("{:.1} ms", stats.p50));
+                            ui.colored_label(color, format!("{:.1} ms", stats.p95));
+                            ui.label(format!("{:.1} ms", stats.jitter));
+                            ui.label(format!("{:.0}%", stats.loss * 100.0));
+                        }
+                        (None, Some(e)) => {
+                            ui.colored_label(egui::Color32::RED, e);
+                            ui.label("");
+                            ui.label("");
+                            ui.label("");
+                        }
+                        (None, None) => {
+                            for _ in 0..4 {
+                                ui.label("-");
+                            }
+                        }
+                    }
+                    if ui.small_button(tr("Trace")).on_hover_text(tr("Trace the route to this region")).clicked() {
+                        trace_set.start_requests.push(StartRequest::new(
+                            region.host.clone(),
+                            vec![format!("planner:{}", region.name)],
+                        ));
+                    }
+                    ui.end_row();
+                }
+            });
+
+            // Latency distribution per region, in rank order
+            let boxes: Vec<BoxElem> = order
+                .iter()
+                .enumerate()
+                .filter_map(|(rank, &i)| {
+                    let s = stats[i]?;
+                    Some(
+                        BoxElem::new(rank as f64, BoxSpread::new(s.min, s.q1, s.p50, s.q3, s.max))
+                            .name(&window.regions[i].name),
+                    )
+                })
+                .collect();
+            Plot::new("planner_distribution")
+                .height(160.)
+                .y_axis_label("ms")
+                .show_x(false)
+                .allow_scroll(false)
+                .show(ui, |plot_ui| {
+                    plot_ui.hline(egui_plot::HLine::new(budget).name(tr("Budget")).color(egui::Color32::RED));
+                    plot_ui.box_plot(BoxPlot::new(boxes));
+                });
+        });
+    window.open = open;
+}
+
+/// Path statistics of a single trace.
+pub struct StatsWindow {
+    trace: Option<usize>,
+    /// MTU measurements by trace id, none while running
+    mtu: HashMap<usize, Option<trace::MtuReport>>,
+    sender: Sender<(usize, trace::MtuReport)>,
+    receiver: Receiver<(usize, trace::MtuReport)>,
+}
+
+impl Default for StatsWindow {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            trace: None,
+            mtu: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl StatsWindow {
+    /// Shows a trace's statistics, measuring its path MTU the first time.
+    pub fn open(&mut self, id: usize, trace_set: &TraceSet, timeout_ms: u64, runtime: &Executor) {
+        self.trace = Some(id);
+        let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) else {
+            return;
+        };
+        if self.mtu.contains_key(&id) || trace.nodes.len() <= 1 {
+            return;
+        }
+        self.mtu.insert(id, None);
+        let target = trace.target.clone();
+        let sender = self.sender.clone();
+        runtime.spawn_blocking(move || {
+            let Some(ip) = trace::resolve(&target) else {
+                return;
+            };
+            info!("Measuring path MTU to {}", ip);
+            let report = trace::MtuReport::measure(ip, trace::MSS_PORT, Duration::from_millis(timeout_ms));
+            sender.send((id, report)).ok();
+        });
+    }
+}
+
+pub fn stats(ui: &Ui, window: &mut StatsWindow, trace_set: &mut TraceSet) {
+    for (id, report) in window.receiver.try_iter() {
+        // Also under the trace in the list, with the scripts' summary lines
+        if let (Some(advice), Some(trace)) = (report.advice(), trace_set.get_mut(id)) {
+            trace.summary.push(advice);
+        }
+        window.mtu.insert(id, Some(report));
+    }
+    let Some(trace) = window.trace.and_then(|id| trace_set.traces.iter().find(|t| t.id == id)) else {
+        window.trace = None;
+        return;
+    };
+    let mtu = window.mtu.get(&trace.id);
+    if matches!(mtu, Some(None)) {
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+    let stats = summary::PathStats::new(&trace.nodes);
+    let mut open = true;
+    Window::new(tr("Path statistics"))
+        .id(egui::Id::new("Path statistics"))
+        .open(&mut open)
+        .resizable(false)
+        .show(ui.ctx(), |ui| {
+            ui.label(RichText::new(&trace.target).color(trace.color).strong());
+            egui::Grid::new("path_stats").num_columns(2).show(ui, |ui| {
+                ui.label(tr("Hops"));
+                ui.label(stats.hops.to_string());
+                ui.end_row();
+                ui.label(tr("Distance"));
+                ui.label(format!("{:.0} km", stats.distance_km));
+                ui.end_row();
+                ui.label(tr("Direct distance"));
+                ui.label(format!("{:.0} km", stats.direct_km));
+                ui.end_row();
+                ui.label(tr("Detour factor"))
+                    .on_hover_text(tr("Distance along the path divided by the direct distance"));
+                ui.label(stats.detour_factor().map(|f| format!("{:.2}×", f)).unwrap_or("-".to_string()));
+                ui.end_row();
+                ui.label(tr("Fingerprint"))
+                    .on_hover_text(tr("Equal for routes through the same networks, compare with --expect-route"));
+                if ui.link(RichText::new(&stats.fingerprint).monospace()).on_hover_text(tr("Copy")).clicked() {
+                    ui.ctx().copy_text(stats.fingerprint.clone());
+                    trace_set.toasts.publish(Toast::success(trf("Copied {}", &[&stats.fingerprint])));
+                }
+                ui.end_row();
+                ui.label(tr("Countries"));
+                ui.label(stats.countries.to_string());
+                ui.end_row();
+                ui.label(tr("Autonomous systems"));
+                ui.label(stats.ases.to_string());
+                ui.end_row();
+                if !stats.ixps.is_empty() {
+                    ui.label(tr("Exchanges")).on_hover_text(tr("Internet exchanges the route goes through"));
+                    ui.label(stats.ixps.join(", "));
+                    ui.end_row();
+                }
+                ui.label(tr("Total RTT"));
+                ui.label(
+                    stats
+                        .rtt
+                        .map(|rtt| format!("{:.1} ms", rtt.as_secs_f64() * 1000.0))
+                        .unwrap_or("-".to_string()),
+                );
+                ui.end_row();
+                if let Some(mtu) = mtu {
+                    ui.label(tr("Path MTU"));
+                    match mtu.as_ref().map(|r| &r.path_mtu) {
+                        None => ui.spinner(),
+                        Some(Ok(Some(path_mtu))) => ui.label(trf("{} bytes", &[&path_mtu])),
+                        Some(Ok(None)) => ui.weak(tr("no reply")),
+                        Some(Err(e)) => ui.weak("-").on_hover_text(e),
+                    };
+                    ui.end_row();
+                    ui.label(trf("TCP MSS, port {}", &[&trace::MSS_PORT]));
+                    match mtu.as_ref().map(|r| &r.mss) {
+                        None => ui.spinner(),
+                        Some(Ok(mss)) => ui.label(trf("{} bytes", &[&mss])),
+                        Some(Err(e)) => ui.weak("-").on_hover_text(e),
+                    };
+                    ui.end_row();
+                }
+            });
+            if let Some(advice) = mtu.and_then(|r| r.as_ref()?.advice()) {
+                ui.label(RichText::new(advice).small());
+            }
+        });
+    if !open {
+        window.trace = None;
+    }
+}
+
+/// Scheduled jobs and the log of their runs.
+pub struct ScheduleWindow {
+    pub open: bool,
+    scheduler: Scheduler,
+    /// Every logged run, oldest first
+    runs: Vec<Run>,
+    bus: Bus,
+    receiver: Receiver<Run>,
+    new_job: Job,
+    /// Only list runs to this target
+    filter: Option<String>,
+}
+
+impl ScheduleWindow {
+    /// Loads the run log and starts the configured jobs.
+    pub fn new(config: &Config, enrichment: &Enrichment, bus: &Bus, runtime: &Executor) -> Self {
+        let mut window = Self {
+            open: false,
+            scheduler: Scheduler::default(),
+            runs: scheduler::load(),
+            bus: bus.clone(),
+            receiver: bus.scheduled.subscribe(),
+            new_job: Job {
+                target: String::new(),
+                every_minutes: 15,
+                notify: NotifyRule::default(),
+            },
+            filter: None,
+        };
+        window.restart(config, enrichment, runtime);
+        window
+    }
+
+    /// RTT trends of the hops in the logged runs.
+    pub fn hop_history(&self) -> HopHistory {
+        let mut history = HopHistory::default();
+        for run in &self.runs {
+            history.record(run);
+        }
+        history
+    }
+
+    /// Adds a job tracing `target` every 5 minutes, turning the schedule on if it's off.
+    /// Returns false when there already is one.
+    pub fn watch(&mut self, target: String, config: &mut Config, enrichment: &Enrichment, runtime: &Executor) -> bool {
+        if config.schedule.jobs.iter().any(|job| job.target.trim() == target) {
+            return false;
+        }
+        config.schedule.jobs.push(Job {
+            target,
+            every_minutes: 5,
+            notify: NotifyRule::default(),
+        });
+        config.schedule.enabled = true;
+        self.restart(config, enrichment, runtime);
+        true
+    }
+
+    fn restart(&mut self, config: &Config, enrichment: &Enrichment, runtime: &Executor) {
+        let settings = TraceSettings {
+            options: config.trace,
+            geolocation: config.geolocation,
+        };
+        self.scheduler.restart(
+            &config.schedule,
+            settings,
+            &config.geofences,
+            &config.transit_policies,
+            enrichment,
+            &self.bus,
+            runtime,
+        );
+    }
+}
+
+/// Edits when a job notifies, returns whether anything changed.
+fn notify_rule(ui: &mut Ui, rule: &mut NotifyRule) -> bool {
+    let mut changed = ui.checkbox(&mut rule.route_change, tr("Route changes")).changed();
+    changed |= ui.checkbox(&mut rule.geofence, tr("Geofence breaches")).changed();
+    changed |= ui.checkbox(&mut rule.policy, tr("Transit policy violations")).changed();
+    ui.horizontal(|ui| {
+        let mut enabled = rule.latency_ms.is_some();
+        if ui.checkbox(&mut enabled, tr("Hops slower than")).changed() {
+            rule.latency_ms = enabled.then_some(200.0);
+            changed = true;
+        }
+        if let Some(latency) = &mut rule.latency_ms {
+            changed |= ui
+                .add(egui::DragValue::new(latency).range(1.0..=5000.0).suffix(" ms"))
+                .changed();
+        }
+    });
+    ui.horizontal(|ui| {
+        let mut enabled = rule.loss_runs.is_some();
+        if ui.checkbox(&mut enabled, tr("Lost hops for")).changed() {
+            rule.loss_runs = enabled.then_some(3);
+            changed = true;
+        }
+        if let Some(runs) = &mut rule.loss_runs {
+            changed |= ui.add(egui::DragValue::new(runs).range(1..=100).suffix(" runs")).changed();
+        }
+    });
+    changed
+}
+
+/// Most runs listed at once, newest first.
+const MAX_LISTED_RUNS: usize = 200;
+
+pub fn schedule(
+    ui: &Ui,
+    window: &mut ScheduleWindow,
+    config: &mut Config,
+    enrichment: &Enrichment,
+    trace_set: &mut TraceSet,
+    runtime: &Executor,
+) {
+    for run in window.receiver.try_iter() {
+        trace_set.hop_rtts.record(&run);
+        window.runs.push(run);
+    }
+    if !window.open {
+        return;
+    }
+
+    let mut open = window.open;
+    let mut restart = false;
+    Window::new(tr("Scheduled traces"))
+        .id(egui::Id::new("Scheduled traces"))
+        .open(&mut open)
+        .default_width(420.)
+        .show(ui.ctx(), |ui| {
+            let jobs = &mut config.schedule.jobs;
+            let mut remove = None;
+            egui::Grid::new("schedule_jobs").num_columns(3).show(ui, |ui| {
+                for (i, job) in jobs.iter_mut().enumerate() {
+                    ui.label(&job.target);
+                    ui.label(trf("every {} min", &[&job.every_minutes]));
+                    ui.horizontal(|ui| {
+                        let bell = if job.notify.any() { "🔔" } else { "🔕" };
+                        ui.menu_button(bell, |ui| restart |= notify_rule(ui, &mut job.notify))
+                            .response
+                            .on_hover_text(tr("Desktop notifications"));
+                        if ui.small_button("▶").described(tr("Trace now")).clicked() {
+                            let tags = vec!["scheduled".to_string()];
+                            trace_set.start_requests.push(StartRequest::new(job.target.clone(), tags));
+                        }
+                        if ui.small_button("🗙").described(tr("Remove")).clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    ui.end_row();
+                }
+                ui.add(egui::TextEdit::singleline(&mut window.new_job.target).hint_text(tr("Target")).desired_width(140.))
+                    .named(tr("Target"));
+                ui.add(
+                    egui::DragValue::new(&mut window.new_job.every_minutes)
+                        .range(1..=1440)
+                        .prefix(tr("every "))
+                        .suffix(" min"),
+                );
+                if ui
+                    .add_enabled(!window.new_job.target.trim().is_empty(), egui::Button::new(tr("Add")))
+                    .clicked()
+                {
+                    jobs.push(Job {
+                        target: window.new_job.target.trim().to_string(),
+                        every_minutes: window.new_job.every_minutes,
+                        notify: NotifyRule::default(),
+                    });
+                    window.new_job.target.clear();
+                    restart = true;
+                }
+                ui.end_row();
+            });
+            if let Some(i) = remove {
+                jobs.remove(i);
+                restart = true;
+            }
+            ui.horizontal(|ui| {
+                restart |= ui.checkbox(&mut config.schedule.enabled, tr("Run in the background")).changed();
+                if window.scheduler.running() > 0 {
+                    ui.weak(trf("{} running", &[&window.scheduler.running()]));
+                }
+            });
+            ui.label(
+                RichText::new(tr("Runs line up with the clock, e.g. every 15 min at :00, :15, :30 and :45"))
+                    .small()
+                    .weak(),
+            );
+
+            ui.separator();
+            let mut targets: Vec<&str> = window.runs.iter().map(|run| run.target.as_str()).collect();
+            targets.sort();
+            targets.dedup();
+            egui::ComboBox::from_label(tr("Runs"))
+                .selected_text(window.filter.as_deref().unwrap_or(tr("All targets")))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut window.filter, None, tr("All targets"));
+                    for target in targets {
+                        ui.selectable_value(&mut window.filter, Some(target.to_string()), target);
+                    }
+                });
+
+            // Whether each run took another route than the run before it to the same target
+            let mut last_routes: HashMap<&str, String> = HashMap::new();
+            let changed: Vec<bool> = window
+                .runs
+                .iter()
+                .map(|run| {
+                    if !run.completed() {
+                        return false;
+                    }
+                    let fingerprint = run.fingerprint();
+                    let previous = last_routes.insert(&run.target, fingerprint.clone());
+                    previous.is_some_and(|previous| previous != fingerprint)
+                })
+                .collect();
+
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                egui::Grid::new("schedule_runs").striped(true).num_columns(4).show(ui, |ui| {
+                    for header in ["Started", "Target", "Hops", ""] {
+                        ui.strong(tr(header));
+                    }
+                    ui.end_row();
+                    let listed = window
+                        .runs
+                        .iter()
+                        .zip(&changed)
+                        .rev()
+                        .filter(|(run, _)| window.filter.as_ref().is_none_or(|target| run.target == *target))
+                        .take(MAX_LISTED_RUNS);
+                    for (run, changed) in listed {
+                        ui.label(run.started.format("%Y-%m-%d %H:%M").to_string());
+                        ui.label(&run.target);
+                        ui.label(run.hops.len().to_string()).on_hover_text(run.path().join("\n"));
+                        match &run.error {
+                            Some(e) => {
+                                ui.colored_label(egui::Color32::RED, tr("failed")).on_hover_text(e);
+                            }
+                            None if !run.completed() => {
+                                ui.colored_label(egui::Color32::RED, tr("no reply"));
+                            }
+                            None if *changed => {
+                                ui.colored_label(DEVIATION_COLOR, tr("⚠ route changed"));
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    window.open = open;
+    if restart {
+        window.restart(config, enrichment, runtime);
+    }
+}
+
+/// Offer to trace the pinned targets again after the network changed.
+#[derive(Default)]
+pub struct NetworkWindow {
+    /// The previous and the new network, while the offer stands
+    change: Option<(NetworkContext, NetworkContext)>,
+}
+
+impl NetworkWindow {
+    pub fn open(&mut self, from: NetworkContext, to: NetworkContext) {
+        self.change = Some((from, to));
+    }
+}
+
+pub fn network(ui: &Ui, window: &mut NetworkWindow, trace_set: &mut TraceSet, auto_retrace: &mut bool) {
+    let Some((from, to)) = &window.change else {
+        return;
+    };
+    let mut close = false;
+    Window::new(tr("Network changed"))
+        .id(egui::Id::new("Network changed"))
+        .collapsible(false)
+        .resizable(false)
+        .pivot(Align2::CENTER_TOP)
+        .default_pos(corner(ui.ctx(), Align2::CENTER_TOP, [0., 10.]))
+        .show(ui.ctx(), |ui| {
+            egui::Grid::new("network_change").num_columns(2).show(ui, |ui| {
+                ui.label(tr("Was"));
+                ui.label(from.to_string());
+                ui.end_row();
+                ui.label(tr("Now"));
+                ui.strong(to.to_string());
+                ui.end_row();
+            });
+            ui.separator();
+            ui.label(trf("{} pinned targets:", &[&trace_set.pinned.len()]));
+            for target in &trace_set.pinned {
+                ui.label(RichText::new(target).weak());
+            }
+            ui.checkbox(auto_retrace, tr("Always trace them again without asking"));
+            ui.horizontal(|ui| {
+                if ui.button(tr("Trace again")).clicked() {
+                    trace_set.rerun_pinned();
+                    close = true;
+                }
+                if ui.button(tr("Dismiss")).clicked() {
+                    close = true;
+                }
+            });
+        });
+    if close {
+        window.change = None;
+    }
+}
+
+/// Lists what went wrong in traces until it's dismissed, so a failed trace isn't just
+/// an empty map.
+pub fn trace_errors(ui: &Ui, trace_set: &mut TraceSet) {
+    if trace_set.traces.iter().all(|t| t.errors.is_empty()) {
+        return;
+    }
+    let (mut retry, mut resume, mut dismiss) = (None, None, None);
+    Window::new(tr("Trace problems"))
+        .id(egui::Id::new("Trace problems"))
+        .collapsible(false)
+        .resizable(false)
+        .pivot(Align2::CENTER_BOTTOM)
+        .default_pos(corner(ui.ctx(), Align2::CENTER_BOTTOM, [0., -10.]))
+        .show(ui.ctx(), |ui| {
+            for trace in trace_set.traces.iter().filter(|t| !t.errors.is_empty()) {
+                ui.horizontal(|ui| {
+                    ui.colored_label(trace.color, "⏺");
+                    ui.strong(&trace.target);
+                    if trace.interrupted && !trace.tracing {
+                        let next = trace.nodes.last().map_or(1, |(ttl, _)| ttl + 1);
+                        if ui
+                            .small_button(trf("Resume from hop {}", &[&next]))
+                            .on_hover_text(tr("Keep the hops found so far and probe on from there"))
+                            .clicked()
+                        {
+                            resume = Some(trace.id);
+                        }
+                    }
+                    if ui.small_button(tr("Retry")).clicked() {
+                        retry = Some(trace.id);
+                    }
+                    if ui.small_button(tr("Dismiss")).clicked() {
+                        dismiss = Some(trace.id);
+                    }
+                });
+                for error in &trace.errors {
+                    ui.colored_label(egui::Color32::RED, format!("⚠ {}", error));
+                }
+            }
+        });
+    if let Some(id) = retry {
+        trace_set.retry(id);
+    }
+    if let Some(id) = resume {
+        trace_set.resume(id);
+    }
+    if let Some(trace) = dismiss.and_then(|id| trace_set.get_mut(id)) {
+        trace.errors.clear();
+    }
+}
+
+/// IPs looked up per request while locating the history again.
+const RELOCATE_BATCH: usize = 100;
+
+/// How many IPs a batch looked up, and the ones it located.
+type Relocated = (usize, Vec<(String, trace::Location)>);
+
+/// Offers to locate the hops of the history again once the geolocation source changed.
+#[derive(Default)]
+pub struct RelocateWindow {
+    /// Provider and site map the history was last seen with
+    source: Option<(GeoProvider, Arc<SiteMap>)>,
+    offer: bool,
+    /// IPs done and in total, while locating
+    progress: Option<(usize, usize)>,
+    receiver: Option<Receiver<Relocated>>,
+}
+
+impl RelocateWindow {
+    /// Makes the offer when the provider or the loaded site map differ from the last call.
+    pub fn watch(&mut self, geolocation: GeoProvider, enrichment: &Enrichment) {
+        let changed = self
+            .source
+            .as_ref()
+            .is_some_and(|(provider, sites)| *provider != geolocation || !Arc::ptr_eq(sites, &enrichment.sites));
+        self.offer |= changed;
+        self.source = Some((geolocation, enrichment.sites.clone()));
+    }
+
+    fn start(&mut self, ips: Vec<String>, geolocation: GeoProvider, sites: Arc<SiteMap>, runtime: &Executor) {
+        info!("Locating {} stored hops again with {}", ips.len(), geolocation.name());
+        let (sender, receiver) = channel();
+        self.progress = Some((0, ips.len()));
+        self.receiver = Some(receiver);
+        runtime.spawn(async move {
+            let client = reqwest::Client::new();
+            for batch in ips.chunks(RELOCATE_BATCH) {
+                // The cache still holds what the previous source said
+                let locations = trace::get_locations(&client, geolocation, &sites, batch, Duration::ZERO).await;
+                let located = batch
+                    .iter()
+                    .zip(locations)
+                    .filter_map(|(ip, location)| Some((ip.clone(), location?)))
+                    .collect();
+                if sender.send((batch.len(), located)).is_err() {
+                    debug!("Stopped locating stored hops");
+                    return;
+                }
+            }
+        });
+    }
+
+    fn stop(&mut self) {
+        self.progress = None;
+        self.receiver = None;
+    }
+}
+
+pub fn relocate(
+    ui: &Ui,
+    window: &mut RelocateWindow,
+    history: &mut History,
+    geolocation: GeoProvider,
+    enrichment: &Enrichment,
+    runtime: &Executor,
+) {
+    window.watch(geolocation, enrichment);
+    if let (Some(receiver), Some((done, total))) = (&window.receiver, &mut window.progress) {
+        loop {
+            match receiver.try_recv() {
+                Ok((count, located)) => {
+                    history.relocate(&located);
+                    *done += count;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    info!("Located {} stored hops again", total);
+                    *done = *total;
+                    break;
+                }
+            }
+        }
+        if *done >= *total {
+            window.stop();
+        } else {
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
+        }
+    }
+    if !window.offer && window.progress.is_none() {
+        return;
+    }
+
+    Window::new(tr("Geolocation changed"))
+        .id(egui::Id::new("Geolocation changed"))
+        .collapsible(false)
+        .resizable(false)
+        .pivot(Align2::CENTER_TOP)
+        .default_pos(corner(ui.ctx(), Align2::CENTER_TOP, [0., 10.]))
+        .show(ui.ctx(), |ui| {
+            if let Some((done, total)) = window.progress {
+                ui.label(trf("Locating the history with {}", &[&geolocation.name()]));
+                ui.add(
+                    egui::ProgressBar::new(done as f32 / total.max(1) as f32)
+                        .text(trf("{} of {} addresses", &[&done, &total]))
+                        .desired_width(240.),
+                );
+                if ui.button(tr("Stop")).clicked() {
+                    window.stop();
+                }
+                return;
+            }
+            ui.label(tr("Hops in the history are placed where the previous source put them."));
+            ui.label(RichText::new(tr("Public addresses are looked up again, which can take a while")).small().weak());
+            ui.horizontal(|ui| {
+                if ui.button(tr("Locate again")).clicked() {
+                    window.offer = false;
+                    let ips = history.ips();
+                    if !ips.is_empty() {
+                        window.start(ips, geolocation, enrichment.sites.clone(), runtime);
+                    }
+                }
+                if ui.button(tr("Not now")).clicked() {
+                    window.offer = false;
+                }
+            });
+        });
+}
+
+/// Token and quota of ipinfo.io, open from the geolocation settings.
+#[derive(Default)]
+pub struct IpInfoWindow {
+    pub open: bool,
+    /// Requests left, or why the token doesn't work
+    status: Option<Result<String, String>>,
+    receiver: Option<Receiver<Result<trace::IpInfoQuota, String>>>,
+}
+
+impl IpInfoWindow {
+    fn check(&mut self, token: String, runtime: &Executor) {
+        let (sender, receiver) = channel();
+        self.receiver = Some(receiver);
+        self.status = None;
+        runtime.spawn(async move {
+            let client = reqwest::Client::new();
+            sender.send(trace::ipinfo_quota(&client, &token).await).ok();
+        });
+    }
+}
+
+pub fn ipinfo(ui: &Ui, window: &mut IpInfoWindow, config: &mut Config, runtime: &Executor) {
+    if let Some(result) = window.receiver.as_ref().and_then(|rx| rx.try_recv().ok()) {
+        window.receiver = None;
+        window.status = Some(result.map(|quota| {
+            if quota.limit == 0 {
+                trf("Token works, {} requests this month", &[&quota.month])
+            } else {
+                trf("{} of {} requests left this month", &[&quota.remaining, &quota.limit])
+            }
+        }));
+    }
+
+    let mut open = window.open;
+    Window::new("ipinfo.io")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .default_width(280.)
+        .show(ui.ctx(), |ui| {
+            ui.label(
+                RichText::new(tr(
+                    "Without a token hops are looked up one by one and carriers aren't known. \
+                     Tokens are at ipinfo.io/account/token.",
+                ))
+                .small()
+                .weak(),
+            );
+            ui.horizontal(|ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut config.ipinfo_token)
+                        .password(true)
+                        .hint_text(tr("Token"))
+                        .desired_width(180.),
+                )
+                .named(tr("ipinfo.io token"));
+                if response.changed() {
+                    trace::set_ipinfo_token(&config.ipinfo_token);
+                    window.status = None;
+                }
+                if window.receiver.is_some() {
+                    ui.spinner();
+                } else if ui
+                    .add_enabled(!config.ipinfo_token.trim().is_empty(), egui::Button::new(tr("Check")))
+                    .on_hover_text(tr("Validate the token and show the requests left"))
+                    .clicked()
+                {
+                    window.check(config.ipinfo_token.clone(), runtime);
+                }
+            });
+            match &window.status {
+                Some(Ok(quota)) => {
+                    ui.label(quota);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+    window.open &= open;
+}
+
+/// Explains what tracing can do without raw socket privileges and how to get them, open from
+/// the start when they're missing.
+pub struct PrivilegesWindow {
+    pub open: bool,
+    /// Result of starting the probe helper, while it's starting
+    helper: Option<Receiver<Result<(), String>>>,
+    /// Why restarting elevated or starting the helper didn't work
+    error: Option<String>,
+}
+
+impl Default for PrivilegesWindow {
+    fn default() -> Self {
+        Self {
+            open: !trace::raw_sockets(),
+            helper: None,
+            error: None,
+        }
+    }
+}
+
+pub fn privileges(ui: &Ui, window: &mut PrivilegesWindow) {
+    let mut open = window.open;
+    Window::new(tr("Limited privileges"))
+        .id(egui::Id::new("Limited privileges"))
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .default_width(320.)
+        .pivot(Align2::CENTER_TOP)
+        .default_pos(corner(ui.ctx(), Align2::CENTER_TOP, [0., 10.]))
+        .show(ui.ctx(), |ui| {
+            #[cfg(windows)]
+            {
+                ui.label(tr("traced isn't running as administrator, so it traces through the Windows ICMP API instead."));
+                ui.label(
+                    RichText::new(tr(
+                        "That probes one hop after another and only over IPv4. Parallel probes, adaptive \
+                         timeouts, IPv6 and path MTU measurements need administrator rights.",
+                    ))
+                    .small()
+                    .weak(),
+                );
+                if ui.button(tr("Restart as administrator")).clicked() {
+                    match trace::helper::run_elevated(&[], true) {
+                        Ok(()) => ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close),
+                        Err(e) => window.error = Some(e),
+                    }
+                }
+            }
+            #[cfg(target_os = "linux")]
+            {
+                ui.label(tr("traced lacks CAP_NET_RAW, so it traces with UDP probes instead."));
+                ui.label(
+                    RichText::new(tr(
+                        "That probes one hop after another, and hops that drop UDP don't show up. Parallel \
+                         probes, adaptive timeouts, ping and path MTU measurements need the capability:",
+                    ))
+                    .small()
+                    .weak(),
+                );
+                let exe = std::env::current_exe().map(|path| path.display().to_string()).unwrap_or("traced".to_string());
+                let command = format!("sudo setcap cap_net_raw+ep {}", exe);
+                ui.horizontal(|ui| {
+                    ui.code(&command);
+                    if ui.small_button("📋").described(tr("Copy")).clicked() {
+                        ui.ctx().copy_text(command.clone());
+                    }
+                });
+                ui.label(RichText::new(tr("Then start traced again.")).small().weak());
+            }
+            #[cfg(not(any(windows, target_os = "linux")))]
+            {
+                ui.label(tr("traced can't open raw sockets, so traces won't find any hops."));
+                ui.label(RichText::new(tr("Run it as root to trace.")).small().weak());
+            }
+            ui.separator();
+            ui.label(
+                RichText::new(tr(
+                    "Or leave this window unprivileged and start a small helper that does the probing. \
+                     It asks for the password once, can only trace, and stops when traced closes.",
+                ))
+                .small()
+                .weak(),
+            );
+            ui.horizontal(|ui| {
+                if window.helper.is_some() {
+                    ui.spinner();
+                    ui.label(tr("Waiting for the password prompt…"));
+                } else if ui.button(tr("Start probe helper")).clicked() {
+                    let (tx, rx) = channel();
+                    std::thread::spawn(move || tx.send(trace::helper::start()).ok());
+                    window.helper = Some(rx);
+                    window.error = None;
+                }
+            });
+            if let Some(e) = &window.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        });
+    if let Some(result) = window.helper.as_ref().and_then(|rx| rx.try_recv().ok()) {
+        window.helper = None;
+        match result {
+            // Traces go through the helper from now on
+            Ok(()) => open = false,
+            Err(e) => window.error = Some(e),
+        }
+    }
+    window.open &= open;
+}
+
+/// Exports and imports the shared setup of a team, see [`Workspace`].
+pub struct WorkspaceWindow {
+    pub open: bool,
+    path: String,
+    /// Put the map style in exported workspaces too
+    style: bool,
+    status: Option<Result<String, String>>,
+}
+
+impl Default for WorkspaceWindow {
+    fn default() -> Self {
+        let dir = directories::UserDirs::new()
+            .and_then(|dirs| dirs.document_dir().map(|d| d.to_path_buf()))
+            .unwrap_or_default();
+        Self {
+            open: false,
+            path: dir.join("traced-workspace.json").display().to_string(),
+            style: false,
+            status: None,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn workspace(
+    ui: &Ui,
+    window: &mut WorkspaceWindow,
+    config: &mut Config,
+    trace_set: &mut TraceSet,
+    history: &mut History,
+    schedule: &mut ScheduleWindow,
+    enrichment: &Enrichment,
+    runtime: &Executor,
+) {
+    let mut open = window.open;
+    Window::new(tr("Workspace"))
+        .id(egui::Id::new("Workspace"))
+        .open(&mut open)
+        .resizable(false)
+        .default_width(360.)
+        .show(ui.ctx(), |ui| {
+            ui.label(tr(
+                "Targets, scheduled jobs, baselines, saved filters, flagged countries, transit policies, \
+                 planner regions, split tunnel checks and managed ranges, to share with a team.",
+            ));
+            ui.label(RichText::new(tr("SNMP credentials and tile API keys are never included")).small().weak());
+            ui.horizontal(|ui| {
+                ui.label(tr("File"));
+                ui.add(egui::TextEdit::singleline(&mut window.path).desired_width(260.)).named(tr("File"));
+            });
+            ui.checkbox(&mut window.style, tr("Include the map style"));
+            ui.horizontal(|ui| {
+                let path = std::path::PathBuf::from(&window.path);
+                if ui.button(tr("Export")).clicked() {
+                    let workspace = Workspace::collect(config, trace_set, history, window.style);
+                    window.status = Some(match workspace.save(&path) {
+                        Ok(()) => {
+                            info!("Exported workspace to {}", path.display());
+                            Ok(trf("Exported {}", &[&workspace.summary()]))
+                        }
+                        Err(e) => Err(trf("Failed to export: {}", &[&e])),
+                    });
+                }
+                let import = ui
+                    .button(tr("Import"))
+                    .on_hover_text(tr("Adds to the current setup, replacing entries of the same name"));
+                if import.clicked() {
+                    window.status = Some(match Workspace::load(&path) {
+                        Ok(workspace) => {
+                            let summary = workspace.summary();
+                            info!("Imported workspace from {}: {}", path.display(), summary);
+                            workspace.apply(config, trace_set, history);
+                            schedule.restart(config, enrichment, runtime);
+                            Ok(trf("Imported {}", &[&summary]))
+                        }
+                        Err(e) => Err(trf("Failed to import: {}", &[&e])),
+                    });
+                }
+            });
+            match &window.status {
+                Some(Ok(status)) => {
+                    ui.label(RichText::new(status).small());
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                None => {}
+            }
+        });
+    window.open = open;
+}
+
+/// Outcome of one split tunnel check.
+#[derive(Clone, Default)]
+struct SplitTunnelResult {
+    egress: Option<(String, splittunnel::Egress)>,
+    first_public: Option<(String, String)>,
+    error: Option<String>,
+}
+
+pub struct SplitTunnelWindow {
+    pub open: bool,
+    checks_text: Option<String>,
+    /// Checks of the current run, so editing the list doesn't mix up results
+    checks: Vec<splittunnel::Check>,
+    results: Vec<SplitTunnelResult>,
+    running: bool,
+    receiver: Receiver<splittunnel::Event>,
+}
+
+impl Default for SplitTunnelWindow {
+    fn default() -> Self {
+        let (_, receiver) = channel();
+        Self {
+            open: false,
+            checks_text: None,
+            checks: Vec::new(),
+            results: Vec::new(),
+            running: false,
+            receiver,
+        }
+    }
+}
+
+impl SplitTunnelWindow {
+    fn start(&mut self, config: &Config, enrichment: &Enrichment, runtime: &Executor) {
+        let (sender, receiver) = channel();
+        self.checks = config.split_tunnel.checks.clone();
+        self.results = vec![SplitTunnelResult::default(); self.checks.len()];
+        self.running = true;
+        self.receiver = receiver;
+        let settings = TraceSettings {
+            options: config.trace,
+            geolocation: config.geolocation,
+        };
+        splittunnel::run(&self.checks, settings, enrichment, runtime, sender);
+    }
+}
+
+/// Traces a list of targets and checks each leaves through the VPN or the physical
+/// interface as expected.
+pub fn split_tunnel(ui: &Ui, window: &mut SplitTunnelWindow, config: &mut Config, enrichment: &Enrichment, runtime: &Executor) {
+    loop {
+        match window.receiver.try_recv() {
+            Ok(splittunnel::Event::Egress(i, interface, egress)) => window.results[i].egress = Some((interface, egress)),
+            Ok(splittunnel::Event::FirstPublic(i, ip, owner)) => window.results[i].first_public = Some((ip, owner)),
+            Ok(splittunnel::Event::Error(i, e)) => window.results[i].error = Some(e),
+            Err(TryRecvError::Empty) => break,
+            // Every check finished
+            Err(TryRecvError::Disconnected) => {
+                window.running = false;
+                break;
+            }
+        }
+    }
+    if window.running {
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+
+    let mut open = window.open;
+    let mut start = false;
+    Window::new(tr("Split tunnel check"))
+        .id(egui::Id::new("Split tunnel check"))
+        .open(&mut open)
+        .default_width(460.)
+        .show(ui.ctx(), |ui| {
+            let settings = &mut config.split_tunnel;
+            let text = window
+                .checks_text
+                .get_or_insert_with(|| splittunnel::format_checks(&settings.checks));
+            if ui
+                .add(
+                    egui::TextEdit::multiline(text)
+                        .hint_text(tr("One target per line, e.g. intranet.example.com = vpn or 8.8.8.8 = direct"))
+                        .desired_rows(4),
+                )
+                .named(tr("Expected routes"))
+                .changed()
+            {
+                settings.checks = splittunnel::parse_checks(text);
+            }
+            ui.horizontal(|ui| {
+                start = ui
+                    .add_enabled(!window.running && !settings.checks.is_empty(), egui::Button::new(tr("Check")))
+                    .clicked();
+                if window.running {
+                    ui.spinner();
+                }
+            });
+            if window.checks.is_empty() {
+                return;
+            }
+            ui.separator();
+
+            let failed = window
+                .checks
+                .iter()
+                .zip(&window.results)
+                .filter(|(check, result)| {
+                    result.error.is_some()
+                        || check.expect.is_some_and(|expect| result.egress.as_ref().is_some_and(|(_, e)| *e != expect))
+                })
+                .count();
+            if !window.running {
+                if failed == 0 {
+                    ui.colored_label(egui::Color32::GREEN, tr("Every target leaves the way it should"));
+                } else {
+                    ui.colored_label(egui::Color32::RED, trf("{} of {} checks failed", &[&failed, &window.checks.len()]));
+                }
+            }
+
+            egui::Grid::new("split_tunnel").striped(true).num_columns(5).show(ui, |ui| {
+                for header in ["", "Target", "Expected", "Leaves via", "First public hop"] {
+                    ui.strong(tr(header));
+                }
+                ui.end_row();
+                for (check, result) in window.checks.iter().zip(&window.results) {
+                    let pass = match (&result.egress, check.expect) {
+                        _ if result.error.is_some() => Some(false),
+                        (Some((_, egress)), Some(expect)) => Some(*egress == expect),
+                        (Some(_), None) => Some(true),
+                        (None, _) => None,
+                    };
+                    match pass {
+                        Some(true) => ui.colored_label(egui::Color32::GREEN, "✔"),
+                        Some(false) => ui.colored_label(egui::Color32::RED, "✘"),
+                        None => ui.spinner(),
+                    };
+                    ui.label(&check.target);
+                    ui.label(tr(check.expect.map_or("either", |e| e.name())));
+                    match (&result.egress, &result.error) {
+                        (Some((interface, egress)), _) => {
+                            ui.label(format!("{} ({})", egress.name(), interface));
+                        }
+                        (None, Some(e)) => {
+                            ui.colored_label(egui::Color32::RED, e);
+                        }
+                        (None, None) => {
+                            ui.label("-");
+                        }
+                    }
+                    match &result.first_public {
+                        Some((ip, owner)) => {
+                            ui.label(ip).on_hover_text(owner);
+                        }
+                        None if window.running && result.error.is_none() => {
+                            ui.weak(tr("tracing…"));
+                        }
+                        None => {
+                            ui.label("-");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.label(
+                RichText::new(tr("The first public hop shows whose network the traffic enters, e.g. the VPN provider's"))
+                    .small()
+                    .weak(),
+            );
+        });
+    window.open = open;
+    if start {
+        window.start(config, enrichment, runtime);
+    }
+}
+
+/// State of the latency profile window.
+#[derive(Default)]
+pub struct ProfileWindow {
+    pub open: bool,
+}
+
+/// RTT by hop of the visible traces, like an elevation profile. Hovering a hop rings it on the map.
+pub fn profile(ui: &Ui, window: &mut ProfileWindow, trace_set: &mut TraceSet) {
+    trace_set.highlight = None;
+    if !window.open {
+        return;
+    }
+    if trace_set.tracing() {
+        // Hops keep arriving
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+    let mut open = window.open;
+    Window::new(tr("Latency profile"))
+        .id(egui::Id::new("Latency profile"))
+        .open(&mut open)
+        .default_width(420.)
+        .show(ui.ctx(), |ui| {
+            let visible: Vec<_> = trace_set.traces.iter().filter(|t| t.visible).collect();
+            if visible.is_empty() {
+                ui.weak(tr("No traces shown"));
+                return;
+            }
+            let hovered = Plot::new("latency_profile")
+                .height(200.)
+                .x_axis_label(tr("Hop"))
+                .y_axis_label("ms")
+                .include_y(0.0)
+                .allow_scroll(false)
+                .show(ui, |plot_ui| {
+                    for trace in &visible {
+                        let points: Vec<[f64; 2]> = trace
+                            .nodes
+                            .iter()
+                            .filter_map(|(ttl, node)| Some([*ttl as f64, node.rtt?.as_secs_f64() * 1000.0]))
+                            .collect();
+                        plot_ui.line(
+                            Line::new(points.clone())
+                                .name(&trace.target)
+                                .color(trace.color)
+                                .fill(0.0),
+                        );
+                        plot_ui.points(Points::new(points).color(trace.color).radius(3.));
+                    }
+
+                    // Nearest hop to the pointer, on screen
+                    let pointer = plot_ui.response().hover_pos()?;
+                    visible
+                        .iter()
+                        .flat_map(|trace| {
+                            trace.nodes.iter().filter_map(move |(ttl, node)| {
+                                let rtt = node.rtt?.as_secs_f64() * 1000.0;
+                                Some((trace.id, *ttl, node, [*ttl as f64, rtt]))
+                            })
+                        })
+                        .map(|(id, ttl, node, at)| {
+                            let screen = plot_ui.screen_from_plot(egui_plot::PlotPoint::new(at[0], at[1]));
+                            (id, ttl, node.clone(), at[1], screen.distance(pointer))
+                        })
+                        .filter(|(.., distance)| *distance < 12.0)
+                        .min_by(|a, b| a.4.total_cmp(&b.4))
+                })
+                .inner;
+            if let Some((id, ttl, node, rtt, _)) = hovered {
+                trace_set.highlight = Some((id, ttl));
+                ui.label(trf("Hop {}: {} {} · {} ms", &[&ttl, &node.ip, &node.hostname, &format!("{:.1}", rtt)]));
+            } else {
+                ui.weak(tr("Hover a hop to find it on the map"));
+            }
+        });
+    window.open = open;
+}
+
+/// A baseline capture in progress.
+struct BaselineCapture {
+    target: String,
+    runs: usize,
+    /// Traces before this are not part of the capture
+    since: chrono::DateTime<chrono::Local>,
+    next_at: Instant,
+}
+
+impl BaselineCapture {
+    /// Completed baseline traces taken since the capture started.
+    fn entries<'a>(&self, history: &'a History) -> Vec<&'a history::HistoryEntry> {
+        history
+            .entries
+            .iter()
+            .filter(|e| {
+                e.target == self.target
+                    && e.started >= self.since
+                    && e.outcome == Outcome::Completed
+                    && e.tags.iter().any(|t| t == baseline::TAG)
+            })
+            .collect()
+    }
+}
+
+/// State of the baselines window.
+pub struct BaselineWindow {
+    pub open: bool,
+    target: String,
+    runs: usize,
+    /// Minutes between traces
+    interval: u64,
+    capture: Option<BaselineCapture>,
+}
+
+impl Default for BaselineWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            target: String::new(),
+            runs: 5,
+            interval: 2,
+            capture: None,
+        }
+    }
+}
+
+pub fn baseline(ui: &Ui, window: &mut BaselineWindow, history: &mut History, trace_set: &mut TraceSet) {
+    if let Some(capture) = &mut window.capture {
+        let entries = capture.entries(history);
+        if entries.len() >= capture.runs {
+            info!("Captured baseline for {} from {} traces", capture.target, entries.len());
+            let captured = baseline::Baseline::from_entries(&entries);
+            history.baselines.set(&capture.target, captured);
+            history.baselines.save();
+            window.capture = None;
+        } else {
+            // Failed traces don't count, so keep going until enough completed
+            if Instant::now() >= capture.next_at && !trace_set.tracing() {
+                trace_set
+                    .start_requests
+                    .push(StartRequest::new(capture.target.clone(), vec![baseline::TAG.to_string()]));
+                capture.next_at = Instant::now() + Duration::from_secs(window.interval * 60);
+            }
+            ui.ctx().request_repaint_after(Duration::from_secs(1));
+        }
+    }
+
+    let mut open = window.open;
+    Window::new(tr("Baselines"))
+        .id(egui::Id::new("Baselines"))
+        .open(&mut open)
+        .default_width(360.)
+        .show(ui.ctx(), |ui| {
+            match &window.capture {
+                Some(capture) => {
+                    let done = capture.entries(history).len();
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(trf("Capturing {}: {} of {} traces", &[&capture.target, &done, &capture.runs]));
+                        let next = capture.next_at.saturating_duration_since(Instant::now());
+                        if !next.is_zero() {
+                            ui.weak(trf("next in {}s", &[&next.as_secs()]));
+                        }
+                    });
+                    if ui.button(tr("Cancel")).clicked() {
+                        window.capture = None;
+                    }
+                }
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Target"));
+                        ui.text_edit_singleline(&mut window.target);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label(tr("Traces"));
+                        ui.add(egui::DragValue::new(&mut window.runs).range(2..=50));
+                        ui.label(tr("every"));
+                        ui.add(egui::DragValue::new(&mut window.interval).range(0..=120).suffix(" min"));
+                    });
+                    let target = window.target.trim();
+                    if ui.add_enabled(!target.is_empty(), egui::Button::new(tr("Capture baseline"))).clicked() {
+                        window.capture = Some(BaselineCapture {
+                            target: target.to_string(),
+                            runs: window.runs,
+                            since: chrono::Local::now(),
+                            next_at: Instant::now(),
+                        });
+                    }
+                }
+            }
+
+            if history.baselines.targets.is_empty() {
+                return;
+            }
+            ui.separator();
+            let mut delete = None;
+            egui::Grid::new("baselines").striped(true).num_columns(4).show(ui, |ui| {
+                for header in ["Target", "Captured", "Hops", ""] {
+                    ui.strong(tr(header));
+                }
+                ui.end_row();
+                for (target, baseline) in &history.baselines.targets {
+                    ui.label(target);
+                    ui.label(baseline.captured.format("%Y-%m-%d %H:%M").to_string())
+                        .on_hover_text(trf("From {} traces", &[&baseline.runs]));
+                    ui.label(baseline.hops.len().to_string()).on_hover_text(
+                        baseline
+                            .hops
+                            .iter()
+                            .map(|hop| format!("{}: {} ({:.0}-{:.0} ms)", hop.ttl, hop.ip, hop.rtt_low, hop.rtt_high))
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    );
+                    if ui.small_button(tr("Delete")).clicked() {
+                        delete = Some(target.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(target) = delete {
+                history.baselines.remove(&target);
+                history.baselines.save();
+            }
+        });
+    window.open = open;
+}
+
+/// Notes on one target from everyone sharing the store.
+#[derive(Default)]
+pub struct NotesWindow {
+    open: bool,
+    target: String,
+    draft: String,
+}
+
+impl NotesWindow {
+    /// Shows the notes on the target of trace `id`.
+    pub fn open(&mut self, id: usize, trace_set: &TraceSet) {
+        if let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) {
+            self.open = true;
+            self.target = trace.target.clone();
+        }
+    }
+}
+
+pub fn notes(ui: &Ui, window: &mut NotesWindow, notes: &mut Notes) {
+    if !window.open {
+        return;
+    }
+    // Notes others add show up on the next sync
+    ui.ctx().request_repaint_after(history::SYNC_INTERVAL);
+    let mut remove = None;
+    Window::new(trf("Notes on {}", &[&window.target]))
+        .id(egui::Id::new("notes"))
+        .open(&mut window.open)
+        .default_width(320.)
+        .show(ui.ctx(), |ui| {
+            let list = notes.for_target(&window.target);
+            if list.is_empty() {
+                ui.weak(tr("No notes yet"));
+            }
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for (author, note) in list {
+                    ui.horizontal(|ui| {
+                        ui.strong(author);
+                        ui.weak(note.written.format("%Y-%m-%d %H:%M").to_string());
+                        if author == notes.author && ui.small_button("🗙").described(tr("Delete")).clicked() {
+                            remove = Some(note.written);
+                        }
+                    });
+                    ui.label(&note.text);
+                    ui.add_space(4.);
+                }
+            });
+            ui.separator();
+            ui.add(
+                egui::TextEdit::multiline(&mut window.draft)
+                    .hint_text(trf("Note as {}", &[&notes.author]))
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            )
+            .named(tr("New note"));
+            let text = window.draft.trim();
+            if ui.add_enabled(!text.is_empty(), egui::Button::new(tr("Add note"))).clicked() {
+                notes.add(&window.target, text);
+                window.draft.clear();
+            }
+        });
+    if let Some(written) = remove {
+        notes.remove(written);
+    }
+}
+
+/// State of the compliance report window.
+#[derive(Default)]
+pub struct ComplianceWindow {
+    pub open: bool,
+    trace: Option<usize>,
+    concerns: Option<String>,
+}
+
+pub fn compliance(ui: &Ui, window: &mut ComplianceWindow, trace_set: &TraceSet, concerns: &mut Vec<String>) {
+    let ComplianceWindow { open, trace, concerns: concerns_text } = window;
+    Window::new(tr("Compliance report"))
+        .id(egui::Id::new("Compliance report"))
+        .open(open)
+        .default_width(320.)
+        .show(ui.ctx(), |ui| {
+            let concerns_text = concerns_text.get_or_insert_with(|| concerns.join(", "));
+            ui.horizontal(|ui| {
+                ui.label(tr("Concerns"));
+                if ui
+                    .add(egui::TextEdit::singleline(concerns_text).hint_text(tr("Country codes, e.g. CN, RU")))
+                    .named(tr("Concerns"))
+                    .changed()
+                {
+                    *concerns = history::parse_tags(concerns_text)
+                        .into_iter()
+                        .map(|c| c.to_uppercase())
+                        .collect();
+                }
+            });
+
+            // Default to the most recent trace
+            if trace.is_none_or(|id| !trace_set.traces.iter().any(|t| t.id == id)) {
+                *trace = trace_set.traces.last().map(|t| t.id);
+            }
+            let Some(selected) = trace_set.traces.iter().find(|t| Some(t.id) == *trace) else {
+                ui.weak(tr("No traces yet"));
+                return;
+            };
+            egui::ComboBox::from_label(tr("Trace"))
+                .selected_text(&selected.target)
+                .show_ui(ui, |ui| {
+                    for t in &trace_set.traces {
+                        ui.selectable_value(trace, Some(t.id), &t.target);
+                    }
+                });
+
+            ui.separator();
+            let jurisdictions = summary::jurisdictions(&selected.nodes, concerns);
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for j in &jurisdictions {
+                    let heading = trf("{} ({}) · {} hops", &[&j.country, &j.country_code, &j.hops]);
+                    if j.flagged {
+                        ui.colored_label(egui::Color32::RED, format!("⚠ {}", heading));
+                    } else {
+                        ui.strong(heading);
+                    }
+                    for organization in &j.organizations {
+                        ui.label(format!("    {}", organization));
+                    }
+                }
+            });
+            ui.separator();
+            let flagged = jurisdictions.iter().filter(|j| j.flagged).count();
+            if flagged > 0 {
+                ui.colored_label(egui::Color32::RED, trf("{} jurisdiction(s) of concern", &[&flagged]));
+            } else {
+                ui.colored_label(egui::Color32::GREEN, tr("No jurisdictions of concern"));
+            }
+            if ui.button(tr("Copy report")).clicked() {
+                ui.output_mut(|o| {
+                    o.copied_text = summary::jurisdiction_report(&selected.target, &jurisdictions)
+                });
+                trace_set.toasts.publish(Toast::success(tr("Copied report")));
+            }
+        });
+}
+
+/// State of the geofences window, and the fence being drawn on the map.
+#[derive(Default)]
+pub struct GeofenceWindow {
+    pub open: bool,
+    name: String,
+    draft: plugins::FenceDraft,
+}
+
+impl GeofenceWindow {
+    /// Map plugin taking clicks as corners of the fence being drawn.
+    pub fn draft_layer(&mut self) -> plugins::FenceDraftLayer<'_> {
+        self.draft.layer()
+    }
+}
+
+/// Lists the geofences, draws new ones and shows which visible traces enter them.
+pub fn geofences(
+    ui: &Ui,
+    window: &mut GeofenceWindow,
+    config: &mut Config,
+    trace_set: &mut TraceSet,
+    schedule: &ScheduleWindow,
+) {
+    let mut open = window.open;
+    let mut changed = false;
+    Window::new(tr("Geofences"))
+        .id(egui::Id::new("Geofences"))
+        .open(&mut open)
+        .default_width(300.)
+        .show(ui.ctx(), |ui| {
+            let mut remove = None;
+            for (i, fence) in config.geofences.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    changed |= ui.checkbox(&mut fence.enabled, &fence.name).changed();
+                    ui.weak(trf("{} corners", &[&fence.points.len()]));
+                    if ui.small_button("🗙").described(tr("Remove")).clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                config.geofences.remove(i);
+                changed = true;
+            }
+            if config.geofences.is_empty() {
+                ui.weak(tr("No geofences yet"));
+            }
+            ui.separator();
+
+            let draft = &mut window.draft;
+            if draft.drawing {
+                ui.label(trf("Click the map to add corners, {} so far", &[&draft.corners.len()]));
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut window.name)
+                            .hint_text(tr("Name"))
+                            .desired_width(140.),
+                    )
+                    .named(tr("Geofence name"));
+                    let ready = draft.corners.len() >= 3 && !window.name.trim().is_empty();
+                    if ui.add_enabled(ready, egui::Button::new(tr("Save"))).clicked() {
+                        info!("Adding geofence {}", window.name.trim());
+                        config.geofences.push(Geofence::new(window.name.trim().to_string(), &draft.corners));
+                        window.name.clear();
+                        *draft = Default::default();
+                        changed = true;
+                    }
+                    if ui.button(tr("Cancel")).clicked() {
+                        *draft = Default::default();
+                    }
+                });
+                if ui.add_enabled(!draft.corners.is_empty(), egui::Button::new(tr("Undo corner"))).clicked() {
+                    draft.corners.pop();
+                }
+            } else if ui.button(tr("Draw geofence")).clicked() {
+                draft.drawing = true;
+            }
+
+            // Visible traces entering any fence
+            let mut any = false;
+            for trace in trace_set.traces.iter().filter(|t| t.visible) {
+                let breaches = geofence::breaches(
+                    &config.geofences,
+                    trace.nodes.iter().map(|(ttl, node)| (*ttl, node.position)),
+                );
+                if let Some(text) = geofence::describe(&breaches) {
+                    if !any {
+                        ui.separator();
+                        any = true;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("⏺").color(trace.color));
+                        ui.colored_label(plugins::GEOFENCE_COLOR, format!("{}: {}", trace.target, text));
+                    });
+                }
+            }
+        });
+    if !open {
+        window.draft = Default::default();
+    }
+    window.open = open;
+    if changed {
+        trace_set.geofences = config.geofences.clone();
+        schedule.scheduler.set_geofences(&config.geofences);
+    }
+}
+
+/// State of the transit policies window, with the policy being added.
+#[derive(Default)]
+pub struct PolicyWindow {
+    pub open: bool,
+    destination: String,
+    via: String,
+    never: String,
+}
+
+/// Lists the transit policies, adds new ones and shows which visible traces break them.
+pub fn policies(
+    ui: &Ui,
+    window: &mut PolicyWindow,
+    config: &mut Config,
+    trace_set: &TraceSet,
+    schedule: &ScheduleWindow,
+) {
+    let mut changed = false;
+    Window::new(tr("Transit policies"))
+        .id(egui::Id::new("Transit policies"))
+        .open(&mut window.open)
+        .default_width(320.)
+        .show(ui.ctx(), |ui| {
+            let mut remove = None;
+            for (i, policy) in config.transit_policies.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    changed |= ui.checkbox(&mut policy.enabled, trf("To AS{}", &[&policy.destination])).changed();
+                    ui.weak(policy.describe());
+                    if ui.small_button("🗙").described(tr("Remove")).clicked() {
+                        remove = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove {
+                config.transit_policies.remove(i);
+                changed = true;
+            }
+            if config.transit_policies.is_empty() {
+                ui.weak(tr("No transit policies yet"));
+            }
+            ui.separator();
+
+            egui::Grid::new("new_policy").num_columns(2).show(ui, |ui| {
+                ui.label(tr("Traffic to"));
+                ui.add(egui::TextEdit::singleline(&mut window.destination).hint_text("AS15169")).named(tr("Traffic to"));
+                ui.end_row();
+                ui.label(tr("goes via"));
+                ui.add(egui::TextEdit::singleline(&mut window.via).hint_text(tr("AS1299, any of them"))).named(tr("goes via"));
+                ui.end_row();
+                ui.label(tr("never via"));
+                ui.add(egui::TextEdit::singleline(&mut window.never).hint_text("AS174")).named(tr("never via"));
+                ui.end_row();
+            });
+            let destination = policy::parse_asns(&window.destination).first().copied();
+            let (via, never) = (policy::parse_asns(&window.via), policy::parse_asns(&window.never));
+            let ready = destination.is_some() && !(via.is_empty() && never.is_empty());
+            if ui.add_enabled(ready, egui::Button::new(tr("Add policy"))).clicked() {
+                if let Some(destination) = destination {
+                    info!("Adding transit policy for AS{}", destination);
+                    config.transit_policies.push(TransitPolicy {
+                        destination,
+                        via,
+                        never,
+                        enabled: true,
+                    });
+                    changed = true;
+                    window.destination.clear();
+                    window.via.clear();
+                    window.never.clear();
+                }
+            }
+
+            // Visible traces breaking any policy
+            let mut any = false;
+            for trace in trace_set.traces.iter().filter(|t| t.visible && !t.tracing) {
+                let violations = policy::violations(
+                    &config.transit_policies,
+                    trace.nodes.iter().map(|(ttl, node)| (*ttl, node.asn)),
+                );
+                for violation in &violations {
+                    if !any {
+                        ui.separator();
+                        any = true;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new("⏺").color(trace.color));
+                        ui.colored_label(DEVIATION_COLOR, format!("{}: {}", trace.target, violation.describe()));
+                    });
+                }
+            }
+        });
+    if changed {
+        schedule.scheduler.set_policies(&config.transit_policies);
+    }
+}
+
+struct WhoisLookup {
+    ip: String,
+    open: bool,
+    result: Option<Result<String, String>>,
+}
+
+/// Open WHOIS windows, one per looked up IP.
+pub struct WhoisWindows {
+    lookups: Vec<WhoisLookup>,
+    topic: Topic<(String, Result<String, String>)>,
+    receiver: Receiver<(String, Result<String, String>)>,
+}
+
+impl WhoisWindows {
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            lookups: Vec::new(),
+            topic: bus.whois.clone(),
+            receiver: bus.whois.subscribe(),
+        }
+    }
+
+    pub fn open(&mut self, ip: String, runtime: &Executor) {
+        if let Some(lookup) = self.lookups.iter_mut().find(|l| l.ip == ip) {
+            lookup.open = true;
+            return;
+        }
+        info!("Starting WHOIS lookup for {}", ip);
+        self.lookups.push(WhoisLookup {
+            ip: ip.clone(),
+            open: true,
+            result: None,
+        });
+        let topic = self.topic.clone();
+        runtime.spawn(async move {
+            let result = whois::lookup(&ip).await;
+            if let Err(e) = &result {
+                warn!("WHOIS lookup for {} failed: {}", ip, e);
+            }
+            topic.publish((ip, result));
+        });
+    }
+}
+
+pub fn whois(ui: &Ui, windows: &mut WhoisWindows) {
+    while let Ok((ip, result)) = windows.receiver.try_recv() {
+        if let Some(lookup) = windows.lookups.iter_mut().find(|l| l.ip == ip) {
+            lookup.result = Some(result);
+        }
+    }
+
+    for lookup in &mut windows.lookups {
+        Window::new(trf("WHOIS {}", &[&lookup.ip]))
+            .id(egui::Id::new("WHOIS").with(&lookup.ip))
+            .open(&mut lookup.open)
+            .default_size([420., 360.])
+            .show(ui.ctx(), |ui| match &lookup.result {
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(tr("Querying whois servers..."));
+                    });
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                Some(Ok(response)) => {
+                    let summary = whois::summarize(response);
+                    egui::Grid::new(("whois_summary", &lookup.ip)).num_columns(2).show(ui, |ui| {
+                        for (label, value) in [
+                            ("Registrant", &summary.registrant),
+                            ("Netblock", &summary.netblock),
+                            ("Abuse", &summary.abuse),
+                        ] {
+                            ui.strong(tr(label));
+                            ui.label(value.as_deref().unwrap_or("-"));
+                            ui.end_row();
+                        }
+                    });
+                    ui.separator();
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.add(
+                            egui::Label::new(RichText::new(response).monospace().size(11.0))
+                                .selectable(true),
+                        );
+                    });
+                }
+            });
+    }
+    windows.lookups.retain(|l| l.open);
+}
+
+/// Export dialog for a single trace.
+pub struct ExportWindow {
+    trace: Option<usize>,
+    formats: Vec<export::Format>,
+    format: usize,
+    output: Result<String, String>,
+    path: String,
+}
+
+impl Default for ExportWindow {
+    fn default() -> Self {
+        Self {
+            trace: None,
+            formats: export::formats(),
+            format: 0,
+            output: Ok(String::new()),
+            path: String::new(),
+        }
+    }
+}
+
+impl ExportWindow {
+    pub fn open(&mut self, id: usize, trace_set: &TraceSet) {
+        let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) else {
+            return;
+        };
+        // Rescan so templates added while running show up
+        self.formats = export::formats();
+        self.format = self.format.min(self.formats.len() - 1);
+        let dir = directories::UserDirs::new()
+            .and_then(|dirs| dirs.download_dir().map(|d| d.to_path_buf()))
+            .unwrap_or_default();
+        let name: String = trace
+            .target
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        self.path = dir
+            .join(format!("trace-{}.{}", name, self.formats[self.format].extension()))
+            .display()
+            .to_string();
+        self.trace = Some(id);
+        self.render(trace);
+    }
+
+    fn render(&mut self, trace: &crate::plugins::Trace) {
+        self.output = export::render(&self.formats[self.format], trace);
+    }
+}
+
+pub fn export(ui: &Ui, window: &mut ExportWindow, trace_set: &TraceSet) {
+    let Some(trace) = window
+        .trace
+        .and_then(|id| trace_set.traces.iter().find(|t| t.id == id))
+    else {
+        window.trace = None;
+        return;
+    };
+
+    let mut open = true;
+    Window::new(trf("Export {}", &[&trace.target]))
+        .id(egui::Id::new("export"))
+        .open(&mut open)
+        .default_size([520., 360.])
+        .show(ui.ctx(), |ui| {
+            let previous = window.format;
+            egui::ComboBox::from_label(tr("Format"))
+                .selected_text(window.formats[window.format].name())
+                .show_ui(ui, |ui| {
+                    for (i, format) in window.formats.iter().enumerate() {
+                        ui.selectable_value(&mut window.format, i, format.name());
+                    }
+                });
+            if window.format != previous {
+                let mut path = std::path::PathBuf::from(&window.path);
+                path.set_extension(window.formats[window.format].extension());
+                window.path = path.display().to_string();
+                window.render(trace);
+            }
+
+            egui::ScrollArea::both().max_height(220.).show(ui, |ui| match &window.output {
+                Ok(output) => {
+                    ui.add(
+                        egui::Label::new(RichText::new(output).monospace().size(11.0))
+                            .selectable(true)
+                            .extend(),
+                    );
+                }
+                Err(e) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+            });
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut window.path);
+                let output = window.output.as_ref().ok();
+                if ui.add_enabled(output.is_some(), egui::Button::new(tr("Save"))).clicked() {
+                    if let Some(output) = output {
+                        match std::fs::write(&window.path, output) {
+                            Ok(()) => {
+                                info!("Exported {} to {}", trace.target, window.path);
+                                trace_set.toasts.publish(Toast::success(trf("Saved to {}", &[&window.path])));
+                            }
+                            Err(e) => trace_set.toasts.publish(Toast::error(trf("Failed to save: {}", &[&e]))),
+                        }
+                    }
+                }
+                if ui.add_enabled(output.is_some(), egui::Button::new(tr("Copy"))).clicked() {
+                    if let Some(output) = output {
+                        ui.output_mut(|o| o.copied_text = output.clone());
+                        trace_set.toasts.publish(Toast::success(tr("Copied to clipboard")));
+                    }
+                }
+            });
+        });
+    if !open {
+        window.trace = None;
+    }
+}
+
+/// Flow summary import for a single trace.
+pub struct FlowsWindow {
+    trace: Option<usize>,
+    path: String,
+    prefix: String,
+    /// Minutes before and after the trace start to count flows in
+    minutes: u32,
+    records: Vec<flows::FlowRecord>,
+    status: Option<Result<String, String>>,
+}
+
+impl Default for FlowsWindow {
+    fn default() -> Self {
+        Self {
+            trace: None,
+            path: String::new(),
+            prefix: String::new(),
+            minutes: 15,
+            records: Vec::new(),
+            status: None,
+        }
+    }
+}
+
+impl FlowsWindow {
+    pub fn open(&mut self, id: usize, trace_set: &TraceSet) {
+        let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) else {
+            return;
+        };
+        // Default to the destination's /24 or /64
+        let destination = trace.nodes.last().and_then(|(_, node)| node.ip.parse::<IpAddr>().ok());
+        self.prefix = destination
+            .and_then(|ip| {
+                let len = if ip.is_ipv4() { 24 } else { 64 };
+                ipnet::IpNet::new(ip, len).ok().map(|net| net.trunc().to_string())
+            })
+            .unwrap_or_default();
+        self.trace = Some(id);
+        self.status = None;
+    }
+}
+
+pub fn flows(ui: &Ui, window: &mut FlowsWindow, trace_set: &mut TraceSet) {
+    let Some(trace) = window.trace.and_then(|id| trace_set.get_mut(id)) else {
+        window.trace = None;
+        return;
+    };
+
+    let mut open = true;
+    Window::new(trf("Flows to {}", &[&trace.target]))
+        .id(egui::Id::new("flows"))
+        .open(&mut open)
+        .default_width(380.)
+        .show(ui.ctx(), |ui| {
+            egui::Grid::new("flow_options").num_columns(2).show(ui, |ui| {
+                ui.label(tr("File"));
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut window.path).hint_text("nfdump -o csv, sflowtool CSV"))
+                        .named(tr("Flow file"));
+                    if ui.button(tr("Load")).clicked() {
+                        match flows::load(std::path::Path::new(window.path.trim())) {
+                            Ok(records) => {
+                                window.status = Some(Ok(trf("{} flow records", &[&records.len()])));
+                                window.records = records;
+                            }
+                            Err(e) => {
+                                window.records.clear();
+                                window.status = Some(Err(e));
+                            }
+                        }
+                    }
+                });
+                ui.end_row();
+                ui.label(tr("Target prefix"));
+                ui.text_edit_singleline(&mut window.prefix);
+                ui.end_row();
+                ui.label(tr("Window"));
+                ui.add(egui::DragValue::new(&mut window.minutes).range(1..=1440).suffix(" min around the trace"));
+                ui.end_row();
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!window.records.is_empty(), egui::Button::new(tr("Correlate")))
+                    .clicked()
+                {
+                    match window.prefix.trim().parse::<ipnet::IpNet>() {
+                        Ok(prefix) => {
+                            let span = chrono::Duration::minutes(window.minutes as i64);
+                            trace.flows = flows::correlate(
+                                &window.records,
+                                prefix,
+                                trace.started - span,
+                                trace.started + span,
+                            );
+                            let on_path = trace
+                                .nodes
+                                .iter()
+                                .filter(|(_, node)| node.ip.parse().is_ok_and(|ip| trace.flows.contains_key(&ip)))
+                                .count();
+                            window.status = Some(Ok(trf(
+                                "{} exporters saw traffic to {}, {} of them on the path",
+                                &[&trace.flows.len(), &prefix, &on_path],
+                            )));
+                        }
+                        Err(_) => window.status = Some(Err(tr("Invalid prefix").to_string())),
+                    }
+                }
+                if ui.add_enabled(!trace.flows.is_empty(), egui::Button::new(tr("Clear"))).clicked() {
+                    trace.flows.clear();
+                }
+            });
+            match &window.status {
+                Some(Ok(status)) => {
+                    ui.label(status);
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                None => {}
+            }
+
+            if trace.flows.is_empty() {
+                return;
+            }
+            ui.separator();
+            egui::Grid::new("flow_volumes").striped(true).num_columns(5).show(ui, |ui| {
+                ui.strong(tr("Hop"));
+                ui.strong("IP");
+                ui.strong(tr("Bytes"));
+                ui.strong(tr("Packets"));
+                ui.strong(tr("Flows"));
+                ui.end_row();
+                for (idx, node) in &trace.nodes {
+                    let Some(volume) = node.ip.parse().ok().and_then(|ip: IpAddr| trace.flows.get(&ip)) else {
+                        continue;
+                    };
+                    ui.label(idx.to_string());
+                    ui.label(&node.ip);
+                    ui.label(flows::format_bytes(volume.bytes));
+                    ui.label(volume.packets.to_string());
+                    ui.label(volume.flows.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+    if !open {
+        window.trace = None;
+    }
+}
+
+struct SnmpLookup {
+    ip: String,
+    open: bool,
+    result: Option<Result<snmp::Interface, String>>,
+}
+
+/// Interface details of managed hops, one window per hop.
+pub struct SnmpWindows {
+    lookups: Vec<SnmpLookup>,
+    topic: Topic<(String, Result<snmp::Interface, String>)>,
+    receiver: Receiver<(String, Result<snmp::Interface, String>)>,
+}
+
+impl SnmpWindows {
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            lookups: Vec::new(),
+            topic: bus.snmp.clone(),
+            receiver: bus.snmp.subscribe(),
+        }
+    }
+
+    pub fn open(&mut self, ip: String, config: &snmp::SnmpConfig, runtime: &Executor) {
+        // Unlike whois, counters go stale, so reopening always queries again
+        self.lookups.retain(|l| l.ip != ip);
+        self.lookups.push(SnmpLookup {
+            ip: ip.clone(),
+            open: true,
+            result: None,
+        });
+        let topic = self.topic.clone();
+        let result = match ip.parse::<IpAddr>() {
+            Ok(addr) if config.is_managed(addr) => {
+                info!("Querying SNMP interface for {}", ip);
+                let config = config.clone();
+                runtime.spawn(async move {
+                    let result = snmp::interface(&config, addr).await;
+                    if let Err(e) = &result {
+                        warn!("SNMP lookup for {} failed: {}", ip, e);
+                    }
+                    topic.publish((ip, result));
+                });
+                return;
+            }
+            Ok(_) if !config.enabled => Err("SNMP is disabled in the settings".to_string()),
+            Ok(_) => Err(tr("Not in a managed range").to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        topic.publish((ip, result));
+    }
+}
+
+pub fn snmp(ui: &Ui, windows: &mut SnmpWindows) {
+    while let Ok((ip, result)) = windows.receiver.try_recv() {
+        if let Some(lookup) = windows.lookups.iter_mut().find(|l| l.ip == ip) {
+            lookup.result = Some(result);
+        }
+    }
+
+    for lookup in &mut windows.lookups {
+        Window::new(trf("Interface {}", &[&lookup.ip]))
+            .id(egui::Id::new("Interface").with(&lookup.ip))
+            .open(&mut lookup.open)
+            .resizable(false)
+            .show(ui.ctx(), |ui| match &lookup.result {
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(tr("Querying SNMP..."));
+                    });
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+                Some(Ok(interface)) => {
+                    egui::Grid::new(("snmp", &lookup.ip)).num_columns(2).show(ui, |ui| {
+                        ui.strong(tr("Router"));
+                        ui.label(&interface.system);
+                        ui.end_row();
+                        ui.strong(tr("Interface"));
+                        ui.label(&interface.name);
+                        ui.end_row();
+                        ui.strong(tr("Description"));
+                        ui.label(&interface.description);
+                        ui.end_row();
+                        ui.strong(tr("Speed"));
+                        ui.label(format!("{} Mbit/s", interface.speed_mbps));
+                        ui.end_row();
+                        if let Some((rx, tx)) = interface.utilization {
+                            ui.strong(tr("Utilization"));
+                            ui.label(trf("in {}%, out {}%", &[&format!("{:.1}", rx), &format!("{:.1}", tx)]));
+                            ui.end_row();
+                        }
+                    });
+                }
+            });
+    }
+    windows.lookups.retain(|l| l.open);
+}
+
+/// Publishes the events of a trace being started as they come in, under its run, see
+/// [`plugins::Trace::run`].
+fn forward_trace(
+    run: usize,
+    events: impl Future<Output = tokio::sync::mpsc::UnboundedReceiver<TraceEvent>> + Send + 'static,
+    topic: Topic<(usize, TraceEvent)>,
+    ctx: egui::Context,
+    runtime: &Executor,
+) {
+    runtime.spawn(async move {
+        let mut events = events.await;
+        while let Some(event) = events.recv().await {
+            topic.publish((run, event));
+            // Hops show up right away, not with the next mouse move
+            ctx.request_repaint();
+        }
+    });
+}
+
+/// Looks up the pending hops of a finished trace again, the locations come back as a
+/// [`TraceEvent::Located`] for it.
+fn locate_pending(
+    trace: &plugins::Trace,
+    enrichment: &Enrichment,
+    topic: Topic<(usize, TraceEvent)>,
+    ctx: egui::Context,
+    runtime: &Executor,
+) {
+    let ips: Vec<String> = trace.nodes.iter().filter(|(_, n)| n.pending).map(|(_, n)| n.ip.clone()).collect();
+    info!("Locating {} pending hops of {} again", ips.len(), trace.target);
+    let (run, geolocation, ttl) = (trace.run, trace.settings.geolocation, trace.settings.options.cache_ttl());
+    let sites = enrichment.sites.clone();
+    runtime.spawn(async move {
+        let client = reqwest::Client::new();
+        let locations = trace::get_locations(&client, geolocation, &sites, &ips, ttl).await;
+        let located: Vec<_> = ips
+            .into_iter()
+            .zip(locations)
+            .filter_map(|(ip, location)| Some((ip, location?)))
+            .collect();
+        debug!("Located {} pending hops", located.len());
+        topic.publish((run, TraceEvent::Located(located)));
+        ctx.request_repaint();
+    });
+}
+
+/// Every hop of the visible traces, including ones collapsed on the map.
+pub fn hops(ui: &Ui, trace_set: &mut TraceSet) {
+    Window::new(tr("Hops"))
+        .id(egui::Id::new("Hops"))
+        .default_open(false)
+        .resizable(false)
+        .pivot(Align2::CENTER_BOTTOM)
+        .default_pos(corner(ui.ctx(), Align2::CENTER_BOTTOM, [0., -10.]))
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut trace_set.hop_filter)
+                        .hint_text(tr("Filter by IP, host, ISP, AS or country"))
+                        .desired_width(260.),
+                )
+                .named(tr("Filter hops"));
+                if !trace_set.hop_filter.is_empty() && ui.small_button("🗙").described(tr("Clear filter")).clicked() {
+                    trace_set.hop_filter.clear();
+                }
+            });
+            let filter = trace_set.hop_filter.trim().to_string();
+            egui::ScrollArea::vertical().max_height(250.).show(ui, |ui| {
+                egui::Grid::new("hops").striped(true).num_columns(11).show(ui, |ui| {
+                    ui.strong("");
+                    ui.strong("#");
+                    ui.strong("IP");
+                    ui.strong(tr("Host"));
+                    ui.strong(tr("ISP"));
+                    ui.strong("AS");
+                    ui.strong(tr("Location"));
+                    ui.strong("TTL").on_hover_text(tr("TTL left in the reply, and the initial TTL it most likely started from"));
+                    ui.strong(tr("OS hint"));
+                    ui.strong(tr("Fields")).on_hover_text(tr("Added by scripts"));
+                    ui.strong(tr("Baseline"));
+                    ui.end_row();
+
+                    for trace in trace_set.traces.iter().filter(|t| t.visible) {
+                        // Interleave the timeouts with the hops that answered, by TTL
+                        let mut rows: Vec<(usize, Option<&TraceNode>)> = trace
+                            .nodes
+                            .iter()
+                            .map(|(idx, node)| (*idx, Some(node)))
+                            .chain(trace.timeouts.iter().map(|idx| (*idx, None)))
+                            .collect();
+                        rows.sort_by_key(|(idx, _)| *idx);
+
+                        // Timeouts have nothing to match, so a filter hides them
+                        let rows = rows
+                            .into_iter()
+                            .filter(|(_, node)| filter.is_empty() || node.is_some_and(|node| node.matches(&filter)));
+                        for (idx, node) in rows {
+                            ui.label(RichText::new("⏺").color(trace.color))
+                                .on_hover_text(&trace.target);
+                            ui.label(idx.to_string());
+                            let Some(node) = node else {
+                                ui.label(RichText::new("* * *").weak()).on_hover_text(tr("No reply"));
+                                ui.end_row();
+                                continue;
+                            };
+                            ui.label(&node.ip).context_menu(|ui| {
+                                plugins::copy_menu(ui, &node.ip, &node.hostname, &mut trace_set.copy_port, &trace_set.toasts);
+                            });
+                            if node.resolving {
+                                ui.label(RichText::new(tr("resolving…")).italics().weak());
+                            } else {
+                                ui.label(&node.hostname);
+                            }
+                            if node.guessed() {
+                                ui.label(RichText::new(&node.isp).italics().weak());
+                            } else if let Some(name) = ixp::lookup(&node.ip) {
+                                ui.label(RichText::new(name).strong())
+                                    .on_hover_text(trf("Internet exchange, run by {}", &[&node.isp]));
+                            } else {
+                                ui.label(&node.isp);
+                            }
+                            ui.label(node.asn.map(|asn| format!("AS{}", asn)).unwrap_or_default())
+                                .on_hover_text(&node.as_name);
+                            ui.label(format!("{} {}", summary::flag(&node.country_code), node.place()).trim())
+                                .on_hover_text(&node.region);
+                            match (node.reply_ttl, node.initial_ttl()) {
+                                (Some(reply_ttl), Some((initial, hint))) => {
+                                    ui.label(format!("{} / {}", reply_ttl, initial)).on_hover_text(trf(
+                                        "About {} hops on the way back",
+                                        &[&(initial - reply_ttl)],
+                                    ));
+                                    ui.label(RichText::new(hint).weak());
+                                }
+                                _ => {
+                                    ui.label("");
+                                    ui.label("");
+                                }
+                            }
+                            ui.label(
+                                node.fields
+                                    .iter()
+                                    .map(|(k, v)| format!("{}: {}", k, v))
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                            );
+                            match trace.deviations.as_ref().and_then(|d| d.get(&idx)) {
+                                Some(deviation) => {
+                                    ui.colored_label(DEVIATION_COLOR, format!("⚠ {}", deviation.describe()));
+                                }
+                                None => {
+                                    ui.label("");
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+        });
+}
+
+/// Details of the hop selected on the map, until it's deselected or its trace goes away.
+pub fn selected_hop(ui: &Ui, trace_set: &mut TraceSet) {
+    let Some((id, ttl)) = trace_set.selected else {
+        return;
+    };
+    let several = trace_set.traces.iter().filter(|t| t.visible).count() > 1;
+    let Some((trace, node)) = trace_set
+        .traces
+        .iter()
+        .find(|t| t.id == id)
+        .and_then(|t| Some((t, t.nodes.iter().find(|(idx, _)| *idx == ttl)?.1.clone())))
+    else {
+        trace_set.selected = None;
+        return;
+    };
+    let (target, color) = (trace.target.clone(), trace.color);
+
+    let mut open = true;
+    Window::new(trf("Hop #{}", &[&ttl]))
+        .id(egui::Id::new("selected_hop"))
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .pivot(Align2::RIGHT_TOP)
+        .default_pos(ui.ctx().screen_rect().right_top() + egui::vec2(-10., 200.))
+        .show(ui.ctx(), |ui| {
+            if several {
+                ui.label(RichText::new(&target).color(color));
+            }
+            egui::Grid::new("selected_hop_details").num_columns(2).show(ui, |ui| {
+                ui.label("IP");
+                ui.label(&node.ip);
+                ui.end_row();
+                ui.label(tr("Host"));
+                if node.resolving {
+                    ui.label(RichText::new(tr("resolving…")).italics().weak());
+                } else {
+                    ui.label(&node.hostname);
+                }
+                ui.end_row();
+                ui.label(tr("ISP"));
+                ui.label(&node.isp);
+                ui.end_row();
+                if let Some(asn) = node.asn {
+                    ui.label("AS");
+                    ui.label(format!("AS{} {}", asn, node.as_name));
+                    ui.end_row();
+                }
+                if !node.prefix.is_empty() {
+                    ui.label(tr("Prefix"));
+                    ui.label(&node.prefix);
+                    ui.end_row();
+                }
+                let place = node.place();
+                if !place.is_empty() {
+                    ui.label(tr("Location"));
+                    ui.label(format!("{} {}", summary::flag(&node.country_code), place).trim());
+                    ui.end_row();
+                }
+                ui.label("RTT");
+                ui.label(node.rtt.map(|rtt| format!("{:.1} ms", rtt.as_secs_f64() * 1000.0)).unwrap_or("–".to_string()));
+                ui.end_row();
+                if let Some(rtts) = trace_set.hop_rtts.rtts(&node.ip).filter(|rtts| !rtts.is_empty()) {
+                    let (min, max) = rtts.iter().fold((f64::MAX, f64::MIN), |(min, max), &rtt| (min.min(rtt), max.max(rtt)));
+                    let avg = rtts.iter().sum::<f64>() / rtts.len() as f64;
+                    ui.label(tr("Scheduled"));
+                    ui.label(format!("{:.1} / {:.1} / {:.1} ms", min, avg, max))
+                        .on_hover_text(trf("Min / avg / max over the last {} scheduled runs", &[&rtts.len()]));
+                    ui.end_row();
+                }
+                for (key, value) in &node.fields {
+                    ui.label(key);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.menu_button(tr("📋 Copy"), |ui| {
+                    plugins::copy_menu(ui, &node.ip, &node.hostname, &mut trace_set.copy_port, &trace_set.toasts);
+                });
+                if ui.button(tr("WHOIS…")).clicked() {
+                    trace_set.whois_request = Some(node.ip.clone());
+                }
+                if ui.button(tr("Ping…")).clicked() {
+                    trace_set.ping_request = Some(node.ip.clone());
+                }
+            });
+        });
+    if !open {
+        trace_set.selected = None;
+    }
+}
+
+enum PingEvent {
+    Located { ip: IpAddr, location: String },
+    Reply { at: f64, rtt: Option<Duration> },
+    Error(String),
+}
+
+/// A continuous ping of a single target, started from the Enter IP window.
+pub struct PingWindow {
+    open: bool,
+    target: String,
+    ip: Option<IpAddr>,
+    location: String,
+    /// Seconds since the start and the round trip time in ms, none for a lost probe
+    samples: Vec<(f64, Option<f64>)>,
+    error: Option<String>,
+    stop: Arc<AtomicBool>,
+    receiver: Receiver<PingEvent>,
+}
+
+impl Default for PingWindow {
+    fn default() -> Self {
+        let (_, receiver) = channel();
+        Self {
+            open: false,
+            target: String::new(),
+            ip: None,
+            location: String::new(),
+            samples: Vec::new(),
+            error: None,
+            stop: Arc::new(AtomicBool::new(true)),
+            receiver,
+        }
+    }
+}
+
+impl PingWindow {
+    /// Stops the current session, if any, and starts pinging `target` about once a second.
+    pub fn start(
+        &mut self,
+        target: String,
+        options: TraceOptions,
+        geolocation: GeoProvider,
+        sites: Arc<SiteMap>,
+        runtime: &Executor,
+    ) {
+        self.stop.store(true, Ordering::Relaxed);
+        info!("Starting ping for {}", target);
+
+        // A fresh channel per session, so stragglers from the last one can't mix in
+        let (sender, receiver) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        *self = Self {
+            open: true,
+            target: target.clone(),
+            stop: stop.clone(),
+            receiver,
+            ..Default::default()
+        };
+
+        runtime.spawn(async move {
+            let Some(ip) = trace::resolve(&target) else {
+                sender.send(PingEvent::Error(trf("Could not resolve {}", &[&target]))).ok();
+                return;
+            };
+            let client = reqwest::Client::new();
+            let location = trace::get_location(&client, geolocation, &sites, &ip.to_string(), options.cache_ttl())
+                .await
+                .map(|l| format!("{}, {} (AS{})", l.isp, l.country, l.asn.unwrap_or_default()))
+                .unwrap_or_default();
+            sender.send(PingEvent::Located { ip, location }).ok();
+
+            thread::spawn(move || {
+                let started = Instant::now();
+                let interval = Duration::from_secs(1);
+                while !stop.load(Ordering::Relaxed) {
+                    let sent = Instant::now();
+                    let rtt = match trace::ping(ip, Duration::from_millis(options.timeout_ms)) {
+                        Ok(rtt) => rtt,
+                        Err(e) => {
+                            sender.send(PingEvent::Error(e)).ok();
+                            break;
+                        }
+                    };
+                    let at = sent.duration_since(started).as_secs_f64();
+                    if sender.send(PingEvent::Reply { at, rtt }).is_err() {
+                        break;
+                    }
+                    thread::sleep(interval.saturating_sub(sent.elapsed()));
+                }
+                debug!("Stopped pinging {}", ip);
+            });
+        });
+    }
+
+    fn running(&self) -> bool {
+        !self.stop.load(Ordering::Relaxed) && self.error.is_none()
+    }
+}
+
+/// RTT chart and loss statistics of the current ping session.
+pub fn ping(ui: &Ui, window: &mut PingWindow) {
+    while let Ok(event) = window.receiver.try_recv() {
+        match event {
+            PingEvent::Located { ip, location } => {
+                window.ip = Some(ip);
+                window.location = location;
+            }
+            PingEvent::Reply { at, rtt } => {
+                window.samples.push((at, rtt.map(|rtt| rtt.as_secs_f64() * 1000.0)));
+            }
+            PingEvent::Error(e) => {
+                warn!("Ping to {} failed: {}", window.target, e);
+                window.error = Some(e);
+            }
+        }
+    }
+    if window.running() {
+        // Replies arrive on their own, keep the chart moving
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+
+    let mut open = window.open;
+    Window::new(trf("Ping {}", &[&window.target]))
+        .id(egui::Id::new("ping"))
+        .open(&mut open)
+        .default_size([460., 300.])
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                match window.ip {
+                    Some(ip) => ui.label(ip.to_string()),
+                    None => ui.spinner(),
+                };
+                if !window.location.is_empty() {
+                    ui.label(&window.location);
+                }
+            });
+            if let Some(e) = &window.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+
+            let sent = window.samples.len();
+            let rtts: Vec<f64> = window.samples.iter().filter_map(|(_, rtt)| *rtt).collect();
+            let loss = if sent > 0 {
+                (sent - rtts.len()) as f64 / sent as f64 * 100.0
+            } else {
+                0.0
+            };
+            ui.horizontal(|ui| {
+                ui.label(trf("Sent {}", &[&sent]));
+                ui.label(trf("Received {}", &[&rtts.len()]));
+                ui.label(trf("Loss {}%", &[&format!("{:.1}", loss)]));
+                if !rtts.is_empty() {
+                    let min = rtts.iter().copied().fold(f64::INFINITY, f64::min);
+                    let max = rtts.iter().copied().fold(0.0, f64::max);
+                    let avg = rtts.iter().sum::<f64>() / rtts.len() as f64;
+                    ui.label(trf(
+                        "RTT {} / {} / {} ms",
+                        &[&format!("{:.1}", min), &format!("{:.1}", avg), &format!("{:.1}", max)],
+                    ))
+                    .on_hover_text(tr("Min / avg / max"));
+                }
+            });
+
+            let replies: Vec<[f64; 2]> = window
+                .samples
+                .iter()
+                .filter_map(|(at, rtt)| rtt.map(|rtt| [*at, rtt]))
+                .collect();
+            let losses: Vec<[f64; 2]> = window
+                .samples
+                .iter()
+                .filter(|(_, rtt)| rtt.is_none())
+                .map(|(at, _)| [*at, 0.0])
+                .collect();
+            Plot::new("ping_rtt")
+                .height(180.)
+                .x_axis_label("s")
+                .y_axis_label("ms")
+                .include_y(0.0)
+                .allow_scroll(false)
+                .show(ui, |plot_ui| {
+                    plot_ui.line(Line::new(replies).name("RTT"));
+                    plot_ui.points(
+                        Points::new(losses)
+                            .name(tr("Lost"))
+                            .color(egui::Color32::RED)
+                            .radius(3.),
+                    );
+                });
+
+            if window.running() && ui.button(tr("Stop")).clicked() {
+                window.stop.store(true, Ordering::Relaxed);
+            }
+        });
+    if !open {
+        window.stop.store(true, Ordering::Relaxed);
+    }
+    window.open = open;
+}
+
+enum SweepEvent {
+    Hop { size: u16, node: tracert::node::Node },
+    /// Every hop of this size is in
+    Size(u16),
+    Error(String),
+}
+
+/// Traces of one target with growing probe sizes, charting each hop's RTT against the size.
+pub struct SweepWindow {
+    open: bool,
+    target: String,
+    options: TraceOptions,
+    from: u16,
+    to: u16,
+    steps: usize,
+    /// Sizes of the running sweep, in the order they are probed
+    sizes: Vec<u16>,
+    /// Sizes done so far
+    done: usize,
+    /// Answering IP and (size, RTT in ms) samples of every TTL
+    hops: BTreeMap<u8, (IpAddr, Vec<(u16, f64)>)>,
+    error: Option<String>,
+    stop: Arc<AtomicBool>,
+    receiver: Receiver<SweepEvent>,
+}
+
+impl Default for SweepWindow {
+    fn default() -> Self {
+        let (_, receiver) = channel();
+        Self {
+            open: false,
+            target: String::new(),
+            options: TraceOptions::default(),
+            from: 64,
+            to: 1400,
+            steps: 8,
+            sizes: Vec::new(),
+            done: 0,
+            hops: BTreeMap::new(),
+            error: None,
+            stop: Arc::new(AtomicBool::new(true)),
+            receiver,
+        }
+    }
+}
+
+impl SweepWindow {
+    /// Shows the window for the target of trace `id`, keeping the sweep range from last time.
+    pub fn open(&mut self, id: usize, trace_set: &TraceSet) {
+        let Some(trace) = trace_set.traces.iter().find(|t| t.id == id) else {
+            return;
+        };
+        self.stop.store(true, Ordering::Relaxed);
+        *self = Self {
+            open: true,
+            target: trace.target.clone(),
+            options: trace.settings.options,
+            from: self.from,
+            to: self.to,
+            steps: self.steps,
+            ..Default::default()
+        };
+    }
+
+    fn start(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        info!("Starting size sweep for {}", self.target);
+        let (sender, receiver) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        self.sizes = trace::sweep_sizes(self.from, self.to, self.steps);
+        self.done = 0;
+        self.hops.clear();
+        self.error = None;
+        self.stop = stop.clone();
+        self.receiver = receiver;
+
+        let target = self.target.clone();
+        let options = self.options;
+        let sizes = self.sizes.clone();
+        thread::spawn(move || {
+            let Some(ip) = trace::resolve(&target) else {
+                sender.send(SweepEvent::Error(trf("Could not resolve {}", &[&target]))).ok();
+                return;
+            };
+            let mut last = None;
+            let result = trace::size_sweep(ip, options, &sizes, |size, node| {
+                if let Some(done) = last.filter(|last| *last != size) {
+                    sender.send(SweepEvent::Size(done)).ok();
+                }
+                last = Some(size);
+                !stop.load(Ordering::Relaxed) && sender.send(SweepEvent::Hop { size, node }).is_ok()
+            });
+            match result {
+                Ok(()) => {
+                    if let Some(size) = last {
+                        sender.send(SweepEvent::Size(size)).ok();
+                    }
+                }
+                Err(e) => {
+                    sender.send(SweepEvent::Error(e)).ok();
+                }
+            }
+            stop.store(true, Ordering::Relaxed);
+            debug!("Size sweep of {} done", target);
+        });
+    }
+
+    fn running(&self) -> bool {
+        !self.stop.load(Ordering::Relaxed)
+    }
+}
+
+/// Sweep range, progress and the RTT by probe size chart of the current size sweep.
+pub fn sweep(ui: &Ui, window: &mut SweepWindow) {
+    while let Ok(event) = window.receiver.try_recv() {
+        match event {
+            SweepEvent::Hop { size, node } => {
+                let (_, samples) = window.hops.entry(node.seq).or_insert((node.ip_addr, Vec::new()));
+                samples.push((size, node.rtt.as_secs_f64() * 1000.0));
+            }
+            SweepEvent::Size(size) => {
+                window.done = window.sizes.iter().position(|s| *s == size).map_or(window.done, |i| i + 1);
+            }
+            SweepEvent::Error(e) => {
+                warn!("Size sweep of {} failed: {}", window.target, e);
+                window.error = Some(e);
+            }
+        }
+    }
+    if window.running() {
+        ui.ctx().request_repaint_after(Duration::from_millis(250));
+    }
+
+    let mut open = window.open;
+    Window::new(trf("Size sweep {}", &[&window.target]))
+        .id(egui::Id::new("size_sweep"))
+        .open(&mut open)
+        .default_size([480., 360.])
+        .show(ui.ctx(), |ui| {
+            let running = window.running();
+            ui.add_enabled_ui(!running, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut window.from).range(28..=9000).prefix(tr("From ")).suffix(" B"));
+                    ui.add(egui::DragValue::new(&mut window.to).range(28..=9000).prefix(tr("to ")).suffix(" B"));
+                    ui.add(egui::DragValue::new(&mut window.steps).range(2..=32).suffix(" steps"));
+                });
+            });
+            ui.horizontal(|ui| {
+                if running {
+                    if ui.button(tr("Stop")).clicked() {
+                        window.stop.store(true, Ordering::Relaxed);
+                    }
+                    ui.spinner();
+                    ui.label(trf("{} of {} sizes", &[&window.done, &window.sizes.len()]));
+                } else if ui.button(tr("Start")).on_hover_text(tr("Trace once with each size")).clicked() {
+                    window.start();
+                }
+            });
+            if let Some(e) = &window.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            ui.label(
+                RichText::new(tr("Sizes include the IP header. Hops whose RTT climbs with size may fragment or rate limit big packets."))
+                    .small()
+                    .weak(),
+            );
+
+            Plot::new("sweep_rtt")
+                .height(200.)
+                .x_axis_label(tr("bytes"))
+                .y_axis_label("ms")
+                .include_y(0.0)
+                .allow_scroll(false)
+                .legend(egui_plot::Legend::default())
+                .show(ui, |plot_ui| {
+                    for (ttl, (ip, samples)) in &window.hops {
+                        let points: Vec<[f64; 2]> = samples.iter().map(|(size, rtt)| [*size as f64, *rtt]).collect();
+                        plot_ui.line(Line::new(points).name(format!("#{} {}", ttl, ip)));
+                    }
+                });
+
+            // How much each hop slowed down from the smallest size to the biggest
+            egui::ScrollArea::vertical().max_height(120.).show(ui, |ui| {
+                egui::Grid::new("sweep_hops").striped(true).show(ui, |ui| {
+                    ui.strong(tr("Hop"));
+                    ui.strong("IP");
+                    ui.strong(tr("Answered"));
+                    ui.strong(tr("Growth"));
+                    ui.end_row();
+                    for (ttl, (ip, samples)) in &window.hops {
+                        ui.label(format!("#{}", ttl));
+                        ui.label(ip.to_string());
+                        ui.label(format!("{}/{}", samples.len(), window.done.max(samples.len())));
+                        let smallest = samples.iter().min_by_key(|(size, _)| *size);
+                        let biggest = samples.iter().max_by_key(|(size, _)| *size);
+                        match (smallest, biggest) {
+                            (Some((from, first)), Some((to, last))) if to > from => {
+                                ui.label(format!("{:+.1} ms", last - first))
+                                    .on_hover_text(trf("From {} to {} bytes", &[&from, &to]));
+                            }
+                            _ => {
+                                ui.weak("-");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    if !open {
+        window.stop.store(true, Ordering::Relaxed);
+    }
+    window.open = open;
+}
+
+/// Editing state of the history window.
+#[derive(Default)]
+pub struct HistoryPanel {
+    filter: Filter,
+    from: String,
+    to: String,
+    filter_name: String,
+    new_tag: String,
+    /// Index of the entry whose target is being edited, and the new target
+    editing: Option<(usize, String)>,
+    stats_target: String,
+    /// Last statistics looked up, for `stats_target`
+    stats: Option<Result<Option<crate::store::TargetStats>, String>>,
+}
+
+pub fn history(ui: &Ui, panel: &mut HistoryPanel, history: &mut History, trace_set: &mut TraceSet) {
+    Window::new(tr("History"))
+        .id(egui::Id::new("History"))
+        .default_open(false)
+        .resizable(false)
+        .pivot(Align2::LEFT_CENTER)
+        .default_pos(corner(ui.ctx(), Align2::LEFT_CENTER, [10., 0.]))
+        .show(ui.ctx(), |ui| {
+            // Quick filters
+            ui.horizontal_wrapped(|ui| {
+                if ui.selectable_label(panel.filter == Filter::default(), tr("All")).clicked() {
+                    panel.filter = Filter::default();
+                    panel.from.clear();
+                    panel.to.clear();
+                }
+                let mut remove = None;
+                for saved in &history.saved_filters {
+                    let response = ui
+                        .selectable_label(panel.filter == saved.filter, &saved.name)
+                        .on_hover_text(tr("Right-click to delete"));
+                    if response.clicked() {
+                        panel.filter = saved.filter.clone();
+                        panel.from = date_text(saved.filter.from);
+                        panel.to = date_text(saved.filter.to);
+                    }
+                    if response.secondary_clicked() {
+                        remove = Some(saved.name.clone());
+                    }
+                }
+                if let Some(name) = remove {
+                    history.saved_filters.retain(|f| f.name != name);
+                }
+            });
+
+            ui.collapsing(tr("Filter"), |ui| {
+                egui::Grid::new("history_filter").num_columns(2).show(ui, |ui| {
+                    ui.label(tr("Tag"));
+                    egui::ComboBox::from_id_salt("history_tag")
+                        .selected_text(panel.filter.tag.as_deref().unwrap_or(tr("Any")))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut panel.filter.tag, None, tr("Any"));
+                            for tag in history.tags() {
+                                ui.selectable_value(&mut panel.filter.tag, Some(tag.clone()), tag);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label(tr("Target"));
+                    ui.text_edit_singleline(&mut panel.filter.target);
+                    ui.end_row();
+
+                    ui.label(tr("From"));
+                    date_input(ui, &mut panel.from, &mut panel.filter.from);
+                    ui.end_row();
+
+                    ui.label(tr("To"));
+                    date_input(ui, &mut panel.to, &mut panel.filter.to);
+                    ui.end_row();
+
+                    ui.label(tr("Outcome"));
+                    egui::ComboBox::from_id_salt("history_outcome")
+                        .selected_text(tr(match panel.filter.outcome {
+                            None => "Any",
+                            Some(Outcome::Completed) => "Completed",
+                            Some(Outcome::Failed) => "Failed",
+                        }))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut panel.filter.outcome, None, tr("Any"));
+                            ui.selectable_value(&mut panel.filter.outcome, Some(Outcome::Completed), tr("Completed"));
+                            ui.selectable_value(&mut panel.filter.outcome, Some(Outcome::Failed), tr("Failed"));
+                        });
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut panel.filter_name).hint_text(tr("Preset name"))).named(tr("Preset name"));
+                    if ui
+                        .add_enabled(!panel.filter_name.trim().is_empty(), egui::Button::new(tr("Save")))
+                        .clicked()
+                    {
+                        history.save_filter(panel.filter_name.trim().to_string(), panel.filter.clone());
+                        panel.filter_name.clear();
+                    }
+                });
+            });
+
+            ui.collapsing(tr("Statistics"), |ui| {
+                ui.horizontal(|ui| {
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut panel.stats_target)
+                            .hint_text(tr("Target"))
+                            .desired_width(140.),
+                    )
+                    .named(tr("Target"));
+                    let enter = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button(tr("Look up")).clicked() || enter {
+                        panel.stats = Some(history.stats(panel.stats_target.trim()));
+                    }
+                });
+                match &panel.stats {
+                    Some(Ok(Some(stats))) => {
+                        egui::Grid::new("history_stats").num_columns(2).show(ui, |ui| {
+                            ui.label(tr("Runs"));
+                            ui.label(trf("{}, {} completed", &[&stats.runs, &stats.completed]));
+                            ui.end_row();
+                            ui.label(tr("Seen"));
+                            ui.label(trf(
+                                "{} to {}",
+                                &[&stats.first.format("%Y-%m-%d"), &stats.last.format("%Y-%m-%d")],
+                            ));
+                            ui.end_row();
+                            ui.label(tr("Distinct paths"));
+                            ui.label(stats.paths.to_string());
+                            ui.end_row();
+                            ui.label("RTT").on_hover_text(tr("To the last hop of completed runs"));
+                            match (stats.rtt_min, stats.rtt_mean, stats.rtt_max) {
+                                (Some(min), Some(mean), Some(max)) => {
+                                    ui.label(format!("{:.1} / {:.1} / {:.1} ms", min, mean, max))
+                                        .on_hover_text(tr("Min / mean / max"));
+                                }
+                                _ => {
+                                    ui.weak("-");
+                                }
+                            }
+                            ui.end_row();
+                        });
+                    }
+                    Some(Ok(None)) => {
+                        ui.weak(tr("Never traced"));
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    None => {}
+                }
+            });
+
+            ui.separator();
+            let mut retagged = None;
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                let mut matched = 0;
+                for (i, entry) in history.entries.iter_mut().enumerate().rev() {
+                    if !panel.filter.matches(entry) {
+                        continue;
+                    }
+                    matched += 1;
+                    ui.horizontal(|ui| {
+                        let started = ui.label(entry.started.format("%Y-%m-%d %H:%M").to_string());
+                        if let Some(network) = &entry.network {
+                            started.on_hover_text(trf("Taken from {}", &[&network]));
+                        }
+                        let text = panel.editing.as_mut().filter(|(idx, _)| *idx == i).map(|(_, text)| text);
+                        match target_label(ui, &entry.target, text, true) {
+                            Some(TargetEdit::Begin) => panel.editing = Some((i, entry.target.clone())),
+                            Some(TargetEdit::Submit(target)) => {
+                                panel.editing = None;
+                                trace_set.start_requests.push(StartRequest {
+                                    target,
+                                    tags: entry.tags.clone(),
+                                    settings: Some(entry.settings),
+                                });
+                                ui.ctx().request_repaint();
+                            }
+                            Some(TargetEdit::Cancel) => panel.editing = None,
+                            None => {}
+                        }
+                        if entry.outcome == Outcome::Failed {
+                            ui.colored_label(egui::Color32::RED, tr("failed"));
+                        }
+                        if ui.small_button(tr("Show")).clicked() {
+                            let id = trace_set.show(
+                                entry.target.clone(),
+                                entry.started,
+                                entry.tags.clone(),
+                                entry.settings,
+                                entry.network.clone(),
+                                entry.nodes.clone(),
+                                entry.timeouts.clone(),
+                            );
+                            if let Some(trace) = trace_set.get_mut(id) {
+                                trace.deviations = history.baselines.compare(&trace.target, &trace.nodes);
+                            }
+                        }
+                    });
+                    ui.horizontal_wrapped(|ui| {
+                        let mut remove = None;
+                        for (i, tag) in entry.tags.iter().enumerate() {
+                            if ui.small_button(format!("🏷 {}", tag)).on_hover_text(tr("Click to remove")).clicked() {
+                                remove = Some(i);
+                            }
+                        }
+                        if let Some(tag) = remove {
+                            entry.tags.remove(tag);
+                            retagged = Some(i);
+                        }
+                        ui.menu_button("+", |ui| {
+                            let response = ui.add(egui::TextEdit::singleline(&mut panel.new_tag).hint_text(tr("New tag"))).named(tr("New tag"));
+                            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                for tag in history::parse_tags(&panel.new_tag) {
+                                    if !entry.tags.contains(&tag) {
+                                        entry.tags.push(tag);
+                                    }
+                                }
+                                retagged = Some(i);
+                                panel.new_tag.clear();
+                                ui.close_menu();
+                            }
+                        })
+                        .response
+                        .described(tr("Add a tag"));
+                    });
+                    ui.separator();
+                }
+                if matched == 0 {
+                    ui.weak(tr("No traces match"));
+                }
+            });
+            if let Some(i) = retagged {
+                history.save_tags(i);
+            }
+        });
+}
+
+/// Sound settings, and the player sounding alerts when built with audio support.
+pub struct SoundsWindow {
+    pub open: bool,
+    #[cfg(feature = "audio")]
+    player: crate::audio::Player,
+}
+
+impl SoundsWindow {
+    #[cfg_attr(not(feature = "audio"), allow(unused_variables))]
+    pub fn new(bus: &Bus) -> Self {
+        Self {
+            open: false,
+            #[cfg(feature = "audio")]
+            player: crate::audio::Player::new(bus),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "audio"), allow(unused_variables))]
+pub fn sounds(ui: &Ui, window: &mut SoundsWindow, config: &mut Config) {
+    #[cfg(feature = "audio")]
+    window.player.poll(&config.audio);
+    Window::new(tr("Sounds"))
+        .id(egui::Id::new("Sounds"))
+        .open(&mut window.open)
+        .resizable(false)
+        .show(ui.ctx(), |ui| {
+            #[cfg(feature = "audio")]
+            sound_settings(ui, &mut window.player, &mut config.audio);
+            #[cfg(not(feature = "audio"))]
+            ui.weak(tr("Built without audio support, enable the audio feature to hear alerts."));
+        });
+}
+
+#[cfg(feature = "audio")]
+fn sound_settings(ui: &mut Ui, player: &mut crate::audio::Player, config: &mut crate::audio::AudioConfig) {
+    use crate::{alert::AlertKind, audio::Sound};
+
+    ui.checkbox(&mut config.muted, tr("Mute"));
+    ui.add_enabled(!config.muted, egui::Slider::new(&mut config.volume, 0.0..=1.0).text(tr("Volume")));
+    let volume = config.volume;
+    egui::Grid::new("sounds").num_columns(3).show(ui, |ui| {
+        for kind in AlertKind::ALL {
+            ui.label(tr(kind.name()));
+            let sound = config.sound_mut(kind);
+            egui::ComboBox::from_id_salt(kind.name())
+                .selected_text(tr(sound.name()))
+                .show_ui(ui, |ui| {
+                    for built_in in Sound::BUILT_IN {
+                        let name = tr(built_in.name());
+                        ui.selectable_value(sound, built_in, name);
+                    }
+                    let file = matches!(sound, Sound::File(_));
+                    if ui.selectable_label(file, tr("File")).clicked() && !file {
+                        *sound = Sound::File(String::new());
+                    }
+                });
+            if ui.small_button("▶").described(tr("Play")).clicked() {
+                player.play(sound, volume);
+            }
+            ui.end_row();
+            if let Sound::File(path) = sound {
+                ui.label("");
+                ui.add(egui::TextEdit::singleline(path).hint_text("alert.wav").desired_width(140.)).named(tr("Sound file"));
+                ui.end_row();
+            }
+        }
+    });
+    if let Some(e) = player.error() {
+        ui.colored_label(egui::Color32::RED, e);
+    }
+}
+
+/// Path changes seen in the history, with the router events around them when listening for syslog.
+pub struct Timeline {
+    #[cfg(feature = "syslog")]
+    pub syslog: crate::syslog::Listener,
+}
+
+#[cfg_attr(not(feature = "syslog"), allow(unused_variables))]
+pub fn timeline(
+    ui: &Ui,
+    timeline: &mut Timeline,
+    route_diff: &mut RouteDiffWindow,
+    history: &History,
+    config: &mut Config,
+    runtime: &Executor,
+) {
+    #[cfg(feature = "syslog")]
+    timeline.syslog.poll();
+    Window::new(tr("Timeline"))
+        .id(egui::Id::new("Timeline"))
+        .default_open(false)
+        .default_width(420.)
+        // Above the zoom buttons
+        .pivot(Align2::LEFT_BOTTOM)
+        .default_pos(corner(ui.ctx(), Align2::LEFT_BOTTOM, [10., -60.]))
+        .show(ui.ctx(), |ui| {
+            #[cfg(feature = "syslog")]
+            syslog_settings(ui, &mut timeline.syslog, &mut config.syslog, runtime);
+
+            let changes = history.path_changes();
+            if changes.is_empty() {
+                ui.weak(tr("No path changes yet, they show up once a target is traced again."));
+                return;
+            }
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for change in changes.iter().rev() {
+                    ui.horizontal(|ui| {
+                        ui.strong(&change.after.target);
+                        ui.label(format!(
+                            "{} → {}",
+                            change.before.started.format("%Y-%m-%d %H:%M"),
+                            change.after.started.format("%H:%M")
+                        ));
+                        if ui.small_button(tr("Diff")).clicked() {
+                            route_diff.open(change.before, change.after);
+                        }
+                    });
+                    let (before, after) = (change.before.path(), change.after.path());
+                    let first = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+                    ui.label(
+                        RichText::new(trf(
+                            "Hop {} was {}, now {}",
+                            &[
+                                &(first + 1),
+                                before.get(first).unwrap_or(&tr("missing")),
+                                after.get(first).unwrap_or(&tr("missing")),
+                            ],
+                        ))
+                        .small(),
+                    );
+                    #[cfg(feature = "syslog")]
+                    related_events(ui, &timeline.syslog.events, change, config.syslog.match_window_secs);
+                    ui.separator();
+                }
+            });
+        });
+}
+
+/// Two routes to the same target side by side, hop by hop.
+#[derive(Default)]
+pub struct RouteDiffWindow {
+    open: bool,
+    before: Option<history::HistoryEntry>,
+    after: Option<history::HistoryEntry>,
+    diff: Option<routediff::RouteDiff>,
+    /// The differing hops still need marking on the traces already on the map
+    mark: bool,
+}
+
+impl RouteDiffWindow {
+    pub fn open(&mut self, before: &history::HistoryEntry, after: &history::HistoryEntry) {
+        self.diff = Some(routediff::RouteDiff::between(&before.nodes, &after.nodes));
+        self.before = Some(before.clone());
+        self.after = Some(after.clone());
+        self.open = true;
+        self.mark = true;
+    }
+}
+
+/// Id of the trace showing `entry`, if it's on the map.
+fn trace_of(trace_set: &TraceSet, entry: &history::HistoryEntry) -> Option<usize> {
+    trace_set
+        .traces
+        .iter()
+        .find(|t| t.target == entry.target && t.started == entry.started)
+        .map(|t| t.id)
+}
+
+pub fn route_diff(ui: &Ui, window: &mut RouteDiffWindow, trace_set: &mut TraceSet) {
+    let (Some(before), Some(after), Some(diff)) = (&window.before, &window.after, &window.diff) else {
+        return;
+    };
+    let mut show = false;
+    if window.mark {
+        window.mark = false;
+        show = true;
+    }
+    Window::new(tr("Route diff"))
+        .id(egui::Id::new("Route diff"))
+        .open(&mut window.open)
+        .default_width(360.)
+        .show(ui.ctx(), |ui| {
+            ui.horizontal(|ui| {
+                ui.strong(&after.target);
+                ui.label(format!(
+                    "{} → {}",
+                    before.started.format("%Y-%m-%d %H:%M"),
+                    after.started.format("%Y-%m-%d %H:%M")
+                ));
+            });
+            ui.label(diff.summary());
+            let as_path = if diff.as_path_changed() {
+                RichText::new(trf(
+                    "AS path changed\n{}\n{}",
+                    &[&routediff::format_as_path(&diff.as_before), &routediff::format_as_path(&diff.as_after)],
+                ))
+                .color(ROUTE_CHANGE_COLOR)
+            } else {
+                RichText::new(trf("Same AS path: {}", &[&routediff::format_as_path(&diff.as_after)])).weak()
+            };
+            ui.label(as_path.small());
+            ui.separator();
+
+            egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                for line in diff.lines() {
+                    let color = match line.chars().next() {
+                        Some('+') => egui::Color32::GREEN,
+                        Some('-') => egui::Color32::RED,
+                        Some('~') => ROUTE_CHANGE_COLOR,
+                        _ => ui.visuals().weak_text_color(),
+                    };
+                    ui.label(RichText::new(line).monospace().color(color));
+                }
+            });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .button(tr("Show both on map"))
+                    .on_hover_text(tr("Differing hops are ringed on both routes"))
+                    .clicked()
+                {
+                    show = true;
+                    for entry in [before, after] {
+                        if trace_of(trace_set, entry).is_none() {
+                            trace_set.show(
+                                entry.target.clone(),
+                                entry.started,
+                                entry.tags.clone(),
+                                entry.settings,
+                                entry.network.clone(),
+                                entry.nodes.clone(),
+                                entry.timeouts.clone(),
+                            );
+                        }
+                    }
+                }
+                if ui.button(tr("Copy")).clicked() {
+                    let mut text = format!("{}: {}\n", after.target, diff.summary());
+                    for line in diff.lines() {
+                        text.push_str(&line);
+                        text.push('\n');
+                    }
+                    ui.ctx().copy_text(text);
+                    trace_set.toasts.publish(Toast::success(tr("Copied diff")));
+                }
+            });
+        });
+
+    if show {
+        for (entry, ttls) in [(before, diff.ttls_before()), (after, diff.ttls_after())] {
+            if let Some(trace) = trace_of(trace_set, entry).and_then(|id| trace_set.get_mut(id)) {
+                trace.route_changes = ttls;
+                trace.visible = true;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "syslog")]
+fn syslog_settings(
+    ui: &mut Ui,
+    listener: &mut crate::syslog::Listener,
+    config: &mut crate::syslog::SyslogConfig,
+    runtime: &Executor,
+) {
+    ui.collapsing(tr("Syslog"), |ui| {
+        ui.checkbox(&mut config.enabled, tr("Listen for router syslog"));
+        ui.horizontal(|ui| {
+            ui.label(tr("Address"));
+            ui.text_edit_singleline(&mut config.bind);
+        });
+        ui.add(
+            egui::Slider::new(&mut config.match_window_secs, 30..=3600)
+                .text(tr("Match window"))
+                .suffix(" s"),
+        );
+        ui.horizontal(|ui| {
+            if ui.button(tr("Apply")).clicked() {
+                listener.restart(config, runtime);
+            }
+            match &listener.status {
+                Ok(Some(addr)) => {
+                    ui.label(trf("Listening on {}, {} events", &[&addr, &listener.events.len()]));
+                }
+                Ok(None) => {
+                    ui.weak(tr("Not listening"));
+                }
+                Err(e) => {
+                    ui.colored_label(egui::Color32::RED, e);
+                }
+            }
+        });
+    });
+    ui.separator();
+}
+
+/// Router events received around a path change. Events from hops on either path are highlighted.
+#[cfg(feature = "syslog")]
+fn related_events(ui: &mut Ui, events: &[crate::syslog::RouteEvent], change: &history::PathChange, window_secs: u64) {
+    let window = chrono::Duration::seconds(window_secs as i64);
+    let (from, to) = (change.before.started - window, change.after.started + window);
+    let hops: Vec<&str> = change.before.path().into_iter().chain(change.after.path()).collect();
+    let related: Vec<_> = events.iter().filter(|e| e.at >= from && e.at <= to).collect();
+    if related.is_empty() {
+        ui.weak(tr("No router events around this change"));
+        return;
+    }
+    for event in related {
+        let text = format!(
+            "{} {} {}: {}",
+            event.at.format("%H:%M:%S"),
+            event.kind.name(),
+            event.source,
+            event.message
+        );
+        let on_path = hops.contains(&event.source.to_string().as_str());
+        let text = RichText::new(text).small();
+        ui.label(if on_path { text.strong() } else { text })
+            .on_hover_text(tr(if on_path { "Sent by a hop on the path" } else { "Sent by another router" }));
+    }
+}
+
+fn date_text(date: Option<chrono::NaiveDate>) -> String {
+    date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default()
+}
+
+/// Text field for a YYYY-MM-DD date; invalid input leaves the date unset.
+fn date_input(ui: &mut Ui, text: &mut String, date: &mut Option<chrono::NaiveDate>) {
+    let parsed = chrono::NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok();
+    let invalid = !text.trim().is_empty() && parsed.is_none();
+    let mut edit = egui::TextEdit::singleline(text).hint_text(tr("YYYY-MM-DD"));
+    if invalid {
+        edit = edit.text_color(egui::Color32::RED);
+    }
+    if ui.add(edit).named(tr("Date, YYYY-MM-DD")).changed() {
+        *date = chrono::NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok();
+    }
 }
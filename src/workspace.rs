@@ -0,0 +1,131 @@
+//! A team's shared monitoring setup, bundled into one file to hand around. Credentials like
+//! SNMP communities and tile API keys never go in.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    baseline::Baseline,
+    config::Config,
+    history::{History, SavedFilter},
+    planner::Region,
+    plugins::{MarkerStyle, TraceSet},
+    policy::TransitPolicy,
+    scheduler::Job,
+    splittunnel::Check,
+};
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workspace {
+    pub favorite_targets: Vec<String>,
+    pub pinned_targets: Vec<String>,
+    pub jobs: Vec<Job>,
+    pub baselines: BTreeMap<String, Baseline>,
+    pub saved_filters: Vec<SavedFilter>,
+    /// Country codes flagged in the compliance report
+    pub concerns: Vec<String>,
+    pub transit_policies: Vec<TransitPolicy>,
+    pub regions: Vec<Region>,
+    pub split_tunnel: Vec<Check>,
+    /// Managed SNMP ranges, without anything needed to query them
+    pub snmp_managed: Vec<String>,
+    /// Left out unless asked for, it's more taste than setup
+    pub style: Option<MarkerStyle>,
+}
+
+/// Adds `new` to `list`, replacing entries with the same key.
+fn merge<T>(list: &mut Vec<T>, new: Vec<T>, key: impl Fn(&T) -> &str) {
+    for item in new {
+        match list.iter_mut().find(|old| key(old) == key(&item)) {
+            Some(old) => *old = item,
+            None => list.push(item),
+        }
+    }
+}
+
+impl Workspace {
+    pub fn collect(config: &Config, trace_set: &TraceSet, history: &History, style: bool) -> Self {
+        Self {
+            favorite_targets: config.favorite_targets.clone(),
+            pinned_targets: trace_set.pinned.iter().cloned().collect(),
+            jobs: config.schedule.jobs.clone(),
+            baselines: history.baselines.targets.clone(),
+            saved_filters: history.saved_filters.clone(),
+            concerns: config.concerns.clone(),
+            transit_policies: config.transit_policies.clone(),
+            regions: config.planner.regions.clone(),
+            split_tunnel: config.split_tunnel.checks.clone(),
+            snmp_managed: config.snmp.managed.iter().filter(|r| !r.trim().is_empty()).cloned().collect(),
+            style: style.then(|| trace_set.style()),
+        }
+    }
+
+    /// Adds everything to the current setup. Entries for the same target or name are
+    /// replaced, everything else is kept.
+    pub fn apply(self, config: &mut Config, trace_set: &mut TraceSet, history: &mut History) {
+        merge(&mut config.favorite_targets, self.favorite_targets, |t| t);
+        trace_set.pinned.extend(self.pinned_targets);
+        merge(&mut config.schedule.jobs, self.jobs, |job| &job.target);
+        if !self.baselines.is_empty() {
+            for (target, baseline) in self.baselines {
+                history.baselines.set(&target, baseline);
+            }
+            history.baselines.save();
+        }
+        for saved in self.saved_filters {
+            history.save_filter(saved.name, saved.filter);
+        }
+        merge(&mut config.concerns, self.concerns, |code| code);
+        // Policies are replaced by destination, which can have several
+        config
+            .transit_policies
+            .retain(|old| !self.transit_policies.iter().any(|new| new.destination == old.destination));
+        config.transit_policies.extend(self.transit_policies);
+        merge(&mut config.planner.regions, self.regions, |region| &region.name);
+        merge(&mut config.split_tunnel.checks, self.split_tunnel, |check| &check.target);
+        config.snmp.managed.retain(|r| !r.trim().is_empty());
+        merge(&mut config.snmp.managed, self.snmp_managed, |range| range);
+        if let Some(style) = self.style {
+            trace_set.set_style(style);
+        }
+    }
+
+    /// e.g. "3 favorite targets, 2 scheduled jobs"
+    pub fn summary(&self) -> String {
+        let mut parts: Vec<String> = [
+            (self.favorite_targets.len(), "favorite targets"),
+            (self.pinned_targets.len(), "pinned targets"),
+            (self.jobs.len(), "scheduled jobs"),
+            (self.baselines.len(), "baselines"),
+            (self.saved_filters.len(), "saved filters"),
+            (self.concerns.len(), "flagged countries"),
+            (self.transit_policies.len(), "transit policies"),
+            (self.regions.len(), "planner regions"),
+            (self.split_tunnel.len(), "split tunnel checks"),
+            (self.snmp_managed.len(), "managed ranges"),
+        ]
+        .into_iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, what)| format!("{} {}", n, what))
+        .collect();
+        if self.style.is_some() {
+            parts.push("the map style".to_string());
+        }
+        if parts.is_empty() {
+            return "nothing".to_string();
+        }
+        parts.join(", ")
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, text).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}